@@ -0,0 +1,488 @@
+#ifndef BUTTPLUG_H
+#define BUTTPLUG_H
+
+/* Generated with cbindgen:0.26.0 */
+
+/* Warning, this file is autogenerated by cbindgen. Don't modify this manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Actuator types, matching [ActuatorType].
+typedef enum ButtplugFfiActuatorType {
+  BUTTPLUG_FFI_ACTUATOR_TYPE_UNKNOWN = 0,
+  BUTTPLUG_FFI_ACTUATOR_TYPE_VIBRATE,
+  BUTTPLUG_FFI_ACTUATOR_TYPE_ROTATE,
+  BUTTPLUG_FFI_ACTUATOR_TYPE_OSCILLATE,
+  BUTTPLUG_FFI_ACTUATOR_TYPE_CONSTRICT,
+  BUTTPLUG_FFI_ACTUATOR_TYPE_INFLATE,
+  BUTTPLUG_FFI_ACTUATOR_TYPE_POSITION,
+} ButtplugFfiActuatorType;
+
+// Type of event passed to a [ButtplugFfiEventCallback].
+typedef enum ButtplugFfiEventType {
+  // A device was added. `device_index` is set, and `message` is the device name.
+  BUTTPLUG_FFI_EVENT_TYPE_DEVICE_ADDED,
+  // A device was removed. `device_index` is set.
+  BUTTPLUG_FFI_EVENT_TYPE_DEVICE_REMOVED,
+  BUTTPLUG_FFI_EVENT_TYPE_SCANNING_FINISHED,
+  // Hardware was found that will not be connected. `message` is the hardware name.
+  BUTTPLUG_FFI_EVENT_TYPE_UNSUPPORTED_DEVICE_FOUND,
+  BUTTPLUG_FFI_EVENT_TYPE_SERVER_CONNECT,
+  BUTTPLUG_FFI_EVENT_TYPE_SERVER_DISCONNECT,
+  BUTTPLUG_FFI_EVENT_TYPE_PING_TIMEOUT,
+  // An error was received that is not tied to a request. `message` is the error description.
+  BUTTPLUG_FFI_EVENT_TYPE_ERROR,
+  // A subscribed sensor sent a reading. `device_index`, `sensor_index`, `sensor_type`, `data` and
+  // `data_len` are set.
+  BUTTPLUG_FFI_EVENT_TYPE_SENSOR_READING,
+  // A subscribed raw endpoint sent data. `device_index`, `bytes` and `bytes_len` are set, and
+  // `message` is the endpoint name.
+  BUTTPLUG_FFI_EVENT_TYPE_RAW_READING,
+} ButtplugFfiEventType;
+
+// Sensor types, matching [SensorType].
+typedef enum ButtplugFfiSensorType {
+  BUTTPLUG_FFI_SENSOR_TYPE_UNKNOWN = 0,
+  BUTTPLUG_FFI_SENSOR_TYPE_BATTERY,
+  BUTTPLUG_FFI_SENSOR_TYPE_RSSI,
+  BUTTPLUG_FFI_SENSOR_TYPE_BUTTON,
+  BUTTPLUG_FFI_SENSOR_TYPE_PRESSURE,
+} ButtplugFfiSensorType;
+
+// Result of a fallible C API call.
+typedef enum ButtplugFfiStatus {
+  BUTTPLUG_FFI_STATUS_OK = 0,
+  // A handle or pointer was null, or a string was not valid UTF-8.
+  BUTTPLUG_FFI_STATUS_INVALID_ARGUMENT,
+  // The connection to the server failed or was lost.
+  BUTTPLUG_FFI_STATUS_CONNECTOR_ERROR,
+  BUTTPLUG_FFI_STATUS_HANDSHAKE_ERROR,
+  BUTTPLUG_FFI_STATUS_MESSAGE_ERROR,
+  BUTTPLUG_FFI_STATUS_PING_ERROR,
+  BUTTPLUG_FFI_STATUS_DEVICE_ERROR,
+  BUTTPLUG_FFI_STATUS_UNKNOWN_ERROR,
+  // The library panicked during the call. The process should be considered unstable.
+  BUTTPLUG_FFI_STATUS_PANIC,
+  // The server didn't reply within the client's request timeout.
+  BUTTPLUG_FFI_STATUS_TIMEOUT,
+} ButtplugFfiStatus;
+
+// Opaque client handle.
+typedef struct ButtplugFfiClient ButtplugFfiClient;
+
+// Opaque device handle.
+typedef struct ButtplugFfiDevice ButtplugFfiDevice;
+
+// Opaque server handle, for connecting a client to a server in the same process.
+typedef struct ButtplugFfiServer ButtplugFfiServer;
+
+// Event passed to a [ButtplugFfiEventCallback]. Pointers in the event are only valid until the
+// callback returns. Fields not used by the event type are zero or null.
+typedef struct ButtplugFfiEvent {
+  ButtplugFfiEventType event_type;
+  uint32_t device_index;
+  uint32_t sensor_index;
+  ButtplugFfiSensorType sensor_type;
+  const int32_t *data;
+  uintptr_t data_len;
+  const uint8_t *bytes;
+  uintptr_t bytes_len;
+  const char *message;
+} ButtplugFfiEvent;
+
+// Callback for client events. `user_data` is the pointer passed to
+// [buttplug_client_set_event_callback].
+typedef void (*ButtplugFfiEventCallback)(const ButtplugFfiEvent *event, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the message of the last error that occurred on the calling thread, or null if there has
+// not been one. The string is owned by the library, and is valid until the next failing call on
+// the same thread.
+const char *buttplug_last_error_message(void);
+
+// Frees a string returned by the API.
+//
+// # Safety
+//
+// `value` must be null or a string returned by this API that has not already been freed.
+void buttplug_free_string(char *value);
+
+// Frees a byte buffer returned by the API.
+//
+// # Safety
+//
+// `data` must be null or a buffer returned by this API that has not already been freed, and
+// `len` must be the length returned with it.
+void buttplug_free_bytes(uint8_t *data, uintptr_t len);
+
+// Creates a server with all device communication managers that ship with the library and work on
+// the current platform. Returns null on failure.
+ButtplugFfiServer *buttplug_server_new(bool allow_raw_messages);
+
+// Frees a server handle that was not passed to [buttplug_client_connect_in_process].
+//
+// # Safety
+//
+// `server` must be null or a live server handle.
+void buttplug_server_free(ButtplugFfiServer *server);
+
+// Creates a new, unconnected client. Returns null on failure.
+//
+// # Safety
+//
+// `name` must be a valid null terminated string.
+ButtplugFfiClient *buttplug_client_new(const char *name);
+
+// Creates a new, unconnected client that presents an authentication token during the handshake.
+// Returns null on failure.
+//
+// # Safety
+//
+// `name` and `auth_token` must be valid null terminated strings.
+ButtplugFfiClient *buttplug_client_new_with_auth_token(const char *name, const char *auth_token);
+
+// Frees a client handle, disconnecting it if needed. No callbacks will be run after this returns.
+// Must not be called from inside an event callback for the same client.
+//
+// # Safety
+//
+// `client` must be null or a live client handle.
+void buttplug_client_free(ButtplugFfiClient *client);
+
+// Sets the callback for client events, replacing any existing callback. Passing a null callback
+// removes it. Must not be called from inside an event callback for the same client.
+//
+// # Safety
+//
+// `client` must be a live client handle. `user_data` must be usable from the callback thread.
+ButtplugFfiStatus buttplug_client_set_event_callback(ButtplugFfiClient *client,
+                                                     ButtplugFfiEventCallback callback,
+                                                     void *user_data);
+
+// Connects a client to a server in the same process. Takes ownership of the server handle, which
+// is invalid after this call whether or not the connection succeeds.
+//
+// # Safety
+//
+// `client` must be a live client handle, and `server` a live server handle.
+ButtplugFfiStatus buttplug_client_connect_in_process(ButtplugFfiClient *client,
+                                                     ButtplugFfiServer *server);
+
+#if defined(BUTTPLUG_FEATURE_WEBSOCKETS)
+// Connects a client to a remote server over websockets, i.e. `ws://127.0.0.1:12345`. `wss://`
+// addresses use TLS, with certificate verification unless `bypass_cert_verify` is set.
+//
+// # Safety
+//
+// `client` must be a live client handle, and `address` a valid null terminated string.
+ButtplugFfiStatus buttplug_client_connect_websocket(ButtplugFfiClient *client,
+                                                    const char *address,
+                                                    bool bypass_cert_verify);
+#endif
+
+// Disconnects a client from its server. The client handle stays valid, and can be reconnected.
+//
+// # Safety
+//
+// `client` must be a live client handle.
+ButtplugFfiStatus buttplug_client_disconnect(ButtplugFfiClient *client);
+
+// Returns true if the client is connected to a server.
+//
+// # Safety
+//
+// `client` must be null or a live client handle.
+bool buttplug_client_connected(const ButtplugFfiClient *client);
+
+// Returns the name of the connected server, or null if not connected. The string must be freed
+// with [buttplug_free_string](super::buttplug_free_string).
+//
+// # Safety
+//
+// `client` must be null or a live client handle.
+char *buttplug_client_server_name(const ButtplugFfiClient *client);
+
+// Starts scanning for devices. Devices are reported through the event callback.
+//
+// # Safety
+//
+// `client` must be a live client handle.
+ButtplugFfiStatus buttplug_client_start_scanning(ButtplugFfiClient *client);
+
+// Stops scanning for devices.
+//
+// # Safety
+//
+// `client` must be a live client handle.
+ButtplugFfiStatus buttplug_client_stop_scanning(ButtplugFfiClient *client);
+
+// Stops all devices connected to the server.
+//
+// # Safety
+//
+// `client` must be a live client handle.
+ButtplugFfiStatus buttplug_client_stop_all_devices(ButtplugFfiClient *client);
+
+// Pings the server. Only needed if the server was set up with a maximum ping time.
+//
+// # Safety
+//
+// `client` must be a live client handle.
+ButtplugFfiStatus buttplug_client_ping(ButtplugFfiClient *client);
+
+// Writes the indexes of connected devices to `indexes`, up to `capacity` entries, and returns the
+// total number of connected devices. Call with a null `indexes` and zero `capacity` to get the
+// count.
+//
+// # Safety
+//
+// `client` must be null or a live client handle. `indexes` must be null or valid for `capacity`
+// writes.
+uintptr_t buttplug_client_device_indexes(const ButtplugFfiClient *client,
+                                         uint32_t *indexes,
+                                         uintptr_t capacity);
+
+// Returns a handle to the device with the given index, or null if there is no such device. The
+// handle must be freed with [buttplug_device_free](super::buttplug_device_free).
+//
+// # Safety
+//
+// `client` must be a live client handle.
+ButtplugFfiDevice *buttplug_client_get_device(const ButtplugFfiClient *client,
+                                              uint32_t device_index);
+
+// Frees a device handle.
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+void buttplug_device_free(ButtplugFfiDevice *device);
+
+// Returns the index of the device.
+//
+// # Safety
+//
+// `device` must be a live device handle.
+uint32_t buttplug_device_index(const ButtplugFfiDevice *device);
+
+// Returns the name of the device. The string must be freed with
+// [buttplug_free_string](super::buttplug_free_string).
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+char *buttplug_device_name(const ButtplugFfiDevice *device);
+
+// Returns the user configured display name of the device, or null if there isn't one. The string
+// must be freed with [buttplug_free_string](super::buttplug_free_string).
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+char *buttplug_device_display_name(const ButtplugFfiDevice *device);
+
+// Returns true if the device is still connected to the server.
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+bool buttplug_device_connected(const ButtplugFfiDevice *device);
+
+// Returns the device's message attributes as JSON, in the same format as the `DeviceMessages`
+// field of a DeviceAdded message. The string must be freed with
+// [buttplug_free_string](super::buttplug_free_string).
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+char *buttplug_device_message_attributes_json(const ButtplugFfiDevice *device);
+
+// Returns the number of scalar features (vibrators, oscillators, etc...) on the device.
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+uint32_t buttplug_device_scalar_count(const ButtplugFfiDevice *device);
+
+// Returns the actuator type of a scalar feature, or Unknown if the feature doesn't exist.
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+ButtplugFfiActuatorType buttplug_device_scalar_actuator_type(const ButtplugFfiDevice *device,
+                                                             uint32_t feature_index);
+
+// Returns the number of steps a scalar feature supports, or 0 if the feature doesn't exist.
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+uint32_t buttplug_device_scalar_step_count(const ButtplugFfiDevice *device, uint32_t feature_index);
+
+// Returns the number of rotation features on the device.
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+uint32_t buttplug_device_rotate_count(const ButtplugFfiDevice *device);
+
+// Returns the number of linear features on the device.
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+uint32_t buttplug_device_linear_count(const ButtplugFfiDevice *device);
+
+// Sets all vibration features of the device to `speed`, from 0.0 to 1.0.
+//
+// # Safety
+//
+// `device` must be a live device handle.
+ButtplugFfiStatus buttplug_device_vibrate(const ButtplugFfiDevice *device, double speed);
+
+// Sets all oscillation features of the device to `speed`, from 0.0 to 1.0.
+//
+// # Safety
+//
+// `device` must be a live device handle.
+ButtplugFfiStatus buttplug_device_oscillate(const ButtplugFfiDevice *device, double speed);
+
+// Sets a single scalar feature of the device to `scalar`, from 0.0 to 1.0.
+//
+// # Safety
+//
+// `device` must be a live device handle.
+ButtplugFfiStatus buttplug_device_scalar(const ButtplugFfiDevice *device,
+                                         uint32_t feature_index,
+                                         double scalar,
+                                         ButtplugFfiActuatorType actuator_type);
+
+// Sets a single rotation feature of the device to `speed`, from 0.0 to 1.0, in the given
+// direction.
+//
+// # Safety
+//
+// `device` must be a live device handle.
+ButtplugFfiStatus buttplug_device_rotate(const ButtplugFfiDevice *device,
+                                         uint32_t feature_index,
+                                         double speed,
+                                         bool clockwise);
+
+// Moves a single linear feature of the device to `position`, from 0.0 to 1.0, over `duration`
+// milliseconds.
+//
+// # Safety
+//
+// `device` must be a live device handle.
+ButtplugFfiStatus buttplug_device_linear(const ButtplugFfiDevice *device,
+                                         uint32_t feature_index,
+                                         uint32_t duration,
+                                         double position);
+
+// Stops all features of the device.
+//
+// # Safety
+//
+// `device` must be a live device handle.
+ButtplugFfiStatus buttplug_device_stop(const ButtplugFfiDevice *device);
+
+// Returns true if the device can report its battery level.
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+bool buttplug_device_has_battery_level(const ButtplugFfiDevice *device);
+
+// Reads the battery level of the device, from 0.0 to 1.0, into `level`.
+//
+// # Safety
+//
+// `device` must be a live device handle, and `level` must be valid for writes.
+ButtplugFfiStatus buttplug_device_battery_level(const ButtplugFfiDevice *device, double *level);
+
+// Returns true if the device can report its signal strength.
+//
+// # Safety
+//
+// `device` must be null or a live device handle.
+bool buttplug_device_has_rssi_level(const ButtplugFfiDevice *device);
+
+// Reads the signal strength of the device, in dBm, into `level`.
+//
+// # Safety
+//
+// `device` must be a live device handle, and `level` must be valid for writes.
+ButtplugFfiStatus buttplug_device_rssi_level(const ButtplugFfiDevice *device, int32_t *level);
+
+// Subscribes to a sensor. Readings are delivered as SensorReading events through the client's
+// event callback.
+//
+// # Safety
+//
+// `device` must be a live device handle.
+ButtplugFfiStatus buttplug_device_subscribe_sensor(const ButtplugFfiDevice *device,
+                                                   uint32_t sensor_index,
+                                                   ButtplugFfiSensorType sensor_type);
+
+// Unsubscribes from a sensor.
+//
+// # Safety
+//
+// `device` must be a live device handle.
+ButtplugFfiStatus buttplug_device_unsubscribe_sensor(const ButtplugFfiDevice *device,
+                                                     uint32_t sensor_index,
+                                                     ButtplugFfiSensorType sensor_type);
+
+// Writes raw data to a device endpoint (i.e. `tx`). Only works if the server allows raw messages.
+//
+// # Safety
+//
+// `device` must be a live device handle, `endpoint` a valid null terminated string, and `data`
+// valid for `len` reads.
+ButtplugFfiStatus buttplug_device_raw_write(const ButtplugFfiDevice *device,
+                                            const char *endpoint,
+                                            const uint8_t *data,
+                                            uintptr_t len,
+                                            bool write_with_response);
+
+// Reads raw data from a device endpoint. On success, `data` and `len` are set to a buffer that
+// must be freed with [buttplug_free_bytes](super::buttplug_free_bytes).
+//
+// # Safety
+//
+// `device` must be a live device handle, `endpoint` a valid null terminated string, and `data` and
+// `len` valid for writes.
+ButtplugFfiStatus buttplug_device_raw_read(const ButtplugFfiDevice *device,
+                                           const char *endpoint,
+                                           uint32_t expected_length,
+                                           uint32_t timeout,
+                                           uint8_t **data,
+                                           uintptr_t *len);
+
+// Subscribes to raw notifications from a device endpoint.
+//
+// # Safety
+//
+// `device` must be a live device handle, and `endpoint` a valid null terminated string.
+ButtplugFfiStatus buttplug_device_raw_subscribe(const ButtplugFfiDevice *device,
+                                                const char *endpoint);
+
+// Unsubscribes from raw notifications from a device endpoint.
+//
+// # Safety
+//
+// `device` must be a live device handle, and `endpoint` a valid null terminated string.
+ButtplugFfiStatus buttplug_device_raw_unsubscribe(const ButtplugFfiDevice *device,
+                                                  const char *endpoint);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* BUTTPLUG_H */
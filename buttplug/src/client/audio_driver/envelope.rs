@@ -0,0 +1,361 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Envelope following for mono audio.
+//!
+//! An [AudioEnvelopeFollower] splits audio into windows, one per update at the configured update
+//! rate, and produces a level from 0.0 to 1.0 for each window:
+//!
+//! 1. Samples are filtered down to the configured [AudioBand].
+//! 2. The RMS amplitude of the filtered window is multiplied by the gain, and capped at 1.0.
+//! 3. The result is smoothed, rising with the attack time and falling with the release time.
+//! 4. Levels at or below the threshold become 0.0, and levels above it are rescaled so the range
+//!    from the threshold to 1.0 covers 0.0 to 1.0.
+//!
+//! Processing is deterministic, so the same audio always produces the same levels, no matter how it
+//! is split into buffers.
+
+use getset::CopyGetters;
+use std::{f64::consts::PI, time::Duration};
+
+/// Frequency band the envelope is computed over. Cutoffs are in Hz.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AudioBand {
+  /// The unfiltered signal.
+  #[default]
+  Full,
+  /// Frequencies below the cutoff, e.g. bass.
+  LowPass(f64),
+  /// Frequencies above the cutoff.
+  HighPass(f64),
+  /// Frequencies between the two cutoffs.
+  BandPass { low: f64, high: f64 },
+}
+
+/// Settings for turning audio into a level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioEnvelopeSettings {
+  band: AudioBand,
+  gain: f64,
+  attack: Duration,
+  release: Duration,
+  threshold: f64,
+  update_rate: f64,
+}
+
+impl Default for AudioEnvelopeSettings {
+  fn default() -> Self {
+    Self {
+      band: AudioBand::Full,
+      gain: 1.0,
+      attack: Duration::ZERO,
+      release: Duration::ZERO,
+      threshold: 0.0,
+      // Fast enough to follow beats, slow enough not to flood devices with commands.
+      update_rate: 20.0,
+    }
+  }
+}
+
+impl AudioEnvelopeSettings {
+  pub fn band(&mut self, band: AudioBand) -> &mut Self {
+    self.band = band;
+    self
+  }
+
+  /// Multiplier for the RMS amplitude. A full scale sine wave has an RMS amplitude of about 0.707.
+  pub fn gain(&mut self, gain: f64) -> &mut Self {
+    self.gain = gain.max(0.0);
+    self
+  }
+
+  /// Time constant for the level rising. Zero follows rises immediately.
+  pub fn attack(&mut self, attack: Duration) -> &mut Self {
+    self.attack = attack;
+    self
+  }
+
+  /// Time constant for the level falling. Zero follows falls immediately.
+  pub fn release(&mut self, release: Duration) -> &mut Self {
+    self.release = release;
+    self
+  }
+
+  /// Sets both the attack and release time.
+  pub fn smoothing(&mut self, smoothing: Duration) -> &mut Self {
+    self.attack = smoothing;
+    self.release = smoothing;
+    self
+  }
+
+  /// Level below which the output is 0.0, from 0.0 to 1.0 (exclusive).
+  pub fn threshold(&mut self, threshold: f64) -> &mut Self {
+    self.threshold = threshold.clamp(0.0, 0.99);
+    self
+  }
+
+  /// Levels produced per second of audio.
+  pub fn update_rate(&mut self, update_rate: f64) -> &mut Self {
+    if update_rate > 0.0 {
+      self.update_rate = update_rate;
+    } else {
+      warn!(
+        "Audio update rate must be positive, ignoring {}.",
+        update_rate
+      );
+    }
+    self
+  }
+}
+
+/// Second order IIR filter, using the coefficients from the RBJ audio EQ cookbook.
+#[derive(Debug, Clone)]
+struct Biquad {
+  b0: f64,
+  b1: f64,
+  b2: f64,
+  a1: f64,
+  a2: f64,
+  z1: f64,
+  z2: f64,
+}
+
+impl Biquad {
+  fn new(cutoff: f64, sample_rate: u32, high_pass: bool) -> Self {
+    // Keep the cutoff below the Nyquist frequency, where the filter would become unstable.
+    let cutoff = cutoff.clamp(1.0, sample_rate as f64 * 0.49);
+    let w0 = 2.0 * PI * cutoff / sample_rate as f64;
+    let cos_w0 = w0.cos();
+    let alpha = w0.sin() / (2.0 * std::f64::consts::FRAC_1_SQRT_2);
+    let a0 = 1.0 + alpha;
+    let (b0, b1) = if high_pass {
+      ((1.0 + cos_w0) / 2.0, -(1.0 + cos_w0))
+    } else {
+      ((1.0 - cos_w0) / 2.0, 1.0 - cos_w0)
+    };
+    Self {
+      b0: b0 / a0,
+      b1: b1 / a0,
+      b2: b0 / a0,
+      a1: -2.0 * cos_w0 / a0,
+      a2: (1.0 - alpha) / a0,
+      z1: 0.0,
+      z2: 0.0,
+    }
+  }
+
+  fn process(&mut self, input: f64) -> f64 {
+    let output = self.b0 * input + self.z1;
+    self.z1 = self.b1 * input - self.a1 * output + self.z2;
+    self.z2 = self.b2 * input - self.a2 * output;
+    output
+  }
+
+  fn reset(&mut self) {
+    self.z1 = 0.0;
+    self.z2 = 0.0;
+  }
+}
+
+/// Turns a stream of mono samples into levels, one per update window.
+#[derive(Debug, Clone, CopyGetters)]
+pub struct AudioEnvelopeFollower {
+  #[getset(get_copy = "pub")]
+  sample_rate: u32,
+  /// Number of samples making up each update window.
+  #[getset(get_copy = "pub")]
+  window_size: usize,
+  filters: Vec<Biquad>,
+  attack_coefficient: f64,
+  release_coefficient: f64,
+  gain: f64,
+  threshold: f64,
+  window_energy: f64,
+  window_samples: usize,
+  level: f64,
+}
+
+impl AudioEnvelopeFollower {
+  pub fn new(settings: &AudioEnvelopeSettings, sample_rate: u32) -> Self {
+    let window_size = ((sample_rate as f64 / settings.update_rate).round() as usize).max(1);
+    let filters = match settings.band {
+      AudioBand::Full => vec![],
+      AudioBand::LowPass(cutoff) => vec![Biquad::new(cutoff, sample_rate, false)],
+      AudioBand::HighPass(cutoff) => vec![Biquad::new(cutoff, sample_rate, true)],
+      AudioBand::BandPass { low, high } => vec![
+        Biquad::new(low, sample_rate, true),
+        Biquad::new(high, sample_rate, false),
+      ],
+    };
+    let window_duration = window_size as f64 / sample_rate as f64;
+    let coefficient = |time_constant: Duration| {
+      if time_constant.is_zero() {
+        1.0
+      } else {
+        1.0 - (-window_duration / time_constant.as_secs_f64()).exp()
+      }
+    };
+    Self {
+      sample_rate,
+      window_size,
+      filters,
+      attack_coefficient: coefficient(settings.attack),
+      release_coefficient: coefficient(settings.release),
+      gain: settings.gain,
+      threshold: settings.threshold,
+      window_energy: 0.0,
+      window_samples: 0,
+      level: 0.0,
+    }
+  }
+
+  /// Length of audio making up each update window.
+  pub fn window_duration(&self) -> Duration {
+    Duration::from_secs_f64(self.window_size as f64 / self.sample_rate as f64)
+  }
+
+  /// Processes samples, returning the levels of all windows completed by them. Samples left over
+  /// are kept for the next call.
+  pub fn process(&mut self, samples: &[f32]) -> Vec<f64> {
+    let mut levels = vec![];
+    for sample in samples {
+      let filtered = self
+        .filters
+        .iter_mut()
+        .fold(*sample as f64, |value, filter| filter.process(value));
+      self.window_energy += filtered * filtered;
+      self.window_samples += 1;
+      if self.window_samples == self.window_size {
+        levels.push(self.finish_window());
+      }
+    }
+    levels
+  }
+
+  /// Clears filter state, partial windows and smoothing, as if no audio had been processed.
+  pub fn reset(&mut self) {
+    self.filters.iter_mut().for_each(Biquad::reset);
+    self.window_energy = 0.0;
+    self.window_samples = 0;
+    self.level = 0.0;
+  }
+
+  fn finish_window(&mut self) -> f64 {
+    let rms = (self.window_energy / self.window_samples as f64).sqrt();
+    self.window_energy = 0.0;
+    self.window_samples = 0;
+    let target = (rms * self.gain).min(1.0);
+    let coefficient = if target > self.level {
+      self.attack_coefficient
+    } else {
+      self.release_coefficient
+    };
+    self.level += (target - self.level) * coefficient;
+    if self.level <= self.threshold {
+      0.0
+    } else {
+      ((self.level - self.threshold) / (1.0 - self.threshold)).min(1.0)
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn sine(frequency: f64, amplitude: f64, sample_rate: u32, samples: usize) -> Vec<f32> {
+    (0..samples)
+      .map(|i| (amplitude * (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin()) as f32)
+      .collect()
+  }
+
+  fn assert_levels(levels: &[f64], expected: &[f64]) {
+    assert_eq!(levels.len(), expected.len(), "{:?}", levels);
+    for (level, expected) in levels.iter().zip(expected) {
+      assert!(
+        (level - expected).abs() < 0.001,
+        "{:?} != {:?}",
+        levels,
+        expected
+      );
+    }
+  }
+
+  #[test]
+  fn test_envelope_windows() {
+    let mut settings = AudioEnvelopeSettings::default();
+    settings.update_rate(10.0);
+    let mut follower = AudioEnvelopeFollower::new(&settings, 8000);
+    assert_eq!(follower.window_size(), 800);
+    // Levels only come out once a window is complete, however the samples are split up.
+    let tone = sine(400.0, 0.5, 8000, 1600);
+    assert!(follower.process(&tone[..500]).is_empty());
+    assert_levels(&follower.process(&tone[500..1300]), &[0.5 / 2f64.sqrt()]);
+    assert_levels(&follower.process(&tone[1300..]), &[0.5 / 2f64.sqrt()]);
+    assert_levels(&follower.process(&[0.0; 800]), &[0.0]);
+  }
+
+  #[test]
+  fn test_envelope_gain_and_threshold() {
+    let mut settings = AudioEnvelopeSettings::default();
+    settings.update_rate(10.0).gain(2f64.sqrt()).threshold(0.2);
+    let mut follower = AudioEnvelopeFollower::new(&settings, 8000);
+    let mut audio = sine(400.0, 0.6, 8000, 800);
+    audio.extend(sine(400.0, 0.1, 8000, 800));
+    audio.extend(sine(400.0, 1.0, 8000, 800));
+    assert_levels(&follower.process(&audio), &[0.5, 0.0, 1.0]);
+  }
+
+  #[test]
+  fn test_envelope_smoothing() {
+    let mut settings = AudioEnvelopeSettings::default();
+    settings
+      .update_rate(10.0)
+      .gain(2f64.sqrt())
+      .attack(Duration::ZERO)
+      .release(Duration::from_millis(100));
+    let mut follower = AudioEnvelopeFollower::new(&settings, 8000);
+    let mut audio = sine(400.0, 1.0, 8000, 800);
+    audio.extend(vec![0.0; 1600]);
+    let decay = (-1f64).exp();
+    assert_levels(&follower.process(&audio), &[1.0, decay, decay * decay]);
+    follower.reset();
+    assert_levels(&follower.process(&[0.0; 800]), &[0.0]);
+  }
+
+  #[test]
+  fn test_envelope_bands() {
+    let mut settings = AudioEnvelopeSettings::default();
+    settings
+      .update_rate(10.0)
+      .gain(2f64.sqrt())
+      .band(AudioBand::LowPass(200.0));
+    let low_tone = sine(50.0, 1.0, 8000, 8000);
+    let high_tone = sine(2000.0, 1.0, 8000, 8000);
+    let last_level = |audio: &[f32]| {
+      *AudioEnvelopeFollower::new(&settings, 8000)
+        .process(audio)
+        .last()
+        .expect("Test, assuming infallible.")
+    };
+    assert!(last_level(&low_tone) > 0.95);
+    assert!(last_level(&high_tone) < 0.05);
+
+    settings.band(AudioBand::BandPass {
+      low: 1000.0,
+      high: 3000.0,
+    });
+    let last_level = |audio: &[f32]| {
+      *AudioEnvelopeFollower::new(&settings, 8000)
+        .process(audio)
+        .last()
+        .expect("Test, assuming infallible.")
+    };
+    assert!(last_level(&low_tone) < 0.05);
+    assert!(last_level(&high_tone) > 0.8);
+  }
+}
@@ -0,0 +1,292 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Audio reactive scalar commands for a [ButtplugClient].
+//!
+//! The driver follows the envelope of an audio signal (see [envelope] for how levels are computed)
+//! and sends it as scalar levels to the features picked out by its [AudioDriverTarget]s. Audio can
+//! either be pushed as it is captured via [ButtplugAudioDriver::push_samples], or played from a
+//! decoded WAV file via [ButtplugAudioDriver::play_wav].
+//!
+//! One level is produced per update window of samples, and at most one level is sent per window's
+//! worth of wall clock time, so audio pushed in bursts can't send faster than the update rate of the
+//! envelope settings. Levels are only sent to a feature when they move it to a different step of its
+//! `step_count`, so quiet passages and steady tones don't flood the server.
+
+pub mod envelope;
+pub mod wav;
+
+use super::{
+  device::{ButtplugClientDevice, ScalarCommand},
+  group::DeviceGroupMembership,
+  ramp::level_to_step,
+  ButtplugClient,
+};
+use crate::{
+  core::message::ActuatorType,
+  util::{sleep, Instant},
+};
+use envelope::{AudioEnvelopeFollower, AudioEnvelopeSettings};
+use futures::future;
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+use tokio::sync::Mutex as AsyncMutex;
+use wav::WavAudio;
+
+/// Selects which device features the audio level is sent to.
+#[derive(Clone, Debug)]
+pub struct AudioDriverTarget {
+  devices: DeviceGroupMembership,
+  actuator_type: ActuatorType,
+  /// Scalar feature to send to, or `None` for every feature of the actuator type.
+  feature_index: Option<u32>,
+}
+
+impl AudioDriverTarget {
+  pub fn new(
+    devices: DeviceGroupMembership,
+    actuator_type: ActuatorType,
+    feature_index: Option<u32>,
+  ) -> Self {
+    Self {
+      devices,
+      actuator_type,
+      feature_index,
+    }
+  }
+
+  fn feature_indexes<'a>(
+    &'a self,
+    device: &'a ButtplugClientDevice,
+  ) -> impl Iterator<Item = (u32, u32)> + 'a {
+    device
+      .scalar_attributes()
+      .into_iter()
+      .enumerate()
+      .filter(move |(index, attrs)| {
+        *attrs.actuator_type() == self.actuator_type
+          && (self.feature_index.is_none() || self.feature_index == Some(*index as u32))
+      })
+      .map(|(index, attrs)| (index as u32, *attrs.step_count()))
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct ButtplugAudioDriverBuilder {
+  /// Settings for computing levels from audio.
+  envelope: AudioEnvelopeSettings,
+  /// Sample rate of audio passed to [ButtplugAudioDriver::push_samples].
+  sample_rate: u32,
+  /// Features levels are sent to.
+  targets: Vec<AudioDriverTarget>,
+}
+
+impl Default for ButtplugAudioDriverBuilder {
+  fn default() -> Self {
+    Self {
+      envelope: AudioEnvelopeSettings::default(),
+      sample_rate: 44100,
+      targets: vec![],
+    }
+  }
+}
+
+impl ButtplugAudioDriverBuilder {
+  pub fn envelope(&mut self, envelope: &AudioEnvelopeSettings) -> &mut Self {
+    self.envelope = envelope.clone();
+    self
+  }
+
+  pub fn sample_rate(&mut self, sample_rate: u32) -> &mut Self {
+    self.sample_rate = sample_rate;
+    self
+  }
+
+  pub fn target(&mut self, target: AudioDriverTarget) -> &mut Self {
+    self.targets.push(target);
+    self
+  }
+
+  pub fn finish(&self, client: Arc<ButtplugClient>) -> ButtplugAudioDriver {
+    let follower = AudioEnvelopeFollower::new(&self.envelope, self.sample_rate);
+    ButtplugAudioDriver {
+      client,
+      envelope: self.envelope.clone(),
+      targets: self.targets.clone(),
+      update_interval: follower.window_duration(),
+      follower: Mutex::new(follower),
+      pushed_level: Mutex::new(PushedLevel::default()),
+      last_steps: AsyncMutex::new(HashMap::new()),
+    }
+  }
+}
+
+/// Rate limiting state for levels from pushed samples.
+#[derive(Default)]
+struct PushedLevel {
+  /// When a pushed level was last sent.
+  last_send: Option<Instant>,
+  /// Most recent level that hasn't been sent yet, because it arrived too soon after the last one.
+  pending: Option<f64>,
+}
+
+/// Drives scalar features of a connected [ButtplugClient]'s devices from audio.
+pub struct ButtplugAudioDriver {
+  client: Arc<ButtplugClient>,
+  envelope: AudioEnvelopeSettings,
+  targets: Vec<AudioDriverTarget>,
+  /// Follower for pushed samples.
+  follower: Mutex<AudioEnvelopeFollower>,
+  /// Minimum time between sends of pushed levels, one update window.
+  update_interval: Duration,
+  pushed_level: Mutex<PushedLevel>,
+  /// Last step sent to each (device index, feature index). Held while sending, so levels arrive in
+  /// order.
+  last_steps: AsyncMutex<HashMap<(u32, u32), u32>>,
+}
+
+impl ButtplugAudioDriver {
+  pub fn client(&self) -> &Arc<ButtplugClient> {
+    &self.client
+  }
+
+  /// Processes mono samples from -1.0 to 1.0, at the sample rate the driver was built with. If they
+  /// complete any update windows, the level of the last one is returned.
+  ///
+  /// Levels are sent to the targets at most once per update window of wall clock time. A level that
+  /// arrives sooner is held, replacing any level already held, and sent by the first push after the
+  /// window has passed, so samples are expected to keep arriving as they are captured. To play
+  /// audio that's already recorded, use [ButtplugAudioDriver::play_wav] instead.
+  pub async fn push_samples(&self, samples: &[f32]) -> Option<f64> {
+    let level = self
+      .follower
+      .lock()
+      .expect("Only locked for single accesses, should never be poisoned.")
+      .process(samples)
+      .pop();
+    let send = {
+      let mut pushed_level = self
+        .pushed_level
+        .lock()
+        .expect("Only locked for single accesses, should never be poisoned.");
+      if level.is_some() {
+        pushed_level.pending = level;
+      }
+      let now = Instant::now();
+      let due = match pushed_level.last_send {
+        Some(last_send) => now - last_send >= self.update_interval,
+        None => true,
+      };
+      if due && pushed_level.pending.is_some() {
+        pushed_level.last_send = Some(now);
+        pushed_level.pending.take()
+      } else {
+        None
+      }
+    };
+    if let Some(send) = send {
+      self.send_level(send, false).await;
+    }
+    level
+  }
+
+  /// Plays a WAV file in real time, sending one level per update window, then stops the targets.
+  /// Channels are mixed down to mono. Dropping the returned future stops playback, but leaves the
+  /// targets at their last level.
+  ///
+  /// Playback uses its own envelope state, so it doesn't affect levels from pushed samples.
+  pub async fn play_wav(&self, audio: &WavAudio) {
+    let mut follower = AudioEnvelopeFollower::new(&self.envelope, audio.sample_rate());
+    let window_duration = follower.window_duration();
+    let start_time = Instant::now();
+    for (window, samples) in audio
+      .mono_samples()
+      .chunks(follower.window_size())
+      .enumerate()
+    {
+      if let Some(level) = follower.process(samples).pop() {
+        self.send_level(level, false).await;
+      }
+      // Sleep until the end of the window, rather than for a window's duration, so time spent
+      // sending doesn't add up over long files.
+      let window_end = start_time + window_duration * (window as u32 + 1);
+      sleep(window_end.saturating_duration_since(Instant::now())).await;
+    }
+    self.stop().await;
+  }
+
+  /// Clears the envelope state for pushed samples, drops any level waiting to be sent, and sets all
+  /// targets to 0.0.
+  pub async fn stop(&self) {
+    self
+      .follower
+      .lock()
+      .expect("Only locked for single accesses, should never be poisoned.")
+      .reset();
+    self
+      .pushed_level
+      .lock()
+      .expect("Only locked for single accesses, should never be poisoned.")
+      .pending = None;
+    self.send_level(0.0, true).await;
+  }
+
+  /// Sends a level to all target features, skipping features already at the level's step unless
+  /// forced. Steps are only recorded once a device accepts them. Failures are logged, and don't stop
+  /// the level being sent to other devices.
+  async fn send_level(&self, level: f64, force: bool) {
+    let mut last_steps = self.last_steps.lock().await;
+    let devices = self.client.devices();
+    // Devices can come back with the same index, forget anything sent to devices that are gone.
+    last_steps
+      .retain(|(device_index, _), _| devices.iter().any(|device| device.index() == *device_index));
+    let mut commands = vec![];
+    for device in devices {
+      let mut map: HashMap<u32, (f64, ActuatorType)> = HashMap::new();
+      let mut steps = HashMap::new();
+      for target in self
+        .targets
+        .iter()
+        .filter(|target| target.devices.matches(&device))
+      {
+        for (feature_index, step_count) in target.feature_indexes(&device) {
+          let step = level_to_step(level, step_count);
+          if last_steps.get(&(device.index(), feature_index)) != Some(&step) || force {
+            map.insert(feature_index, (level, target.actuator_type));
+            steps.insert(feature_index, step);
+          }
+        }
+      }
+      if !map.is_empty() {
+        commands.push((device, map, steps));
+      }
+    }
+    let sent_steps =
+      future::join_all(commands.into_iter().map(|(device, map, steps)| async move {
+        match device.scalar(&ScalarCommand::ScalarMap(map)).await {
+          Ok(()) => Some((device.index(), steps)),
+          Err(e) => {
+            warn!(
+              "Could not send audio level to device {}: {}",
+              device.index(),
+              e
+            );
+            None
+          }
+        }
+      }))
+      .await;
+    for (device_index, steps) in sent_steps.into_iter().flatten() {
+      for (feature_index, step) in steps {
+        last_steps.insert((device_index, feature_index), step);
+      }
+    }
+  }
+}
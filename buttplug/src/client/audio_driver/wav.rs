@@ -0,0 +1,362 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Minimal WAV file decoding and encoding.
+//!
+//! Decodes uncompressed PCM (8, 16, 24 and 32 bit integer) and IEEE float (32 and 64 bit) data,
+//! including files using the extensible format header. Samples are converted to floats from -1.0
+//! to 1.0.
+
+use byteorder::{ByteOrder, LittleEndian};
+use getset::{CopyGetters, Getters};
+use std::path::Path;
+use thiserror::Error;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum WavError {
+  #[error("Could not read WAV file: {0}")]
+  Io(String),
+  #[error("Not a RIFF WAVE file")]
+  NotWav,
+  #[error("WAV file ended unexpectedly")]
+  UnexpectedEnd,
+  #[error("WAV file has no {0} chunk")]
+  MissingChunk(&'static str),
+  #[error("WAV format {0} with {1} bits per sample is not supported")]
+  UnsupportedFormat(u16, u16),
+  #[error("WAV file has invalid header: {0}")]
+  InvalidHeader(String),
+  #[error("Audio is too large to encode as WAV: {0}")]
+  TooLarge(String),
+}
+
+/// Decoded audio, with samples from all channels interleaved.
+#[derive(Debug, Clone, PartialEq, Getters, CopyGetters)]
+pub struct WavAudio {
+  #[getset(get_copy = "pub")]
+  sample_rate: u32,
+  #[getset(get_copy = "pub")]
+  channels: u16,
+  #[getset(get = "pub")]
+  samples: Vec<f32>,
+}
+
+impl WavAudio {
+  pub fn new(sample_rate: u32, channels: u16, samples: Vec<f32>) -> Self {
+    Self {
+      sample_rate,
+      channels,
+      samples,
+    }
+  }
+
+  /// Reads and decodes a WAV file.
+  pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Self, WavError> {
+    let data = std::fs::read(path).map_err(|e| WavError::Io(e.to_string()))?;
+    Self::decode(&data)
+  }
+
+  /// Decodes the contents of a WAV file.
+  pub fn decode(data: &[u8]) -> Result<Self, WavError> {
+    let mut offset = 0;
+    if take(data, &mut offset, 4)? != b"RIFF" {
+      return Err(WavError::NotWav);
+    }
+    take(data, &mut offset, 4)?;
+    if take(data, &mut offset, 4)? != b"WAVE" {
+      return Err(WavError::NotWav);
+    }
+    let mut format = None;
+    while offset < data.len() {
+      let id = take(data, &mut offset, 4)?;
+      let size = LittleEndian::read_u32(take(data, &mut offset, 4)?) as usize;
+      // Writers sometimes leave the data chunk size unset when streaming, so a data chunk running
+      // past the end of the file is cut short rather than rejected.
+      let chunk = if id == b"data" {
+        let chunk = &data[offset..data.len().min(offset.saturating_add(size))];
+        offset += chunk.len();
+        chunk
+      } else {
+        take(data, &mut offset, size)?
+      };
+      // Chunks are padded to an even length.
+      if size % 2 == 1 && offset < data.len() {
+        offset += 1;
+      }
+      match id {
+        b"fmt " => format = Some(WavFormat::decode(chunk)?),
+        b"data" => {
+          let format = format.ok_or(WavError::MissingChunk("fmt"))?;
+          return Ok(Self {
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+            samples: format.decode_samples(chunk)?,
+          });
+        }
+        _ => {}
+      }
+    }
+    Err(WavError::MissingChunk(if format.is_none() {
+      "fmt"
+    } else {
+      "data"
+    }))
+  }
+
+  /// Encodes the audio as a 16 bit PCM WAV file, for round trip tests. Samples outside -1.0 to 1.0
+  /// are clipped.
+  #[cfg(test)]
+  pub(crate) fn encode(&self) -> Result<Vec<u8>, WavError> {
+    let block_align = self
+      .channels
+      .checked_mul(2)
+      .ok_or_else(|| WavError::TooLarge(format!("{} channels of 16 bit samples", self.channels)))?;
+    let byte_rate = self
+      .sample_rate
+      .checked_mul(block_align as u32)
+      .ok_or_else(|| {
+        WavError::TooLarge(format!(
+          "{} Hz with {} channels of 16 bit samples",
+          self.sample_rate, self.channels
+        ))
+      })?;
+    // The RIFF chunk size covers the data plus 36 bytes of headers, and has to fit in a u32 too.
+    let data_len = self
+      .samples
+      .len()
+      .checked_mul(2)
+      .and_then(|len| u32::try_from(len).ok())
+      .filter(|len| len.checked_add(36).is_some())
+      .ok_or_else(|| WavError::TooLarge(format!("{} samples", self.samples.len())))?;
+    let mut buf = Vec::with_capacity(44 + data_len as usize);
+    buf.extend_from_slice(b"RIFF");
+    buf.extend_from_slice(&(36 + data_len).to_le_bytes());
+    buf.extend_from_slice(b"WAVE");
+    buf.extend_from_slice(b"fmt ");
+    buf.extend_from_slice(&16u32.to_le_bytes());
+    buf.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+    buf.extend_from_slice(&self.channels.to_le_bytes());
+    buf.extend_from_slice(&self.sample_rate.to_le_bytes());
+    buf.extend_from_slice(&byte_rate.to_le_bytes());
+    buf.extend_from_slice(&block_align.to_le_bytes());
+    buf.extend_from_slice(&16u16.to_le_bytes());
+    buf.extend_from_slice(b"data");
+    buf.extend_from_slice(&data_len.to_le_bytes());
+    for sample in &self.samples {
+      let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+      buf.extend_from_slice(&value.to_le_bytes());
+    }
+    Ok(buf)
+  }
+
+  /// Length of the audio, in frames (one sample per channel).
+  pub fn frames(&self) -> usize {
+    if self.channels == 0 {
+      0
+    } else {
+      self.samples.len() / self.channels as usize
+    }
+  }
+
+  /// Mixes all channels down to a single channel by averaging them.
+  pub fn mono_samples(&self) -> Vec<f32> {
+    if self.channels <= 1 {
+      return self.samples.clone();
+    }
+    self
+      .samples
+      .chunks_exact(self.channels as usize)
+      .map(|frame| frame.iter().sum::<f32>() / self.channels as f32)
+      .collect()
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WavFormat {
+  format: u16,
+  channels: u16,
+  sample_rate: u32,
+  block_align: u16,
+  bits_per_sample: u16,
+}
+
+impl WavFormat {
+  fn decode(chunk: &[u8]) -> Result<Self, WavError> {
+    let mut offset = 0;
+    let mut format = LittleEndian::read_u16(take(chunk, &mut offset, 2)?);
+    let channels = LittleEndian::read_u16(take(chunk, &mut offset, 2)?);
+    let sample_rate = LittleEndian::read_u32(take(chunk, &mut offset, 4)?);
+    take(chunk, &mut offset, 4)?;
+    let block_align = LittleEndian::read_u16(take(chunk, &mut offset, 2)?);
+    let bits_per_sample = LittleEndian::read_u16(take(chunk, &mut offset, 2)?);
+    if format == WAVE_FORMAT_EXTENSIBLE {
+      // Skip the extension size, valid bits and channel mask. The sub format GUID starts with the
+      // actual format code.
+      take(chunk, &mut offset, 8)?;
+      format = LittleEndian::read_u16(take(chunk, &mut offset, 2)?);
+    }
+    if channels == 0 || sample_rate == 0 {
+      return Err(WavError::InvalidHeader(format!(
+        "{} channels at {} Hz",
+        channels, sample_rate
+      )));
+    }
+    match (format, bits_per_sample) {
+      (WAVE_FORMAT_PCM, 8 | 16 | 24 | 32) | (WAVE_FORMAT_IEEE_FLOAT, 32 | 64) => {}
+      _ => return Err(WavError::UnsupportedFormat(format, bits_per_sample)),
+    }
+    let bytes_per_sample = bits_per_sample / 8;
+    if (block_align as u32) < channels as u32 * bytes_per_sample as u32 {
+      return Err(WavError::InvalidHeader(format!(
+        "block alignment {} too small for {} channels of {} bit samples",
+        block_align, channels, bits_per_sample
+      )));
+    }
+    Ok(Self {
+      format,
+      channels,
+      sample_rate,
+      block_align,
+      bits_per_sample,
+    })
+  }
+
+  fn decode_samples(&self, data: &[u8]) -> Result<Vec<f32>, WavError> {
+    let bytes_per_sample = (self.bits_per_sample / 8) as usize;
+    let mut samples = Vec::with_capacity(data.len() / bytes_per_sample);
+    // Trailing partial frames are dropped.
+    for frame in data.chunks_exact(self.block_align as usize) {
+      for channel in 0..self.channels as usize {
+        let bytes = &frame[channel * bytes_per_sample..(channel + 1) * bytes_per_sample];
+        samples.push(match (self.format, self.bits_per_sample) {
+          (WAVE_FORMAT_PCM, 8) => (bytes[0] as f32 - 128.0) / 128.0,
+          (WAVE_FORMAT_PCM, 16) => LittleEndian::read_i16(bytes) as f32 / 32768.0,
+          (WAVE_FORMAT_PCM, 24) => LittleEndian::read_i24(bytes) as f32 / 8388608.0,
+          (WAVE_FORMAT_PCM, 32) => LittleEndian::read_i32(bytes) as f32 / 2147483648.0,
+          (WAVE_FORMAT_IEEE_FLOAT, 32) => LittleEndian::read_f32(bytes),
+          (WAVE_FORMAT_IEEE_FLOAT, 64) => LittleEndian::read_f64(bytes) as f32,
+          _ => {
+            return Err(WavError::UnsupportedFormat(
+              self.format,
+              self.bits_per_sample,
+            ))
+          }
+        });
+      }
+    }
+    Ok(samples)
+  }
+}
+
+fn take<'a>(data: &'a [u8], offset: &mut usize, len: usize) -> Result<&'a [u8], WavError> {
+  let end = offset
+    .checked_add(len)
+    .filter(|end| *end <= data.len())
+    .ok_or(WavError::UnexpectedEnd)?;
+  let slice = &data[*offset..end];
+  *offset = end;
+  Ok(slice)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn wav_with_format(format: u16, bits_per_sample: u16, data: &[u8]) -> Vec<u8> {
+    let mut buf = vec![];
+    buf.extend_from_slice(b"RIFF");
+    buf.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(b"WAVE");
+    buf.extend_from_slice(b"fmt ");
+    buf.extend_from_slice(&16u32.to_le_bytes());
+    buf.extend_from_slice(&format.to_le_bytes());
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(&8000u32.to_le_bytes());
+    buf.extend_from_slice(&(8000 * bits_per_sample as u32 / 8).to_le_bytes());
+    buf.extend_from_slice(&(bits_per_sample / 8).to_le_bytes());
+    buf.extend_from_slice(&bits_per_sample.to_le_bytes());
+    buf.extend_from_slice(b"data");
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+    buf
+  }
+
+  #[test]
+  fn test_wav_round_trip() {
+    let audio = WavAudio::new(44100, 2, vec![0.0, 0.5, -0.5, 1.0, 2.0, -2.0]);
+    let encoded = audio.encode().expect("Test, assuming infallible.");
+    assert_eq!(encoded.len(), 44 + 12);
+    let decoded = WavAudio::decode(&encoded).expect("Test, assuming infallible.");
+    assert_eq!(decoded.sample_rate(), 44100);
+    assert_eq!(decoded.channels(), 2);
+    assert_eq!(decoded.frames(), 3);
+    let expected = [0.0, 0.5, -0.5, 1.0, 1.0, -1.0];
+    for (sample, expected) in decoded.samples().iter().zip(expected) {
+      assert!((sample - expected).abs() < 0.0001);
+    }
+    let mono = decoded.mono_samples();
+    assert_eq!(mono.len(), 3);
+    assert!((mono[0] - 0.25).abs() < 0.0001);
+    assert!(mono[2].abs() < 0.0001);
+  }
+
+  #[test]
+  fn test_wav_encode_too_large() {
+    assert!(matches!(
+      WavAudio::new(44100, u16::MAX, vec![]).encode(),
+      Err(WavError::TooLarge(_))
+    ));
+    assert!(matches!(
+      WavAudio::new(u32::MAX, 2, vec![]).encode(),
+      Err(WavError::TooLarge(_))
+    ));
+  }
+
+  #[test]
+  fn test_wav_sample_formats() {
+    let decoded = WavAudio::decode(&wav_with_format(WAVE_FORMAT_PCM, 8, &[128, 0, 192]))
+      .expect("Test, assuming infallible.");
+    assert_eq!(*decoded.samples(), vec![0.0, -1.0, 0.5]);
+    let decoded = WavAudio::decode(&wav_with_format(
+      WAVE_FORMAT_PCM,
+      24,
+      &[0x00, 0x00, 0x40, 0x00, 0x00, 0x80],
+    ))
+    .expect("Test, assuming infallible.");
+    assert_eq!(*decoded.samples(), vec![0.5, -1.0]);
+    let mut data = vec![];
+    data.extend_from_slice(&0.25f32.to_le_bytes());
+    data.extend_from_slice(&(-0.75f32).to_le_bytes());
+    let decoded = WavAudio::decode(&wav_with_format(WAVE_FORMAT_IEEE_FLOAT, 32, &data))
+      .expect("Test, assuming infallible.");
+    assert_eq!(*decoded.samples(), vec![0.25, -0.75]);
+  }
+
+  #[test]
+  fn test_wav_invalid_files() {
+    assert_eq!(WavAudio::decode(b"RIFF"), Err(WavError::UnexpectedEnd));
+    assert_eq!(WavAudio::decode(b"RIFF\0\0\0\0AVI "), Err(WavError::NotWav));
+    assert_eq!(
+      WavAudio::decode(b"RIFF\0\0\0\0WAVE"),
+      Err(WavError::MissingChunk("fmt"))
+    );
+    assert_eq!(
+      WavAudio::decode(&wav_with_format(2, 4, &[])),
+      Err(WavError::UnsupportedFormat(2, 4))
+    );
+    let mut no_data = wav_with_format(WAVE_FORMAT_PCM, 16, &[]);
+    no_data.truncate(36);
+    assert_eq!(
+      WavAudio::decode(&no_data),
+      Err(WavError::MissingChunk("data"))
+    );
+  }
+}
@@ -0,0 +1,269 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{
+  BlockingRuntime,
+  ButtplugBlockingClientDeviceEventIter,
+  ButtplugBlockingClientResult,
+  ButtplugBlockingEventIter,
+};
+use crate::{
+  client::{
+    ButtplugClientDevice,
+    ClientDeviceState,
+    LinearCommand,
+    RampEasing,
+    RotateCommand,
+    ScalarCommand,
+    ScalarValueCommand,
+  },
+  core::message::{
+    ClientDeviceMessageAttributes,
+    ClientGenericDeviceMessageAttributes,
+    Endpoint,
+    SensorType,
+  },
+};
+use std::{fmt, sync::Arc, time::Duration};
+
+/// Synchronous version of [ButtplugClientDevice].
+///
+/// Obtained from a [ButtplugBlockingClient][super::ButtplugBlockingClient]. All commands block
+/// until the server has replied.
+#[derive(Clone)]
+pub struct ButtplugBlockingClientDevice {
+  device: Arc<ButtplugClientDevice>,
+  runtime: Arc<BlockingRuntime>,
+}
+
+// Results match the async client's, so its error type is returned as-is.
+#[allow(clippy::result_large_err)]
+impl ButtplugBlockingClientDevice {
+  pub(super) fn new(device: Arc<ButtplugClientDevice>, runtime: &Arc<BlockingRuntime>) -> Self {
+    Self {
+      device,
+      runtime: runtime.clone(),
+    }
+  }
+
+  /// The async device this wraps, for mixing blocking calls with async code.
+  pub fn device(&self) -> &Arc<ButtplugClientDevice> {
+    &self.device
+  }
+
+  /// Name of the device
+  pub fn name(&self) -> &String {
+    self.device.name()
+  }
+
+  /// Display name of the device
+  pub fn display_name(&self) -> &Option<String> {
+    self.device.display_name()
+  }
+
+  /// Index of the device on the server.
+  pub fn index(&self) -> u32 {
+    self.device.index()
+  }
+
+  /// Map of messages the device can take, along with the attributes of those messages.
+  pub fn message_attributes(&self) -> &ClientDeviceMessageAttributes {
+    self.device.message_attributes()
+  }
+
+  pub fn connected(&self) -> bool {
+    self.device.connected()
+  }
+
+  /// Returns a blocking iterator over events for this device, emitted from now on.
+  pub fn event_iter(&self) -> ButtplugBlockingClientDeviceEventIter {
+    ButtplugBlockingEventIter::new(self.device.event_stream(), &self.runtime)
+  }
+
+  /// Last acknowledged values of the device's actuators, and its latest sensor readings.
+  pub fn state(&self) -> ClientDeviceState {
+    self.device.state()
+  }
+
+  /// Returns a blocking iterator over state snapshots for this device, emitted on every change from
+  /// now on.
+  pub fn state_iter(&self) -> ButtplugBlockingEventIter<ClientDeviceState> {
+    ButtplugBlockingEventIter::new(self.device.state_stream(), &self.runtime)
+  }
+
+  pub fn scalar_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributes> {
+    self.device.scalar_attributes()
+  }
+
+  pub fn vibrate_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributes> {
+    self.device.vibrate_attributes()
+  }
+
+  pub fn vibrate(&self, speed_cmd: &ScalarValueCommand) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.device.vibrate(speed_cmd))
+  }
+
+  pub fn oscillate_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributes> {
+    self.device.oscillate_attributes()
+  }
+
+  pub fn oscillate(&self, speed_cmd: &ScalarValueCommand) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.device.oscillate(speed_cmd))
+  }
+
+  pub fn scalar(&self, scalar_cmd: &ScalarCommand) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.device.scalar(scalar_cmd))
+  }
+
+  /// Ramps scalar features to the levels in `ramp_cmd` over `duration`, blocking until the ramp
+  /// finishes or is cancelled. See [ButtplugClientDevice::ramp_scalar].
+  pub fn ramp_scalar(
+    &self,
+    ramp_cmd: &ScalarCommand,
+    duration: Duration,
+    easing: RampEasing,
+  ) -> ButtplugBlockingClientResult {
+    self
+      .runtime
+      .block_on(self.device.ramp_scalar(ramp_cmd, duration, easing))
+  }
+
+  pub fn ramp_vibrate(
+    &self,
+    speed_cmd: &ScalarValueCommand,
+    duration: Duration,
+    easing: RampEasing,
+  ) -> ButtplugBlockingClientResult {
+    self
+      .runtime
+      .block_on(self.device.ramp_vibrate(speed_cmd, duration, easing))
+  }
+
+  pub fn ramp_oscillate(
+    &self,
+    speed_cmd: &ScalarValueCommand,
+    duration: Duration,
+    easing: RampEasing,
+  ) -> ButtplugBlockingClientResult {
+    self
+      .runtime
+      .block_on(self.device.ramp_oscillate(speed_cmd, duration, easing))
+  }
+
+  pub fn linear_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributes> {
+    self.device.linear_attributes()
+  }
+
+  pub fn linear(&self, linear_cmd: &LinearCommand) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.device.linear(linear_cmd))
+  }
+
+  pub fn rotate_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributes> {
+    self.device.rotate_attributes()
+  }
+
+  pub fn rotate(&self, rotate_cmd: &RotateCommand) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.device.rotate(rotate_cmd))
+  }
+
+  pub fn subscribe_sensor(
+    &self,
+    sensor_index: u32,
+    sensor_type: SensorType,
+  ) -> ButtplugBlockingClientResult {
+    self
+      .runtime
+      .block_on(self.device.subscribe_sensor(sensor_index, sensor_type))
+  }
+
+  pub fn unsubscribe_sensor(
+    &self,
+    sensor_index: u32,
+    sensor_type: SensorType,
+  ) -> ButtplugBlockingClientResult {
+    self
+      .runtime
+      .block_on(self.device.unsubscribe_sensor(sensor_index, sensor_type))
+  }
+
+  /// Reads the sensor at the given index, returning the raw sensor values.
+  pub fn read_sensor(
+    &self,
+    sensor_index: u32,
+    sensor_type: SensorType,
+  ) -> ButtplugBlockingClientResult<Vec<i32>> {
+    self
+      .runtime
+      .block_on(self.device.read_sensor(sensor_index, sensor_type))
+  }
+
+  pub fn has_battery_level(&self) -> bool {
+    self.device.has_battery_level()
+  }
+
+  pub fn battery_level(&self) -> ButtplugBlockingClientResult<f64> {
+    self.runtime.block_on(self.device.battery_level())
+  }
+
+  pub fn has_rssi_level(&self) -> bool {
+    self.device.has_rssi_level()
+  }
+
+  pub fn rssi_level(&self) -> ButtplugBlockingClientResult<i32> {
+    self.runtime.block_on(self.device.rssi_level())
+  }
+
+  pub fn raw_write(
+    &self,
+    endpoint: Endpoint,
+    data: &[u8],
+    write_with_response: bool,
+  ) -> ButtplugBlockingClientResult {
+    self
+      .runtime
+      .block_on(self.device.raw_write(endpoint, data, write_with_response))
+  }
+
+  pub fn raw_read(
+    &self,
+    endpoint: Endpoint,
+    expected_length: u32,
+    timeout: u32,
+  ) -> ButtplugBlockingClientResult<Vec<u8>> {
+    self
+      .runtime
+      .block_on(self.device.raw_read(endpoint, expected_length, timeout))
+  }
+
+  pub fn raw_subscribe(&self, endpoint: Endpoint) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.device.raw_subscribe(endpoint))
+  }
+
+  pub fn raw_unsubscribe(&self, endpoint: Endpoint) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.device.raw_unsubscribe(endpoint))
+  }
+
+  /// Commands device to stop all movement.
+  pub fn stop(&self) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.device.stop())
+  }
+}
+
+impl Eq for ButtplugBlockingClientDevice {
+}
+
+impl PartialEq for ButtplugBlockingClientDevice {
+  fn eq(&self, other: &Self) -> bool {
+    self.device == other.device
+  }
+}
+
+impl fmt::Debug for ButtplugBlockingClientDevice {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    self.device.fmt(f)
+  }
+}
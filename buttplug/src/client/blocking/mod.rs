@@ -0,0 +1,355 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Synchronous wrapper around [ButtplugClient], for applications that don't run an async runtime.
+//!
+//! [ButtplugBlockingClient] owns a small tokio runtime that runs the client's event loop and
+//! connector. Every call blocks the calling thread until the server replies, so the client can be
+//! used from game engine threads, CLI tools, or anywhere else an executor isn't available.
+//!
+//! Events are available either by pulling from a [ButtplugBlockingEventIter] (as returned by
+//! [ButtplugBlockingClient::event_iter]), or by registering a callback via
+//! [ButtplugBlockingClient::on_event], which runs on the client's runtime thread.
+//!
+//! Connectors and servers may need to spawn tasks when they're created. Build them while holding
+//! the guard returned by [ButtplugBlockingClient::enter], so they land on the client's runtime:
+//!
+//! ```no_run
+//! use buttplug::{
+//!   client::{blocking::ButtplugBlockingClient, ScalarValueCommand},
+//!   core::connector::ButtplugInProcessClientConnectorBuilder,
+//!   server::ButtplugServerBuilder,
+//! };
+//!
+//! let client = ButtplugBlockingClient::new("Example Client");
+//! let connector = {
+//!   let _guard = client.enter();
+//!   ButtplugInProcessClientConnectorBuilder::default()
+//!     .server(ButtplugServerBuilder::default().finish().unwrap())
+//!     .finish()
+//! };
+//! client.connect(connector).unwrap();
+//! client.start_scanning().unwrap();
+//! for device in client.devices() {
+//!   device.vibrate(&ScalarValueCommand::ScalarValue(0.5)).unwrap();
+//! }
+//! ```
+
+mod device;
+pub use device::ButtplugBlockingClientDevice;
+
+use super::{ButtplugClient, ButtplugClientDeviceEvent, ButtplugClientError, ButtplugClientEvent};
+use crate::core::{
+  connector::ButtplugConnector,
+  errors::ButtplugError,
+  message::{
+    ButtplugCurrentSpecClientMessage,
+    ButtplugCurrentSpecServerMessage,
+    UnsupportedDeviceFound,
+  },
+};
+use futures::{Stream, StreamExt};
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+use tokio::runtime::{EnterGuard, Handle, Runtime};
+
+type ButtplugBlockingClientResult<T = ()> = Result<T, ButtplugClientError>;
+
+/// Runtime owned by a blocking client, shared with all of the devices it creates.
+pub(super) struct BlockingRuntime {
+  // Only ever None while dropping.
+  runtime: Option<Runtime>,
+}
+
+impl BlockingRuntime {
+  fn new() -> Self {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+      .worker_threads(1)
+      .thread_name("buttplug-blocking-client")
+      .enable_all()
+      .build()
+      .expect("Runtime creation should only fail if the OS is out of resources.");
+    Self {
+      runtime: Some(runtime),
+    }
+  }
+
+  fn runtime(&self) -> &Runtime {
+    self
+      .runtime
+      .as_ref()
+      .expect("Runtime is only taken on drop.")
+  }
+
+  /// Runs a future to completion on the runtime, blocking the current thread.
+  pub(super) fn block_on<F>(&self, future: F) -> F::Output
+  where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+  {
+    if Handle::try_current().is_err() {
+      return self.runtime().block_on(future);
+    }
+    // Runtimes can't be blocked on from inside a runtime (including our own, in event callbacks), so
+    // run the future as a task and wait for it on this thread instead.
+    let (sender, receiver) = std::sync::mpsc::channel();
+    self.runtime().spawn(async move {
+      let _ = sender.send(future.await);
+    });
+    receiver
+      .recv()
+      .expect("Task always sends a result unless it panics")
+  }
+}
+
+impl Drop for BlockingRuntime {
+  fn drop(&mut self) {
+    // Dropping a runtime normally blocks until its tasks finish, which panics if the last handle is
+    // dropped from an async context.
+    if let Some(runtime) = self.runtime.take() {
+      runtime.shutdown_background();
+    }
+  }
+}
+
+/// Events emitted by a [ButtplugBlockingClient]. Mirrors [ButtplugClientEvent], with devices
+/// wrapped as [ButtplugBlockingClientDevice]s.
+#[derive(Clone, Debug)]
+pub enum ButtplugBlockingClientEvent {
+  /// Emitted when a scanning session has finished.
+  ScanningFinished,
+  /// Emitted when a device has been added to the server.
+  DeviceAdded(ButtplugBlockingClientDevice),
+  /// Emitted when a device has been removed from the server.
+  DeviceRemoved(ButtplugBlockingClientDevice),
+  /// Emitted when the server finds hardware it will not connect to. Only sent after reporting has
+  /// been enabled via [ButtplugBlockingClient::report_unsupported_devices].
+  UnsupportedDeviceFound(UnsupportedDeviceFound),
+  /// Emitted when a client has not pinged the server in a sufficient amount of time.
+  PingTimeout,
+  /// Emitted when the client successfully connects to a server.
+  ServerConnect,
+  /// Emitted when a client connector detects that the server has disconnected.
+  ServerDisconnect,
+  /// Emitted when an error that cannot be matched to a request is received from the server.
+  Error(ButtplugError),
+}
+
+impl ButtplugBlockingClientEvent {
+  fn new(event: ButtplugClientEvent, runtime: &Arc<BlockingRuntime>) -> Self {
+    match event {
+      ButtplugClientEvent::ScanningFinished => Self::ScanningFinished,
+      ButtplugClientEvent::DeviceAdded(device) => {
+        Self::DeviceAdded(ButtplugBlockingClientDevice::new(device, runtime))
+      }
+      ButtplugClientEvent::DeviceRemoved(device) => {
+        Self::DeviceRemoved(ButtplugBlockingClientDevice::new(device, runtime))
+      }
+      ButtplugClientEvent::UnsupportedDeviceFound(info) => Self::UnsupportedDeviceFound(info),
+      ButtplugClientEvent::PingTimeout => Self::PingTimeout,
+      ButtplugClientEvent::ServerConnect => Self::ServerConnect,
+      ButtplugClientEvent::ServerDisconnect => Self::ServerDisconnect,
+      ButtplugClientEvent::Error(err) => Self::Error(err),
+    }
+  }
+}
+
+/// Blocking iterator over client or device events.
+///
+/// Only events emitted after the iterator was created are returned. Iteration ends once the event
+/// source shuts down.
+pub struct ButtplugBlockingEventIter<T> {
+  stream: Pin<Box<dyn Stream<Item = T> + Send>>,
+  runtime: Arc<BlockingRuntime>,
+}
+
+/// Iterator over [ButtplugBlockingClientEvent]s.
+pub type ButtplugBlockingClientEventIter = ButtplugBlockingEventIter<ButtplugBlockingClientEvent>;
+/// Iterator over [ButtplugClientDeviceEvent]s.
+pub type ButtplugBlockingClientDeviceEventIter =
+  ButtplugBlockingEventIter<ButtplugClientDeviceEvent>;
+
+impl<T> ButtplugBlockingEventIter<T>
+where
+  T: Send + 'static,
+{
+  fn new(stream: impl Stream<Item = T> + Send + 'static, runtime: &Arc<BlockingRuntime>) -> Self {
+    Self {
+      stream: Box::pin(stream),
+      runtime: runtime.clone(),
+    }
+  }
+
+  /// Waits up to `timeout` for the next event. Returns None if no event arrived in time, or if the
+  /// event source has been dropped.
+  pub fn next_timeout(&mut self, timeout: Duration) -> Option<T> {
+    // The stream has to move into the runtime, so swap in a placeholder while it's there.
+    let stream = std::mem::replace(&mut self.stream, Box::pin(futures::stream::empty()));
+    let (stream, event) = self.runtime.block_on(async move {
+      let mut stream = stream;
+      let event = tokio::time::timeout(timeout, stream.next())
+        .await
+        .ok()
+        .flatten();
+      (stream, event)
+    });
+    self.stream = stream;
+    event
+  }
+}
+
+impl<T> Iterator for ButtplugBlockingEventIter<T>
+where
+  T: Send + 'static,
+{
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    let stream = std::mem::replace(&mut self.stream, Box::pin(futures::stream::empty()));
+    let (stream, event) = self.runtime.block_on(async move {
+      let mut stream = stream;
+      let event = stream.next().await;
+      (stream, event)
+    });
+    self.stream = stream;
+    event
+  }
+}
+
+/// Synchronous version of [ButtplugClient].
+///
+/// All methods block until the server has replied. See the [module documentation](self) for
+/// details on runtimes and events.
+pub struct ButtplugBlockingClient {
+  client: Arc<ButtplugClient>,
+  runtime: Arc<BlockingRuntime>,
+}
+
+// Results match the async client's, so its error type is returned as-is.
+#[allow(clippy::result_large_err)]
+impl ButtplugBlockingClient {
+  pub fn new(name: &str) -> Self {
+    Self::new_from_client(ButtplugClient::new(name))
+  }
+
+  /// Creates a client that presents `auth_token` to the server during the handshake.
+  pub fn new_with_auth_token(name: &str, auth_token: &str) -> Self {
+    Self::new_from_client(ButtplugClient::new_with_auth_token(name, auth_token))
+  }
+
+  fn new_from_client(client: ButtplugClient) -> Self {
+    Self {
+      client: Arc::new(client),
+      runtime: Arc::new(BlockingRuntime::new()),
+    }
+  }
+
+  /// Enters the client's runtime until the returned guard is dropped. Use this when building
+  /// connectors or in-process servers that spawn tasks on creation.
+  pub fn enter(&self) -> EnterGuard<'_> {
+    self.runtime.runtime().enter()
+  }
+
+  /// The async client this wraps, for mixing blocking calls with async code.
+  pub fn client(&self) -> &ButtplugClient {
+    &self.client
+  }
+
+  pub fn connect<ConnectorType>(&self, connector: ConnectorType) -> ButtplugBlockingClientResult
+  where
+    ConnectorType: ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+      + 'static,
+  {
+    let client = self.client.clone();
+    self
+      .runtime
+      .block_on(async move { client.connect(connector).await })
+  }
+
+  /// Returns true if client is currently connected.
+  pub fn connected(&self) -> bool {
+    self.client.connected()
+  }
+
+  /// Disconnects from server, if connected.
+  pub fn disconnect(&self) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.client.disconnect())
+  }
+
+  /// Tells server to start scanning for devices.
+  pub fn start_scanning(&self) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.client.start_scanning())
+  }
+
+  /// Tells server to stop scanning for devices.
+  pub fn stop_scanning(&self) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.client.stop_scanning())
+  }
+
+  /// Tells server whether to report hardware it finds but will not connect to.
+  pub fn report_unsupported_devices(&self, enabled: bool) -> ButtplugBlockingClientResult {
+    self
+      .runtime
+      .block_on(self.client.report_unsupported_devices(enabled))
+  }
+
+  /// Tells server to stop all devices.
+  pub fn stop_all_devices(&self) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.client.stop_all_devices())
+  }
+
+  pub fn ping(&self) -> ButtplugBlockingClientResult {
+    self.runtime.block_on(self.client.ping())
+  }
+
+  pub fn server_name(&self) -> Option<String> {
+    self.client.server_name()
+  }
+
+  /// Retreives a list of currently connected devices.
+  pub fn devices(&self) -> Vec<ButtplugBlockingClientDevice> {
+    self
+      .client
+      .devices()
+      .into_iter()
+      .map(|device| ButtplugBlockingClientDevice::new(device, &self.runtime))
+      .collect()
+  }
+
+  /// Returns a blocking iterator over events emitted from now on.
+  pub fn event_iter(&self) -> ButtplugBlockingClientEventIter {
+    let runtime = self.runtime.clone();
+    let stream = self
+      .client
+      .event_stream()
+      .map(move |event| ButtplugBlockingClientEvent::new(event, &runtime));
+    ButtplugBlockingEventIter::new(stream, &self.runtime)
+  }
+
+  /// Calls `callback` for every event emitted from now on, until the client is dropped.
+  ///
+  /// Callbacks run one at a time on the client's runtime thread, and can call back into the
+  /// client.
+  pub fn on_event<F>(&self, mut callback: F)
+  where
+    F: FnMut(ButtplugBlockingClientEvent) + Send + 'static,
+  {
+    // The task lives on the runtime, so holding a strong reference would keep the runtime alive
+    // forever.
+    let runtime = Arc::downgrade(&self.runtime);
+    let mut stream = self.client.event_stream();
+    self.runtime.runtime().spawn(async move {
+      while let Some(event) = stream.next().await {
+        let Some(runtime) = runtime.upgrade() else {
+          return;
+        };
+        let event = ButtplugBlockingClientEvent::new(event, &runtime);
+        // Blocking calls from the callback wait on the runtime's worker, so move off of it.
+        tokio::task::block_in_place(|| callback(event));
+      }
+    });
+  }
+}
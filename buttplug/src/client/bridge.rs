@@ -0,0 +1,288 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Pieces shared by the protocol bridges (OSC, MQTT) that drive a [ButtplugClient] from outside.
+
+use super::{
+  device::{ButtplugClientDevice, ButtplugClientDeviceEvent},
+  ButtplugClient,
+  ButtplugClientError,
+};
+use crate::{
+  core::message::{ButtplugCurrentSpecServerMessage, SensorReading},
+  util::{async_manager, sleep},
+};
+use async_trait::async_trait;
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// Output side of a bridge, for device state read from the client.
+#[async_trait]
+pub(crate) trait BridgePublisher: Clone + Send + Sync + 'static {
+  async fn publish_battery_level(&self, device_index: u32, level: f64);
+  async fn publish_sensor_reading(&self, device_index: u32, reading: &SensorReading);
+}
+
+/// Indexes of the features a bridge command applies to, out of `count` features. A `feature_index`
+/// of `None` selects all of them.
+pub(crate) fn feature_indexes(
+  count: usize,
+  feature_index: Option<u32>,
+) -> impl Iterator<Item = u32> {
+  (0..count as u32).filter(move |i| feature_index.is_none() || feature_index == Some(*i))
+}
+
+/// Reads the battery level of every device that has one each `interval`, until cancelled.
+pub(crate) fn spawn_battery_publisher<P: BridgePublisher>(
+  client: Arc<ButtplugClient>,
+  publisher: P,
+  interval: Duration,
+  token: CancellationToken,
+) {
+  async_manager::spawn(async move {
+    while !token.is_cancelled() {
+      for device in client.devices() {
+        if !device.has_battery_level() {
+          continue;
+        }
+        match device.battery_level().await {
+          Ok(level) => publisher.publish_battery_level(device.index(), level).await,
+          Err(e) => warn!(
+            "Could not read battery level for device {}: {}",
+            device.index(),
+            e
+          ),
+        }
+      }
+      select! {
+        _ = sleep(interval).fuse() => {},
+        _ = token.cancelled().fuse() => break,
+      }
+    }
+  });
+}
+
+/// Forwards sensor readings from a device until it disconnects or the task is cancelled.
+pub(crate) fn spawn_device_sensor_publisher<P: BridgePublisher>(
+  device: Arc<ButtplugClientDevice>,
+  publisher: P,
+  token: CancellationToken,
+) {
+  async_manager::spawn(async move {
+    let mut events = device.event_stream();
+    loop {
+      select! {
+        event = events.next().fuse() => match event {
+          Some(ButtplugClientDeviceEvent::Message(ButtplugCurrentSpecServerMessage::SensorReading(
+            reading,
+          ))) => {
+            publisher
+              .publish_sensor_reading(device.index(), &reading)
+              .await
+          }
+          Some(ButtplugClientDeviceEvent::Message(_)) => {}
+          _ => break,
+        },
+        _ = token.cancelled().fuse() => break,
+      }
+    }
+  });
+}
+
+/// Command received by a bridge, to be run against a single device.
+pub(crate) trait QueuedDeviceCommand: Send + 'static {
+  /// Whether queueing this command makes an already queued command redundant, usually because it
+  /// sets a new value for the same feature.
+  fn replaces(&self, queued: &Self) -> bool;
+  fn send(
+    self,
+    device: Arc<ButtplugClientDevice>,
+  ) -> BoxFuture<'static, Result<(), ButtplugClientError>>;
+}
+
+struct DeviceCommandLane<C> {
+  commands: Mutex<VecDeque<(Arc<ButtplugClientDevice>, C)>>,
+}
+
+type DeviceCommandLanes<C> = Arc<Mutex<HashMap<u32, Arc<DeviceCommandLane<C>>>>>;
+
+/// Runs bridge commands one at a time per device, in the order they were received.
+///
+/// Bridge inputs (slider movements, home automation state changes) can arrive much faster than a
+/// device can take commands. While a device is busy, a new command replaces any queued command it
+/// [replaces](QueuedDeviceCommand::replaces), so the device catches up to the latest value instead
+/// of working through a backlog. A device's worker exits and drops its lane once the lane is empty,
+/// so devices that go away don't leave anything behind. Workers stop when the queue is dropped.
+pub(crate) struct DeviceCommandQueue<C> {
+  lanes: DeviceCommandLanes<C>,
+  token: CancellationToken,
+  _token_guard: DropGuard,
+}
+
+impl<C: QueuedDeviceCommand> DeviceCommandQueue<C> {
+  pub(crate) fn new() -> Self {
+    let token = CancellationToken::new();
+    Self {
+      lanes: Arc::new(Mutex::new(HashMap::new())),
+      _token_guard: token.clone().drop_guard(),
+      token,
+    }
+  }
+
+  pub(crate) fn push(&self, device: Arc<ButtplugClientDevice>, command: C) {
+    // The lane map stays locked while queueing, so a worker can't drop the lane between us finding
+    // it and adding the command.
+    let mut lanes = self
+      .lanes
+      .lock()
+      .expect("Lane map lock should never be poisoned.");
+    let device_index = device.index();
+    let lane = lanes
+      .entry(device_index)
+      .or_insert_with(|| self.spawn_lane(device_index));
+    let mut commands = lane
+      .commands
+      .lock()
+      .expect("Lane lock should never be poisoned.");
+    commands.retain(|(_, queued)| !command.replaces(queued));
+    commands.push_back((device, command));
+  }
+
+  #[cfg(test)]
+  fn lane_count(&self) -> usize {
+    self
+      .lanes
+      .lock()
+      .expect("Lane map lock should never be poisoned.")
+      .len()
+  }
+
+  fn spawn_lane(&self, device_index: u32) -> Arc<DeviceCommandLane<C>> {
+    let lane = Arc::new(DeviceCommandLane::<C> {
+      commands: Mutex::new(VecDeque::new()),
+    });
+    let worker_lane = lane.clone();
+    let lanes = self.lanes.clone();
+    let token = self.token.clone();
+    async_manager::spawn(async move {
+      while !token.is_cancelled() {
+        let next = {
+          // Only give up the lane while holding the map lock, so nothing can be queued on it after
+          // we've decided it's empty.
+          let mut lanes = lanes
+            .lock()
+            .expect("Lane map lock should never be poisoned.");
+          let next = worker_lane
+            .commands
+            .lock()
+            .expect("Lane lock should never be poisoned.")
+            .pop_front();
+          if next.is_none() {
+            lanes.remove(&device_index);
+          }
+          next
+        };
+        match next {
+          Some((device, command)) => {
+            if let Err(e) = command.send(device).await {
+              warn!(
+                "Could not send bridged command to device {}: {}",
+                device_index, e
+              );
+            }
+          }
+          None => break,
+        }
+      }
+    });
+    lane
+  }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod test {
+  use super::*;
+  use crate::{
+    client::{ButtplugClientEvent, ScalarValueCommand},
+    core::message::ActuatorType,
+    testing::{ButtplugTestServerBuilder, FakeDeviceBuilder},
+  };
+
+  struct VibrateCommand(f64);
+
+  impl QueuedDeviceCommand for VibrateCommand {
+    fn replaces(&self, _: &Self) -> bool {
+      false
+    }
+
+    fn send(
+      self,
+      device: Arc<ButtplugClientDevice>,
+    ) -> BoxFuture<'static, Result<(), ButtplugClientError>> {
+      async move {
+        device
+          .vibrate(&ScalarValueCommand::ScalarValue(self.0))
+          .await
+      }
+      .boxed()
+    }
+  }
+
+  #[tokio::test]
+  async fn test_device_command_queue_drops_idle_lanes() {
+    let mut builder = ButtplugTestServerBuilder::default();
+    let fake_device =
+      builder.add_device(FakeDeviceBuilder::new("Fake Toy").scalar(ActuatorType::Vibrate, 20));
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    client
+      .connect(builder.connector().expect("Test, assuming infallible."))
+      .await
+      .expect("Test, assuming infallible.");
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let device = loop {
+      if let Some(ButtplugClientEvent::DeviceAdded(device)) = event_stream.next().await {
+        break device;
+      }
+    };
+
+    let queue = DeviceCommandQueue::new();
+    for level in [0.25, 0.5, 0.75] {
+      queue.push(device.clone(), VibrateCommand(level));
+    }
+    assert_eq!(queue.lane_count(), 1);
+    assert_eq!(
+      fake_device
+        .wait_for_commands(3, Duration::from_secs(5))
+        .await
+        .len(),
+      3
+    );
+    while queue.lane_count() != 0 {
+      sleep(Duration::from_millis(10)).await;
+    }
+
+    // Devices that have gone away still get a fresh lane, and don't keep it.
+    fake_device.disconnect();
+    while !matches!(
+      event_stream.next().await,
+      Some(ButtplugClientEvent::DeviceRemoved(_))
+    ) {}
+    queue.push(device, VibrateCommand(1.0));
+    while queue.lane_count() != 0 {
+      sleep(Duration::from_millis(10)).await;
+    }
+  }
+}
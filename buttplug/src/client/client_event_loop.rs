@@ -14,20 +14,24 @@ use super::{
   ButtplugClientMessageFuturePair,
   ButtplugClientMessageSender,
 };
-use crate::core::{
-  connector::{ButtplugConnector, ButtplugConnectorStateShared},
-  errors::{ButtplugDeviceError, ButtplugError},
-  message::{
-    ButtplugCurrentSpecClientMessage,
-    ButtplugCurrentSpecServerMessage,
-    ButtplugDeviceMessage,
-    ButtplugMessageValidator,
-    DeviceList,
-    DeviceMessageInfo,
+use crate::{
+  core::{
+    connector::{ButtplugConnector, ButtplugConnectorStateShared},
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      ButtplugCurrentSpecClientMessage,
+      ButtplugCurrentSpecServerMessage,
+      ButtplugDeviceMessage,
+      ButtplugMessage,
+      ButtplugMessageValidator,
+      DeviceList,
+      DeviceMessageInfo,
+    },
   },
+  util::{sleep, Instant},
 };
 use dashmap::DashMap;
-use futures::FutureExt;
+use futures::{future, FutureExt};
 use std::sync::{
   atomic::{AtomicBool, Ordering},
   Arc,
@@ -243,6 +247,10 @@ where
         trace!("Scanning finished event received, forwarding to client.");
         self.send_client_event(ButtplugClientEvent::ScanningFinished);
       }
+      ButtplugCurrentSpecServerMessage::UnsupportedDeviceFound(msg) => {
+        trace!("Unsupported device event received, forwarding to client.");
+        self.send_client_event(ButtplugClientEvent::UnsupportedDeviceFound(msg));
+      }
       ButtplugCurrentSpecServerMessage::RawReading(msg) => {
         let device_idx = msg.device_index();
         if let Some(device) = self.device_map.get(&device_idx) {
@@ -256,6 +264,7 @@ where
       ButtplugCurrentSpecServerMessage::SensorReading(msg) => {
         let device_idx = msg.device_index();
         if let Some(device) = self.device_map.get(&device_idx) {
+          device.value().record_subscribed_sensor_reading(&msg);
           device
             .value()
             .queue_event(ButtplugClientDeviceEvent::Message(
@@ -282,8 +291,10 @@ where
 
     trace!("Sending message to connector: {:?}", msg_fut.msg);
     self.sorter.register_future(&mut msg_fut);
-    if self.connector.send(msg_fut.msg).await.is_err() {
-      error!("Sending message failed, connector most likely no longer connected.");
+    let id = msg_fut.msg.id();
+    if let Err(e) = self.connector.send(msg_fut.msg).await {
+      error!("Sending message failed: {}", e);
+      self.sorter.fail_future(id, e.into());
     }
   }
 
@@ -325,6 +336,19 @@ where
   pub async fn run(&mut self) {
     debug!("Running client event loop.");
     loop {
+      // Single timer for all requests waiting on a reply, rebuilt as requests come and go.
+      let next_timeout = self.sorter.next_timeout();
+      let request_timeout = async move {
+        match next_timeout {
+          Some(deadline) => {
+            let now = Instant::now();
+            if deadline > now {
+              sleep(deadline - now).await;
+            }
+          }
+          None => future::pending::<()>().await,
+        }
+      };
       select! {
         event = self.from_connector_receiver.recv().fuse() => match event {
           None => {
@@ -346,6 +370,7 @@ where
             }
           }
         },
+        _ = request_timeout.fuse() => self.sorter.expire_timed_out(),
       };
     }
     self
@@ -14,13 +14,28 @@ use crate::{
     ButtplugServerMessageStateShared,
   },
   core::message::{ButtplugCurrentSpecServerMessage, ButtplugMessage, ButtplugMessageValidator},
+  util::Instant,
 };
 use dashmap::DashMap;
-use std::sync::{
-  atomic::{AtomicU32, Ordering},
-  Arc,
+use std::{
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+  },
+  time::Duration,
 };
 
+/// How long the `id` of a timed out message is remembered, so that a late response to it is dropped
+/// instead of being treated as an event.
+const TIMED_OUT_ID_RETENTION: Duration = Duration::from_secs(60);
+
+/// A message waiting on a response, along with when to give up on it.
+struct PendingResponse {
+  state: ButtplugServerMessageStateShared,
+  /// Timeout the message was registered with, and the time it runs out.
+  timeout: Option<(Duration, Instant)>,
+}
+
 /// Message sorting and pairing for remote client connectors.
 ///
 /// In order to create reliable connections to remote systems, we need a way to maintain message
@@ -52,13 +67,25 @@ use std::sync::{
 /// - If the message `id` is not zero but there is no future waiting, the message is dropped and an
 ///   error is emitted.
 ///
+/// Messages registered with a timeout have their future resolved with
+/// [ButtplugClientError::RequestTimeout] if no response arrives in time. The sorter doesn't run a
+/// timer itself. Its owner waits until [next_timeout](Self::next_timeout), then calls
+/// [expire_timed_out](Self::expire_timed_out). A response that shows up after its message timed out
+/// is logged and dropped, as long as it arrives within a minute of the timeout.
+///
 pub struct ClientMessageSorter {
   /// Map of message `id`s to their related future.
   ///
   /// This is where we store message `id`s that are waiting for a return from the server. Once we
   /// get back a response with a matching `id`, we remove the entry from this map, and use the waker
   /// to complete the future with the received response message.
-  future_map: DashMap<u32, ButtplugServerMessageStateShared>,
+  future_map: DashMap<u32, PendingResponse>,
+
+  /// Message `id`s whose futures were resolved with a timeout error, and when that happened.
+  ///
+  /// Kept so that a response arriving after its timeout is recognized as stale, rather than being
+  /// treated as an event. Entries are pruned after [TIMED_OUT_ID_RETENTION].
+  timed_out_ids: DashMap<u32, Instant>,
 
   /// Message `id` counter
   ///
@@ -77,10 +104,62 @@ impl ClientMessageSorter {
     let id = self.current_id.load(Ordering::SeqCst);
     trace!("Setting message id to {}", id);
     msg_fut.msg.set_id(id);
-    self.future_map.insert(id, msg_fut.waker.clone());
+    self.future_map.insert(
+      id,
+      PendingResponse {
+        state: msg_fut.waker.clone(),
+        timeout: msg_fut
+          .timeout
+          .map(|timeout| (timeout, Instant::now() + timeout)),
+      },
+    );
     self.current_id.store(id + 1, Ordering::SeqCst);
   }
 
+  /// Earliest time a message waiting on a response times out, if any have a timeout.
+  pub fn next_timeout(&self) -> Option<Instant> {
+    self
+      .future_map
+      .iter()
+      .filter_map(|entry| entry.value().timeout.map(|(_, deadline)| deadline))
+      .min()
+  }
+
+  /// Resolves the futures of messages whose timeout has run out with
+  /// [ButtplugClientError::RequestTimeout], and forgets old timed out `id`s.
+  pub fn expire_timed_out(&self) {
+    let now = Instant::now();
+    self
+      .timed_out_ids
+      .retain(|_, timed_out_at| now < *timed_out_at + TIMED_OUT_ID_RETENTION);
+    let expired: Vec<u32> = self
+      .future_map
+      .iter()
+      .filter(|entry| matches!(entry.value().timeout, Some((_, deadline)) if deadline <= now))
+      .map(|entry| *entry.key())
+      .collect();
+    for id in expired {
+      if let Some((_, pending)) = self.future_map.remove(&id) {
+        let (timeout, _) = pending
+          .timeout
+          .expect("Only messages with timeouts expire.");
+        warn!("Message id {} timed out after {:?}.", id, timeout);
+        self.timed_out_ids.insert(id, now);
+        pending
+          .state
+          .set_reply(Err(ButtplugClientError::RequestTimeout(timeout)));
+      }
+    }
+  }
+
+  /// Resolves the future of a message that couldn't be sent with `error`, as no response will
+  /// arrive for it.
+  pub fn fail_future(&self, id: u32, error: ButtplugClientError) {
+    if let Some((_, pending)) = self.future_map.remove(&id) {
+      pending.state.set_reply(Err(error));
+    }
+  }
+
   /// Given a response message from the server, resolve related future if we have one.
   ///
   /// Returns true if the response message was resolved to a future via matching `id`, otherwise
@@ -89,7 +168,7 @@ impl ClientMessageSorter {
     let id = msg.id();
     trace!("Trying to resolve message future for id {}.", id);
     match self.future_map.remove(&id) {
-      Some((_, state)) => {
+      Some((_, PendingResponse { state, .. })) => {
         trace!("Resolved id {} to a future.", id);
         if let Err(e) = msg.is_valid() {
           error!("Message not valid: {:?} - Error: {}", msg, e);
@@ -101,6 +180,13 @@ impl ClientMessageSorter {
         }
         true
       }
+      None if self.timed_out_ids.remove(&id).is_some() => {
+        warn!(
+          "Received response for message id {} after it timed out, dropping: {:?}",
+          id, msg
+        );
+        true
+      }
       None => {
         trace!("Message id {} not found, considering it an event.", id);
         false
@@ -117,7 +203,86 @@ impl Default for ClientMessageSorter {
   fn default() -> Self {
     Self {
       future_map: DashMap::new(),
+      timed_out_ids: DashMap::new(),
       current_id: Arc::new(AtomicU32::new(1)),
     }
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::{
+    client::{ButtplugClientMessageFuturePair, ButtplugServerMessageFuture},
+    core::{
+      connector::ButtplugConnectorError,
+      message::{Ok as OkMessage, Ping},
+    },
+  };
+  use futures::FutureExt;
+
+  fn register_ping(
+    sorter: &ClientMessageSorter,
+    timeout: Duration,
+  ) -> (u32, ButtplugServerMessageFuture) {
+    let fut = ButtplugServerMessageFuture::default();
+    let mut msg_fut = ButtplugClientMessageFuturePair::new_with_timeout(
+      Ping::default().into(),
+      fut.get_state_clone(),
+      Some(timeout),
+    );
+    sorter.register_future(&mut msg_fut);
+    (msg_fut.msg.id(), fut)
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_timed_out_ids_expire() {
+    let sorter = ClientMessageSorter::default();
+    let (id, fut) = register_ping(&sorter, Duration::from_millis(100));
+    assert_eq!(
+      sorter.next_timeout(),
+      Some(Instant::now() + Duration::from_millis(100))
+    );
+
+    // Nothing has run out yet.
+    sorter.expire_timed_out();
+    let mut fut = fut.fuse();
+    assert!((&mut fut).now_or_never().is_none());
+
+    tokio::time::advance(Duration::from_millis(100)).await;
+    sorter.expire_timed_out();
+    assert!(matches!(
+      fut.await,
+      Err(ButtplugClientError::RequestTimeout(duration)) if duration == Duration::from_millis(100)
+    ));
+    assert_eq!(sorter.next_timeout(), None);
+
+    // A late response is recognized as stale...
+    let mut late_reply: ButtplugCurrentSpecServerMessage = OkMessage::new(id).into();
+    assert!(sorter.maybe_resolve_result(&late_reply));
+
+    // ...until the id has been forgotten.
+    let (id, _fut) = register_ping(&sorter, Duration::from_millis(100));
+    tokio::time::advance(Duration::from_millis(100)).await;
+    sorter.expire_timed_out();
+    tokio::time::advance(TIMED_OUT_ID_RETENTION).await;
+    sorter.expire_timed_out();
+    assert!(sorter.timed_out_ids.is_empty());
+    late_reply.set_id(id);
+    assert!(!sorter.maybe_resolve_result(&late_reply));
+  }
+
+  #[tokio::test]
+  async fn test_failed_send_resolves_future() {
+    let sorter = ClientMessageSorter::default();
+    let (id, fut) = register_ping(&sorter, Duration::from_secs(10));
+    sorter.fail_future(id, ButtplugConnectorError::ConnectorNotConnected.into());
+    assert!(matches!(
+      fut.await,
+      Err(ButtplugClientError::ButtplugConnectorError(
+        ButtplugConnectorError::ConnectorNotConnected
+      ))
+    ));
+    assert_eq!(sorter.next_timeout(), None);
+  }
+}
@@ -9,6 +9,9 @@
 
 use super::{
   create_boxed_future_client_error,
+  device_state::{ClientDeviceState, ClientDeviceStateTracker},
+  feature::{DeviceSensor, LinearActuator, RotateActuator, ScalarActuator, SensorAccess},
+  ramp::{run_scalar_ramp, RampEasing, ScalarRampFeature, ScalarRampState},
   ButtplugClientMessageSender,
   ButtplugClientResultFuture,
 };
@@ -31,9 +34,9 @@ use crate::{
       RawWriteCmd,
       RotateCmd,
       RotationSubcommand,
-      ScalarCmd,
       ScalarSubcommand,
       SensorReadCmd,
+      SensorReading,
       SensorSubscribeCmd,
       SensorType,
       SensorUnsubscribeCmd,
@@ -41,9 +44,9 @@ use crate::{
       VectorSubcommand,
     },
   },
-  util::stream::convert_broadcast_receiver_to_stream,
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
 };
-use futures::{FutureExt, Stream};
+use futures::{channel::oneshot, FutureExt, Stream};
 use getset::{CopyGetters, Getters};
 use std::{
   collections::HashMap,
@@ -52,6 +55,7 @@ use std::{
     atomic::{AtomicBool, Ordering},
     Arc,
   },
+  time::Duration,
 };
 use tokio::sync::broadcast;
 
@@ -176,6 +180,10 @@ pub struct ButtplugClientDevice {
   /// [ButtplugClientDevice] instance is still connected to the
   /// [ButtplugServer][crate::server::ButtplugServer].
   client_connected: Arc<AtomicBool>,
+  /// Last acknowledged actuator values and sensor readings.
+  device_state: ClientDeviceStateTracker,
+  /// Ramp currently running on the device, if any.
+  scalar_ramp_state: ScalarRampState,
 }
 
 impl ButtplugClientDevice {
@@ -206,6 +214,12 @@ impl ButtplugClientDevice {
     let (event_sender, _) = broadcast::channel(256);
     let device_connected = Arc::new(AtomicBool::new(true));
     let client_connected = Arc::new(AtomicBool::new(true));
+    let device_state = ClientDeviceStateTracker::new(
+      message_attributes.scalar_cmd().as_ref().map_or(0, Vec::len),
+      message_attributes.rotate_cmd().as_ref().map_or(0, Vec::len),
+      message_attributes.linear_cmd().as_ref().map_or(0, Vec::len),
+    );
+    let scalar_ramp_state = ScalarRampState::new(&device_state);
 
     Self {
       name: name.to_owned(),
@@ -216,6 +230,8 @@ impl ButtplugClientDevice {
       internal_event_sender: event_sender,
       device_connected,
       client_connected,
+      device_state,
+      scalar_ramp_state,
     }
   }
 
@@ -232,6 +248,27 @@ impl ButtplugClientDevice {
     )
   }
 
+  /// Returns a handle to the same device whose requests fail with
+  /// [RequestTimeout][super::ButtplugClientError::RequestTimeout] if the server hasn't replied within `timeout`, instead
+  /// of using the client-wide timeout set via
+  /// [ButtplugClient::set_request_timeout][super::ButtplugClient::set_request_timeout].
+  ///
+  /// The handle shares its connection status, state and ramps with this device.
+  pub fn with_request_timeout(&self, timeout: Duration) -> ButtplugClientDevice {
+    Self {
+      name: self.name.clone(),
+      display_name: self.display_name.clone(),
+      index: self.index,
+      message_attributes: self.message_attributes.clone(),
+      event_loop_sender: Arc::new(self.event_loop_sender.with_request_timeout(timeout)),
+      internal_event_sender: self.internal_event_sender.clone(),
+      device_connected: self.device_connected.clone(),
+      client_connected: self.client_connected.clone(),
+      device_state: self.device_state.clone(),
+      scalar_ramp_state: self.scalar_ramp_state.clone(),
+    }
+  }
+
   pub fn connected(&self) -> bool {
     self.device_connected.load(Ordering::SeqCst)
   }
@@ -242,6 +279,74 @@ impl ButtplugClientDevice {
     )))
   }
 
+  /// Handles for each ScalarCmd feature of the device, in index order.
+  pub fn scalar_actuators(&self) -> Vec<ScalarActuator<'_>> {
+    self
+      .message_attributes
+      .scalar_cmd()
+      .iter()
+      .flatten()
+      .enumerate()
+      .map(|(index, attrs)| ScalarActuator::new(self, index as u32, attrs))
+      .collect()
+  }
+
+  /// Handles for each RotateCmd feature of the device, in index order.
+  pub fn rotate_actuators(&self) -> Vec<RotateActuator<'_>> {
+    self
+      .message_attributes
+      .rotate_cmd()
+      .iter()
+      .flatten()
+      .enumerate()
+      .map(|(index, attrs)| RotateActuator::new(self, index as u32, attrs))
+      .collect()
+  }
+
+  /// Handles for each LinearCmd feature of the device, in index order.
+  pub fn linear_actuators(&self) -> Vec<LinearActuator<'_>> {
+    self
+      .message_attributes
+      .linear_cmd()
+      .iter()
+      .flatten()
+      .enumerate()
+      .map(|(index, attrs)| LinearActuator::new(self, index as u32, attrs))
+      .collect()
+  }
+
+  /// Handles for each sensor of the device. Readable sensors come first, followed by subscribable
+  /// sensors, each in index order.
+  pub fn sensors(&self) -> Vec<DeviceSensor<'_>> {
+    let readable = self
+      .message_attributes
+      .sensor_read_cmd()
+      .iter()
+      .flatten()
+      .enumerate()
+      .map(|(index, attrs)| DeviceSensor::new(self, index as u32, SensorAccess::Read, attrs));
+    let subscribable = self
+      .message_attributes
+      .sensor_subscribe_cmd()
+      .iter()
+      .flatten()
+      .enumerate()
+      .map(|(index, attrs)| DeviceSensor::new(self, index as u32, SensorAccess::Subscribe, attrs));
+    readable.chain(subscribable).collect()
+  }
+
+  /// Last acknowledged values of the device's actuators, and its latest sensor readings.
+  pub fn state(&self) -> ClientDeviceState {
+    self.device_state.state()
+  }
+
+  /// Stream of [ClientDeviceState] snapshots, emitted whenever the state changes.
+  pub fn state_stream(&self) -> Box<dyn Stream<Item = ClientDeviceState> + Send + Unpin> {
+    Box::new(Box::pin(convert_broadcast_receiver_to_stream(
+      self.device_state.subscribe(),
+    )))
+  }
+
   fn scalar_value_attributes(
     &self,
     actuator: &ActuatorType,
@@ -277,14 +382,14 @@ impl ButtplugClientDevice {
   // device, we need to resolve that we're only talking to attributes 0 and 2 here. In Message Spec
   // v3, in order to build ergonomic APIs, this requires a TON of bookkeeping on the client
   // developer side. Which fucking sucks.
-  fn scalar_from_value_command(
+  fn scalar_subcommands_from_value_command(
     &self,
     value_cmd: &ScalarValueCommand,
     actuator: &ActuatorType,
     attrs: &Vec<ClientGenericDeviceMessageAttributes>,
-  ) -> ButtplugClientResultFuture {
+  ) -> Result<Vec<ScalarSubcommand>, ButtplugError> {
     if attrs.is_empty() {
-      return create_boxed_future_client_error(
+      return Err(
         ButtplugDeviceError::UnhandledCommand(format!(
           "ScalarCmd with {actuator} is not handled by this device"
         ))
@@ -304,16 +409,14 @@ impl ButtplugClientDevice {
       }
       ScalarValueCommand::ScalarValueMap(map) => {
         if map.len() as u32 > scalar_count {
-          return create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(scalar_count, map.len() as u32).into(),
           );
         }
         scalar_vec = Vec::with_capacity(map.len() as usize);
         for (idx, speed) in map {
           if *idx >= scalar_count {
-            return create_boxed_future_client_error(
-              ButtplugDeviceError::DeviceFeatureIndexError(scalar_count, *idx).into(),
-            );
+            return Err(ButtplugDeviceError::DeviceFeatureIndexError(scalar_count, *idx).into());
           }
           scalar_vec.push(ScalarSubcommand::new(
             *attrs[*idx as usize].index(),
@@ -324,7 +427,7 @@ impl ButtplugClientDevice {
       }
       ScalarValueCommand::ScalarValueVec(vec) => {
         if vec.len() as u32 > scalar_count {
-          return create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(scalar_count, vec.len() as u32).into(),
           );
         }
@@ -334,9 +437,37 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = ScalarCmd::new(self.index, scalar_vec).into();
-    info!("{:?}", msg);
-    self.event_loop_sender.send_message_expect_ok(msg)
+    Ok(scalar_vec)
+  }
+
+  fn scalar_from_value_command(
+    &self,
+    value_cmd: &ScalarValueCommand,
+    actuator: &ActuatorType,
+    attrs: &Vec<ClientGenericDeviceMessageAttributes>,
+  ) -> ButtplugClientResultFuture {
+    match self.scalar_subcommands_from_value_command(value_cmd, actuator, attrs) {
+      Ok(scalar_vec) => self.send_scalar_subcommands(scalar_vec),
+      Err(err) => create_boxed_future_client_error(err),
+    }
+  }
+
+  /// Sends a ScalarCmd, cancelling any running ramp and updating the tracked levels.
+  fn send_scalar_subcommands(
+    &self,
+    scalar_vec: Vec<ScalarSubcommand>,
+  ) -> ButtplugClientResultFuture {
+    self.scalar_ramp_state.cancel_ramp();
+    trace!("{:?}", scalar_vec);
+    let ramp_state = self.scalar_ramp_state.clone();
+    let event_loop_sender = self.event_loop_sender.clone();
+    let device_index = self.index;
+    async move {
+      ramp_state
+        .send(&event_loop_sender, device_index, scalar_vec)
+        .await
+    }
+    .boxed()
   }
 
   pub fn vibrate_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributes> {
@@ -365,9 +496,12 @@ impl ButtplugClientDevice {
     )
   }
 
-  pub fn scalar(&self, scalar_cmd: &ScalarCommand) -> ButtplugClientResultFuture {
+  fn scalar_subcommands(
+    &self,
+    scalar_cmd: &ScalarCommand,
+  ) -> Result<Vec<ScalarSubcommand>, ButtplugError> {
     if self.message_attributes.scalar_cmd().is_none() {
-      return create_boxed_future_client_error(
+      return Err(
         ButtplugDeviceError::MessageNotSupported(ButtplugDeviceMessageType::VibrateCmd).into(),
       );
     }
@@ -389,23 +523,21 @@ impl ButtplugClientDevice {
       }
       ScalarCommand::ScalarMap(map) => {
         if map.len() as u32 > scalar_count {
-          return create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(scalar_count, map.len() as u32).into(),
           );
         }
         scalar_vec = Vec::with_capacity(map.len() as usize);
         for (idx, (scalar, actuator)) in map {
           if *idx >= scalar_count {
-            return create_boxed_future_client_error(
-              ButtplugDeviceError::DeviceFeatureIndexError(scalar_count, *idx).into(),
-            );
+            return Err(ButtplugDeviceError::DeviceFeatureIndexError(scalar_count, *idx).into());
           }
           scalar_vec.push(ScalarSubcommand::new(*idx, *scalar, *actuator));
         }
       }
       ScalarCommand::ScalarVec(vec) => {
         if vec.len() as u32 > scalar_count {
-          return create_boxed_future_client_error(
+          return Err(
             ButtplugDeviceError::DeviceFeatureCountMismatch(scalar_count, vec.len() as u32).into(),
           );
         }
@@ -415,8 +547,128 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = ScalarCmd::new(self.index, scalar_vec).into();
-    self.event_loop_sender.send_message_expect_ok(msg)
+    Ok(scalar_vec)
+  }
+
+  pub fn scalar(&self, scalar_cmd: &ScalarCommand) -> ButtplugClientResultFuture {
+    match self.scalar_subcommands(scalar_cmd) {
+      Ok(scalar_vec) => self.send_scalar_subcommands(scalar_vec),
+      Err(err) => create_boxed_future_client_error(err),
+    }
+  }
+
+  /// Starts a ramp of the given scalar features, from their last commanded levels to the levels in
+  /// `ramp_cmd`, over `duration`.
+  ///
+  /// The ramp runs in the background, cancelling any ramp already running on this device. The
+  /// returned future resolves once the ramp reaches its targets, or is cancelled by a later scalar,
+  /// stop, or ramp command. Dropping the future does not stop the ramp.
+  pub fn ramp_scalar(
+    &self,
+    ramp_cmd: &ScalarCommand,
+    duration: Duration,
+    easing: RampEasing,
+  ) -> ButtplugClientResultFuture {
+    match self.scalar_subcommands(ramp_cmd) {
+      Ok(scalar_vec) => self.ramp_scalar_subcommands(scalar_vec, duration, easing),
+      Err(err) => create_boxed_future_client_error(err),
+    }
+  }
+
+  /// Ramps vibration features, assuming the device has them. See
+  /// [ramp_scalar][ButtplugClientDevice::ramp_scalar] for details.
+  pub fn ramp_vibrate(
+    &self,
+    speed_cmd: &ScalarValueCommand,
+    duration: Duration,
+    easing: RampEasing,
+  ) -> ButtplugClientResultFuture {
+    match self.scalar_subcommands_from_value_command(
+      speed_cmd,
+      &ActuatorType::Vibrate,
+      &self.vibrate_attributes(),
+    ) {
+      Ok(scalar_vec) => self.ramp_scalar_subcommands(scalar_vec, duration, easing),
+      Err(err) => create_boxed_future_client_error(err),
+    }
+  }
+
+  /// Ramps oscillation features, assuming the device has them. See
+  /// [ramp_scalar][ButtplugClientDevice::ramp_scalar] for details.
+  pub fn ramp_oscillate(
+    &self,
+    speed_cmd: &ScalarValueCommand,
+    duration: Duration,
+    easing: RampEasing,
+  ) -> ButtplugClientResultFuture {
+    match self.scalar_subcommands_from_value_command(
+      speed_cmd,
+      &ActuatorType::Oscillate,
+      &self.oscillate_attributes(),
+    ) {
+      Ok(scalar_vec) => self.ramp_scalar_subcommands(scalar_vec, duration, easing),
+      Err(err) => create_boxed_future_client_error(err),
+    }
+  }
+
+  fn ramp_scalar_subcommands(
+    &self,
+    scalar_vec: Vec<ScalarSubcommand>,
+    duration: Duration,
+    easing: RampEasing,
+  ) -> ButtplugClientResultFuture {
+    // Levels are sent over the course of the ramp, so bad targets need to be caught up front.
+    if let Some(target) = scalar_vec
+      .iter()
+      .find(|target| !(0.0..=1.0).contains(&target.scalar()))
+    {
+      return create_boxed_future_client_error(
+        ButtplugMessageError::InvalidMessageContents(format!(
+          "Ramp target {} for feature {} is outside of the range [0.0, 1.0]",
+          target.scalar(),
+          target.index()
+        ))
+        .into(),
+      );
+    }
+    let attrs = self.scalar_attributes();
+    let features = scalar_vec
+      .into_iter()
+      .map(|target| ScalarRampFeature {
+        start: self.scalar_ramp_state.level(target.index()),
+        step_count: attrs
+          .get(target.index() as usize)
+          .map_or(1, |attr| *attr.step_count()),
+        target,
+      })
+      .collect();
+    let token = self.scalar_ramp_state.cancel_ramp();
+    let (result_sender, result_receiver) = oneshot::channel();
+    let ramp_fut = run_scalar_ramp(
+      self.index,
+      self.event_loop_sender.clone(),
+      self.scalar_ramp_state.clone(),
+      features,
+      duration,
+      easing,
+      token,
+    );
+    async_manager::spawn(async move {
+      // The caller may not be waiting on the result, so don't care if this fails.
+      let _ = result_sender.send(ramp_fut.await);
+    });
+    async move {
+      match result_receiver.await {
+        Ok(result) => result,
+        Err(_) => Err(
+          ButtplugError::from(ButtplugDeviceError::DeviceCommunicationError(
+            "Ramp task ended without a result".to_owned(),
+          ))
+          .into(),
+        ),
+      }
+    }
+    .boxed()
   }
 
   pub fn linear_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributes> {
@@ -473,12 +725,19 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = LinearCmd::new(self.index, linear_vec).into();
-    self.event_loop_sender.send_message_expect_ok(msg)
+    let msg = LinearCmd::new(self.index, linear_vec.clone()).into();
+    let send_fut = self.event_loop_sender.send_message_expect_ok(msg);
+    let device_state = self.device_state.clone();
+    async move {
+      send_fut.await?;
+      device_state.record_linear(&linear_vec);
+      Ok(())
+    }
+    .boxed()
   }
 
   pub fn rotate_attributes(&self) -> Vec<ClientGenericDeviceMessageAttributes> {
-    if let Some(attrs) = self.message_attributes.linear_cmd() {
+    if let Some(attrs) = self.message_attributes.rotate_cmd() {
       attrs.clone()
     } else {
       vec![]
@@ -531,8 +790,15 @@ impl ButtplugClientDevice {
         }
       }
     }
-    let msg = RotateCmd::new(self.index, rotate_vec).into();
-    self.event_loop_sender.send_message_expect_ok(msg)
+    let msg = RotateCmd::new(self.index, rotate_vec.clone()).into();
+    let send_fut = self.event_loop_sender.send_message_expect_ok(msg);
+    let device_state = self.device_state.clone();
+    async move {
+      send_fut.await?;
+      device_state.record_rotations(&rotate_vec);
+      Ok(())
+    }
+    .boxed()
   }
 
   pub fn subscribe_sensor(
@@ -586,10 +852,26 @@ impl ButtplugClientDevice {
         ButtplugDeviceError::ProtocolSensorNotSupported(*sensor_type).into(),
       );
     }
-    let msg = SensorReadCmd::new(self.index, sensor_indexes[0], *sensor_type).into();
+    self.read_sensor(sensor_indexes[0], *sensor_type)
+  }
+
+  /// Reads the sensor at the given index, returning the raw sensor values.
+  pub fn read_sensor(
+    &self,
+    sensor_index: u32,
+    sensor_type: SensorType,
+  ) -> ButtplugClientResultFuture<Vec<i32>> {
+    if self.message_attributes.sensor_read_cmd().is_none() {
+      return create_boxed_future_client_error(
+        ButtplugDeviceError::MessageNotSupported(ButtplugDeviceMessageType::SensorReadCmd).into(),
+      );
+    }
+    let msg = SensorReadCmd::new(self.index, sensor_index, sensor_type).into();
     let reply = self.event_loop_sender.send_message(msg);
+    let device_state = self.device_state.clone();
     async move {
       if let ButtplugCurrentSpecServerMessage::SensorReading(data) = reply.await? {
+        device_state.record_sensor_reading(ButtplugDeviceMessageType::SensorReadCmd, &data);
         Ok(data.data().clone())
       } else {
         Err(
@@ -715,18 +997,51 @@ impl ButtplugClientDevice {
 
   /// Commands device to stop all movement.
   pub fn stop(&self) -> ButtplugClientResultFuture {
+    self.cancel_ramp();
     // All devices accept StopDeviceCmd
-    self
+    let send_fut = self
       .event_loop_sender
-      .send_message_expect_ok(StopDeviceCmd::new(self.index).into())
+      .send_message_expect_ok(StopDeviceCmd::new(self.index).into());
+    let device_state = self.device_state.clone();
+    async move {
+      send_fut.await?;
+      device_state.clear_actuators();
+      Ok(())
+    }
+    .boxed()
+  }
+
+  /// Cancels any running ramp, so it can't undo a stop. Done as soon as the stop is requested.
+  pub(super) fn cancel_ramp(&self) {
+    self.scalar_ramp_state.cancel_ramp();
+  }
+
+  /// Forgets actuator values, once the server has acknowledged stopping the device.
+  pub(super) fn clear_actuator_state(&self) {
+    self.device_state.clear_actuators();
   }
 
   pub(super) fn set_device_connected(&self, connected: bool) {
     self.device_connected.store(connected, Ordering::SeqCst);
+    if !connected {
+      self.scalar_ramp_state.cancel_ramp();
+      self.device_state.clear();
+    }
   }
 
   pub(super) fn set_client_connected(&self, connected: bool) {
     self.client_connected.store(connected, Ordering::SeqCst);
+    if !connected {
+      self.scalar_ramp_state.cancel_ramp();
+      self.device_state.clear();
+    }
+  }
+
+  /// Records a reading sent by the server for a sensor subscription.
+  pub(super) fn record_subscribed_sensor_reading(&self, reading: &SensorReading) {
+    self
+      .device_state
+      .record_sensor_reading(ButtplugDeviceMessageType::SensorSubscribeCmd, reading);
   }
 
   pub(super) fn queue_event(&self, event: ButtplugClientDeviceEvent) {
@@ -0,0 +1,225 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Client-side record of what a device was last told to do.
+//!
+//! Each [ButtplugClientDevice][super::ButtplugClientDevice] keeps a [ClientDeviceState] with the
+//! last value the server acknowledged for each of its actuator features, and the latest reading of
+//! each of its sensors. Values are only recorded once the server has replied Ok, so the state never
+//! shows commands that failed.
+//!
+//! Actuator values are cleared when the device is stopped. Everything is cleared when the device or
+//! client disconnects.
+
+use crate::core::message::{
+  ButtplugDeviceMessageType,
+  RotationSubcommand,
+  ScalarSubcommand,
+  SensorReading,
+  SensorType,
+  VectorSubcommand,
+};
+use getset::Getters;
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+
+/// Snapshot of the last acknowledged state of a device.
+///
+/// Actuator vectors are indexed the same way as the device's attributes for the matching command,
+/// with None for features that haven't been commanded since the last stop.
+#[derive(Clone, Debug, Default, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct ClientDeviceState {
+  /// Level of each ScalarCmd feature.
+  scalars: Vec<Option<f64>>,
+  /// Speed and direction (true for clockwise) of each RotateCmd feature.
+  rotations: Vec<Option<(f64, bool)>>,
+  /// Duration in milliseconds and position of the last movement of each LinearCmd feature.
+  linear: Vec<Option<(u32, f64)>>,
+  /// Latest data received for each sensor, keyed by the message type the reading came in response to
+  /// (SensorReadCmd or SensorSubscribeCmd), then sensor index and type. Read and subscribe sensors
+  /// are indexed separately in the device's attributes.
+  sensor_readings: HashMap<(ButtplugDeviceMessageType, u32, SensorType), Vec<i32>>,
+}
+
+impl ClientDeviceState {
+  fn new(scalar_count: usize, rotate_count: usize, linear_count: usize) -> Self {
+    Self {
+      scalars: vec![None; scalar_count],
+      rotations: vec![None; rotate_count],
+      linear: vec![None; linear_count],
+      sensor_readings: HashMap::new(),
+    }
+  }
+
+  /// Latest data received for the sensor with the given index and type, if any. `message_type` is
+  /// SensorReadCmd or SensorSubscribeCmd, matching the attributes `sensor_index` comes from.
+  pub fn sensor_reading(
+    &self,
+    message_type: ButtplugDeviceMessageType,
+    sensor_index: u32,
+    sensor_type: SensorType,
+  ) -> Option<&Vec<i32>> {
+    self
+      .sensor_readings
+      .get(&(message_type, sensor_index, sensor_type))
+  }
+
+  /// Returns true if anything was cleared.
+  fn clear_actuators(&mut self) -> bool {
+    // Non-short-circuiting, every vector needs clearing.
+    clear_values(&mut self.scalars)
+      | clear_values(&mut self.rotations)
+      | clear_values(&mut self.linear)
+  }
+}
+
+fn clear_values<T>(values: &mut [Option<T>]) -> bool {
+  let changed = values.iter().any(Option::is_some);
+  values.iter_mut().for_each(|value| *value = None);
+  changed
+}
+
+fn set_values<T, I>(values: &mut [Option<T>], updates: I) -> bool
+where
+  T: PartialEq,
+  I: IntoIterator<Item = (u32, T)>,
+{
+  let mut changed = false;
+  for (index, value) in updates {
+    if let Some(slot) = values.get_mut(index as usize) {
+      if slot.as_ref() != Some(&value) {
+        *slot = Some(value);
+        changed = true;
+      }
+    }
+  }
+  changed
+}
+
+/// Shared, updatable [ClientDeviceState] that broadcasts a snapshot on every change.
+#[derive(Clone)]
+pub(super) struct ClientDeviceStateTracker {
+  state: Arc<Mutex<ClientDeviceState>>,
+  change_sender: broadcast::Sender<ClientDeviceState>,
+}
+
+impl ClientDeviceStateTracker {
+  pub(super) fn new(scalar_count: usize, rotate_count: usize, linear_count: usize) -> Self {
+    let (change_sender, _) = broadcast::channel(256);
+    Self {
+      state: Arc::new(Mutex::new(ClientDeviceState::new(
+        scalar_count,
+        rotate_count,
+        linear_count,
+      ))),
+      change_sender,
+    }
+  }
+
+  pub(super) fn state(&self) -> ClientDeviceState {
+    self
+      .state
+      .lock()
+      .expect("Only locked for single updates, should never be poisoned.")
+      .clone()
+  }
+
+  pub(super) fn subscribe(&self) -> broadcast::Receiver<ClientDeviceState> {
+    self.change_sender.subscribe()
+  }
+
+  /// Applies `update` to the state, broadcasting the result if `update` reports a change.
+  fn update<F>(&self, update: F)
+  where
+    F: FnOnce(&mut ClientDeviceState) -> bool,
+  {
+    let mut state = self
+      .state
+      .lock()
+      .expect("Only locked for single updates, should never be poisoned.");
+    if update(&mut state) {
+      // Nobody listening is fine, the state is still available via the getter.
+      let _ = self.change_sender.send(state.clone());
+    }
+  }
+
+  /// Last acknowledged level of a ScalarCmd feature.
+  pub(super) fn scalar(&self, index: u32) -> Option<f64> {
+    self
+      .state
+      .lock()
+      .expect("Only locked for single updates, should never be poisoned.")
+      .scalars
+      .get(index as usize)
+      .copied()
+      .flatten()
+  }
+
+  pub(super) fn record_scalars(&self, subcommands: &[ScalarSubcommand]) {
+    self.update(|state| {
+      set_values(
+        &mut state.scalars,
+        subcommands.iter().map(|cmd| (cmd.index(), cmd.scalar())),
+      )
+    });
+  }
+
+  pub(super) fn record_rotations(&self, subcommands: &[RotationSubcommand]) {
+    self.update(|state| {
+      set_values(
+        &mut state.rotations,
+        subcommands
+          .iter()
+          .map(|cmd| (cmd.index(), (cmd.speed(), cmd.clockwise()))),
+      )
+    });
+  }
+
+  pub(super) fn record_linear(&self, subcommands: &[VectorSubcommand]) {
+    self.update(|state| {
+      set_values(
+        &mut state.linear,
+        subcommands
+          .iter()
+          .map(|cmd| (cmd.index(), (cmd.duration(), cmd.position()))),
+      )
+    });
+  }
+
+  pub(super) fn record_sensor_reading(
+    &self,
+    message_type: ButtplugDeviceMessageType,
+    reading: &SensorReading,
+  ) {
+    self.update(|state| {
+      let key = (message_type, reading.sensor_index(), reading.sensor_type());
+      if state.sensor_readings.get(&key) == Some(reading.data()) {
+        return false;
+      }
+      state.sensor_readings.insert(key, reading.data().clone());
+      true
+    });
+  }
+
+  /// Forgets actuator values, for when the device has been stopped.
+  pub(super) fn clear_actuators(&self) {
+    self.update(|state| state.clear_actuators());
+  }
+
+  /// Forgets everything, for when the device is no longer reachable.
+  pub(super) fn clear(&self) {
+    self.update(|state| {
+      let changed = state.clear_actuators() || !state.sensor_readings.is_empty();
+      state.sensor_readings.clear();
+      changed
+    });
+  }
+}
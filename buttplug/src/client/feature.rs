@@ -0,0 +1,324 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Typed handles for the individual features of a device.
+//!
+//! Commanding a single feature through [ButtplugClientDevice] means building a map keyed by the
+//! feature's index, and scalar, rotate, linear and sensor features are each indexed separately. The
+//! handles here carry their own index and attributes, so a feature can be driven without any index
+//! bookkeeping. They build the same messages as the device methods they wrap.
+
+use super::{
+  create_boxed_future_client_error,
+  ButtplugClientDevice,
+  ButtplugClientResultFuture,
+  LinearCommand,
+  RampEasing,
+  RotateCommand,
+  ScalarCommand,
+};
+use crate::core::{
+  errors::{ButtplugDeviceError, ButtplugMessageError},
+  message::{
+    ActuatorType,
+    ButtplugDeviceMessageType,
+    ClientGenericDeviceMessageAttributes,
+    SensorDeviceMessageAttributes,
+    SensorType,
+  },
+};
+use std::{collections::HashMap, ops::RangeInclusive, time::Duration};
+
+fn check_unit_range(name: &str, value: f64, index: u32) -> Result<(), ButtplugMessageError> {
+  if (0.0..=1.0).contains(&value) {
+    Ok(())
+  } else {
+    Err(ButtplugMessageError::InvalidMessageContents(format!(
+      "{name} {value} for feature {index} is outside of the range [0.0, 1.0]"
+    )))
+  }
+}
+
+/// A single ScalarCmd feature of a device, e.g. one vibrator of a device with two.
+#[derive(Clone, Copy, Debug)]
+pub struct ScalarActuator<'a> {
+  device: &'a ButtplugClientDevice,
+  index: u32,
+  attributes: &'a ClientGenericDeviceMessageAttributes,
+}
+
+impl<'a> ScalarActuator<'a> {
+  pub(super) fn new(
+    device: &'a ButtplugClientDevice,
+    index: u32,
+    attributes: &'a ClientGenericDeviceMessageAttributes,
+  ) -> Self {
+    Self {
+      device,
+      index,
+      attributes,
+    }
+  }
+
+  /// Index of the feature within the device's ScalarCmd attributes.
+  pub fn index(&self) -> u32 {
+    self.index
+  }
+
+  pub fn actuator_type(&self) -> ActuatorType {
+    *self.attributes.actuator_type()
+  }
+
+  pub fn step_count(&self) -> u32 {
+    *self.attributes.step_count()
+  }
+
+  pub fn feature_descriptor(&self) -> &'a str {
+    self.attributes.feature_descriptor()
+  }
+
+  pub fn attributes(&self) -> &'a ClientGenericDeviceMessageAttributes {
+    self.attributes
+  }
+
+  fn scalar_command(&self, level: f64) -> ScalarCommand {
+    ScalarCommand::ScalarMap(HashMap::from([(self.index, (level, self.actuator_type()))]))
+  }
+
+  /// Sets the feature to `level`, which must be in the range [0.0, 1.0].
+  pub fn scalar(&self, level: f64) -> ButtplugClientResultFuture {
+    if let Err(err) = check_unit_range("Level", level, self.index) {
+      return create_boxed_future_client_error(err.into());
+    }
+    self.device.scalar(&self.scalar_command(level))
+  }
+
+  /// Ramps the feature to `level` over `duration`. See
+  /// [ButtplugClientDevice::ramp_scalar] for how ramps behave.
+  pub fn ramp(
+    &self,
+    level: f64,
+    duration: Duration,
+    easing: RampEasing,
+  ) -> ButtplugClientResultFuture {
+    self
+      .device
+      .ramp_scalar(&self.scalar_command(level), duration, easing)
+  }
+}
+
+/// A single RotateCmd feature of a device.
+#[derive(Clone, Copy, Debug)]
+pub struct RotateActuator<'a> {
+  device: &'a ButtplugClientDevice,
+  index: u32,
+  attributes: &'a ClientGenericDeviceMessageAttributes,
+}
+
+impl<'a> RotateActuator<'a> {
+  pub(super) fn new(
+    device: &'a ButtplugClientDevice,
+    index: u32,
+    attributes: &'a ClientGenericDeviceMessageAttributes,
+  ) -> Self {
+    Self {
+      device,
+      index,
+      attributes,
+    }
+  }
+
+  /// Index of the feature within the device's RotateCmd attributes.
+  pub fn index(&self) -> u32 {
+    self.index
+  }
+
+  pub fn step_count(&self) -> u32 {
+    *self.attributes.step_count()
+  }
+
+  pub fn feature_descriptor(&self) -> &'a str {
+    self.attributes.feature_descriptor()
+  }
+
+  pub fn attributes(&self) -> &'a ClientGenericDeviceMessageAttributes {
+    self.attributes
+  }
+
+  /// Rotates the feature at `speed`, which must be in the range [0.0, 1.0].
+  pub fn rotate(&self, speed: f64, clockwise: bool) -> ButtplugClientResultFuture {
+    if let Err(err) = check_unit_range("Speed", speed, self.index) {
+      return create_boxed_future_client_error(err.into());
+    }
+    self
+      .device
+      .rotate(&RotateCommand::RotateMap(HashMap::from([(
+        self.index,
+        (speed, clockwise),
+      )])))
+  }
+}
+
+/// A single LinearCmd feature of a device.
+#[derive(Clone, Copy, Debug)]
+pub struct LinearActuator<'a> {
+  device: &'a ButtplugClientDevice,
+  index: u32,
+  attributes: &'a ClientGenericDeviceMessageAttributes,
+}
+
+impl<'a> LinearActuator<'a> {
+  pub(super) fn new(
+    device: &'a ButtplugClientDevice,
+    index: u32,
+    attributes: &'a ClientGenericDeviceMessageAttributes,
+  ) -> Self {
+    Self {
+      device,
+      index,
+      attributes,
+    }
+  }
+
+  /// Index of the feature within the device's LinearCmd attributes.
+  pub fn index(&self) -> u32 {
+    self.index
+  }
+
+  pub fn step_count(&self) -> u32 {
+    *self.attributes.step_count()
+  }
+
+  pub fn feature_descriptor(&self) -> &'a str {
+    self.attributes.feature_descriptor()
+  }
+
+  pub fn attributes(&self) -> &'a ClientGenericDeviceMessageAttributes {
+    self.attributes
+  }
+
+  /// Moves the feature to `position`, which must be in the range [0.0, 1.0], over `duration`
+  /// milliseconds.
+  pub fn linear(&self, duration: u32, position: f64) -> ButtplugClientResultFuture {
+    if let Err(err) = check_unit_range("Position", position, self.index) {
+      return create_boxed_future_client_error(err.into());
+    }
+    self
+      .device
+      .linear(&LinearCommand::LinearMap(HashMap::from([(
+        self.index,
+        (duration, position),
+      )])))
+  }
+}
+
+/// Which sensor message a [DeviceSensor] is used with. Readable and subscribable sensors are
+/// indexed separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensorAccess {
+  Read,
+  Subscribe,
+}
+
+/// A single sensor of a device.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceSensor<'a> {
+  device: &'a ButtplugClientDevice,
+  index: u32,
+  access: SensorAccess,
+  attributes: &'a SensorDeviceMessageAttributes,
+}
+
+impl<'a> DeviceSensor<'a> {
+  pub(super) fn new(
+    device: &'a ButtplugClientDevice,
+    index: u32,
+    access: SensorAccess,
+    attributes: &'a SensorDeviceMessageAttributes,
+  ) -> Self {
+    Self {
+      device,
+      index,
+      access,
+      attributes,
+    }
+  }
+
+  /// Index of the sensor within the device's SensorReadCmd or SensorSubscribeCmd attributes,
+  /// depending on its [access][DeviceSensor::access].
+  pub fn index(&self) -> u32 {
+    self.index
+  }
+
+  pub fn access(&self) -> SensorAccess {
+    self.access
+  }
+
+  pub fn sensor_type(&self) -> SensorType {
+    *self.attributes.sensor_type()
+  }
+
+  pub fn sensor_range(&self) -> &'a Vec<RangeInclusive<u32>> {
+    self.attributes.sensor_range()
+  }
+
+  pub fn feature_descriptor(&self) -> &'a str {
+    self.attributes.feature_descriptor()
+  }
+
+  pub fn attributes(&self) -> &'a SensorDeviceMessageAttributes {
+    self.attributes
+  }
+
+  fn check_access(
+    &self,
+    access: SensorAccess,
+    message_type: ButtplugDeviceMessageType,
+  ) -> Result<(), ButtplugDeviceError> {
+    if self.access == access {
+      Ok(())
+    } else {
+      Err(ButtplugDeviceError::MessageNotSupported(message_type))
+    }
+  }
+
+  /// Reads the sensor, returning its raw values. Only valid for [SensorAccess::Read] sensors.
+  pub fn read(&self) -> ButtplugClientResultFuture<Vec<i32>> {
+    if let Err(err) =
+      self.check_access(SensorAccess::Read, ButtplugDeviceMessageType::SensorReadCmd)
+    {
+      return create_boxed_future_client_error(err.into());
+    }
+    self.device.read_sensor(self.index, self.sensor_type())
+  }
+
+  /// Subscribes to the sensor. Readings show up as
+  /// [Message][super::ButtplugClientDeviceEvent::Message] device events. Only valid for
+  /// [SensorAccess::Subscribe] sensors.
+  pub fn subscribe(&self) -> ButtplugClientResultFuture {
+    if let Err(err) = self.check_access(
+      SensorAccess::Subscribe,
+      ButtplugDeviceMessageType::SensorSubscribeCmd,
+    ) {
+      return create_boxed_future_client_error(err.into());
+    }
+    self.device.subscribe_sensor(self.index, self.sensor_type())
+  }
+
+  /// Unsubscribes from the sensor. Only valid for [SensorAccess::Subscribe] sensors.
+  pub fn unsubscribe(&self) -> ButtplugClientResultFuture {
+    if let Err(err) = self.check_access(
+      SensorAccess::Subscribe,
+      ButtplugDeviceMessageType::SensorUnsubscribeCmd,
+    ) {
+      return create_boxed_future_client_error(err.into());
+    }
+    self
+      .device
+      .unsubscribe_sensor(self.index, self.sensor_type())
+  }
+}
@@ -0,0 +1,301 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Groups of devices that can be commanded together.
+//!
+//! A [ButtplugClientDeviceGroup] is created via [ButtplugClient::device_group][super::ButtplugClient::device_group],
+//! with a [DeviceGroupMembership] deciding which devices belong to it. Groups follow the client's
+//! device events, so devices join as they are added to the server and leave when they are removed.
+//!
+//! Commands sent to a group go to every member concurrently. A failure on one member doesn't stop
+//! the others, and all failures are reported together in a [DeviceGroupError].
+
+use super::{
+  ButtplugClientDevice,
+  ButtplugClientError,
+  ButtplugClientEvent,
+  ButtplugClientResultFuture,
+  LinearCommand,
+  RotateCommand,
+  ScalarCommand,
+  ScalarValueCommand,
+};
+use crate::{core::message::ActuatorType, util::async_manager};
+use dashmap::DashMap;
+use futures::{future::BoxFuture, select, FutureExt, Stream, StreamExt};
+use getset::Getters;
+use std::{collections::HashSet, fmt, sync::Arc};
+use thiserror::Error;
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// Decides which devices belong to a [ButtplugClientDeviceGroup].
+#[derive(Clone)]
+pub enum DeviceGroupMembership {
+  /// Devices with the given server indexes. Devices that reconnect with the same index rejoin the
+  /// group.
+  Devices(HashSet<u32>),
+  /// Devices with at least one feature of the given actuator type, either as a ScalarCmd feature,
+  /// or as a RotateCmd feature for [ActuatorType::Rotate].
+  ActuatorType(ActuatorType),
+  /// Devices whose name or display name matches exactly.
+  Name(String),
+  /// Devices for which the predicate returns true.
+  Predicate(Arc<dyn Fn(&ButtplugClientDevice) -> bool + Send + Sync>),
+}
+
+impl DeviceGroupMembership {
+  /// Membership for an explicit set of devices.
+  pub fn devices(devices: &[Arc<ButtplugClientDevice>]) -> Self {
+    Self::Devices(devices.iter().map(|device| device.index()).collect())
+  }
+
+  /// Membership decided by a predicate.
+  pub fn predicate<F>(predicate: F) -> Self
+  where
+    F: Fn(&ButtplugClientDevice) -> bool + Send + Sync + 'static,
+  {
+    Self::Predicate(Arc::new(predicate))
+  }
+
+  pub fn matches(&self, device: &ButtplugClientDevice) -> bool {
+    match self {
+      Self::Devices(indexes) => indexes.contains(&device.index()),
+      Self::ActuatorType(actuator_type) => {
+        device
+          .scalar_attributes()
+          .iter()
+          .any(|attrs| attrs.actuator_type() == actuator_type)
+          || (*actuator_type == ActuatorType::Rotate && !device.rotate_attributes().is_empty())
+      }
+      Self::Name(name) => device.name() == name || device.display_name().as_ref() == Some(name),
+      Self::Predicate(predicate) => predicate(device),
+    }
+  }
+}
+
+impl fmt::Debug for DeviceGroupMembership {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Devices(indexes) => f.debug_tuple("Devices").field(indexes).finish(),
+      Self::ActuatorType(actuator_type) => {
+        f.debug_tuple("ActuatorType").field(actuator_type).finish()
+      }
+      Self::Name(name) => f.debug_tuple("Name").field(name).finish(),
+      Self::Predicate(_) => f.write_str("Predicate"),
+    }
+  }
+}
+
+/// Returned when a group command fails on any of the group's members.
+#[derive(Debug, Error, Getters)]
+#[error("Group command failed on {} of {member_count} devices", failures.len())]
+pub struct DeviceGroupError {
+  /// Number of devices the command was sent to.
+  #[getset(get = "pub")]
+  member_count: usize,
+  /// The devices the command failed on, along with their errors.
+  #[getset(get = "pub")]
+  failures: Vec<(Arc<ButtplugClientDevice>, ButtplugClientError)>,
+}
+
+pub type DeviceGroupResultFuture = BoxFuture<'static, Result<(), DeviceGroupError>>;
+
+/// A set of devices that can be commanded as one.
+///
+/// Membership is kept up to date until the group is dropped.
+pub struct ButtplugClientDeviceGroup {
+  membership: Arc<DeviceGroupMembership>,
+  members: Arc<DashMap<u32, Arc<ButtplugClientDevice>>>,
+  _event_task_guard: DropGuard,
+}
+
+impl ButtplugClientDeviceGroup {
+  /// Creates the group from the client's current devices, then follows `event_stream` for changes.
+  /// The stream needs to be subscribed before `devices` is read, so no device events are missed.
+  pub(super) fn new(
+    membership: DeviceGroupMembership,
+    devices: Vec<Arc<ButtplugClientDevice>>,
+    event_stream: impl Stream<Item = ButtplugClientEvent> + Send + Unpin + 'static,
+  ) -> Self {
+    let membership = Arc::new(membership);
+    let members = Arc::new(DashMap::new());
+    for device in devices {
+      if membership.matches(&device) {
+        members.insert(device.index(), device);
+      }
+    }
+    let token = CancellationToken::new();
+    async_manager::spawn(Self::follow_events(
+      membership.clone(),
+      members.clone(),
+      event_stream,
+      token.child_token(),
+    ));
+    Self {
+      membership,
+      members,
+      _event_task_guard: token.drop_guard(),
+    }
+  }
+
+  async fn follow_events(
+    membership: Arc<DeviceGroupMembership>,
+    members: Arc<DashMap<u32, Arc<ButtplugClientDevice>>>,
+    mut event_stream: impl Stream<Item = ButtplugClientEvent> + Send + Unpin,
+    token: CancellationToken,
+  ) {
+    loop {
+      let event = select! {
+        _ = token.cancelled().fuse() => return,
+        event = event_stream.next().fuse() => event,
+      };
+      match event {
+        Some(ButtplugClientEvent::DeviceAdded(device)) => {
+          if membership.matches(&device) {
+            members.insert(device.index(), device);
+          }
+        }
+        Some(ButtplugClientEvent::DeviceRemoved(device)) => {
+          members.remove(&device.index());
+        }
+        Some(ButtplugClientEvent::ServerDisconnect) => members.clear(),
+        Some(_) => {}
+        None => return,
+      }
+    }
+  }
+
+  pub fn membership(&self) -> &DeviceGroupMembership {
+    &self.membership
+  }
+
+  /// Current members of the group, ordered by device index.
+  pub fn devices(&self) -> Vec<Arc<ButtplugClientDevice>> {
+    let mut devices: Vec<Arc<ButtplugClientDevice>> = self
+      .members
+      .iter()
+      .map(|entry| entry.value().clone())
+      .collect();
+    devices.sort_by_key(|device| device.index());
+    devices
+  }
+
+  pub fn contains(&self, device: &ButtplugClientDevice) -> bool {
+    self.members.contains_key(&device.index())
+  }
+
+  pub fn len(&self) -> usize {
+    self.members.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.members.is_empty()
+  }
+
+  /// Runs `command` on every member, skipping members it returns None for.
+  fn command_each<F>(&self, command: F) -> DeviceGroupResultFuture
+  where
+    F: Fn(&ButtplugClientDevice) -> Option<ButtplugClientResultFuture>,
+  {
+    let commands: Vec<(Arc<ButtplugClientDevice>, ButtplugClientResultFuture)> = self
+      .devices()
+      .into_iter()
+      .filter_map(|device| command(&device).map(|fut| (device, fut)))
+      .collect();
+    let member_count = commands.len();
+    async move {
+      let results = futures::future::join_all(
+        commands
+          .into_iter()
+          .map(|(device, fut)| async move { (device, fut.await) }),
+      )
+      .await;
+      let failures: Vec<(Arc<ButtplugClientDevice>, ButtplugClientError)> = results
+        .into_iter()
+        .filter_map(|(device, result)| result.err().map(|err| (device, err)))
+        .collect();
+      if failures.is_empty() {
+        Ok(())
+      } else {
+        Err(DeviceGroupError {
+          member_count,
+          failures,
+        })
+      }
+    }
+    .boxed()
+  }
+
+  /// Sends the same vibrate command to every member with vibrators.
+  pub fn vibrate(&self, speed_cmd: &ScalarValueCommand) -> DeviceGroupResultFuture {
+    self.command_each(|device| {
+      (!device.vibrate_attributes().is_empty()).then(|| device.vibrate(speed_cmd))
+    })
+  }
+
+  /// Sends the same oscillate command to every member with oscillators.
+  pub fn oscillate(&self, speed_cmd: &ScalarValueCommand) -> DeviceGroupResultFuture {
+    self.command_each(|device| {
+      (!device.oscillate_attributes().is_empty()).then(|| device.oscillate(speed_cmd))
+    })
+  }
+
+  /// Sends a ScalarCmd to every member, built per member by `mapping`. Members `mapping` returns
+  /// None for are skipped.
+  pub fn scalar_with<F>(&self, mapping: F) -> DeviceGroupResultFuture
+  where
+    F: Fn(&ButtplugClientDevice) -> Option<ScalarCommand>,
+  {
+    self.command_each(|device| mapping(device).map(|cmd| device.scalar(&cmd)))
+  }
+
+  /// Sends the same rotate command to every member with rotators.
+  pub fn rotate(&self, rotate_cmd: &RotateCommand) -> DeviceGroupResultFuture {
+    self.command_each(|device| {
+      (!device.rotate_attributes().is_empty()).then(|| device.rotate(rotate_cmd))
+    })
+  }
+
+  /// Sends a RotateCmd to every member, built per member by `mapping`. Members `mapping` returns
+  /// None for are skipped.
+  pub fn rotate_with<F>(&self, mapping: F) -> DeviceGroupResultFuture
+  where
+    F: Fn(&ButtplugClientDevice) -> Option<RotateCommand>,
+  {
+    self.command_each(|device| mapping(device).map(|cmd| device.rotate(&cmd)))
+  }
+
+  /// Sends the same linear command to every member with linear actuators.
+  pub fn linear(&self, linear_cmd: &LinearCommand) -> DeviceGroupResultFuture {
+    self.command_each(|device| {
+      (!device.linear_attributes().is_empty()).then(|| device.linear(linear_cmd))
+    })
+  }
+
+  /// Sends a LinearCmd to every member, built per member by `mapping`. Members `mapping` returns
+  /// None for are skipped.
+  pub fn linear_with<F>(&self, mapping: F) -> DeviceGroupResultFuture
+  where
+    F: Fn(&ButtplugClientDevice) -> Option<LinearCommand>,
+  {
+    self.command_each(|device| mapping(device).map(|cmd| device.linear(&cmd)))
+  }
+
+  /// Stops every member.
+  pub fn stop(&self) -> DeviceGroupResultFuture {
+    self.command_each(|device| Some(device.stop()))
+  }
+}
+
+impl fmt::Debug for ButtplugClientDeviceGroup {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ButtplugClientDeviceGroup")
+      .field("membership", &self.membership)
+      .field("devices", &self.devices())
+      .finish()
+  }
+}
@@ -6,9 +6,23 @@
 // for full license information.
 
 //! Communications API for accessing Buttplug Servers
+#[cfg(feature = "audio-driver")]
+pub mod audio_driver;
+#[cfg(feature = "blocking-client")]
+pub mod blocking;
+#[cfg(any(feature = "osc-bridge", feature = "mqtt-bridge"))]
+mod bridge;
 pub mod client_event_loop;
 pub mod client_message_sorter;
 pub mod device;
+pub mod device_state;
+pub mod feature;
+pub mod group;
+#[cfg(feature = "mqtt-bridge")]
+pub mod mqtt_bridge;
+#[cfg(feature = "osc-bridge")]
+pub mod osc_bridge;
+pub mod ramp;
 
 use crate::{
   core::{
@@ -17,12 +31,15 @@ use crate::{
     message::{
       ButtplugCurrentSpecClientMessage,
       ButtplugCurrentSpecServerMessage,
+      ButtplugMessageSpecVersion,
       Ping,
       RequestDeviceList,
       RequestServerInfo,
+      RequestUnsupportedDeviceReports,
       StartScanning,
       StopAllDevices,
       StopScanning,
+      UnsupportedDeviceFound,
       BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     },
   },
@@ -42,13 +59,21 @@ pub use device::{
   ScalarCommand,
   ScalarValueCommand,
 };
+pub use device_state::ClientDeviceState;
+pub use feature::{DeviceSensor, LinearActuator, RotateActuator, ScalarActuator, SensorAccess};
 use futures::{
   future::{self, BoxFuture, FutureExt},
   Stream,
 };
-use std::sync::{
-  atomic::{AtomicBool, Ordering},
-  Arc,
+pub use group::{ButtplugClientDeviceGroup, DeviceGroupError, DeviceGroupMembership};
+pub use ramp::RampEasing;
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+    RwLock,
+  },
+  time::Duration,
 };
 use thiserror::Error;
 use tokio::sync::{broadcast, mpsc, Mutex};
@@ -88,6 +113,9 @@ pub(crate) type ButtplugServerMessageFuture = ButtplugFuture<ButtplugServerMessa
 pub struct ButtplugClientMessageFuturePair {
   msg: ButtplugCurrentSpecClientMessage,
   waker: ButtplugServerMessageStateShared,
+  /// How long to wait for the server's reply before resolving with
+  /// [ButtplugClientError::RequestTimeout]. None waits forever.
+  timeout: Option<Duration>,
 }
 
 impl ButtplugClientMessageFuturePair {
@@ -95,7 +123,23 @@ impl ButtplugClientMessageFuturePair {
     msg: ButtplugCurrentSpecClientMessage,
     waker: ButtplugServerMessageStateShared,
   ) -> Self {
-    Self { msg, waker }
+    Self {
+      msg,
+      waker,
+      timeout: None,
+    }
+  }
+
+  pub fn new_with_timeout(
+    msg: ButtplugCurrentSpecClientMessage,
+    waker: ButtplugServerMessageStateShared,
+    timeout: Option<Duration>,
+  ) -> Self {
+    Self {
+      msg,
+      waker,
+      timeout,
+    }
   }
 }
 
@@ -114,6 +158,9 @@ pub enum ButtplugClientError {
   /// Protocol error
   #[error(transparent)]
   ButtplugError(#[from] ButtplugError),
+  /// The server didn't reply to a request within the request timeout
+  #[error("Request timed out after {0:?} without a reply from the server")]
+  RequestTimeout(Duration),
 }
 
 /// Enum representing different events that can be emitted by a client.
@@ -132,6 +179,10 @@ pub enum ButtplugClientEvent {
   /// Emitted when a device has been removed from the server. Includes a
   /// [ButtplugClientDevice] object representing the device.
   DeviceRemoved(Arc<ButtplugClientDevice>),
+  /// Emitted when the server finds hardware it will not connect to, either because no protocol
+  /// supports it or because it is denied by configuration. Only sent after reporting has been
+  /// enabled via [ButtplugClient::report_unsupported_devices].
+  UnsupportedDeviceFound(UnsupportedDeviceFound),
   /// Emitted when a client has not pinged the server in a sufficient amount of
   /// time.
   PingTimeout,
@@ -156,9 +207,42 @@ where
   future::ready(Err(ButtplugClientError::ButtplugError(err))).boxed()
 }
 
+/// Picks the message spec version to retry the handshake with after the server rejected `version`,
+/// or None if the error wasn't caused by the version or there is nothing older to try.
+fn fallback_message_version(
+  version: ButtplugMessageSpecVersion,
+  error: &ButtplugClientError,
+) -> Option<ButtplugMessageSpecVersion> {
+  let older_version = match version {
+    ButtplugMessageSpecVersion::Version0 => return None,
+    ButtplugMessageSpecVersion::Version1 => ButtplugMessageSpecVersion::Version0,
+    ButtplugMessageSpecVersion::Version2 => ButtplugMessageSpecVersion::Version1,
+    ButtplugMessageSpecVersion::Version3 => ButtplugMessageSpecVersion::Version2,
+  };
+  match error {
+    // Servers that know about newer versions tell us which one they support.
+    ButtplugClientError::ButtplugError(ButtplugError::ButtplugHandshakeError(
+      ButtplugHandshakeError::MessageSpecVersionMismatch(server_version, _),
+    )) => Some(*server_version).filter(|server_version| *server_version < version),
+    // Older servers may not be able to read the request at all, or reject it without a reason we
+    // can parse, so just try the next version down.
+    ButtplugClientError::ButtplugError(ButtplugError::ButtplugMessageError(_))
+    | ButtplugClientError::ButtplugError(ButtplugError::ButtplugHandshakeError(
+      ButtplugHandshakeError::UntypedDeserializedError(_),
+    )) => Some(older_version),
+    _ => None,
+  }
+}
+
 pub(super) struct ButtplugClientMessageSender {
   message_sender: broadcast::Sender<ButtplugClientRequest>,
   connected: Arc<AtomicBool>,
+  /// Client-wide request timeout, shared by every sender created from the same client.
+  default_request_timeout: Arc<RwLock<Option<Duration>>>,
+  /// Timeout used instead of the client-wide one for requests sent through this sender.
+  request_timeout_override: Option<Duration>,
+  /// Spec version negotiated with the server, shared by every sender created from the same client.
+  message_version: Arc<RwLock<ButtplugMessageSpecVersion>>,
 }
 
 impl ButtplugClientMessageSender {
@@ -169,9 +253,54 @@ impl ButtplugClientMessageSender {
     Self {
       message_sender: message_sender.clone(),
       connected: connected.clone(),
+      default_request_timeout: Arc::new(RwLock::new(None)),
+      request_timeout_override: None,
+      message_version: Arc::new(RwLock::new(BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)),
+    }
+  }
+
+  /// Creates a sender for the same client that uses `timeout` for its requests, regardless of the
+  /// client-wide timeout.
+  pub fn with_request_timeout(&self, timeout: Duration) -> Self {
+    Self {
+      message_sender: self.message_sender.clone(),
+      connected: self.connected.clone(),
+      default_request_timeout: self.default_request_timeout.clone(),
+      request_timeout_override: Some(timeout),
+      message_version: self.message_version.clone(),
     }
   }
 
+  pub fn request_timeout(&self) -> Option<Duration> {
+    self.request_timeout_override.or_else(|| {
+      *self
+        .default_request_timeout
+        .read()
+        .expect("Only locked for single reads and writes, should never be poisoned.")
+    })
+  }
+
+  pub fn set_default_request_timeout(&self, timeout: Option<Duration>) {
+    *self
+      .default_request_timeout
+      .write()
+      .expect("Only locked for single reads and writes, should never be poisoned.") = timeout;
+  }
+
+  pub fn message_version(&self) -> ButtplugMessageSpecVersion {
+    *self
+      .message_version
+      .read()
+      .expect("Only locked for single reads and writes, should never be poisoned.")
+  }
+
+  pub fn set_message_version(&self, version: ButtplugMessageSpecVersion) {
+    *self
+      .message_version
+      .write()
+      .expect("Only locked for single reads and writes, should never be poisoned.") = version;
+  }
+
   /// Send message to the internal event loop.
   ///
   /// Mostly for handling boilerplate around possible send errors.
@@ -216,12 +345,19 @@ impl ButtplugClientMessageSender {
     &self,
     msg: ButtplugCurrentSpecClientMessage,
   ) -> ButtplugServerMessageResultFuture {
+    // Servers on older spec versions can't receive messages that have no equivalent in their spec,
+    // so fail those here instead of sending something the server won't understand.
+    if let Err(e) = msg.clone().downgrade(self.message_version()) {
+      return create_boxed_future_client_error(e.into());
+    }
     // Create a future to pair with the message being resolved.
     let fut = ButtplugServerMessageFuture::default();
-    let internal_msg = ButtplugClientRequest::Message(ButtplugClientMessageFuturePair::new(
-      msg,
-      fut.get_state_clone(),
-    ));
+    let internal_msg =
+      ButtplugClientRequest::Message(ButtplugClientMessageFuturePair::new_with_timeout(
+        msg,
+        fut.get_state_clone(),
+        self.request_timeout(),
+      ));
 
     // Send message to internal loop and wait for return.
     let send_fut = self.send_message_to_event_loop(internal_msg);
@@ -241,6 +377,34 @@ impl ButtplugClientMessageSender {
     let send_fut = self.send_message(msg);
     async move { send_fut.await.map(|_| ()) }.boxed()
   }
+
+  /// Queues a message to the internal event loop, returning a future that resolves once the server
+  /// replies with [Ok].
+  ///
+  /// Unlike [send_message_expect_ok][Self::send_message_expect_ok], the message is queued by the
+  /// time this returns, so callers can keep messages in order without waiting for replies.
+  pub(super) fn queue_message_expect_ok(
+    &self,
+    msg: ButtplugCurrentSpecClientMessage,
+  ) -> ButtplugClientResultFuture {
+    if !self.connected.load(Ordering::Relaxed) {
+      return future::ready(Err(ButtplugConnectorError::ConnectorNotConnected.into())).boxed();
+    }
+    if let Err(e) = msg.clone().downgrade(self.message_version()) {
+      return create_boxed_future_client_error(e.into());
+    }
+    let fut = ButtplugServerMessageFuture::default();
+    let internal_msg =
+      ButtplugClientRequest::Message(ButtplugClientMessageFuturePair::new_with_timeout(
+        msg,
+        fut.get_state_clone(),
+        self.request_timeout(),
+      ));
+    if self.message_sender.send(internal_msg).is_err() {
+      return future::ready(Err(ButtplugConnectorError::ConnectorChannelClosed.into())).boxed();
+    }
+    async move { fut.await.map(|_| ()) }.boxed()
+  }
 }
 
 /// Struct used by applications to communicate with a Buttplug Server.
@@ -263,6 +427,8 @@ pub struct ButtplugClient {
   /// The client name. Depending on the connection type and server being used,
   /// this name is sometimes shown on the server logs or GUI.
   client_name: String,
+  /// Token sent during the handshake, for servers that require authentication.
+  auth_token: Option<String>,
   /// The server name that we're current connected to.
   server_name: Arc<Mutex<Option<String>>>,
   event_stream: broadcast::Sender<ButtplugClientEvent>,
@@ -279,6 +445,7 @@ impl ButtplugClient {
     let connected = Arc::new(AtomicBool::new(false));
     Self {
       client_name: name.to_owned(),
+      auth_token: None,
       server_name: Arc::new(Mutex::new(None)),
       event_stream,
       message_sender: Arc::new(ButtplugClientMessageSender::new(
@@ -290,6 +457,17 @@ impl ButtplugClient {
     }
   }
 
+  /// Creates a client that presents `auth_token` to the server during the handshake.
+  ///
+  /// Use this when connecting to servers that require authentication. Servers that don't will
+  /// ignore the token.
+  pub fn new_with_auth_token(name: &str, auth_token: &str) -> Self {
+    Self {
+      auth_token: Some(auth_token.to_owned()),
+      ..Self::new(name)
+    }
+  }
+
   pub async fn connect<ConnectorType>(
     &self,
     mut connector: ConnectorType,
@@ -339,19 +517,47 @@ impl ButtplugClient {
   /// the struct, then tries to run connect and execute the Buttplug protocol
   /// handshake. Will return a connected and ready to use ButtplugClient is all
   /// goes well.
+  ///
+  /// Starts at the current message spec version, and steps down through older versions while the
+  /// server rejects the ones asked for, so the highest version both sides support is used.
   async fn run_handshake(&self) -> ButtplugClientResult {
     // Run our handshake
     info!("Running handshake with server.");
-    let msg = self
-      .message_sender
-      .send_message_ignore_connect_status(
-        RequestServerInfo::new(&self.client_name, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
-      )
-      .await?;
+    let mut message_version = BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION;
+    let msg = loop {
+      let request_server_info = match &self.auth_token {
+        Some(auth_token) => {
+          RequestServerInfo::new_with_auth_token(&self.client_name, message_version, auth_token)
+        }
+        None => RequestServerInfo::new(&self.client_name, message_version),
+      };
+      self.message_sender.set_message_version(message_version);
+      match self
+        .message_sender
+        .send_message_ignore_connect_status(request_server_info.into())
+        .await
+      {
+        Ok(msg) => break msg,
+        Err(e) => match fallback_message_version(message_version, &e) {
+          Some(older_version) => {
+            info!(
+              "Server rejected message spec version {}, retrying with {}: {}",
+              message_version, older_version, e
+            );
+            message_version = older_version;
+          }
+          None => return Err(e),
+        },
+      }
+    };
 
     debug!("Got ServerInfo return.");
     if let ButtplugCurrentSpecServerMessage::ServerInfo(server_info) = msg {
-      info!("Connected to {}", server_info.server_name());
+      info!(
+        "Connected to {} using message spec version {}",
+        server_info.server_name(),
+        message_version
+      );
       *self.server_name.lock().await = Some(server_info.server_name().clone());
       // Don't set ourselves as connected until after ServerInfo has been
       // received. This means we avoid possible races with the RequestServerInfo
@@ -428,14 +634,40 @@ impl ButtplugClient {
       .send_message_expect_ok(StopScanning::default().into())
   }
 
+  /// Tells server whether to report hardware it finds but will not connect to.
+  ///
+  /// When enabled, the server will emit [ButtplugClientEvent::UnsupportedDeviceFound] events
+  /// during scanning. Useful for diagnosing why a device is not showing up, or for building user
+  /// configurations for it.
+  ///
+  /// Returns Err([ButtplugClientError]) if request fails due to issues with
+  /// DeviceManagers on the server, disconnection, etc.
+  pub fn report_unsupported_devices(&self, enabled: bool) -> ButtplugClientResultFuture {
+    self
+      .message_sender
+      .send_message_expect_ok(RequestUnsupportedDeviceReports::new(enabled).into())
+  }
+
   /// Tells server to stop all devices.
   ///
   /// Returns Err([ButtplugClientError]) if request fails due to issues with
   /// DeviceManagers on the server, disconnection, etc.
   pub fn stop_all_devices(&self) -> ButtplugClientResultFuture {
-    self
+    let devices = self.devices();
+    for device in &devices {
+      device.cancel_ramp();
+    }
+    let send_fut = self
       .message_sender
-      .send_message_expect_ok(StopAllDevices::default().into())
+      .send_message_expect_ok(StopAllDevices::default().into());
+    async move {
+      send_fut.await?;
+      for device in devices {
+        device.clear_actuator_state();
+      }
+      Ok(())
+    }
+    .boxed()
   }
 
   pub fn event_stream(&self) -> impl Stream<Item = ButtplugClientEvent> {
@@ -457,6 +689,28 @@ impl ButtplugClient {
       .collect()
   }
 
+  /// Creates a group of the devices matching `membership`. The group keeps following devices as
+  /// they are added and removed, until it is dropped.
+  pub fn device_group(&self, membership: DeviceGroupMembership) -> ButtplugClientDeviceGroup {
+    // Subscribe before taking the device list, so devices added in between aren't missed.
+    let event_stream = Box::pin(self.event_stream());
+    ButtplugClientDeviceGroup::new(membership, self.devices(), event_stream)
+  }
+
+  /// Sets how long requests wait for a reply from the server before failing with
+  /// [ButtplugClientError::RequestTimeout]. None, the default, waits forever.
+  ///
+  /// Applies to requests sent after the call, from the client and all of its devices. Devices
+  /// created via [ButtplugClientDevice::with_request_timeout] keep their own timeout.
+  pub fn set_request_timeout(&self, timeout: Option<Duration>) {
+    self.message_sender.set_default_request_timeout(timeout);
+  }
+
+  /// Current client-wide request timeout. See [ButtplugClient::set_request_timeout].
+  pub fn request_timeout(&self) -> Option<Duration> {
+    self.message_sender.request_timeout()
+  }
+
   pub fn ping(&self) -> ButtplugClientResultFuture {
     let ping_fut = self
       .message_sender
@@ -464,6 +718,20 @@ impl ButtplugClient {
     async move { ping_fut.await }.boxed()
   }
 
+  /// Message spec version negotiated with the server, if connected.
+  ///
+  /// This is older than [BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION] when connected to an older server.
+  /// Device attributes then only describe what that version can express (e.g. vibrators as the only
+  /// scalar features, and no step counts before v2), and commands that have no equivalent in it
+  /// fail with [ButtplugMessageError::VersionError](crate::core::errors::ButtplugMessageError).
+  pub fn server_message_version(&self) -> Option<ButtplugMessageSpecVersion> {
+    if self.connected() {
+      Some(self.message_sender.message_version())
+    } else {
+      None
+    }
+  }
+
   pub fn server_name(&self) -> Option<String> {
     // We'd have to be calling server_name in an extremely tight, asynchronous
     // loop for this to return None, so we'll treat this as lockless.
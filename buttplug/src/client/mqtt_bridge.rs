@@ -0,0 +1,658 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! MQTT bridge for a [ButtplugClient], for home automation systems.
+//!
+//! The bridge connects to an MQTT broker and exposes the client's devices as topics under a base
+//! topic (`buttplug` by default). Devices are identified by their device index.
+//!
+//! | Topic                                      | Direction | Payload                                  |
+//! |--------------------------------------------|-----------|------------------------------------------|
+//! | `<base>/status`                            | Published | `online`/`offline`, retained, last will  |
+//! | `<base>/<index>/available`                 | Published | `online`/`offline`, retained             |
+//! | `<base>/<index>/info`                      | Published | JSON name and message attributes, retained |
+//! | `<base>/<index>/battery`                   | Published | Battery level from 0.0 to 1.0, retained  |
+//! | `<base>/<index>/sensor/<sensor>/<type>`    | Published | JSON array of sensor reading data        |
+//! | `<base>/<index>/<command>/set`             | Received  | Command value                            |
+//! | `<base>/<index>/<command>/<feature>/set`   | Received  | Command value                            |
+//! | `<base>/<index>/stop/set`                  | Received  | Ignored                                  |
+//! | `<base>/stop/set`                          | Received  | Ignored                                  |
+//!
+//! `<command>` is the lowercase name of a scalar actuator type (`vibrate`, `oscillate`, `constrict`,
+//! `inflate`, `position`), `rotate` or `linear`. Values are numbers, or `on`/`off`, and are clamped
+//! to their valid ranges:
+//!
+//! - Scalar commands take a value from 0.0 to 1.0.
+//! - `rotate` takes a value from -1.0 to 1.0 for devices that support RotateCmd, with the sign
+//!   giving the direction (positive is clockwise). Devices with scalar rotation actuators take a
+//!   value from 0.0 to 1.0.
+//! - `linear` takes a position from 0.0 to 1.0, moving over the duration set via
+//!   [ButtplugMqttBridgeBuilder::linear_duration].
+//!
+//! Sensor readings are published for sensors that have been subscribed to via
+//! [ButtplugClientDevice::subscribe_sensor]. Battery levels are only published if a poll interval
+//! is set.
+
+use super::{
+  bridge::{
+    feature_indexes,
+    spawn_battery_publisher,
+    spawn_device_sensor_publisher,
+    BridgePublisher,
+    DeviceCommandQueue,
+    QueuedDeviceCommand,
+  },
+  device::{ButtplugClientDevice, LinearCommand, RotateCommand, ScalarCommand},
+  ButtplugClient,
+  ButtplugClientError,
+  ButtplugClientEvent,
+};
+use crate::{
+  core::message::{ActuatorType, SensorReading},
+  util::{async_manager, sleep},
+};
+use async_trait::async_trait;
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+/// Error enum for the MQTT bridge.
+#[derive(Error, Debug)]
+pub enum ButtplugMqttBridgeError {
+  /// The initial connection to the broker failed.
+  #[error("Could not connect to MQTT broker: {0}")]
+  ConnectionError(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ButtplugMqttBridgeBuilder {
+  /// Host name or IP address of the broker.
+  broker_host: String,
+  /// Port of the broker.
+  broker_port: u16,
+  /// MQTT client ID for the bridge.
+  client_id: String,
+  /// Username and password for the broker, if required.
+  credentials: Option<(String, String)>,
+  /// Topic that all bridge topics are published under.
+  base_topic: String,
+  /// How often to read and publish device battery levels, if at all.
+  battery_poll_interval: Option<Duration>,
+  /// Duration of movements for linear commands, in milliseconds.
+  linear_duration: u32,
+}
+
+impl Default for ButtplugMqttBridgeBuilder {
+  fn default() -> Self {
+    Self {
+      broker_host: "127.0.0.1".to_owned(),
+      broker_port: 1883,
+      client_id: "buttplug-mqtt-bridge".to_owned(),
+      credentials: None,
+      base_topic: "buttplug".to_owned(),
+      battery_poll_interval: None,
+      linear_duration: 500,
+    }
+  }
+}
+
+impl ButtplugMqttBridgeBuilder {
+  pub fn broker_host(&mut self, broker_host: &str) -> &mut Self {
+    self.broker_host = broker_host.to_owned();
+    self
+  }
+
+  pub fn broker_port(&mut self, broker_port: u16) -> &mut Self {
+    self.broker_port = broker_port;
+    self
+  }
+
+  pub fn client_id(&mut self, client_id: &str) -> &mut Self {
+    self.client_id = client_id.to_owned();
+    self
+  }
+
+  pub fn credentials(&mut self, username: &str, password: &str) -> &mut Self {
+    self.credentials = Some((username.to_owned(), password.to_owned()));
+    self
+  }
+
+  pub fn base_topic(&mut self, base_topic: &str) -> &mut Self {
+    self.base_topic = base_topic.trim_end_matches('/').to_owned();
+    self
+  }
+
+  pub fn battery_poll_interval(&mut self, battery_poll_interval: Duration) -> &mut Self {
+    self.battery_poll_interval = Some(battery_poll_interval);
+    self
+  }
+
+  pub fn linear_duration(&mut self, linear_duration: u32) -> &mut Self {
+    self.linear_duration = linear_duration;
+    self
+  }
+
+  pub fn finish(&self, client: Arc<ButtplugClient>) -> ButtplugMqttBridge {
+    ButtplugMqttBridge {
+      client,
+      options: self.clone(),
+    }
+  }
+}
+
+/// Bridge between an MQTT broker and the devices of a connected [ButtplugClient].
+pub struct ButtplugMqttBridge {
+  client: Arc<ButtplugClient>,
+  options: ButtplugMqttBridgeBuilder,
+}
+
+impl ButtplugMqttBridge {
+  pub fn client(&self) -> &Arc<ButtplugClient> {
+    &self.client
+  }
+
+  /// Runs the bridge. Returns an error if the first connection to the broker fails. After that, the
+  /// bridge reconnects on its own if the connection is lost, and runs until the returned future is
+  /// dropped.
+  pub async fn run(&self) -> Result<(), ButtplugMqttBridgeError> {
+    let base = self.options.base_topic.clone();
+    let mut mqtt_options = MqttOptions::new(
+      &self.options.client_id,
+      &self.options.broker_host,
+      self.options.broker_port,
+    );
+    mqtt_options.set_keep_alive(Duration::from_secs(30));
+    mqtt_options.set_last_will(LastWill::new(
+      format!("{}/status", base),
+      "offline",
+      QoS::AtLeastOnce,
+      true,
+    ));
+    if let Some((username, password)) = &self.options.credentials {
+      mqtt_options.set_credentials(username, password);
+    }
+    let (mqtt_client, mut event_loop) = AsyncClient::new(mqtt_options, 64);
+    let publisher = MqttPublisher {
+      client: mqtt_client,
+      base: base.clone(),
+    };
+    let cancellation_token = CancellationToken::new();
+    let _cancel_guard = cancellation_token.clone().drop_guard();
+    let queue = DeviceCommandQueue::new();
+
+    let mut connected_once = false;
+    loop {
+      match event_loop.poll().await {
+        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+          info!(
+            "MQTT bridge connected to {}:{}",
+            self.options.broker_host, self.options.broker_port
+          );
+          // Publishing waits on the event loop, so it can't happen inline here.
+          let client = self.client.clone();
+          let announce_publisher = publisher.clone();
+          async_manager::spawn(async move {
+            announce_publisher.announce(&client).await;
+          });
+          if !connected_once {
+            connected_once = true;
+            self.spawn_device_publisher(publisher.clone(), cancellation_token.child_token());
+            if let Some(interval) = self.options.battery_poll_interval {
+              spawn_battery_publisher(
+                self.client.clone(),
+                publisher.clone(),
+                interval,
+                cancellation_token.child_token(),
+              );
+            }
+          }
+        }
+        Ok(Event::Incoming(Packet::Publish(publish))) => {
+          match MqttCommand::parse(&base, &publish.topic, &publish.payload) {
+            Some(command) => self.queue_command(&queue, command),
+            None => warn!("Ignoring invalid MQTT command on {}", publish.topic),
+          }
+        }
+        Ok(_) => {}
+        Err(e) => {
+          if !connected_once {
+            return Err(ButtplugMqttBridgeError::ConnectionError(e.to_string()));
+          }
+          warn!("MQTT bridge connection error, reconnecting: {}", e);
+          sleep(Duration::from_secs(1)).await;
+        }
+      }
+    }
+  }
+
+  fn spawn_device_publisher(&self, publisher: MqttPublisher, token: CancellationToken) {
+    let client = self.client.clone();
+    async_manager::spawn(async move {
+      // Devices that already exist were announced on connection, but still need their sensor
+      // readings forwarded.
+      let mut client_events = client.event_stream();
+      for device in client.devices() {
+        spawn_device_sensor_publisher(device, publisher.clone(), token.clone());
+      }
+      loop {
+        select! {
+          event = client_events.next().fuse() => match event {
+            Some(ButtplugClientEvent::DeviceAdded(device)) => {
+              publisher.announce_device(&device).await;
+              spawn_device_sensor_publisher(device, publisher.clone(), token.clone());
+            }
+            Some(ButtplugClientEvent::DeviceRemoved(device)) => {
+              publisher
+                .publish(&format!("{}/available", device.index()), true, "offline")
+                .await;
+            }
+            Some(ButtplugClientEvent::ServerDisconnect) | None => break,
+            _ => {}
+          },
+          _ = token.cancelled().fuse() => break,
+        }
+      }
+    });
+  }
+
+  /// Queues a command on the devices it applies to. Commands run in order per device, with newer
+  /// values replacing ones the device hasn't been sent yet.
+  fn queue_command(&self, queue: &DeviceCommandQueue<QueuedMqttCommand>, command: MqttCommand) {
+    let devices = self.client.devices();
+    let commands: Vec<_> = match command {
+      MqttCommand::StopAll => devices
+        .into_iter()
+        .map(|device| (MqttCommand::Stop(device.index()), device))
+        .collect(),
+      command => devices
+        .into_iter()
+        .filter(|device| Some(device.index()) == command.device_index())
+        .map(|device| (command.clone(), device))
+        .collect(),
+    };
+    for (command, device) in commands {
+      queue.push(
+        device,
+        QueuedMqttCommand {
+          command,
+          linear_duration: self.options.linear_duration,
+        },
+      );
+    }
+  }
+}
+
+#[derive(Clone)]
+struct MqttPublisher {
+  client: AsyncClient,
+  base: String,
+}
+
+impl MqttPublisher {
+  async fn publish(&self, path: &str, retain: bool, payload: impl Into<Vec<u8>>) {
+    let topic = format!("{}/{}", self.base, path);
+    if let Err(e) = self
+      .client
+      .publish(&topic, QoS::AtLeastOnce, retain, payload)
+      .await
+    {
+      warn!("Could not publish MQTT message to {}: {}", topic, e);
+    }
+  }
+
+  /// Publishes bridge status and subscribes to command topics. Run on every connection, as the
+  /// broker may have lost retained messages and subscriptions.
+  async fn announce(&self, client: &ButtplugClient) {
+    self.publish("status", true, "online").await;
+    for filter in ["+/set", "+/+/set", "+/+/+/set"] {
+      let topic = format!("{}/{}", self.base, filter);
+      if let Err(e) = self.client.subscribe(&topic, QoS::AtLeastOnce).await {
+        warn!("Could not subscribe to MQTT topic {}: {}", topic, e);
+      }
+    }
+    for device in client.devices() {
+      self.announce_device(&device).await;
+    }
+  }
+
+  async fn announce_device(&self, device: &ButtplugClientDevice) {
+    let info = json!({
+      "Name": device.name(),
+      "DisplayName": device.display_name(),
+      "DeviceMessages": device.message_attributes(),
+    });
+    self
+      .publish(&format!("{}/info", device.index()), true, info.to_string())
+      .await;
+    self
+      .publish(&format!("{}/available", device.index()), true, "online")
+      .await;
+  }
+}
+
+#[async_trait]
+impl BridgePublisher for MqttPublisher {
+  async fn publish_battery_level(&self, device_index: u32, level: f64) {
+    self
+      .publish(
+        &format!("{}/battery", device_index),
+        true,
+        level.to_string(),
+      )
+      .await
+  }
+
+  async fn publish_sensor_reading(&self, device_index: u32, reading: &SensorReading) {
+    self
+      .publish(
+        &format!(
+          "{}/sensor/{}/{}",
+          device_index,
+          reading.sensor_index(),
+          reading.sensor_type().to_string().to_lowercase()
+        ),
+        false,
+        json!(reading.data()).to_string(),
+      )
+      .await
+  }
+}
+
+/// Command received on a set topic.
+#[derive(Debug, Clone, PartialEq)]
+enum MqttCommand {
+  StopAll,
+  Stop(u32),
+  Scalar {
+    device_index: u32,
+    actuator_type: ActuatorType,
+    feature_index: Option<u32>,
+    value: f64,
+  },
+  Rotate {
+    device_index: u32,
+    feature_index: Option<u32>,
+    value: f64,
+  },
+  Linear {
+    device_index: u32,
+    feature_index: Option<u32>,
+    value: f64,
+  },
+}
+
+impl MqttCommand {
+  fn parse(base: &str, topic: &str, payload: &[u8]) -> Option<Self> {
+    let path = topic.strip_prefix(base)?.strip_prefix('/')?;
+    let parts: Vec<&str> = path.split('/').collect();
+    let (&"set", parts) = parts.split_last()? else {
+      return None;
+    };
+    let (device_index, command, feature_index) = match parts {
+      ["stop"] => return Some(MqttCommand::StopAll),
+      [device_index, "stop"] => return Some(MqttCommand::Stop(device_index.parse().ok()?)),
+      [device_index, command] => (device_index.parse().ok()?, *command, None),
+      [device_index, command, feature_index] => (
+        device_index.parse().ok()?,
+        *command,
+        Some(feature_index.parse().ok()?),
+      ),
+      _ => return None,
+    };
+    let value = parse_value(payload)?;
+    Some(match command {
+      "rotate" => MqttCommand::Rotate {
+        device_index,
+        feature_index,
+        value,
+      },
+      "linear" => MqttCommand::Linear {
+        device_index,
+        feature_index,
+        value,
+      },
+      _ => MqttCommand::Scalar {
+        device_index,
+        actuator_type: parse_actuator_type(command)?,
+        feature_index,
+        value,
+      },
+    })
+  }
+
+  /// Index of the device the command is for, or `None` for [MqttCommand::StopAll].
+  fn device_index(&self) -> Option<u32> {
+    Some(match self {
+      MqttCommand::StopAll => return None,
+      MqttCommand::Stop(device_index)
+      | MqttCommand::Scalar { device_index, .. }
+      | MqttCommand::Rotate { device_index, .. }
+      | MqttCommand::Linear { device_index, .. } => *device_index,
+    })
+  }
+
+  async fn send(
+    self,
+    device: &ButtplugClientDevice,
+    linear_duration: u32,
+  ) -> Result<(), ButtplugClientError> {
+    match self {
+      MqttCommand::StopAll | MqttCommand::Stop(_) => device.stop().await,
+      MqttCommand::Scalar {
+        actuator_type,
+        feature_index,
+        value,
+        ..
+      } => send_scalar(device, actuator_type, feature_index, value).await,
+      MqttCommand::Rotate {
+        feature_index,
+        value,
+        ..
+      } => {
+        let rotate_count = device
+          .message_attributes()
+          .rotate_cmd()
+          .as_ref()
+          .map_or(0, |attrs| attrs.len());
+        if rotate_count == 0 {
+          return send_scalar(device, ActuatorType::Rotate, feature_index, value).await;
+        }
+        let speed = value.clamp(-1.0, 1.0);
+        let map: HashMap<u32, (f64, bool)> = feature_indexes(rotate_count, feature_index)
+          .map(|i| (i, (speed.abs(), speed >= 0.0)))
+          .collect();
+        if map.is_empty() {
+          return Ok(());
+        }
+        device.rotate(&RotateCommand::RotateMap(map)).await
+      }
+      MqttCommand::Linear {
+        feature_index,
+        value,
+        ..
+      } => {
+        let position = value.clamp(0.0, 1.0);
+        let map: HashMap<u32, (u32, f64)> =
+          feature_indexes(device.linear_attributes().len(), feature_index)
+            .map(|i| (i, (linear_duration, position)))
+            .collect();
+        if map.is_empty() {
+          return Ok(());
+        }
+        device.linear(&LinearCommand::LinearMap(map)).await
+      }
+    }
+  }
+}
+
+struct QueuedMqttCommand {
+  command: MqttCommand,
+  linear_duration: u32,
+}
+
+impl QueuedDeviceCommand for QueuedMqttCommand {
+  fn replaces(&self, queued: &Self) -> bool {
+    match (&self.command, &queued.command) {
+      // Stopping makes anything still waiting pointless.
+      (MqttCommand::Stop(_), _) => true,
+      (
+        MqttCommand::Scalar {
+          actuator_type,
+          feature_index,
+          ..
+        },
+        MqttCommand::Scalar {
+          actuator_type: queued_actuator_type,
+          feature_index: queued_feature_index,
+          ..
+        },
+      ) => actuator_type == queued_actuator_type && feature_index == queued_feature_index,
+      (
+        MqttCommand::Rotate { feature_index, .. },
+        MqttCommand::Rotate {
+          feature_index: queued_feature_index,
+          ..
+        },
+      )
+      | (
+        MqttCommand::Linear { feature_index, .. },
+        MqttCommand::Linear {
+          feature_index: queued_feature_index,
+          ..
+        },
+      ) => feature_index == queued_feature_index,
+      _ => false,
+    }
+  }
+
+  fn send(
+    self,
+    device: Arc<ButtplugClientDevice>,
+  ) -> BoxFuture<'static, Result<(), ButtplugClientError>> {
+    async move { self.command.send(&device, self.linear_duration).await }.boxed()
+  }
+}
+
+async fn send_scalar(
+  device: &ButtplugClientDevice,
+  actuator_type: ActuatorType,
+  feature_index: Option<u32>,
+  value: f64,
+) -> Result<(), ButtplugClientError> {
+  let scalar = value.clamp(0.0, 1.0);
+  let attrs = device.scalar_attributes();
+  let map: HashMap<u32, (f64, ActuatorType)> = feature_indexes(attrs.len(), feature_index)
+    .filter(|i| *attrs[*i as usize].actuator_type() == actuator_type)
+    .map(|i| (i, (scalar, actuator_type)))
+    .collect();
+  if map.is_empty() {
+    return Ok(());
+  }
+  device.scalar(&ScalarCommand::ScalarMap(map)).await
+}
+
+fn parse_actuator_type(name: &str) -> Option<ActuatorType> {
+  [
+    ActuatorType::Vibrate,
+    ActuatorType::Rotate,
+    ActuatorType::Oscillate,
+    ActuatorType::Constrict,
+    ActuatorType::Inflate,
+    ActuatorType::Position,
+  ]
+  .into_iter()
+  .find(|actuator_type| actuator_type.to_string().to_lowercase() == name)
+}
+
+fn parse_value(payload: &[u8]) -> Option<f64> {
+  let value = std::str::from_utf8(payload).ok()?.trim();
+  match value.to_lowercase().as_str() {
+    "on" | "true" => Some(1.0),
+    "off" | "false" => Some(0.0),
+    _ => value.parse::<f64>().ok().filter(|v| !v.is_nan()),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_mqtt_command_parsing() {
+    assert_eq!(
+      MqttCommand::parse("buttplug", "buttplug/stop/set", b""),
+      Some(MqttCommand::StopAll)
+    );
+    assert_eq!(
+      MqttCommand::parse("buttplug", "buttplug/2/stop/set", b""),
+      Some(MqttCommand::Stop(2))
+    );
+    assert_eq!(
+      MqttCommand::parse("buttplug", "buttplug/0/vibrate/set", b"0.5"),
+      Some(MqttCommand::Scalar {
+        device_index: 0,
+        actuator_type: ActuatorType::Vibrate,
+        feature_index: None,
+        value: 0.5
+      })
+    );
+    assert_eq!(
+      MqttCommand::parse("buttplug", "buttplug/1/rotate/3/set", b"-0.25"),
+      Some(MqttCommand::Rotate {
+        device_index: 1,
+        feature_index: Some(3),
+        value: -0.25
+      })
+    );
+    assert_eq!(
+      MqttCommand::parse("buttplug", "buttplug/1/linear/set", b"ON"),
+      Some(MqttCommand::Linear {
+        device_index: 1,
+        feature_index: None,
+        value: 1.0
+      })
+    );
+    assert_eq!(
+      MqttCommand::parse("buttplug", "buttplug/0/vibrate", b"0.5"),
+      None
+    );
+    assert_eq!(
+      MqttCommand::parse("buttplug", "buttplug/0/unknown/set", b"0.5"),
+      None
+    );
+    assert_eq!(
+      MqttCommand::parse("buttplug", "buttplug/0/vibrate/set", b"loud"),
+      None
+    );
+    assert_eq!(
+      MqttCommand::parse("buttplug", "other/0/vibrate/set", b"0.5"),
+      None
+    );
+  }
+
+  #[test]
+  fn test_mqtt_command_coalescing() {
+    let queued = |topic: &str, payload: &[u8]| QueuedMqttCommand {
+      command: MqttCommand::parse("buttplug", topic, payload).expect("Test, assuming infallible."),
+      linear_duration: 500,
+    };
+    let vibrate = queued("buttplug/0/vibrate/0/set", b"0.5");
+    assert!(queued("buttplug/0/vibrate/0/set", b"0.7").replaces(&vibrate));
+    assert!(!queued("buttplug/0/vibrate/1/set", b"0.7").replaces(&vibrate));
+    assert!(!queued("buttplug/0/vibrate/set", b"0.7").replaces(&vibrate));
+    assert!(!queued("buttplug/0/oscillate/0/set", b"0.7").replaces(&vibrate));
+    assert!(!queued("buttplug/0/rotate/0/set", b"0.7").replaces(&vibrate));
+    assert!(queued("buttplug/0/stop/set", b"").replaces(&vibrate));
+    assert!(!vibrate.replaces(&queued("buttplug/0/stop/set", b"")));
+    let linear = queued("buttplug/0/linear/set", b"1");
+    assert!(queued("buttplug/0/linear/set", b"0").replaces(&linear));
+    assert!(!queued("buttplug/0/rotate/set", b"0").replaces(&linear));
+  }
+}
@@ -0,0 +1,440 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Open Sound Control bridge for a [ButtplugClient].
+//!
+//! VR platforms (VRChat avatar parameters), control surfaces (TouchOSC) and audio software all
+//! speak OSC over UDP. The bridge listens for OSC messages on a UDP port, and turns messages sent
+//! to configured addresses into device commands. The first argument of the message is used as the
+//! command value:
+//!
+//! - Scalar commands take a value from 0.0 to 1.0.
+//! - Rotate commands take a value from -1.0 to 1.0, with the sign giving the direction (positive is
+//!   clockwise).
+//! - Linear commands take a position from 0.0 to 1.0, with the movement duration set in the
+//!   mapping.
+//!
+//! Integer and boolean arguments are converted to floats, and values outside the valid range are
+//! clamped.
+//!
+//! The bridge can also publish device information back out over OSC, to an address set via
+//! [ButtplugOscBridgeBuilder::publish_to]. Sensor readings received for devices (after subscribing
+//! to sensors via [ButtplugClientDevice::subscribe_sensor]) are sent to
+//! `<prefix>/<device index>/sensor/<sensor index>/<sensor type>` as integer arguments, and if a poll
+//! interval is set, battery levels are sent to `<prefix>/<device index>/battery` as a float.
+
+pub mod packet;
+
+use super::{
+  bridge::{
+    feature_indexes,
+    spawn_battery_publisher,
+    spawn_device_sensor_publisher,
+    BridgePublisher,
+    DeviceCommandQueue,
+    QueuedDeviceCommand,
+  },
+  device::{ButtplugClientDevice, LinearCommand, RotateCommand, ScalarCommand},
+  ButtplugClient,
+  ButtplugClientError,
+  ButtplugClientEvent,
+};
+use crate::{
+  core::message::{ActuatorType, SensorReading},
+  util::async_manager,
+};
+use async_trait::async_trait;
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use packet::{decode_packet, OscArgument, OscMessage};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::net::UdpSocket;
+use tokio_util::sync::CancellationToken;
+
+/// Error enum for the OSC bridge.
+#[derive(Error, Debug)]
+pub enum ButtplugOscBridgeError {
+  /// The UDP socket could not be opened.
+  #[error("Could not listen on {0}: {1}")]
+  ListenError(SocketAddr, String),
+  /// The UDP socket failed while receiving.
+  #[error("OSC socket error: {0}")]
+  SocketError(String),
+}
+
+/// Selects which devices an OSC address controls.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscDeviceSelector {
+  /// All connected devices.
+  All,
+  /// The device with the given index.
+  Index(u32),
+  /// Devices whose name or display name matches the given string.
+  Name(String),
+  /// Devices that have at least one scalar actuator of the given type.
+  Actuator(ActuatorType),
+}
+
+impl OscDeviceSelector {
+  pub fn matches(&self, device: &ButtplugClientDevice) -> bool {
+    match self {
+      OscDeviceSelector::All => true,
+      OscDeviceSelector::Index(index) => device.index() == *index,
+      OscDeviceSelector::Name(name) => {
+        device.name() == name || device.display_name().as_ref() == Some(name)
+      }
+      OscDeviceSelector::Actuator(actuator_type) => device
+        .scalar_attributes()
+        .iter()
+        .any(|attr| attr.actuator_type() == actuator_type),
+    }
+  }
+}
+
+/// Command sent to selected devices when an OSC message is received.
+///
+/// A `feature_index` of `None` sends the command to all matching features on the device.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscDeviceCommand {
+  Scalar {
+    actuator_type: ActuatorType,
+    feature_index: Option<u32>,
+  },
+  Rotate {
+    feature_index: Option<u32>,
+  },
+  Linear {
+    feature_index: Option<u32>,
+    duration: u32,
+  },
+}
+
+/// Maps an OSC address to a command sent to a set of devices.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscAddressMapping {
+  address: String,
+  device: OscDeviceSelector,
+  command: OscDeviceCommand,
+}
+
+impl OscAddressMapping {
+  pub fn new(address: &str, device: OscDeviceSelector, command: OscDeviceCommand) -> Self {
+    Self {
+      address: address.to_owned(),
+      device,
+      command,
+    }
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct ButtplugOscBridgeBuilder {
+  /// Address to receive OSC messages on.
+  listen_address: SocketAddr,
+  /// Address mappings to device commands.
+  mappings: Vec<OscAddressMapping>,
+  /// Address to send sensor and battery messages to, if any.
+  publish_address: Option<SocketAddr>,
+  /// Prefix for published OSC addresses.
+  publish_prefix: String,
+  /// How often to read and publish device battery levels, if at all.
+  battery_poll_interval: Option<Duration>,
+}
+
+impl Default for ButtplugOscBridgeBuilder {
+  fn default() -> Self {
+    Self {
+      // VRChat sends avatar parameters to 9001 by default.
+      listen_address: SocketAddr::from(([127, 0, 0, 1], 9001)),
+      mappings: vec![],
+      publish_address: None,
+      publish_prefix: "/buttplug".to_owned(),
+      battery_poll_interval: None,
+    }
+  }
+}
+
+impl ButtplugOscBridgeBuilder {
+  pub fn listen_address(&mut self, listen_address: SocketAddr) -> &mut Self {
+    self.listen_address = listen_address;
+    self
+  }
+
+  pub fn mapping(&mut self, mapping: OscAddressMapping) -> &mut Self {
+    self.mappings.push(mapping);
+    self
+  }
+
+  pub fn publish_to(&mut self, publish_address: SocketAddr) -> &mut Self {
+    self.publish_address = Some(publish_address);
+    self
+  }
+
+  pub fn publish_prefix(&mut self, publish_prefix: &str) -> &mut Self {
+    self.publish_prefix = publish_prefix.trim_end_matches('/').to_owned();
+    self
+  }
+
+  pub fn battery_poll_interval(&mut self, battery_poll_interval: Duration) -> &mut Self {
+    self.battery_poll_interval = Some(battery_poll_interval);
+    self
+  }
+
+  pub fn finish(&self, client: Arc<ButtplugClient>) -> ButtplugOscBridge {
+    ButtplugOscBridge {
+      client,
+      listen_address: self.listen_address,
+      mappings: self.mappings.clone(),
+      publish_address: self.publish_address,
+      publish_prefix: self.publish_prefix.clone(),
+      battery_poll_interval: self.battery_poll_interval,
+    }
+  }
+}
+
+/// Bridge between OSC messages on a UDP socket and the devices of a connected [ButtplugClient].
+pub struct ButtplugOscBridge {
+  client: Arc<ButtplugClient>,
+  listen_address: SocketAddr,
+  mappings: Vec<OscAddressMapping>,
+  publish_address: Option<SocketAddr>,
+  publish_prefix: String,
+  battery_poll_interval: Option<Duration>,
+}
+
+impl ButtplugOscBridge {
+  pub fn client(&self) -> &Arc<ButtplugClient> {
+    &self.client
+  }
+
+  /// Runs the bridge until the socket fails. Publishing tasks are stopped when this returns, or when
+  /// the returned future is dropped.
+  pub async fn run(&self) -> Result<(), ButtplugOscBridgeError> {
+    let socket = Arc::new(
+      UdpSocket::bind(self.listen_address)
+        .await
+        .map_err(|e| ButtplugOscBridgeError::ListenError(self.listen_address, e.to_string()))?,
+    );
+    info!(
+      "OSC bridge listening on {}",
+      socket.local_addr().unwrap_or(self.listen_address)
+    );
+    let cancellation_token = CancellationToken::new();
+    let _cancel_guard = cancellation_token.clone().drop_guard();
+
+    if let Some(publish_address) = self.publish_address {
+      let publisher = OscPublisher {
+        socket: socket.clone(),
+        address: publish_address,
+        prefix: self.publish_prefix.clone(),
+      };
+      self.spawn_sensor_publisher(publisher.clone(), cancellation_token.child_token());
+      if let Some(interval) = self.battery_poll_interval {
+        spawn_battery_publisher(
+          self.client.clone(),
+          publisher,
+          interval,
+          cancellation_token.child_token(),
+        );
+      }
+    }
+
+    let queue = DeviceCommandQueue::new();
+    let mut buf = vec![0u8; 65536];
+    loop {
+      let (len, _) = socket
+        .recv_from(&mut buf)
+        .await
+        .map_err(|e| ButtplugOscBridgeError::SocketError(e.to_string()))?;
+      match decode_packet(&buf[..len]) {
+        Ok(messages) => {
+          for message in messages {
+            self.handle_message(&message, &queue);
+          }
+        }
+        Err(e) => warn!("Received invalid OSC packet: {}", e),
+      }
+    }
+  }
+
+  /// Queues the commands mapped to a message, so a slow device doesn't hold up the socket.
+  fn handle_message(&self, message: &OscMessage, queue: &DeviceCommandQueue<QueuedOscCommand>) {
+    for mapping in self
+      .mappings
+      .iter()
+      .filter(|m| m.address == message.address)
+    {
+      let Some(value) = message
+        .args
+        .first()
+        .and_then(OscArgument::as_f64)
+        .filter(|v| !v.is_nan())
+      else {
+        warn!(
+          "OSC message to {} has no numeric argument, ignoring.",
+          message.address
+        );
+        continue;
+      };
+      for device in self
+        .client
+        .devices()
+        .into_iter()
+        .filter(|device| mapping.device.matches(device))
+      {
+        queue.push(
+          device,
+          QueuedOscCommand {
+            command: mapping.command.clone(),
+            value,
+          },
+        );
+      }
+    }
+  }
+
+  fn spawn_sensor_publisher(&self, publisher: OscPublisher, token: CancellationToken) {
+    let client = self.client.clone();
+    async_manager::spawn(async move {
+      // Subscribe to client events before looking at the device list, so devices added in between
+      // aren't missed.
+      let mut client_events = client.event_stream();
+      for device in client.devices() {
+        spawn_device_sensor_publisher(device, publisher.clone(), token.clone());
+      }
+      loop {
+        select! {
+          event = client_events.next().fuse() => match event {
+            Some(ButtplugClientEvent::DeviceAdded(device)) => {
+              spawn_device_sensor_publisher(device, publisher.clone(), token.clone());
+            }
+            Some(ButtplugClientEvent::ServerDisconnect) | None => break,
+            _ => {}
+          },
+          _ = token.cancelled().fuse() => break,
+        }
+      }
+    });
+  }
+}
+
+#[derive(Clone)]
+struct OscPublisher {
+  socket: Arc<UdpSocket>,
+  address: SocketAddr,
+  prefix: String,
+}
+
+impl OscPublisher {
+  async fn send(&self, path: &str, args: Vec<OscArgument>) {
+    let message = OscMessage::new(&format!("{}/{}", self.prefix, path), args);
+    if let Err(e) = self.socket.send_to(&message.encode(), self.address).await {
+      warn!("Could not publish OSC message to {}: {}", self.address, e);
+    }
+  }
+}
+
+#[async_trait]
+impl BridgePublisher for OscPublisher {
+  async fn publish_battery_level(&self, device_index: u32, level: f64) {
+    self
+      .send(
+        &format!("{}/battery", device_index),
+        vec![OscArgument::Float(level as f32)],
+      )
+      .await
+  }
+
+  async fn publish_sensor_reading(&self, device_index: u32, reading: &SensorReading) {
+    self
+      .send(
+        &format!(
+          "{}/sensor/{}/{}",
+          device_index,
+          reading.sensor_index(),
+          reading.sensor_type()
+        ),
+        reading
+          .data()
+          .iter()
+          .map(|v| OscArgument::Int(*v))
+          .collect(),
+      )
+      .await
+  }
+}
+
+struct QueuedOscCommand {
+  command: OscDeviceCommand,
+  value: f64,
+}
+
+impl QueuedDeviceCommand for QueuedOscCommand {
+  fn replaces(&self, queued: &Self) -> bool {
+    self.command == queued.command
+  }
+
+  fn send(
+    self,
+    device: Arc<ButtplugClientDevice>,
+  ) -> BoxFuture<'static, Result<(), ButtplugClientError>> {
+    async move { send_command(&device, &self.command, self.value).await }.boxed()
+  }
+}
+
+async fn send_command(
+  device: &ButtplugClientDevice,
+  command: &OscDeviceCommand,
+  value: f64,
+) -> Result<(), ButtplugClientError> {
+  match command {
+    OscDeviceCommand::Scalar {
+      actuator_type,
+      feature_index,
+    } => {
+      let scalar = value.clamp(0.0, 1.0);
+      let attrs = device.scalar_attributes();
+      let map: HashMap<u32, (f64, ActuatorType)> = feature_indexes(attrs.len(), *feature_index)
+        .filter(|i| attrs[*i as usize].actuator_type() == actuator_type)
+        .map(|i| (i, (scalar, *actuator_type)))
+        .collect();
+      if map.is_empty() {
+        return Ok(());
+      }
+      device.scalar(&ScalarCommand::ScalarMap(map)).await
+    }
+    OscDeviceCommand::Rotate { feature_index } => {
+      let speed = value.clamp(-1.0, 1.0);
+      let count = device
+        .message_attributes()
+        .rotate_cmd()
+        .as_ref()
+        .map_or(0, |attrs| attrs.len());
+      let map: HashMap<u32, (f64, bool)> = feature_indexes(count, *feature_index)
+        .map(|i| (i, (speed.abs(), speed >= 0.0)))
+        .collect();
+      if map.is_empty() {
+        return Ok(());
+      }
+      device.rotate(&RotateCommand::RotateMap(map)).await
+    }
+    OscDeviceCommand::Linear {
+      feature_index,
+      duration,
+    } => {
+      let position = value.clamp(0.0, 1.0);
+      let map: HashMap<u32, (u32, f64)> =
+        feature_indexes(device.linear_attributes().len(), *feature_index)
+          .map(|i| (i, (*duration, position)))
+          .collect();
+      if map.is_empty() {
+        return Ok(());
+      }
+      device.linear(&LinearCommand::LinearMap(map)).await
+    }
+  }
+}
@@ -0,0 +1,228 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Minimal OSC 1.0 packet encoding and decoding.
+//!
+//! Only handles the argument types that are useful for driving devices (`i`, `f`, `s`, `T`, `F`).
+//! Bundles are flattened into their contained messages, with timetags ignored.
+
+use byteorder::{BigEndian, ByteOrder};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum OscPacketError {
+  #[error("OSC packet ended unexpectedly")]
+  UnexpectedEnd,
+  #[error("OSC string is not valid UTF-8")]
+  InvalidString,
+  #[error("OSC packet has invalid address: {0}")]
+  InvalidAddress(String),
+  #[error("OSC argument type '{0}' is not supported")]
+  UnsupportedType(char),
+}
+
+/// Argument to an OSC message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArgument {
+  Int(i32),
+  Float(f32),
+  String(String),
+  Bool(bool),
+}
+
+impl OscArgument {
+  /// Converts the argument to a number, if possible. Booleans are treated as 0 or 1.
+  pub fn as_f64(&self) -> Option<f64> {
+    match self {
+      OscArgument::Int(value) => Some(*value as f64),
+      OscArgument::Float(value) => Some(*value as f64),
+      OscArgument::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+      OscArgument::String(_) => None,
+    }
+  }
+}
+
+/// A single OSC message, made up of an address pattern and a list of arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+  pub address: String,
+  pub args: Vec<OscArgument>,
+}
+
+impl OscMessage {
+  pub fn new(address: &str, args: Vec<OscArgument>) -> Self {
+    Self {
+      address: address.to_owned(),
+      args,
+    }
+  }
+
+  /// Encodes the message as an OSC packet.
+  pub fn encode(&self) -> Vec<u8> {
+    let mut buf = vec![];
+    write_string(&mut buf, &self.address);
+    let mut type_tags = ",".to_owned();
+    for arg in &self.args {
+      type_tags.push(match arg {
+        OscArgument::Int(_) => 'i',
+        OscArgument::Float(_) => 'f',
+        OscArgument::String(_) => 's',
+        OscArgument::Bool(true) => 'T',
+        OscArgument::Bool(false) => 'F',
+      });
+    }
+    write_string(&mut buf, &type_tags);
+    for arg in &self.args {
+      match arg {
+        OscArgument::Int(value) => buf.extend_from_slice(&value.to_be_bytes()),
+        OscArgument::Float(value) => buf.extend_from_slice(&value.to_be_bytes()),
+        OscArgument::String(value) => write_string(&mut buf, value),
+        OscArgument::Bool(_) => {}
+      }
+    }
+    buf
+  }
+}
+
+/// Decodes an OSC packet into the messages it contains.
+pub fn decode_packet(data: &[u8]) -> Result<Vec<OscMessage>, OscPacketError> {
+  let mut messages = vec![];
+  decode_into(data, &mut messages)?;
+  Ok(messages)
+}
+
+fn decode_into(data: &[u8], messages: &mut Vec<OscMessage>) -> Result<(), OscPacketError> {
+  let mut offset = 0;
+  let address = read_string(data, &mut offset)?;
+  if address == "#bundle" {
+    // Skip the timetag, then read each size-prefixed element.
+    take(data, &mut offset, 8)?;
+    while offset < data.len() {
+      let size = BigEndian::read_i32(take(data, &mut offset, 4)?);
+      if size < 0 {
+        return Err(OscPacketError::UnexpectedEnd);
+      }
+      decode_into(take(data, &mut offset, size as usize)?, messages)?;
+    }
+    return Ok(());
+  }
+  if !address.starts_with('/') {
+    return Err(OscPacketError::InvalidAddress(address));
+  }
+  // Type tag strings are optional in very old OSC implementations, treat a missing one as no
+  // arguments.
+  if offset >= data.len() {
+    messages.push(OscMessage::new(&address, vec![]));
+    return Ok(());
+  }
+  let type_tags = read_string(data, &mut offset)?;
+  let mut args = vec![];
+  for tag in type_tags.chars().skip(1) {
+    args.push(match tag {
+      'i' => OscArgument::Int(BigEndian::read_i32(take(data, &mut offset, 4)?)),
+      'f' => OscArgument::Float(BigEndian::read_f32(take(data, &mut offset, 4)?)),
+      's' => OscArgument::String(read_string(data, &mut offset)?),
+      'T' => OscArgument::Bool(true),
+      'F' => OscArgument::Bool(false),
+      other => return Err(OscPacketError::UnsupportedType(other)),
+    });
+  }
+  messages.push(OscMessage { address, args });
+  Ok(())
+}
+
+fn take<'a>(data: &'a [u8], offset: &mut usize, len: usize) -> Result<&'a [u8], OscPacketError> {
+  let end = offset
+    .checked_add(len)
+    .filter(|end| *end <= data.len())
+    .ok_or(OscPacketError::UnexpectedEnd)?;
+  let slice = &data[*offset..end];
+  *offset = end;
+  Ok(slice)
+}
+
+fn read_string(data: &[u8], offset: &mut usize) -> Result<String, OscPacketError> {
+  let remaining = data.get(*offset..).ok_or(OscPacketError::UnexpectedEnd)?;
+  let len = remaining
+    .iter()
+    .position(|b| *b == 0)
+    .ok_or(OscPacketError::UnexpectedEnd)?;
+  let value = std::str::from_utf8(&remaining[..len])
+    .map_err(|_| OscPacketError::InvalidString)?
+    .to_owned();
+  // Strings are null terminated, then padded to a multiple of 4 bytes.
+  take(data, offset, (len + 4) & !3)?;
+  Ok(value)
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+  buf.extend_from_slice(value.as_bytes());
+  let padded_len = (value.len() + 4) & !3;
+  buf.resize(buf.len() + padded_len - value.len(), 0);
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_osc_message_round_trip() {
+    let msg = OscMessage::new(
+      "/avatar/parameters/Vibe",
+      vec![
+        OscArgument::Float(0.5),
+        OscArgument::Int(-3),
+        OscArgument::String("test".to_owned()),
+        OscArgument::Bool(true),
+        OscArgument::Bool(false),
+      ],
+    );
+    let encoded = msg.encode();
+    assert_eq!(encoded.len() % 4, 0);
+    assert_eq!(decode_packet(&encoded), Ok(vec![msg]));
+  }
+
+  #[test]
+  fn test_osc_message_encoding() {
+    let msg = OscMessage::new("/a", vec![OscArgument::Int(1)]);
+    assert_eq!(
+      msg.encode(),
+      vec![b'/', b'a', 0, 0, b',', b'i', 0, 0, 0, 0, 0, 1]
+    );
+  }
+
+  #[test]
+  fn test_osc_bundle_decoding() {
+    let first = OscMessage::new("/first", vec![OscArgument::Float(1.0)]);
+    let second = OscMessage::new("/second", vec![]);
+    let mut bundle = vec![];
+    write_string(&mut bundle, "#bundle");
+    bundle.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+    for msg in [&first, &second] {
+      let encoded = msg.encode();
+      bundle.extend_from_slice(&(encoded.len() as i32).to_be_bytes());
+      bundle.extend_from_slice(&encoded);
+    }
+    assert_eq!(decode_packet(&bundle), Ok(vec![first, second]));
+  }
+
+  #[test]
+  fn test_osc_invalid_packets() {
+    assert_eq!(
+      decode_packet(&[b'/', b'a', 0, 0, b',', b'f', 0, 0, 0, 0]),
+      Err(OscPacketError::UnexpectedEnd)
+    );
+    assert_eq!(
+      decode_packet(&[b'/', b'a', 0, 0, b',', b'b', 0, 0]),
+      Err(OscPacketError::UnsupportedType('b'))
+    );
+    assert!(matches!(
+      decode_packet(&[b'a', 0, 0, 0]),
+      Err(OscPacketError::InvalidAddress(_))
+    ));
+  }
+}
@@ -0,0 +1,270 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Smooth transitions between scalar actuator levels.
+//!
+//! Ramps are started via [ButtplugClientDevice::ramp_scalar][super::ButtplugClientDevice::ramp_scalar]
+//! and friends. They move features from their last commanded level to a target level over a
+//! duration, following a [RampEasing] curve. Updates are only sent when a feature crosses into a
+//! new step of its `step_count`, so slow ramps on coarse devices don't flood the server.
+//!
+//! Only one ramp runs per device at a time. Any new scalar, stop, or ramp command for a device
+//! cancels the ramp that's currently running on it.
+
+use super::{
+  device_state::ClientDeviceStateTracker,
+  ButtplugClientMessageSender,
+  ButtplugClientResult,
+};
+use crate::{
+  core::message::{ScalarCmd, ScalarSubcommand},
+  util::{sleep, Instant},
+};
+use futures::{select, FutureExt};
+use std::{
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+use tokio::sync::Mutex as AsyncMutex;
+use tokio_util::sync::CancellationToken;
+
+/// How often ramps check whether an update needs to be sent.
+const RAMP_UPDATE_INTERVAL_MS: u64 = 20;
+
+/// Easing curves for ramps, mapping the elapsed fraction of a ramp's duration to the fraction of
+/// the distance to the target covered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RampEasing {
+  /// Constant rate of change.
+  #[default]
+  Linear,
+  /// Starts slow, speeds up towards the target.
+  EaseIn,
+  /// Starts fast, slows down towards the target.
+  EaseOut,
+  /// Starts and ends slow, fastest in the middle.
+  EaseInOut,
+}
+
+impl RampEasing {
+  /// Applies the curve to `progress`, which is clamped to [0.0, 1.0].
+  pub fn apply(&self, progress: f64) -> f64 {
+    let t = progress.clamp(0.0, 1.0);
+    match self {
+      RampEasing::Linear => t,
+      RampEasing::EaseIn => t * t,
+      RampEasing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+      RampEasing::EaseInOut => {
+        if t < 0.5 {
+          2.0 * t * t
+        } else {
+          1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+        }
+      }
+    }
+  }
+}
+
+/// Movement of a single scalar feature over a ramp.
+#[derive(Debug, Clone)]
+pub(super) struct ScalarRampFeature {
+  pub(super) target: ScalarSubcommand,
+  pub(super) start: f64,
+  pub(super) step_count: u32,
+}
+
+impl ScalarRampFeature {
+  fn level_at(&self, eased_progress: f64) -> f64 {
+    self.start + (self.target.scalar() - self.start) * eased_progress
+  }
+}
+
+/// Step a level will end up at on the device. Matches the rounding the server uses when converting
+/// scalars to steps.
+pub(super) fn level_to_step(level: f64, step_count: u32) -> u32 {
+  let step = level * step_count as f64;
+  if step < 0.0001 {
+    0
+  } else {
+    step.ceil() as u32
+  }
+}
+
+/// Shared bookkeeping for scalar levels and the ramp currently running on a device.
+#[derive(Clone)]
+pub(super) struct ScalarRampState {
+  /// Where the last acknowledged level of each ScalarCmd feature is kept.
+  device_state: ClientDeviceStateTracker,
+  /// Cancels the currently running ramp, if any.
+  ramp_token: Arc<Mutex<CancellationToken>>,
+  /// Held while a ScalarCmd is queued. Keeps a ramp from queueing an update after the command that
+  /// cancelled it.
+  send_lock: Arc<AsyncMutex<()>>,
+}
+
+impl ScalarRampState {
+  pub(super) fn new(device_state: &ClientDeviceStateTracker) -> Self {
+    Self {
+      device_state: device_state.clone(),
+      ramp_token: Arc::new(Mutex::new(CancellationToken::new())),
+      send_lock: Arc::new(AsyncMutex::new(())),
+    }
+  }
+
+  /// Level ramps start from. Features that haven't been commanded are assumed to be off.
+  pub(super) fn level(&self, feature_index: u32) -> f64 {
+    self.device_state.scalar(feature_index).unwrap_or(0.0)
+  }
+
+  fn record_levels(&self, subcommands: &[ScalarSubcommand]) {
+    self.device_state.record_scalars(subcommands);
+  }
+
+  /// Cancels the running ramp, if any, and returns a token for the next one.
+  pub(super) fn cancel_ramp(&self) -> CancellationToken {
+    let mut token = self
+      .ramp_token
+      .lock()
+      .expect("Only locked for single accesses, should never be poisoned.");
+    token.cancel();
+    *token = CancellationToken::new();
+    token.clone()
+  }
+
+  /// Sends a ScalarCmd, recording its levels if the server accepts it.
+  pub(super) async fn send(
+    &self,
+    message_sender: &ButtplugClientMessageSender,
+    device_index: u32,
+    subcommands: Vec<ScalarSubcommand>,
+  ) -> ButtplugClientResult {
+    let reply = {
+      let _guard = self.send_lock.lock().await;
+      message_sender
+        .queue_message_expect_ok(ScalarCmd::new(device_index, subcommands.clone()).into())
+    };
+    reply.await?;
+    self.record_levels(&subcommands);
+    Ok(())
+  }
+}
+
+/// Runs a ramp until it reaches its targets or is cancelled. Returns Ok(()) in both cases.
+pub(super) async fn run_scalar_ramp(
+  device_index: u32,
+  message_sender: Arc<ButtplugClientMessageSender>,
+  state: ScalarRampState,
+  features: Vec<ScalarRampFeature>,
+  duration: Duration,
+  easing: RampEasing,
+  token: CancellationToken,
+) -> ButtplugClientResult {
+  let start_time = Instant::now();
+  let mut last_steps: Vec<u32> = features
+    .iter()
+    .map(|feature| level_to_step(feature.start, feature.step_count))
+    .collect();
+  loop {
+    let progress = if duration.is_zero() {
+      1.0
+    } else {
+      start_time.elapsed().as_secs_f64() / duration.as_secs_f64()
+    };
+    let eased_progress = easing.apply(progress);
+    let mut subcommands = vec![];
+    for (feature, last_step) in features.iter().zip(last_steps.iter_mut()) {
+      // Use exact targets at the end, in case they don't sit on a step boundary.
+      let level = if progress >= 1.0 {
+        feature.target.scalar()
+      } else {
+        feature.level_at(eased_progress)
+      };
+      let step = level_to_step(level, feature.step_count);
+      if step != *last_step {
+        *last_step = step;
+        subcommands.push(ScalarSubcommand::new(
+          feature.target.index(),
+          level,
+          feature.target.actuator_type(),
+        ));
+      }
+    }
+    if !subcommands.is_empty() {
+      let reply = {
+        let _guard = state.send_lock.lock().await;
+        // Checked while holding the lock, so nothing gets queued after a cancelling command.
+        if token.is_cancelled() {
+          return Ok(());
+        }
+        message_sender
+          .queue_message_expect_ok(ScalarCmd::new(device_index, subcommands.clone()).into())
+      };
+      reply.await?;
+      // The cancelling command's levels win, even if its reply came back first.
+      if token.is_cancelled() {
+        return Ok(());
+      }
+      state.record_levels(&subcommands);
+    }
+    if progress >= 1.0 {
+      // Steps may not have changed on the last update, but the levels still end up at the target.
+      if token.is_cancelled() {
+        return Ok(());
+      }
+      state.record_levels(
+        &features
+          .iter()
+          .map(|feature| feature.target.clone())
+          .collect::<Vec<ScalarSubcommand>>(),
+      );
+      return Ok(());
+    }
+    select! {
+      _ = token.cancelled().fuse() => return Ok(()),
+      _ = sleep(Duration::from_millis(RAMP_UPDATE_INTERVAL_MS)).fuse() => {}
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_ramp_easing_endpoints() {
+    for easing in [
+      RampEasing::Linear,
+      RampEasing::EaseIn,
+      RampEasing::EaseOut,
+      RampEasing::EaseInOut,
+    ] {
+      assert_eq!(easing.apply(0.0), 0.0);
+      assert_eq!(easing.apply(1.0), 1.0);
+      assert_eq!(easing.apply(-1.0), 0.0);
+      assert_eq!(easing.apply(2.0), 1.0);
+    }
+  }
+
+  #[test]
+  fn test_ramp_easing_curves() {
+    assert_eq!(RampEasing::Linear.apply(0.25), 0.25);
+    assert_eq!(RampEasing::EaseIn.apply(0.5), 0.25);
+    assert_eq!(RampEasing::EaseOut.apply(0.5), 0.75);
+    assert_eq!(RampEasing::EaseInOut.apply(0.25), 0.125);
+    assert_eq!(RampEasing::EaseInOut.apply(0.5), 0.5);
+    assert_eq!(RampEasing::EaseInOut.apply(0.75), 0.875);
+  }
+
+  #[test]
+  fn test_level_to_step() {
+    assert_eq!(level_to_step(0.0, 20), 0);
+    assert_eq!(level_to_step(0.000001, 20), 0);
+    assert_eq!(level_to_step(0.01, 20), 1);
+    assert_eq!(level_to_step(0.5, 20), 10);
+    assert_eq!(level_to_step(1.0, 20), 20);
+  }
+}
@@ -10,7 +10,15 @@
 use crate::{
   core::{
     connector::{ButtplugConnector, ButtplugConnectorError, ButtplugConnectorResultFuture},
-    message::{ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage},
+    errors::ButtplugError,
+    message::{
+      self,
+      ButtplugCurrentSpecClientMessage,
+      ButtplugCurrentSpecServerMessage,
+      ButtplugMessage,
+      ButtplugMessageSpecVersion,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    },
   },
   server::{ButtplugServer, ButtplugServerBuilder},
   util::async_manager,
@@ -19,12 +27,10 @@ use futures::{
   future::{self, BoxFuture, FutureExt},
   StreamExt,
 };
-use std::{
-  convert::TryInto,
-  sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-  },
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+  RwLock,
 };
 use tokio::sync::mpsc::{channel, Sender};
 use tracing_futures::Instrument;
@@ -66,12 +72,17 @@ impl ButtplugInProcessClientConnectorBuilder {
 /// develop (and we highly recommend developing that way), and also an easy way to get users up and
 /// running as quickly as possible, we recommend also including some sort of IPC Connector in order
 /// for your application to connect to newer servers when they come out.
+///
+/// Messages are passed through the message spec version the client asks for in its handshake, so
+/// in-process servers limited to an older version behave the same way remote ones would.
 #[derive(Clone)]
 pub struct ButtplugInProcessClientConnector {
   /// Internal server object for the embedded connector.
   server: Arc<ButtplugServer>,
   server_outbound_sender: Sender<ButtplugCurrentSpecServerMessage>,
   connected: Arc<AtomicBool>,
+  /// Spec version from the client's last RequestServerInfo.
+  message_version: Arc<RwLock<ButtplugMessageSpecVersion>>,
 }
 
 impl Default for ButtplugInProcessClientConnector {
@@ -97,6 +108,7 @@ impl<'a> ButtplugInProcessClientConnector {
           .expect("Default server builder should always work.")
       })),
       connected: Arc::new(AtomicBool::new(false)),
+      message_version: Arc::new(RwLock::new(BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION)),
     }
   }
 
@@ -123,17 +135,25 @@ impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServ
       let send = message_sender.clone();
       self.server_outbound_sender = message_sender;
       let server_recv = self.server.event_stream();
+      let message_version = self.message_version.clone();
       async move {
         async_manager::spawn(async move {
           info!("Starting In Process Client Connector Event Sender Loop");
           pin_mut!(server_recv);
           while let Some(event) = server_recv.next().await {
+            let version = *message_version.read().expect("Only locked for single accesses, should never be poisoned.");
+            // Events that don't exist in the client's spec version wouldn't be sent to a remote
+            // client either.
+            let event = match ButtplugCurrentSpecServerMessage::upgrade(event, version) {
+              Ok(event) => event,
+              Err(e) => {
+                debug!("Dropping event not supported by spec version {}: {}", version, e);
+                continue;
+              }
+            };
             // If we get an error back, it means the client dropped our event
-            // handler, so just stop trying. Otherwise, since this is an
-            // in-process conversion, we can unwrap because we know our
-            // try_into() will always succeed (which may not be the case with
-            // remote connections that have different spec versions).
-            if send.send(event.try_into().expect("This is in-process so we're always on the latest message spec, this will always work.")).await.is_err() {
+            // handler, so just stop trying.
+            if send.send(event).await.is_err() {
               break;
             }
           }
@@ -160,17 +180,31 @@ impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServ
     if !self.connected.load(Ordering::SeqCst) {
       return ButtplugConnectorError::ConnectorNotConnected.into();
     }
-    let input = msg
-      .try_into()
-      .expect("This is in-process so message conversions will always work.");
-    let output_fut = self.server.parse_message(input);
+    let id = msg.id();
+    let version = {
+      let mut message_version = self
+        .message_version
+        .write()
+        .expect("Only locked for single accesses, should never be poisoned.");
+      if let ButtplugCurrentSpecClientMessage::RequestServerInfo(rsi) = &msg {
+        *message_version = rsi.message_version();
+      }
+      *message_version
+    };
+    // Send messages the way a client would over the wire, so the server sees the same thing it
+    // would from a remote client using that spec version.
+    let output_fut = msg
+      .downgrade(version)
+      .map(|input| self.server.parse_message(input));
     let sender = self.server_outbound_sender.clone();
     async move {
-      let output: ButtplugCurrentSpecServerMessage = output_fut
-        .await
-        .unwrap_or_else(|e| e.into())
-        .try_into()
-        .expect("This is in-process so message conversions will always work.");
+      let output = match output_fut {
+        Ok(output_fut) => output_fut.await.unwrap_or_else(|e| e.into()),
+        Err(e) => message::Error::from(ButtplugError::from(e)).into(),
+      };
+      let mut output = ButtplugCurrentSpecServerMessage::upgrade(output, version)
+        .unwrap_or_else(|e| message::Error::from(ButtplugError::from(e)).into());
+      output.set_id(id);
       sender
         .send(output)
         .await
@@ -69,7 +69,10 @@ pub mod remote_connector;
 pub mod transport;
 
 use crate::{
-  core::message::{serializer::ButtplugSerializedMessage, ButtplugMessage},
+  core::message::{
+    serializer::{ButtplugSerializedMessage, ButtplugSerializerError},
+    ButtplugMessage,
+  },
   util::future::{ButtplugFuture, ButtplugFutureStateShared},
 };
 use displaydoc::Display;
@@ -86,8 +89,17 @@ pub use remote_connector::{
 };
 use thiserror::Error;
 use tokio::sync::mpsc::Sender;
+#[cfg(feature = "stdio-transport")]
+pub use transport::ButtplugStdioTransport;
+pub use transport::ButtplugStreamTransport;
 #[cfg(feature = "websockets")]
 pub use transport::ButtplugWebsocketClientTransport;
+#[cfg(feature = "tcp-transport")]
+pub use transport::{
+  ButtplugTcpClientTransport,
+  ButtplugTcpServerTransport,
+  ButtplugTcpServerTransportBuilder,
+};
 
 #[cfg(feature = "websockets")]
 pub use transport::{ButtplugWebsocketServerTransport, ButtplugWebsocketServerTransportBuilder};
@@ -114,6 +126,8 @@ pub enum ButtplugConnectorError {
   ConnectorGenericError(String),
   /// Specific error for connector type: {0}.
   TransportSpecificError(transport::ButtplugConnectorTransportSpecificError),
+  /// Cannot serialize message: {0}
+  SerializerError(ButtplugSerializerError),
 }
 
 impl<T> From<ButtplugConnectorError> for BoxFuture<'static, Result<T, ButtplugConnectorError>>
@@ -183,7 +197,10 @@ where
   fn send(&self, msg: OutboundMessageType) -> ButtplugConnectorResultFuture;
 }
 
-#[cfg(all(feature = "websockets", feature = "serialize-json"))]
+#[cfg(all(
+  any(feature = "websockets", feature = "tcp-transport"),
+  feature = "serialize-json"
+))]
 use crate::core::message::{ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage};
 
 /// Convenience method for creating a new Buttplug Client Websocket connector that uses the JSON
@@ -202,3 +219,16 @@ pub fn new_json_ws_client_connector(
     address,
   ))
 }
+
+/// Convenience method for creating a new Buttplug Client raw TCP connector that uses the JSON
+/// serializer. Address should be in "host:port" format, i.e. "127.0.0.1:12345".
+#[cfg(all(feature = "tcp-transport", feature = "serialize-json"))]
+pub fn new_json_tcp_client_connector(
+  address: &str,
+) -> impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage> {
+  use crate::core::message::serializer::ButtplugClientJSONSerializer;
+
+  ButtplugRemoteClientConnector::<ButtplugTcpClientTransport, ButtplugClientJSONSerializer>::new(
+    ButtplugTcpClientTransport::new(address),
+  )
+}
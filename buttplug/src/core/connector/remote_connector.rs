@@ -29,24 +29,18 @@ use crate::{
   util::async_manager,
 };
 use futures::{future::BoxFuture, select, FutureExt};
-use std::marker::PhantomData;
+use std::sync::Arc;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
-enum ButtplugRemoteConnectorMessage<T>
-where
-  T: ButtplugMessage + 'static,
-{
-  Message(T),
+enum ButtplugRemoteConnectorMessage {
+  Message(ButtplugSerializedMessage),
   Close,
 }
 
-enum StreamValue<T>
-where
-  T: ButtplugMessage + 'static,
-{
+enum StreamValue {
   NoValue,
   Incoming(ButtplugTransportIncomingMessage),
-  Outgoing(ButtplugRemoteConnectorMessage<T>),
+  Outgoing(ButtplugRemoteConnectorMessage),
 }
 
 async fn remote_connector_event_loop<
@@ -55,8 +49,10 @@ async fn remote_connector_event_loop<
   OutboundMessageType,
   InboundMessageType,
 >(
-  // Takes messages from the client
-  mut connector_outgoing_recv: Receiver<ButtplugRemoteConnectorMessage<OutboundMessageType>>,
+  // Takes messages from the client, already serialized.
+  mut connector_outgoing_recv: Receiver<ButtplugRemoteConnectorMessage>,
+  // Shared with the connector, which serializes outgoing messages.
+  serializer: Arc<SerializerType>,
   // Sends messages not matched in the sorter to the client.
   connector_incoming_sender: Sender<InboundMessageType>,
   transport: TransportType,
@@ -71,15 +67,13 @@ async fn remote_connector_event_loop<
   OutboundMessageType: ButtplugMessage + 'static,
   InboundMessageType: ButtplugMessage + 'static,
 {
-  // Message sorter that receives messages that come in from the client.
-  let serializer = SerializerType::default();
   loop {
     // We use two Options instead of an enum because we may never get anything.
     //
     // For the type, we will get back one of two things: Either a serialized
     // incoming message from the transport for the connector, or an outgoing
     // message from the connector to go to the transport.
-    let mut stream_return = select! {
+    let stream_return = select! {
       // Catch messages coming in from the transport.
       transport = transport_incoming_recv.recv().fuse() =>
       match transport {
@@ -138,29 +132,24 @@ async fn remote_connector_event_loop<
       }
       // If we receive something from the client, register it with our sorter
       // then let the connector figure out what to do with it.
-      StreamValue::Outgoing(ref mut buttplug_msg) => {
-        match buttplug_msg {
-          ButtplugRemoteConnectorMessage::Message(msg) => {
-            // Create future sets our message ID, so make sure this
-            // happens before we send out the message.
-            let serialized_msg = serializer.serialize(&[msg.clone()]);
-            if transport_outgoing_sender
-              .send(serialized_msg)
-              .await
-              .is_err()
-            {
-              error!("Transport has disconnected, exiting remote connector loop.");
-              return;
-            }
+      StreamValue::Outgoing(buttplug_msg) => match buttplug_msg {
+        ButtplugRemoteConnectorMessage::Message(serialized_msg) => {
+          if transport_outgoing_sender
+            .send(serialized_msg)
+            .await
+            .is_err()
+          {
+            error!("Transport has disconnected, exiting remote connector loop.");
+            return;
           }
-          ButtplugRemoteConnectorMessage::Close => {
-            if let Err(e) = transport.disconnect().await {
-              error!("Error disconnecting transport: {:?}", e);
-            }
-            break;
+        }
+        ButtplugRemoteConnectorMessage::Close => {
+          if let Err(e) = transport.disconnect().await {
+            error!("Error disconnecting transport: {:?}", e);
           }
+          break;
         }
-      }
+      },
     }
   }
 }
@@ -205,8 +194,10 @@ pub struct ButtplugRemoteConnector<
   /// sure the transport is dropped.
   transport: Option<TransportType>,
   /// Sender for forwarding outgoing messages to the connector event loop.
-  event_loop_sender: Option<Sender<ButtplugRemoteConnectorMessage<OutboundMessageType>>>,
-  dummy_serializer: PhantomData<SerializerType>,
+  event_loop_sender: Option<Sender<ButtplugRemoteConnectorMessage>>,
+  /// Serializes outgoing messages in [ButtplugConnector::send], so failures go back to the sender,
+  /// and deserializes incoming messages in the event loop.
+  serializer: Arc<SerializerType>,
 }
 
 impl<TransportType, SerializerType, OutboundMessageType, InboundMessageType>
@@ -222,7 +213,7 @@ where
     Self {
       transport: Some(transport),
       event_loop_sender: None,
-      dummy_serializer: PhantomData::default(),
+      serializer: Arc::new(SerializerType::default()),
     }
   }
 }
@@ -253,6 +244,7 @@ where
         .expect("Already checked that this would be a valid take().");
       let (connector_outgoing_sender, connector_outgoing_receiver) = channel(256);
       self.event_loop_sender = Some(connector_outgoing_sender);
+      let serializer = self.serializer.clone();
       async move {
         let (transport_outgoing_sender, transport_outgoing_receiver) = channel(256);
         let (transport_incoming_sender, transport_incoming_receiver) = channel(256);
@@ -271,6 +263,7 @@ where
                 InboundMessageType,
               >(
                 connector_outgoing_receiver,
+                serializer,
                 connector_incoming_sender,
                 transport,
                 transport_outgoing_sender,
@@ -306,10 +299,16 @@ where
 
   fn send(&self, msg: OutboundMessageType) -> ButtplugConnectorResultFuture {
     if let Some(ref sender) = self.event_loop_sender {
+      // Serialize here rather than in the event loop, so messages that can't be sent fail the send
+      // instead of being dropped.
+      let serialized_msg = match self.serializer.try_serialize(&[msg]) {
+        Ok(serialized_msg) => serialized_msg,
+        Err(e) => return ButtplugConnectorError::SerializerError(e).into(),
+      };
       let sender_clone = sender.clone();
       async move {
         sender_clone
-          .send(ButtplugRemoteConnectorMessage::Message(msg))
+          .send(ButtplugRemoteConnectorMessage::Message(serialized_msg))
           .await
           .map_err(|_| ButtplugConnectorError::ConnectorNotConnected)
       }
@@ -7,6 +7,9 @@
 
 //! Transports for remote (IPC/network/etc) communication between clients and servers
 
+mod stream;
+#[cfg(feature = "tcp-transport")]
+mod tcp;
 #[cfg(feature = "websockets")]
 mod websocket;
 use crate::core::connector::{
@@ -15,6 +18,15 @@ use crate::core::connector::{
   ButtplugSerializedMessage,
 };
 use futures::future::BoxFuture;
+#[cfg(feature = "stdio-transport")]
+pub use stream::ButtplugStdioTransport;
+pub use stream::ButtplugStreamTransport;
+#[cfg(feature = "tcp-transport")]
+pub use tcp::{
+  ButtplugTcpClientTransport,
+  ButtplugTcpServerTransport,
+  ButtplugTcpServerTransportBuilder,
+};
 use thiserror::Error;
 use tokio::sync::mpsc::{Receiver, Sender};
 #[cfg(feature = "websockets")]
@@ -55,4 +67,7 @@ pub enum ButtplugConnectorTransportSpecificError {
   TungsteniteError(#[from] TungsteniteError),
   #[error("Network error: {0}")]
   GenericNetworkError(String),
+  #[cfg(feature = "websockets")]
+  #[error("TLS error: {0}")]
+  TlsError(String),
 }
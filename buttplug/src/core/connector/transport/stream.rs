@@ -0,0 +1,209 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Newline delimited transport over arbitrary async byte streams (pipes, sockets, stdio, etc...)
+
+use crate::{
+  core::{
+    connector::{
+      transport::{ButtplugConnectorTransport, ButtplugTransportIncomingMessage},
+      ButtplugConnectorError,
+      ButtplugConnectorResultFuture,
+    },
+    message::serializer::ButtplugSerializedMessage,
+  },
+  util::async_manager,
+};
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+};
+use std::sync::{Arc, Mutex};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+  sync::{
+    mpsc::{Receiver, Sender},
+    Notify,
+  },
+};
+use tracing::Instrument;
+
+/// Runs the read/write loop for a newline delimited stream connection.
+///
+/// Each outgoing serialized message is written as a single line. Each non-empty line read from the
+/// stream is handed to the owning connector as a text message. The loop exits when either side of
+/// the stream closes, the connector drops its channels, or disconnect is requested.
+pub(super) async fn run_stream_connection_loop<R, W>(
+  reader: R,
+  mut writer: W,
+  mut outgoing_receiver: Receiver<ButtplugSerializedMessage>,
+  incoming_sender: Sender<ButtplugTransportIncomingMessage>,
+  disconnect_notifier: Arc<Notify>,
+) where
+  R: AsyncRead + Unpin,
+  W: AsyncWrite + Unpin,
+{
+  let mut lines = BufReader::new(reader).lines();
+  loop {
+    select! {
+      _ = disconnect_notifier.notified().fuse() => {
+        info!("Stream transport requested to disconnect.");
+        if let Err(e) = writer.shutdown().await {
+          warn!("Cannot shut down stream, assuming already closed: {:?}", e);
+        }
+        let _ = incoming_sender
+          .send(ButtplugTransportIncomingMessage::Close("Disconnect requested".to_owned()))
+          .await;
+        return;
+      },
+      serialized_msg = outgoing_receiver.recv().fuse() => match serialized_msg {
+        Some(ButtplugSerializedMessage::Text(text_msg)) => {
+          trace!("Sending text message: {}", text_msg);
+          // Serializers output single line JSON, so we can use newlines as message delimiters.
+          let line = format!("{}\n", text_msg);
+          if writer.write_all(line.as_bytes()).await.is_err() || writer.flush().await.is_err() {
+            warn!("Cannot write to stream, considering connection closed.");
+            let _ = incoming_sender
+              .send(ButtplugTransportIncomingMessage::Close("Stream closed".to_owned()))
+              .await;
+            return;
+          }
+        }
+        Some(ButtplugSerializedMessage::Binary(_)) => {
+          error!("Don't know how to handle binary message types!");
+        }
+        None => {
+          info!("Stream transport owner dropped, closing stream.");
+          if let Err(e) = writer.shutdown().await {
+            warn!("Cannot shut down stream, assuming already closed: {:?}", e);
+          }
+          return;
+        }
+      },
+      line = lines.next_line().fuse() => match line {
+        Ok(Some(line)) => {
+          let line = line.trim();
+          if line.is_empty() {
+            continue;
+          }
+          trace!("Got text: {}", line);
+          if incoming_sender
+            .send(ButtplugTransportIncomingMessage::Message(ButtplugSerializedMessage::Text(
+              line.to_owned(),
+            )))
+            .await
+            .is_err()
+          {
+            warn!("Connector that owns transport no longer available, exiting.");
+            return;
+          }
+        }
+        Ok(None) => {
+          info!("Remote closed stream.");
+          let _ = incoming_sender
+            .send(ButtplugTransportIncomingMessage::Close("Stream closed".to_owned()))
+            .await;
+          return;
+        }
+        Err(e) => {
+          warn!("Error reading from stream, assuming disconnection: {:?}", e);
+          let _ = incoming_sender
+            .send(ButtplugTransportIncomingMessage::Close("Stream closed".to_owned()))
+            .await;
+          return;
+        }
+      }
+    }
+  }
+}
+
+/// Newline delimited transport over an already established pair of async streams.
+///
+/// Can be used with anything that implements tokio's [AsyncRead]/[AsyncWrite], such as the
+/// stdin/stdout handles of a child process. For the server side of a child process setup, see
+/// [ButtplugStdioTransport](crate::core::connector::ButtplugStdioTransport).
+pub struct ButtplugStreamTransport<R, W>
+where
+  R: AsyncRead + Unpin + Send + 'static,
+  W: AsyncWrite + Unpin + Send + 'static,
+{
+  streams: Mutex<Option<(R, W)>>,
+  disconnect_notifier: Arc<Notify>,
+}
+
+impl<R, W> ButtplugStreamTransport<R, W>
+where
+  R: AsyncRead + Unpin + Send + 'static,
+  W: AsyncWrite + Unpin + Send + 'static,
+{
+  pub fn new(reader: R, writer: W) -> Self {
+    Self {
+      streams: Mutex::new(Some((reader, writer))),
+      disconnect_notifier: Arc::new(Notify::new()),
+    }
+  }
+}
+
+impl<R, W> ButtplugConnectorTransport for ButtplugStreamTransport<R, W>
+where
+  R: AsyncRead + Unpin + Send + 'static,
+  W: AsyncWrite + Unpin + Send + 'static,
+{
+  fn connect(
+    &self,
+    outgoing_receiver: Receiver<ButtplugSerializedMessage>,
+    incoming_sender: Sender<ButtplugTransportIncomingMessage>,
+  ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+    let streams = self
+      .streams
+      .lock()
+      .expect("Lock is only held during take, cannot be poisoned.")
+      .take();
+    let Some((reader, writer)) = streams else {
+      return ButtplugConnectorError::ConnectorAlreadyConnected.into();
+    };
+    let disconnect_notifier = self.disconnect_notifier.clone();
+    async_manager::spawn(
+      run_stream_connection_loop(
+        reader,
+        writer,
+        outgoing_receiver,
+        incoming_sender,
+        disconnect_notifier,
+      )
+      .instrument(tracing::info_span!("Stream Transport I/O Task")),
+    );
+    future::ready(Ok(())).boxed()
+  }
+
+  fn disconnect(self) -> ButtplugConnectorResultFuture {
+    let disconnect_notifier = self.disconnect_notifier;
+    async move {
+      // The connection loop may be busy reading or writing rather than waiting on the notifier, so
+      // store a permit with notify_one instead of only waking current waiters.
+      disconnect_notifier.notify_one();
+      Ok(())
+    }
+    .boxed()
+  }
+}
+
+#[cfg(feature = "stdio-transport")]
+/// Newline delimited transport over the stdin/stdout of the current process.
+///
+/// Meant for running a server as a child process of a host application, which talks to it over the
+/// child's pipes. Note that anything else printed to stdout (including logging) will corrupt the
+/// message stream, so log output should go to stderr or a file.
+pub type ButtplugStdioTransport = ButtplugStreamTransport<tokio::io::Stdin, tokio::io::Stdout>;
+
+#[cfg(feature = "stdio-transport")]
+impl ButtplugStreamTransport<tokio::io::Stdin, tokio::io::Stdout> {
+  /// Creates a transport using the stdin/stdout of the current process.
+  pub fn new_stdio() -> Self {
+    Self::new(tokio::io::stdin(), tokio::io::stdout())
+  }
+}
@@ -0,0 +1,14 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Raw TCP connector for client/server communication, using newline delimited messages
+
+pub mod tcp_client;
+pub mod tcp_server;
+
+pub use tcp_client::ButtplugTcpClientTransport;
+pub use tcp_server::{ButtplugTcpServerTransport, ButtplugTcpServerTransportBuilder};
@@ -0,0 +1,94 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Raw TCP client transport
+
+use crate::{
+  core::{
+    connector::{
+      transport::{
+        stream::run_stream_connection_loop,
+        ButtplugConnectorTransport,
+        ButtplugConnectorTransportSpecificError,
+        ButtplugTransportIncomingMessage,
+      },
+      ButtplugConnectorError,
+      ButtplugConnectorResultFuture,
+    },
+    message::serializer::ButtplugSerializedMessage,
+  },
+  util::async_manager,
+};
+use futures::{future::BoxFuture, FutureExt};
+use std::sync::Arc;
+use tokio::{
+  net::TcpStream,
+  sync::{
+    mpsc::{Receiver, Sender},
+    Notify,
+  },
+};
+use tracing::Instrument;
+
+/// Raw TCP transport for ButtplugClients, sending newline delimited messages.
+pub struct ButtplugTcpClientTransport {
+  /// Address of the server we'll connect to, i.e. "127.0.0.1:12345"
+  address: String,
+  /// Internally held notifier, used for when disconnect is called.
+  disconnect_notifier: Arc<Notify>,
+}
+
+impl ButtplugTcpClientTransport {
+  /// Creates a new transport for connecting to a server at `address`, in "host:port" format.
+  pub fn new(address: &str) -> Self {
+    Self {
+      address: address.to_owned(),
+      disconnect_notifier: Arc::new(Notify::new()),
+    }
+  }
+}
+
+impl ButtplugConnectorTransport for ButtplugTcpClientTransport {
+  fn connect(
+    &self,
+    outgoing_receiver: Receiver<ButtplugSerializedMessage>,
+    incoming_sender: Sender<ButtplugTransportIncomingMessage>,
+  ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+    let disconnect_notifier = self.disconnect_notifier.clone();
+    let address = self.address.clone();
+    async move {
+      let stream = TcpStream::connect(&address).await.map_err(|e| {
+        ButtplugConnectorError::TransportSpecificError(
+          ButtplugConnectorTransportSpecificError::GenericNetworkError(format!("{:?}", e)),
+        )
+      })?;
+      info!("TCP: Connected to {}", address);
+      let (reader, writer) = stream.into_split();
+      async_manager::spawn(
+        run_stream_connection_loop(
+          reader,
+          writer,
+          outgoing_receiver,
+          incoming_sender,
+          disconnect_notifier,
+        )
+        .instrument(tracing::info_span!("TCP Client I/O Task")),
+      );
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn disconnect(self) -> ButtplugConnectorResultFuture {
+    let disconnect_notifier = self.disconnect_notifier;
+    async move {
+      disconnect_notifier.notify_one();
+      Ok(())
+    }
+    .boxed()
+  }
+}
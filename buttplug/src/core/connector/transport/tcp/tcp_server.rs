@@ -0,0 +1,133 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Raw TCP server transport
+
+use crate::{
+  core::{
+    connector::{
+      transport::{
+        stream::run_stream_connection_loop,
+        ButtplugConnectorTransport,
+        ButtplugConnectorTransportSpecificError,
+        ButtplugTransportIncomingMessage,
+      },
+      ButtplugConnectorError,
+      ButtplugConnectorResultFuture,
+    },
+    message::serializer::ButtplugSerializedMessage,
+  },
+  util::async_manager,
+};
+use futures::{future::BoxFuture, FutureExt};
+use std::sync::Arc;
+use tokio::{
+  net::TcpListener,
+  sync::{
+    mpsc::{Receiver, Sender},
+    Notify,
+  },
+};
+use tracing::Instrument;
+
+#[derive(Clone, Debug)]
+pub struct ButtplugTcpServerTransportBuilder {
+  /// If true, listens all on available interfaces. Otherwise, only listens on 127.0.0.1.
+  listen_on_all_interfaces: bool,
+  /// Port for listening for TCP connections.
+  port: u16,
+}
+
+impl Default for ButtplugTcpServerTransportBuilder {
+  fn default() -> Self {
+    Self {
+      listen_on_all_interfaces: false,
+      port: 12345,
+    }
+  }
+}
+
+impl ButtplugTcpServerTransportBuilder {
+  pub fn listen_on_all_interfaces(&mut self, listen_on_all_interfaces: bool) -> &mut Self {
+    self.listen_on_all_interfaces = listen_on_all_interfaces;
+    self
+  }
+
+  pub fn port(&mut self, port: u16) -> &mut Self {
+    self.port = port;
+    self
+  }
+
+  pub fn finish(&self) -> ButtplugTcpServerTransport {
+    ButtplugTcpServerTransport {
+      port: self.port,
+      listen_on_all_interfaces: self.listen_on_all_interfaces,
+      disconnect_notifier: Arc::new(Notify::new()),
+    }
+  }
+}
+
+/// Raw TCP transport for ButtplugServers, sending newline delimited messages. Accepts a single
+/// connection.
+pub struct ButtplugTcpServerTransport {
+  port: u16,
+  listen_on_all_interfaces: bool,
+  disconnect_notifier: Arc<Notify>,
+}
+
+impl ButtplugConnectorTransport for ButtplugTcpServerTransport {
+  fn connect(
+    &self,
+    outgoing_receiver: Receiver<ButtplugSerializedMessage>,
+    incoming_sender: Sender<ButtplugTransportIncomingMessage>,
+  ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+    let disconnect_notifier = self.disconnect_notifier.clone();
+    let base_addr = if self.listen_on_all_interfaces {
+      "0.0.0.0"
+    } else {
+      "127.0.0.1"
+    };
+    let addr = format!("{}:{}", base_addr, self.port);
+    debug!("TCP: Trying to listen on {}", addr);
+    async move {
+      let listener = TcpListener::bind(&addr).await.map_err(|e| {
+        ButtplugConnectorError::TransportSpecificError(
+          ButtplugConnectorTransportSpecificError::GenericNetworkError(format!("{:?}", e)),
+        )
+      })?;
+      debug!("TCP: Listening on: {}", addr);
+      let (stream, remote_addr) = listener.accept().await.map_err(|e| {
+        ButtplugConnectorError::TransportSpecificError(
+          ButtplugConnectorTransportSpecificError::GenericNetworkError(format!("{:?}", e)),
+        )
+      })?;
+      info!("TCP: Got connection from {}", remote_addr);
+      let (reader, writer) = stream.into_split();
+      async_manager::spawn(
+        run_stream_connection_loop(
+          reader,
+          writer,
+          outgoing_receiver,
+          incoming_sender,
+          disconnect_notifier,
+        )
+        .instrument(tracing::info_span!("TCP Server I/O Task")),
+      );
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn disconnect(self) -> ButtplugConnectorResultFuture {
+    let disconnect_notifier = self.disconnect_notifier;
+    async move {
+      disconnect_notifier.notify_one();
+      Ok(())
+    }
+    .boxed()
+  }
+}
@@ -22,36 +22,73 @@ use crate::{
   },
   util::async_manager,
 };
-use async_tungstenite::{tokio::connect_async_with_tls_connector, tungstenite::protocol::Message};
-use futures::{future::BoxFuture, FutureExt, SinkExt, StreamExt};
+use async_tungstenite::{
+  tokio::{client_async, connect_async_with_tls_connector, TokioAdapter},
+  tungstenite::{
+    client::IntoClientRequest,
+    handshake::client::Request,
+    http::{HeaderName, HeaderValue},
+    protocol::Message,
+  },
+  WebSocketStream,
+};
+use futures::{future::BoxFuture, AsyncRead, AsyncWrite, FutureExt, SinkExt, StreamExt};
 use std::sync::Arc;
-use tokio::sync::{
-  mpsc::{Receiver, Sender},
-  Notify,
+use tokio::{
+  net::TcpStream,
+  sync::{
+    mpsc::{Receiver, Sender},
+    Notify,
+  },
 };
-use tokio_native_tls::native_tls::TlsConnector as NativeTlsConnector;
-use tokio_native_tls::TlsConnector;
+use tokio_native_tls::native_tls::{self, Certificate, TlsConnector as NativeTlsConnector};
+use tokio_native_tls::{TlsConnector, TlsStream};
 use tracing::Instrument;
+use url::Url;
+
+/// How the server certificate is verified on secure connections.
+#[derive(Clone, Debug)]
+enum TlsVerification {
+  /// Verify against the system trust store.
+  System,
+  /// Do not verify the certificate at all.
+  Bypass,
+  /// Verify against the system trust store plus the given PEM encoded CA certificate.
+  CustomCa(Vec<u8>),
+  /// Only accept a server presenting exactly the given PEM encoded certificate.
+  PinnedCertificate(Vec<u8>),
+}
+
+fn tls_error(err: impl std::fmt::Display) -> ButtplugConnectorError {
+  ButtplugConnectorError::TransportSpecificError(ButtplugConnectorTransportSpecificError::TlsError(
+    err.to_string(),
+  ))
+}
+
+fn tungstenite_error(err: async_tungstenite::tungstenite::Error) -> ButtplugConnectorError {
+  ButtplugConnectorError::TransportSpecificError(
+    ButtplugConnectorTransportSpecificError::TungsteniteError(err),
+  )
+}
 
 /// Websocket connector for ButtplugClients, using [async_tungstenite]
 pub struct ButtplugWebsocketClientTransport {
   /// Address of the server we'll connect to.
   address: String,
-  /// If true, use a TLS wrapper on our connection.
-  should_use_tls: bool,
-  /// If true, bypass certificate verification. Should be true for self-signed
-  /// certs.
-  bypass_cert_verify: bool,
+  /// If set, use a TLS wrapper on our connection, verifying the server as specified.
+  tls_verification: Option<TlsVerification>,
+  /// Extra HTTP headers sent with the websocket upgrade request.
+  headers: Vec<(String, String)>,
   /// Internally held sender, used for when disconnect is called.
   disconnect_notifier: Arc<Notify>,
 }
 
 impl ButtplugWebsocketClientTransport {
-  fn create(address: &str, should_use_tls: bool, bypass_cert_verify: bool) -> Self {
+  fn create(address: &str, tls_verification: Option<TlsVerification>) -> Self {
     Self {
-      should_use_tls,
       address: address.to_owned(),
-      bypass_cert_verify,
+      tls_verification,
+      headers: vec![],
       disconnect_notifier: Arc::new(Notify::new()),
     }
   }
@@ -62,173 +99,315 @@ impl ButtplugWebsocketClientTransport {
   /// server. Address should be the full URL of the server, i.e.
   /// "ws://127.0.0.1:12345"
   pub fn new_insecure_connector(address: &str) -> Self {
-    ButtplugWebsocketClientTransport::create(address, false, false)
+    ButtplugWebsocketClientTransport::create(address, None)
   }
 
   /// Creates a new connector for "wss://" addresses
   ///
   /// Returns a websocket connector for connecting over secure websockets to a
   /// server. Address should be the full URL of the server, i.e.
-  /// "ws://127.0.0.1:12345". If `bypass_cert_verify` is true, then the
+  /// "wss://127.0.0.1:12345". If `bypass_cert_verify` is true, then the
   /// certificate of the server will not be verified (useful for servers using
   /// self-signed certs).
   pub fn new_secure_connector(address: &str, bypass_cert_verify: bool) -> Self {
-    ButtplugWebsocketClientTransport::create(address, true, bypass_cert_verify)
+    let verification = if bypass_cert_verify {
+      TlsVerification::Bypass
+    } else {
+      TlsVerification::System
+    };
+    ButtplugWebsocketClientTransport::create(address, Some(verification))
+  }
+
+  /// Creates a new connector for "wss://" addresses, trusting a custom CA
+  ///
+  /// The server certificate must be issued by either a CA in the system trust
+  /// store or the PEM encoded `ca_certificate`, and must match the host in the
+  /// address.
+  pub fn new_secure_connector_with_ca(address: &str, ca_certificate: &[u8]) -> Self {
+    ButtplugWebsocketClientTransport::create(
+      address,
+      Some(TlsVerification::CustomCa(ca_certificate.to_vec())),
+    )
+  }
+
+  /// Creates a new connector for "wss://" addresses, pinned to a certificate
+  ///
+  /// The connection is only accepted if the server presents exactly the PEM
+  /// encoded `certificate`. Chain and hostname verification are skipped, which
+  /// makes this suitable for self-signed certs whose contents are known ahead
+  /// of time.
+  pub fn new_secure_connector_with_pinned_cert(address: &str, certificate: &[u8]) -> Self {
+    ButtplugWebsocketClientTransport::create(
+      address,
+      Some(TlsVerification::PinnedCertificate(certificate.to_vec())),
+    )
+  }
+
+  /// Adds an HTTP header to the websocket upgrade request.
+  ///
+  /// Useful for authenticating with proxies or gateways sitting in front of a server.
+  pub fn with_header(mut self, name: &str, value: &str) -> Self {
+    self.headers.push((name.to_owned(), value.to_owned()));
+    self
+  }
+
+  /// Adds an "Authorization: Bearer" header with the given token to the websocket upgrade request.
+  ///
+  /// Websocket server transports with an authenticator set check this header before accepting the
+  /// connection. Servers that require authentication during the Buttplug handshake instead need the
+  /// token passed to [ButtplugClient::new_with_auth_token](crate::client::ButtplugClient::new_with_auth_token).
+  pub fn with_bearer_token(self, token: &str) -> Self {
+    self.with_header("Authorization", &format!("Bearer {}", token))
+  }
+
+  /// Builds the websocket upgrade request, including any extra headers.
+  fn create_request(&self) -> Result<Request, String> {
+    let mut request = self
+      .address
+      .as_str()
+      .into_client_request()
+      .map_err(|e| format!("Invalid address {}: {}", self.address, e))?;
+    for (name, value) in &self.headers {
+      let name = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|e| format!("Invalid header name {}: {}", name, e))?;
+      let value = HeaderValue::from_str(value)
+        .map_err(|e| format!("Invalid value for header {}: {}", name, e))?;
+      request.headers_mut().append(name, value);
+    }
+    Ok(request)
+  }
+}
+
+/// Builds the TLS connector for non-pinned verification modes.
+fn create_tls_connector(verification: &TlsVerification) -> Result<TlsConnector, native_tls::Error> {
+  let mut builder = NativeTlsConnector::builder();
+  match verification {
+    TlsVerification::System => {}
+    TlsVerification::Bypass => {
+      builder.danger_accept_invalid_certs(true);
+    }
+    TlsVerification::CustomCa(ca_certificate) => {
+      builder.add_root_certificate(Certificate::from_pem(ca_certificate)?);
+    }
+    TlsVerification::PinnedCertificate(_) => {
+      // Chain verification is replaced by the pin check after the handshake.
+      builder
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true);
+    }
+  }
+  Ok(builder.build()?.into())
+}
+
+/// Connects to a "wss://" address, only accepting the server if it presents the pinned certificate.
+async fn connect_with_pinned_cert(
+  address: &str,
+  request: Request,
+  tls_connector: TlsConnector,
+  pinned_certificate: &[u8],
+) -> Result<WebSocketStream<TokioAdapter<TlsStream<TcpStream>>>, ButtplugConnectorError> {
+  let pinned_der = Certificate::from_pem(pinned_certificate)
+    .and_then(|cert| cert.to_der())
+    .map_err(tls_error)?;
+  let url = Url::parse(address)
+    .map_err(|e| ButtplugConnectorError::ConnectorGenericError(format!("{:?}", e)))?;
+  let host = url.host_str().ok_or_else(|| {
+    ButtplugConnectorError::ConnectorGenericError(format!("No host in address {}", address))
+  })?;
+  let port = url.port_or_known_default().unwrap_or(443);
+  let tcp_stream = TcpStream::connect((host, port)).await.map_err(|e| {
+    ButtplugConnectorError::TransportSpecificError(
+      ButtplugConnectorTransportSpecificError::GenericNetworkError(format!("{:?}", e)),
+    )
+  })?;
+  let tls_stream = tls_connector
+    .connect(host, tcp_stream)
+    .await
+    .map_err(tls_error)?;
+  let peer_der = tls_stream
+    .get_ref()
+    .peer_certificate()
+    .map_err(tls_error)?
+    .map(|cert| cert.to_der())
+    .transpose()
+    .map_err(tls_error)?;
+  if peer_der.as_ref() != Some(&pinned_der) {
+    return Err(tls_error(
+      "Server certificate does not match pinned certificate",
+    ));
+  }
+  let (stream, _) = client_async(request, tls_stream)
+    .await
+    .map_err(tungstenite_error)?;
+  Ok(stream)
+}
+
+async fn run_connection_loop<S>(
+  stream: WebSocketStream<S>,
+  mut outgoing_receiver: Receiver<ButtplugSerializedMessage>,
+  incoming_sender: Sender<ButtplugTransportIncomingMessage>,
+  disconnect_notifier: Arc<Notify>,
+) where
+  S: AsyncRead + AsyncWrite + Unpin,
+{
+  let (mut writer, mut reader) = stream.split();
+
+  loop {
+    select! {
+      msg = outgoing_receiver.recv().fuse() => {
+        if let Some(msg) = msg {
+          let out_msg = match msg {
+            ButtplugSerializedMessage::Text(text) => Message::Text(text),
+            ButtplugSerializedMessage::Binary(bin) => Message::Binary(bin),
+          };
+          // TODO see what happens when we try to send to a remote that's closed connection.
+          writer.send(out_msg).await.expect("This should never fail?");
+        } else {
+          info!("Connector holding websocket dropped, returning");
+          writer.close().await.unwrap_or_else(|err| error!("{}", err));
+          if incoming_sender
+            .send(ButtplugTransportIncomingMessage::Close("Server closed connection".to_owned()))
+            .await
+            .is_err()
+          {
+            warn!("Websocket holder has closed, exiting websocket loop.");
+          }
+          return;
+        }
+      },
+      response = reader.next().fuse() => {
+        trace!("Websocket receiving: {:?}", response);
+        if response.is_none() {
+          info!("Connector holding websocket dropped, returning");
+          writer.close().await.unwrap_or_else(|err| error!("{}", err));
+          return;
+        }
+        match response.expect("Already checked for none.") {
+          Ok(msg) => match msg {
+            Message::Text(t) => {
+              if incoming_sender
+                .send(ButtplugTransportIncomingMessage::Message(
+                  ButtplugSerializedMessage::Text(t.to_string()),
+                ))
+                .await
+                .is_err()
+              {
+                warn!("Websocket holder has closed, exiting websocket loop.");
+                return;
+              }
+            }
+            Message::Binary(v) => {
+              if incoming_sender
+                .send(ButtplugTransportIncomingMessage::Message(
+                  ButtplugSerializedMessage::Binary(v),
+                ))
+                .await
+                .is_err()
+              {
+                warn!("Websocket holder has closed, exiting websocket loop.");
+                return;
+              }
+            }
+            Message::Ping(data) => {
+              writer.send(Message::Pong(data)).await.expect("This should never fail?");
+            }
+            Message::Pong(_) => {}
+            Message::Frame(_) => {}
+            Message::Close(_) => {
+              info!("Websocket has requested close.");
+              if incoming_sender
+                .send(ButtplugTransportIncomingMessage::Close("Server closed connection".to_owned()))
+                .await
+                .is_err()
+              {
+                warn!("Websocket holder has closed, exiting websocket loop.");
+                return;
+              }
+              return;
+            }
+          },
+          Err(err) => {
+            error!(
+              "Error in websocket client loop (assuming disconnect): {}",
+              err
+            );
+            break;
+          }
+        }
+      }
+      _ = disconnect_notifier.notified().fuse() => {
+        // If we can't close, just print the error to the logs but
+        // still break out of the loop.
+        //
+        // TODO Emit a full error here that should bubble up to the client.
+        info!("Websocket requested to disconnect.");
+        writer.close().await.unwrap_or_else(|err| error!("{}", err));
+        if incoming_sender
+          .send(ButtplugTransportIncomingMessage::Close("Disconnect notifier triggered, closed connection".to_owned()))
+          .await
+          .is_err()
+        {
+          warn!("Websocket holder has closed, exiting websocket loop.");
+          return;
+        }
+        return;
+      }
+    }
   }
 }
 
 impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
   fn connect(
     &self,
-    mut outgoing_receiver: Receiver<ButtplugSerializedMessage>,
+    outgoing_receiver: Receiver<ButtplugSerializedMessage>,
     incoming_sender: Sender<ButtplugTransportIncomingMessage>,
   ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
     let disconnect_notifier = self.disconnect_notifier.clone();
-
-    // If we're supposed to be a secure connection, generate a TLS connector
-    // based on our certificate verfication needs. Otherwise, just pass None in
-    // which case we won't wrap.
-    let tls_connector: Option<TlsConnector> = if self.should_use_tls {
-      if self.bypass_cert_verify {
-        Some(
-          NativeTlsConnector::builder()
-            .danger_accept_invalid_certs(true)
-            .build()
-            .expect("Should always succeed, we're not setting any fallible options.")
-            .into(),
-        )
-      } else {
-        Some(
-          NativeTlsConnector::new()
-            .expect("Should always succeed, not setting options.")
-            .into(),
-        )
-      }
-    } else {
-      // If we're not using a secure connection, just return None, at which
-      // point async_tungstenite won't use a wrapper.
-      None
-    };
+    let tls_verification = self.tls_verification.clone();
     let address = self.address.clone();
+    let request = self.create_request();
 
     async move {
-      match connect_async_with_tls_connector(&address, tls_connector).await {
-        Ok((stream, _)) => {
-          let (mut writer, mut reader) = stream.split();
-
+      let request = request.map_err(ButtplugConnectorError::ConnectorGenericError)?;
+      // If we're supposed to be a secure connection, generate a TLS connector
+      // based on our certificate verfication needs. Otherwise, just pass None in
+      // which case async_tungstenite won't use a wrapper.
+      let tls_connector = tls_verification
+        .as_ref()
+        .map(create_tls_connector)
+        .transpose()
+        .map_err(tls_error)?;
+      let span = tracing::info_span!("Websocket Client I/O Task");
+      match (tls_verification, tls_connector) {
+        (Some(TlsVerification::PinnedCertificate(certificate)), Some(tls_connector)) => {
+          let stream =
+            connect_with_pinned_cert(&address, request, tls_connector, &certificate).await?;
           async_manager::spawn(
-            async move {
-              loop {
-                select! {
-                  msg = outgoing_receiver.recv().fuse() => {
-                    if let Some(msg) = msg {
-                      let out_msg = match msg {
-                        ButtplugSerializedMessage::Text(text) => Message::Text(text),
-                        ButtplugSerializedMessage::Binary(bin) => Message::Binary(bin),
-                      };
-                      // TODO see what happens when we try to send to a remote that's closed connection.
-                      writer.send(out_msg).await.expect("This should never fail?");
-                    } else {
-                      info!("Connector holding websocket dropped, returning");
-                      writer.close().await.unwrap_or_else(|err| error!("{}", err));
-                      if incoming_sender
-                        .send(ButtplugTransportIncomingMessage::Close("Server closed connection".to_owned()))
-                        .await
-                        .is_err()
-                      {
-                        warn!("Websocket holder has closed, exiting websocket loop.");
-                      }
-                      return;
-                    }
-                  },
-                  response = reader.next().fuse() => {
-                    trace!("Websocket receiving: {:?}", response);
-                    if response.is_none() {
-                      info!("Connector holding websocket dropped, returning");
-                      writer.close().await.unwrap_or_else(|err| error!("{}", err));
-                      return;
-                    }
-                    match response.expect("Already checked for none.") {
-                      Ok(msg) => match msg {
-                        Message::Text(t) => {
-                          if incoming_sender
-                            .send(ButtplugTransportIncomingMessage::Message(
-                              ButtplugSerializedMessage::Text(t.to_string()),
-                            ))
-                            .await
-                            .is_err()
-                          {
-                            warn!("Websocket holder has closed, exiting websocket loop.");                                
-                            return;
-                          }
-                        }
-                        Message::Binary(v) => {
-                          if incoming_sender
-                            .send(ButtplugTransportIncomingMessage::Message(
-                              ButtplugSerializedMessage::Binary(v),
-                            ))
-                            .await
-                            .is_err()
-                          {
-                            warn!("Websocket holder has closed, exiting websocket loop.");
-                            return;
-                          }
-                        }
-                        Message::Ping(data) => {
-                          writer.send(Message::Pong(data)).await.expect("This should never fail?");
-                        }
-                        Message::Pong(_) => {}
-                        Message::Frame(_) => {}
-                        Message::Close(_) => {
-                          info!("Websocket has requested close.");
-                          if incoming_sender
-                            .send(ButtplugTransportIncomingMessage::Close("Server closed connection".to_owned()))
-                            .await
-                            .is_err()
-                          {
-                            warn!("Websocket holder has closed, exiting websocket loop.");
-                            return;
-                          }
-                          return;
-                        }
-                      },
-                      Err(err) => {
-                        error!(
-                          "Error in websocket client loop (assuming disconnect): {}",
-                          err
-                        );
-                        break;
-                      }
-                    }
-                  }
-                  _ = disconnect_notifier.notified().fuse() => {
-                    // If we can't close, just print the error to the logs but
-                    // still break out of the loop.
-                    //
-                    // TODO Emit a full error here that should bubble up to the client.
-                    info!("Websocket requested to disconnect.");
-                    writer.close().await.unwrap_or_else(|err| error!("{}", err));
-                    if incoming_sender
-                      .send(ButtplugTransportIncomingMessage::Close("Disconnect notifier triggered, closed connection".to_owned()))
-                      .await
-                      .is_err()
-                    {
-                      warn!("Websocket holder has closed, exiting websocket loop.");
-                      return;
-                    }
-                    return;
-                  }
-                }
-              }
-            }
-            .instrument(tracing::info_span!("Websocket Client I/O Task")),
+            run_connection_loop(
+              stream,
+              outgoing_receiver,
+              incoming_sender,
+              disconnect_notifier,
+            )
+            .instrument(span),
+          );
+        }
+        (_, tls_connector) => {
+          let (stream, _) = connect_async_with_tls_connector(request, tls_connector)
+            .await
+            .map_err(tungstenite_error)?;
+          async_manager::spawn(
+            run_connection_loop(
+              stream,
+              outgoing_receiver,
+              incoming_sender,
+              disconnect_notifier,
+            )
+            .instrument(span),
           );
-          Ok(())
         }
-        Err(websocket_error) => Err(ButtplugConnectorError::TransportSpecificError(
-          ButtplugConnectorTransportSpecificError::TungsteniteError(websocket_error),
-        )),
       }
+      Ok(())
     }
     .boxed()
   }
@@ -243,3 +422,26 @@ impl ButtplugConnectorTransport for ButtplugWebsocketClientTransport {
     .boxed()
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::ButtplugWebsocketClientTransport;
+
+  #[test]
+  fn test_websocket_client_request_headers() {
+    let transport =
+      ButtplugWebsocketClientTransport::new_insecure_connector("ws://127.0.0.1:12345")
+        .with_header("X-Test-Header", "test")
+        .with_bearer_token("secret");
+    let request = transport
+      .create_request()
+      .expect("Test, assuming infallible.");
+    assert_eq!(request.headers()["X-Test-Header"], "test");
+    assert_eq!(request.headers()["Authorization"], "Bearer secret");
+
+    let transport =
+      ButtplugWebsocketClientTransport::new_insecure_connector("ws://127.0.0.1:12345")
+        .with_header("Bad Header", "test");
+    assert!(transport.create_request().is_err());
+  }
+}
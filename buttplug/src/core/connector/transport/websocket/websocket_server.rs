@@ -5,6 +5,8 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
+#[cfg(feature = "server")]
+use crate::server::authenticator::{bearer_token, ButtplugServerAuthenticator};
 use crate::{
   core::{
     connector::{
@@ -20,8 +22,12 @@ use crate::{
   },
   util::async_manager,
 };
+use async_tungstenite::tungstenite::{
+  handshake::server::{ErrorResponse, Request, Response},
+  http::{header::AUTHORIZATION, StatusCode},
+};
 use futures::{future::BoxFuture, AsyncRead, AsyncWrite, FutureExt, SinkExt, StreamExt};
-use std::{sync::Arc, time::Duration};
+use std::{fmt, fs, io, path::Path, sync::Arc, time::Duration};
 use tokio::{
   net::TcpListener,
   sync::{
@@ -30,13 +36,44 @@ use tokio::{
   },
   time::sleep,
 };
+use tokio_native_tls::{native_tls, TlsAcceptor};
+
+/// PEM encoded certificate chain and private key used for serving "wss://" connections.
+#[derive(Clone)]
+struct ButtplugWebsocketServerTlsIdentity {
+  certificate: Vec<u8>,
+  private_key: Vec<u8>,
+}
+
+impl fmt::Debug for ButtplugWebsocketServerTlsIdentity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // Never print the private key into logs.
+    f.debug_struct("ButtplugWebsocketServerTlsIdentity")
+      .field("certificate", &String::from_utf8_lossy(&self.certificate))
+      .finish_non_exhaustive()
+  }
+}
+
+impl ButtplugWebsocketServerTlsIdentity {
+  fn acceptor(&self) -> Result<TlsAcceptor, native_tls::Error> {
+    let identity = native_tls::Identity::from_pkcs8(&self.certificate, &self.private_key)?;
+    Ok(native_tls::TlsAcceptor::new(identity)?.into())
+  }
+}
+
+/// Decides whether a websocket upgrade request is allowed, given its Authorization header value.
+type UpgradeAuthenticator = Arc<dyn Fn(Option<&str>) -> bool + Send + Sync>;
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct ButtplugWebsocketServerTransportBuilder {
   /// If true, listens all on available interfaces. Otherwise, only listens on 127.0.0.1.
   listen_on_all_interfaces: bool,
-  /// Insecure port for listening for websocket connections.
+  /// Port for listening for websocket connections.
   port: u16,
+  /// If set, wrap connections in TLS, serving "wss://" instead of "ws://".
+  tls_identity: Option<ButtplugWebsocketServerTlsIdentity>,
+  /// If set, websocket upgrade requests must carry credentials this accepts.
+  authenticator: Option<UpgradeAuthenticator>,
 }
 
 impl Default for ButtplugWebsocketServerTransportBuilder {
@@ -44,10 +81,23 @@ impl Default for ButtplugWebsocketServerTransportBuilder {
     Self {
       listen_on_all_interfaces: false,
       port: 12345,
+      tls_identity: None,
+      authenticator: None,
     }
   }
 }
 
+impl fmt::Debug for ButtplugWebsocketServerTransportBuilder {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ButtplugWebsocketServerTransportBuilder")
+      .field("listen_on_all_interfaces", &self.listen_on_all_interfaces)
+      .field("port", &self.port)
+      .field("tls_identity", &self.tls_identity)
+      .field("authenticator", &self.authenticator.is_some())
+      .finish()
+  }
+}
+
 impl ButtplugWebsocketServerTransportBuilder {
   pub fn listen_on_all_interfaces(&mut self, listen_on_all_interfaces: bool) -> &mut Self {
     self.listen_on_all_interfaces = listen_on_all_interfaces;
@@ -59,10 +109,53 @@ impl ButtplugWebsocketServerTransportBuilder {
     self
   }
 
+  /// Serve "wss://" using a PEM encoded certificate (chain) and PKCS#8 private key.
+  ///
+  /// The certificate and key are parsed when the transport starts listening, so invalid contents
+  /// are reported as a connection error.
+  pub fn tls_pem(&mut self, certificate: &[u8], private_key: &[u8]) -> &mut Self {
+    self.tls_identity = Some(ButtplugWebsocketServerTlsIdentity {
+      certificate: certificate.to_vec(),
+      private_key: private_key.to_vec(),
+    });
+    self
+  }
+
+  /// Serve "wss://" using a PEM encoded certificate (chain) and PKCS#8 private key loaded from
+  /// files.
+  pub fn tls_pem_files(
+    &mut self,
+    certificate_path: impl AsRef<Path>,
+    private_key_path: impl AsRef<Path>,
+  ) -> Result<&mut Self, io::Error> {
+    let certificate = fs::read(certificate_path)?;
+    let private_key = fs::read(private_key_path)?;
+    Ok(self.tls_pem(&certificate, &private_key))
+  }
+
+  /// Require websocket upgrade requests to carry an `Authorization: Bearer <token>` header that the
+  /// authenticator accepts. Requests that fail are rejected with HTTP 401 before the websocket
+  /// connection is established.
+  ///
+  /// This is separate from [ButtplugServerBuilder::authenticator](crate::server::ButtplugServerBuilder::authenticator),
+  /// which checks the token in the handshake message once the websocket is connected.
+  #[cfg(feature = "server")]
+  pub fn authenticator<T>(&mut self, authenticator: T) -> &mut Self
+  where
+    T: ButtplugServerAuthenticator + 'static,
+  {
+    self.authenticator = Some(Arc::new(move |authorization| {
+      authenticator.authenticate_token(authorization.and_then(bearer_token))
+    }));
+    self
+  }
+
   pub fn finish(&self) -> ButtplugWebsocketServerTransport {
     ButtplugWebsocketServerTransport {
       port: self.port,
       listen_on_all_interfaces: self.listen_on_all_interfaces,
+      tls_identity: self.tls_identity.clone(),
+      authenticator: self.authenticator.clone(),
       disconnect_notifier: Arc::new(Notify::new()),
     }
   }
@@ -195,10 +288,62 @@ async fn run_connection_loop<S>(
   }
 }
 
+/// Runs the websocket handshake on an accepted stream, then spawns the connection loop for it.
+async fn accept_and_run_connection_loop<S>(
+  stream: S,
+  authenticator: Option<UpgradeAuthenticator>,
+  request_receiver: Receiver<ButtplugSerializedMessage>,
+  response_sender: Sender<ButtplugTransportIncomingMessage>,
+  disconnect_notifier: Arc<Notify>,
+) -> Result<(), ButtplugConnectorError>
+where
+  S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+  // The error type here is set by tungstenite's handshake callback, so we can't shrink it.
+  #[allow(clippy::result_large_err)]
+  let check_authorization = move |request: &Request, response: Response| {
+    let Some(authenticator) = authenticator else {
+      return Ok(response);
+    };
+    let authorization = request
+      .headers()
+      .get(AUTHORIZATION)
+      .and_then(|value| value.to_str().ok());
+    if authenticator(authorization) {
+      Ok(response)
+    } else {
+      warn!("Websocket client failed authentication, rejecting connection.");
+      let mut error_response = ErrorResponse::new(Some("Unauthorized".to_owned()));
+      *error_response.status_mut() = StatusCode::UNAUTHORIZED;
+      Err(error_response)
+    }
+  };
+  let ws_stream = async_tungstenite::tokio::accept_hdr_async(stream, check_authorization)
+    .await
+    .map_err(|err| {
+      error!("Websocket server accept error: {:?}", err);
+      ButtplugConnectorError::TransportSpecificError(
+        ButtplugConnectorTransportSpecificError::TungsteniteError(err),
+      )
+    })?;
+  async_manager::spawn(async move {
+    run_connection_loop(
+      ws_stream,
+      request_receiver,
+      response_sender,
+      disconnect_notifier,
+    )
+    .await;
+  });
+  Ok(())
+}
+
 /// Websocket connector for ButtplugClients, using [async_tungstenite]
 pub struct ButtplugWebsocketServerTransport {
   port: u16,
   listen_on_all_interfaces: bool,
+  tls_identity: Option<ButtplugWebsocketServerTlsIdentity>,
+  authenticator: Option<UpgradeAuthenticator>,
   disconnect_notifier: Arc<Notify>,
 }
 
@@ -220,7 +365,19 @@ impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
     debug!("Websocket: Trying to listen on {}", addr);
     let response_sender_clone = incoming_sender;
     let disconnect_notifier_clone = disconnect_notifier;
+    let tls_identity = self.tls_identity.clone();
+    let authenticator = self.authenticator.clone();
     let fut = async move {
+      // Fail on bad certificates before we start listening.
+      let tls_acceptor = tls_identity
+        .as_ref()
+        .map(ButtplugWebsocketServerTlsIdentity::acceptor)
+        .transpose()
+        .map_err(|e| {
+          ButtplugConnectorError::TransportSpecificError(
+            ButtplugConnectorTransportSpecificError::TlsError(e.to_string()),
+          )
+        })?;
       // Create the event loop and TCP listener we'll accept connections on.
       let try_socket = TcpListener::bind(&addr).await;
       debug!("Websocket: Socket bound.");
@@ -232,22 +389,31 @@ impl ButtplugConnectorTransport for ButtplugWebsocketServerTransport {
       debug!("Websocket: Listening on: {}", addr);
       if let Ok((stream, _)) = listener.accept().await {
         info!("Websocket: Got connection");
-        let ws_fut = async_tungstenite::tokio::accept_async(stream);
-        let ws_stream = ws_fut.await.map_err(|err| {
-          error!("Websocket server accept error: {:?}", err);
-          ButtplugConnectorError::TransportSpecificError(
-            ButtplugConnectorTransportSpecificError::TungsteniteError(err),
+        if let Some(tls_acceptor) = tls_acceptor {
+          let tls_stream = tls_acceptor.accept(stream).await.map_err(|err| {
+            error!("Websocket server TLS accept error: {:?}", err);
+            ButtplugConnectorError::TransportSpecificError(
+              ButtplugConnectorTransportSpecificError::TlsError(err.to_string()),
+            )
+          })?;
+          accept_and_run_connection_loop(
+            tls_stream,
+            authenticator,
+            outgoing_receiver,
+            response_sender_clone,
+            disconnect_notifier_clone,
           )
-        })?;
-        async_manager::spawn(async move {
-          run_connection_loop(
-            ws_stream,
+          .await?;
+        } else {
+          accept_and_run_connection_loop(
+            stream,
+            authenticator,
             outgoing_receiver,
             response_sender_clone,
             disconnect_notifier_clone,
           )
-          .await;
-        });
+          .await?;
+        }
         Ok(())
       } else {
         Err(ButtplugConnectorError::ConnectorGenericError(
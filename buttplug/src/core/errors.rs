@@ -50,6 +50,8 @@ pub enum ButtplugHandshakeError {
   HandshakeAlreadyHappened,
   /// Server spec version ({0}) must be equal or greater than client version ({1})
   MessageSpecVersionMismatch(ButtplugMessageSpecVersion, ButtplugMessageSpecVersion),
+  /// Client failed authentication, connection rejected.
+  AuthenticationFailed,
   /// Untyped Deserialized Error: {0}
   UntypedDeserializedError(String),
 }
@@ -179,6 +181,31 @@ pub enum ButtplugDeviceError {
   DeviceSensorTypeMismatch(u32, SensorType, SensorType),
   /// Protocol does not have an implementation available for Sensor Type {0}
   ProtocolSensorNotSupported(SensorType),
+  /// No protocol could connect to device, attempts: {0:?}
+  ProtocolConnectionFailed(Vec<ProtocolConnectionAttempt>),
+}
+
+/// Stage of device connection during which a protocol failed.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum ProtocolConnectionStage {
+  /// hardware specialization
+  Specialization,
+  /// identification
+  Identification,
+  /// attribute lookup
+  AttributeLookup,
+  /// initialization
+  Initialization,
+}
+
+/// Protocol {protocol} failed during {stage}: {error}
+#[derive(Debug, Display, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct ProtocolConnectionAttempt {
+  pub protocol: String,
+  pub stage: ProtocolConnectionStage,
+  pub error: ButtplugDeviceError,
 }
 
 /// Unknown errors occur in exceptional circumstances where no other error type
@@ -27,7 +27,7 @@ pub enum ActuatorType {
   Position,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
 pub enum SensorType {
   Unknown,
   Battery,
@@ -318,13 +318,31 @@ pub struct SensorDeviceMessageAttributes {
   index: u32,
 }
 
-/*
 impl SensorDeviceMessageAttributes {
-  pub fn new(feature_descriptor: &str, sensor_type: SensorType) -> Self {
-    Self { feature_descriptor: feature_descriptor.to_owned(), sensor_type }
+  pub fn new(
+    feature_descriptor: &str,
+    sensor_type: SensorType,
+    sensor_range: &[RangeInclusive<u32>],
+  ) -> Self {
+    Self::new_with_index(feature_descriptor, sensor_type, sensor_range, 0)
+  }
+
+  /// Same as [new](Self::new), but with the sensor's position in the device's sensor list
+  /// instead of 0.
+  pub fn new_with_index(
+    feature_descriptor: &str,
+    sensor_type: SensorType,
+    sensor_range: &[RangeInclusive<u32>],
+    index: u32,
+  ) -> Self {
+    Self {
+      feature_descriptor: feature_descriptor.to_owned(),
+      sensor_type,
+      sensor_range: sensor_range.to_vec(),
+      index,
+    }
   }
 }
- */
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Getters, Setters)]
 pub struct ClientDeviceMessageAttributesV2 {
@@ -496,16 +514,21 @@ pub struct ClientDeviceMessageAttributesV1 {
 
   // StopDeviceCmd always exists
   #[getset(get = "pub")]
+  #[serde(rename = "StopDeviceCmd")]
+  #[serde(default)]
   stop_device_cmd: NullDeviceMessageAttributes,
 
   // Obsolete commands are only added post-serialization
   #[getset(get = "pub")]
+  #[serde(rename = "SingleMotorVibrateCmd")]
   #[serde(skip_serializing_if = "Option::is_none")]
   single_motor_vibrate_cmd: Option<NullDeviceMessageAttributes>,
   #[getset(get = "pub")]
+  #[serde(rename = "FleshlightLaunchFW12Cmd")]
   #[serde(skip_serializing_if = "Option::is_none")]
   fleshlight_launch_fw12_cmd: Option<NullDeviceMessageAttributes>,
   #[getset(get = "pub")]
+  #[serde(rename = "VorzeA10CycloneCmd")]
   #[serde(skip_serializing_if = "Option::is_none")]
   vorze_a10_cyclone_cmd: Option<NullDeviceMessageAttributes>,
 }
@@ -550,3 +573,115 @@ impl From<GenericDeviceMessageAttributesV2> for GenericDeviceMessageAttributesV1
     }
   }
 }
+
+// Older spec versions don't describe features as precisely as v3, so converting up from them only
+// gives the capabilities the older message set can express.
+
+/// Step count used for features converted from spec v1 and v0 attributes, which don't report steps.
+const ASSUMED_LEGACY_STEP_COUNT: u32 = 20;
+
+impl GenericDeviceMessageAttributesV2 {
+  fn to_client_attributes(
+    &self,
+    actuator_type: ActuatorType,
+  ) -> Option<Vec<ClientGenericDeviceMessageAttributes>> {
+    if self.feature_count == 0 {
+      return None;
+    }
+    Some(
+      (0..self.feature_count as usize)
+        .map(|index| {
+          ClientGenericDeviceMessageAttributes::new(
+            &unspecified_feature(),
+            *self
+              .step_count
+              .get(index)
+              .unwrap_or(&ASSUMED_LEGACY_STEP_COUNT),
+            actuator_type,
+          )
+        })
+        .collect(),
+    )
+  }
+}
+
+impl From<ClientDeviceMessageAttributesV2> for ClientDeviceMessageAttributes {
+  fn from(other: ClientDeviceMessageAttributesV2) -> Self {
+    let mut attrs = Self {
+      scalar_cmd: other
+        .vibrate_cmd
+        .and_then(|x| x.to_client_attributes(ActuatorType::Vibrate)),
+      rotate_cmd: other
+        .rotate_cmd
+        .and_then(|x| x.to_client_attributes(ActuatorType::Rotate)),
+      linear_cmd: other
+        .linear_cmd
+        .and_then(|x| x.to_client_attributes(ActuatorType::Position)),
+      // BatteryLevelCmd is read as a sensor reporting 0-100. RSSILevelCmd can't be mapped to a
+      // sensor index on its own, so it is left out.
+      sensor_read_cmd: other.battery_level_cmd.map(|_| {
+        vec![SensorDeviceMessageAttributes::new(
+          &unspecified_feature(),
+          SensorType::Battery,
+          &[RangeInclusive::new(0, 100)],
+        )]
+      }),
+      sensor_subscribe_cmd: None,
+      stop_device_cmd: other.stop_device_cmd,
+      raw_read_cmd: other.raw_read_cmd,
+      raw_write_cmd: other.raw_write_cmd,
+      raw_subscribe_cmd: other.raw_subscribe_cmd,
+      fleshlight_launch_fw12_cmd: other.fleshlight_launch_fw12_cmd,
+      vorze_a10_cyclone_cmd: other.vorze_a10_cyclone_cmd,
+    };
+    attrs.finalize();
+    attrs
+  }
+}
+
+impl From<ClientDeviceMessageAttributesV1> for ClientDeviceMessageAttributesV2 {
+  fn from(other: ClientDeviceMessageAttributesV1) -> Self {
+    let to_v2 = |attrs: GenericDeviceMessageAttributesV1| GenericDeviceMessageAttributesV2 {
+      feature_count: attrs.feature_count,
+      step_count: vec![ASSUMED_LEGACY_STEP_COUNT; attrs.feature_count as usize],
+    };
+    Self {
+      vibrate_cmd: other.vibrate_cmd.map(to_v2),
+      rotate_cmd: other.rotate_cmd.map(to_v2),
+      linear_cmd: other.linear_cmd.map(to_v2),
+      battery_level_cmd: None,
+      rssi_level_cmd: None,
+      stop_device_cmd: other.stop_device_cmd,
+      raw_read_cmd: None,
+      raw_write_cmd: None,
+      raw_subscribe_cmd: None,
+      raw_unsubscribe_cmd: None,
+      fleshlight_launch_fw12_cmd: other.fleshlight_launch_fw12_cmd,
+      vorze_a10_cyclone_cmd: other.vorze_a10_cyclone_cmd,
+    }
+  }
+}
+
+impl From<Vec<ButtplugDeviceMessageType>> for ClientDeviceMessageAttributesV1 {
+  /// Converts a spec v0 message list. SingleMotorVibrateCmd drives all of a device's vibrators
+  /// together, so it becomes a single vibrate feature.
+  fn from(message_types: Vec<ButtplugDeviceMessageType>) -> Self {
+    let supports = |message_type| {
+      if message_types.contains(&message_type) {
+        Some(NullDeviceMessageAttributes::default())
+      } else {
+        None
+      }
+    };
+    Self {
+      vibrate_cmd: supports(ButtplugDeviceMessageType::SingleMotorVibrateCmd)
+        .map(|_| GenericDeviceMessageAttributesV1 { feature_count: 1 }),
+      rotate_cmd: None,
+      linear_cmd: None,
+      stop_device_cmd: NullDeviceMessageAttributes::default(),
+      single_motor_vibrate_cmd: supports(ButtplugDeviceMessageType::SingleMotorVibrateCmd),
+      fleshlight_launch_fw12_cmd: supports(ButtplugDeviceMessageType::FleshlightLaunchFW12Cmd),
+      vorze_a10_cyclone_cmd: supports(ButtplugDeviceMessageType::VorzeA10CycloneCmd),
+    }
+  }
+}
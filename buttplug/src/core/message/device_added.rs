@@ -68,6 +68,50 @@ impl DeviceAdded {
   }
 }
 
+impl From<DeviceMessageInfo> for DeviceAdded {
+  fn from(device_message_info: DeviceMessageInfo) -> Self {
+    Self::new(
+      device_message_info.device_index(),
+      device_message_info.device_name(),
+      device_message_info.device_display_name(),
+      device_message_info.device_message_timing_gap(),
+      device_message_info.device_messages(),
+    )
+  }
+}
+
+impl From<DeviceAddedV2> for DeviceAdded {
+  fn from(msg: DeviceAddedV2) -> Self {
+    let id = msg.id();
+    let mut device_added =
+      DeviceAdded::from(DeviceMessageInfo::from(DeviceMessageInfoV2::from(msg)));
+    device_added.set_id(id);
+    device_added
+  }
+}
+
+impl From<DeviceAddedV1> for DeviceAdded {
+  fn from(msg: DeviceAddedV1) -> Self {
+    let id = msg.id();
+    let dmiv1 = DeviceMessageInfoV1::from(msg);
+    let mut device_added =
+      DeviceAdded::from(DeviceMessageInfo::from(DeviceMessageInfoV2::from(dmiv1)));
+    device_added.set_id(id);
+    device_added
+  }
+}
+
+impl From<DeviceAddedV0> for DeviceAdded {
+  fn from(msg: DeviceAddedV0) -> Self {
+    let id = msg.id();
+    let dmiv1 = DeviceMessageInfoV1::from(DeviceMessageInfoV0::from(msg));
+    let mut device_added =
+      DeviceAdded::from(DeviceMessageInfo::from(DeviceMessageInfoV2::from(dmiv1)));
+    device_added.set_id(id);
+    device_added
+  }
+}
+
 impl ButtplugMessageValidator for DeviceAdded {
   fn is_valid(&self) -> Result<(), ButtplugMessageError> {
     self.is_system_id(self.id)
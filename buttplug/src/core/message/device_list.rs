@@ -140,3 +140,42 @@ impl ButtplugMessageValidator for DeviceListV0 {
 
 impl ButtplugMessageFinalizer for DeviceListV0 {
 }
+
+impl From<DeviceListV2> for DeviceList {
+  fn from(msg: DeviceListV2) -> Self {
+    Self {
+      id: msg.id,
+      devices: msg
+        .devices
+        .into_iter()
+        .map(DeviceMessageInfo::from)
+        .collect(),
+    }
+  }
+}
+
+impl From<DeviceListV1> for DeviceList {
+  fn from(msg: DeviceListV1) -> Self {
+    Self {
+      id: msg.id,
+      devices: msg
+        .devices
+        .into_iter()
+        .map(|d| DeviceMessageInfo::from(DeviceMessageInfoV2::from(d)))
+        .collect(),
+    }
+  }
+}
+
+impl From<DeviceListV0> for DeviceList {
+  fn from(msg: DeviceListV0) -> Self {
+    Self {
+      id: msg.id,
+      devices: msg
+        .devices
+        .into_iter()
+        .map(|d| DeviceMessageInfo::from(DeviceMessageInfoV2::from(DeviceMessageInfoV1::from(d))))
+        .collect(),
+    }
+  }
+}
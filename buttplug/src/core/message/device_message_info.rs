@@ -207,3 +207,56 @@ impl From<DeviceMessageInfoV1> for DeviceMessageInfoV0 {
     }
   }
 }
+
+impl From<DeviceMessageInfoV2> for DeviceMessageInfo {
+  fn from(device_message_info: DeviceMessageInfoV2) -> Self {
+    // Older specs have no display names or timing gaps, so those stay unset.
+    Self {
+      device_index: device_message_info.device_index,
+      device_name: device_message_info.device_name,
+      device_display_name: None,
+      device_message_timing_gap: None,
+      device_messages: device_message_info.device_messages.into(),
+    }
+  }
+}
+
+impl From<DeviceMessageInfoV1> for DeviceMessageInfoV2 {
+  fn from(device_message_info: DeviceMessageInfoV1) -> Self {
+    Self {
+      device_index: device_message_info.device_index,
+      device_name: device_message_info.device_name,
+      device_messages: device_message_info.device_messages.into(),
+    }
+  }
+}
+
+impl From<DeviceMessageInfoV0> for DeviceMessageInfoV1 {
+  fn from(device_message_info: DeviceMessageInfoV0) -> Self {
+    Self {
+      device_index: device_message_info.device_index,
+      device_name: device_message_info.device_name,
+      device_messages: device_message_info.device_messages.into(),
+    }
+  }
+}
+
+impl From<DeviceAddedV1> for DeviceMessageInfoV1 {
+  fn from(device_added: DeviceAddedV1) -> Self {
+    Self {
+      device_index: device_added.device_index(),
+      device_name: device_added.device_name().clone(),
+      device_messages: device_added.device_messages().clone(),
+    }
+  }
+}
+
+impl From<DeviceAddedV0> for DeviceMessageInfoV0 {
+  fn from(device_added: DeviceAddedV0) -> Self {
+    Self {
+      device_index: device_added.device_index(),
+      device_name: device_added.device_name().clone(),
+      device_messages: device_added.device_messages().clone(),
+    }
+  }
+}
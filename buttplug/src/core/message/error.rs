@@ -165,6 +165,14 @@ impl From<Error> for ErrorV0 {
   }
 }
 
+impl From<ErrorV0> for Error {
+  fn from(error: ErrorV0) -> Self {
+    let mut err = Error::new(error.error_code, &error.error_message, None);
+    err.set_id(error.id());
+    err
+  }
+}
+
 #[cfg(feature = "serialize-json")]
 #[cfg(test)]
 mod test {
@@ -38,6 +38,7 @@ mod raw_write_cmd;
 mod request_device_list;
 mod request_log;
 mod request_server_info;
+mod request_unsupported_device_reports;
 mod rotate_cmd;
 mod rssi_level_cmd;
 mod rssi_level_reading;
@@ -50,11 +51,13 @@ mod sensor_unsubscribe_cmd;
 pub mod serializer;
 mod server_info;
 mod single_motor_vibrate_cmd;
+mod spec_version_conversion;
 mod start_scanning;
 mod stop_all_devices;
 mod stop_device_cmd;
 mod stop_scanning;
 mod test;
+mod unsupported_device_found;
 mod vibrate_cmd;
 mod vorze_a10_cyclone_cmd;
 
@@ -99,6 +102,7 @@ pub use raw_write_cmd::RawWriteCmd;
 pub use request_device_list::RequestDeviceList;
 pub use request_log::RequestLog;
 pub use request_server_info::RequestServerInfo;
+pub use request_unsupported_device_reports::RequestUnsupportedDeviceReports;
 pub use rotate_cmd::{RotateCmd, RotationSubcommand};
 pub use rssi_level_cmd::RSSILevelCmd;
 pub use rssi_level_reading::RSSILevelReading;
@@ -115,6 +119,7 @@ pub use stop_all_devices::StopAllDevices;
 pub use stop_device_cmd::StopDeviceCmd;
 pub use stop_scanning::StopScanning;
 pub use test::Test;
+pub use unsupported_device_found::{UnsupportedDeviceFound, UnsupportedDeviceReason};
 pub use vibrate_cmd::{VibrateCmd, VibrateSubcommand};
 pub use vorze_a10_cyclone_cmd::VorzeA10CycloneCmd;
 
@@ -278,6 +283,7 @@ pub enum ButtplugClientMessage {
   StartScanning(StartScanning),
   StopScanning(StopScanning),
   RequestDeviceList(RequestDeviceList),
+  RequestUnsupportedDeviceReports(RequestUnsupportedDeviceReports),
   // Generic commands
   StopAllDevices(StopAllDevices),
   VibrateCmd(VibrateCmd),
@@ -331,6 +337,7 @@ pub enum ButtplugServerMessage {
   DeviceAdded(DeviceAdded),
   DeviceRemoved(DeviceRemoved),
   ScanningFinished(ScanningFinished),
+  UnsupportedDeviceFound(UnsupportedDeviceFound),
   // Generic commands
   RawReading(RawReading),
   // Sensor Reading Messages
@@ -344,12 +351,16 @@ pub enum ButtplugServerMessage {
 /// [ButtplugClient][crate::client::ButtplugClient] that denote an EVENT from a device. These are
 /// only used in notifications, so read requests will not need to be added here, only messages that
 /// will require Id of 0.
+///
+/// Every variant is a device message, so the enum implements [ButtplugDeviceMessage] too. This lets
+/// the device manager fill in the device index on notifications from sources that don't know which
+/// index their device was given, like raw readings or sensor readings relayed by federation.
 #[derive(
   Debug,
   Clone,
   PartialEq,
   Eq,
-  ButtplugMessage,
+  ButtplugDeviceMessage,
   ButtplugMessageValidator,
   ButtplugServerMessageType,
   ButtplugMessageFinalizer,
@@ -397,6 +408,7 @@ pub enum ButtplugSpecV3ClientMessage {
   StartScanning(StartScanning),
   StopScanning(StopScanning),
   RequestDeviceList(RequestDeviceList),
+  RequestUnsupportedDeviceReports(RequestUnsupportedDeviceReports),
   // Generic commands
   StopAllDevices(StopAllDevices),
   VibrateCmd(VibrateCmd),
@@ -437,6 +449,7 @@ pub enum ButtplugSpecV3ServerMessage {
   DeviceAdded(DeviceAdded),
   DeviceRemoved(DeviceRemoved),
   ScanningFinished(ScanningFinished),
+  UnsupportedDeviceFound(UnsupportedDeviceFound),
   // Generic commands
   RawReading(RawReading),
   // Sensor commands
@@ -540,6 +553,13 @@ impl TryFrom<ButtplugServerMessage> for ButtplugSpecV2ServerMessage {
       ButtplugServerMessage::ScanningFinished(msg) => {
         Ok(ButtplugSpecV2ServerMessage::ScanningFinished(msg))
       }
+      ButtplugServerMessage::RawReading(msg) => Ok(ButtplugSpecV2ServerMessage::RawReading(msg)),
+      ButtplugServerMessage::BatteryLevelReading(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::BatteryLevelReading(msg))
+      }
+      ButtplugServerMessage::RSSILevelReading(msg) => {
+        Ok(ButtplugSpecV2ServerMessage::RSSILevelReading(msg))
+      }
       _ => Err(ButtplugMessageError::VersionError(
         "ButtplugServerMessage".to_owned(),
         format!("{:?}", msg),
@@ -756,6 +776,7 @@ pub enum ButtplugDeviceManagerMessageUnion {
   StopAllDevices(StopAllDevices),
   StartScanning(StartScanning),
   StopScanning(StopScanning),
+  RequestUnsupportedDeviceReports(RequestUnsupportedDeviceReports),
 }
 
 /// Represents all possible device command message types.
@@ -32,6 +32,13 @@ pub struct RequestServerInfo {
   )]
   #[getset(get_copy = "pub")]
   message_version: ButtplugMessageSpecVersion,
+  // Only sent by clients connecting to servers that require authentication.
+  #[cfg_attr(
+    feature = "serialize-json",
+    serde(rename = "AuthToken"),
+    serde(default, skip_serializing_if = "Option::is_none")
+  )]
+  auth_token: Option<String>,
 }
 
 impl RequestServerInfo {
@@ -40,8 +47,24 @@ impl RequestServerInfo {
       id: 1,
       client_name: client_name.to_string(),
       message_version,
+      auth_token: None,
     }
   }
+
+  pub fn new_with_auth_token(
+    client_name: &str,
+    message_version: ButtplugMessageSpecVersion,
+    auth_token: &str,
+  ) -> Self {
+    Self {
+      auth_token: Some(auth_token.to_owned()),
+      ..Self::new(client_name, message_version)
+    }
+  }
+
+  pub fn auth_token(&self) -> Option<&str> {
+    self.auth_token.as_deref()
+  }
 }
 
 impl ButtplugMessageValidator for RequestServerInfo {
@@ -68,6 +91,7 @@ mod test {
       id: 1,
       client_name: "Test Client".to_owned(),
       message_version: ButtplugMessageSpecVersion::Version2,
+      auth_token: None,
     };
     assert_eq!(
       serde_json::from_str::<RequestServerInfo>(new_json).expect("Test unwrap"),
@@ -88,10 +112,38 @@ mod test {
       id: 1,
       client_name: "Test Client".to_owned(),
       message_version: ButtplugMessageSpecVersion::Version0,
+      auth_token: None,
     };
     assert_eq!(
       serde_json::from_str::<RequestServerInfo>(old_json).expect("Test unwrap"),
       old_msg
     );
   }
+
+  #[cfg(feature = "serialize-json")]
+  #[test]
+  fn test_request_server_info_auth_token_json_conversion() {
+    let json = r#"
+{
+        "Id": 1,
+        "ClientName": "Test Client",
+        "MessageVersion": 3,
+        "AuthToken": "secret"
+}
+        "#;
+    let msg = RequestServerInfo::new_with_auth_token(
+      "Test Client",
+      ButtplugMessageSpecVersion::Version3,
+      "secret",
+    );
+    assert_eq!(
+      serde_json::from_str::<RequestServerInfo>(json).expect("Test unwrap"),
+      msg
+    );
+    // Token is left out entirely when not set, so older servers don't see an unknown field.
+    let no_token = RequestServerInfo::new("Test Client", ButtplugMessageSpecVersion::Version3);
+    assert!(!serde_json::to_string(&no_token)
+      .expect("Test unwrap")
+      .contains("AuthToken"));
+  }
 }
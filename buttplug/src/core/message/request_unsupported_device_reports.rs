@@ -0,0 +1,35 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Request for the server to report (or stop reporting) hardware it finds but cannot use.
+
+use super::*;
+use getset::CopyGetters;
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, ButtplugMessage, ButtplugMessageFinalizer, Clone, PartialEq, Eq, CopyGetters)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct RequestUnsupportedDeviceReports {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Enabled"))]
+  #[getset(get_copy = "pub")]
+  enabled: bool,
+}
+
+impl RequestUnsupportedDeviceReports {
+  pub fn new(enabled: bool) -> Self {
+    Self { id: 1, enabled }
+  }
+}
+
+impl ButtplugMessageValidator for RequestUnsupportedDeviceReports {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_not_system_id(self.id)
+  }
+}
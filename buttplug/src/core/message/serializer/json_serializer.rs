@@ -7,7 +7,7 @@
 
 use super::{ButtplugMessageSerializer, ButtplugSerializedMessage, ButtplugSerializerError};
 use crate::core::{
-  errors::{ButtplugError, ButtplugHandshakeError},
+  errors::{ButtplugError, ButtplugHandshakeError, ButtplugMessageError},
   message::{
     self,
     ButtplugClientMessage,
@@ -25,12 +25,13 @@ use crate::core::{
     ButtplugSpecV2ServerMessage,
     ButtplugSpecV3ClientMessage,
     ButtplugSpecV3ServerMessage,
+    BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
   },
 };
 use jsonschema::JSONSchema;
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
-use std::{convert::TryFrom, fmt::Debug};
+use std::{convert::TryFrom, fmt::Debug, sync::RwLock};
 
 static MESSAGE_JSON_SCHEMA: &str =
   include_str!("../../../../buttplug-schema/schema/buttplug-schema.json");
@@ -304,9 +305,115 @@ impl ButtplugClientJSONSerializerImpl {
   }
 }
 
-#[derive(Default)]
+/// Client side JSON serializer.
+///
+/// Messages are written in the spec version of the last RequestServerInfo sent through the
+/// serializer, and messages read back are converted up to the current spec, so clients can talk to
+/// servers running older spec versions.
 pub struct ButtplugClientJSONSerializer {
   serializer_impl: ButtplugClientJSONSerializerImpl,
+  message_version: RwLock<ButtplugMessageSpecVersion>,
+}
+
+impl Default for ButtplugClientJSONSerializer {
+  fn default() -> Self {
+    Self {
+      serializer_impl: ButtplugClientJSONSerializerImpl::default(),
+      message_version: RwLock::new(BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION),
+    }
+  }
+}
+
+impl ButtplugClientJSONSerializer {
+  fn message_version(&self) -> ButtplugMessageSpecVersion {
+    *self
+      .message_version
+      .read()
+      .expect("Only locked for single accesses, should never be poisoned.")
+  }
+
+  fn deserialize_version<T>(
+    &self,
+    msg: &ButtplugSerializedMessage,
+  ) -> Result<Vec<ButtplugCurrentSpecServerMessage>, ButtplugSerializerError>
+  where
+    T: serde::de::DeserializeOwned + ButtplugMessageFinalizer + Clone + Debug,
+    ButtplugCurrentSpecServerMessage: TryFrom<T, Error = ButtplugMessageError>,
+  {
+    // A message without a current spec equivalent shouldn't cost the client the rest of the batch,
+    // so it is dropped on its own.
+    Ok(
+      self
+        .serializer_impl
+        .deserialize::<T>(msg)?
+        .into_iter()
+        .filter_map(
+          |msg| match ButtplugCurrentSpecServerMessage::try_from(msg) {
+            Ok(msg) => Some(msg),
+            Err(e) => {
+              error!(
+                "Cannot upgrade message from server spec version, dropping: {}",
+                e
+              );
+              None
+            }
+          },
+        )
+        .collect(),
+    )
+  }
+
+  /// Serializes messages for the server's spec version. Messages that can't be converted to it
+  /// either fail serialization, or are logged and left out if `drop_unconvertible` is set.
+  fn serialize_version<T>(
+    &self,
+    msgs: &[ButtplugCurrentSpecClientMessage],
+    drop_unconvertible: bool,
+  ) -> Result<ButtplugSerializedMessage, ButtplugSerializerError>
+  where
+    T: ButtplugMessage + Serialize + Deserialize<'static>,
+    T: TryFrom<ButtplugCurrentSpecClientMessage, Error = ButtplugMessageError>,
+  {
+    let mut msg_vec: Vec<T> = vec![];
+    for msg in msgs.iter().cloned() {
+      match T::try_from(msg) {
+        Ok(msg) => msg_vec.push(msg),
+        Err(e) if drop_unconvertible => {
+          error!("Cannot serialize message for server spec version: {}", e)
+        }
+        Err(e) => return Err(ButtplugSerializerError::JsonSerializerError(e.to_string())),
+      }
+    }
+    Ok(self.serializer_impl.serialize(&msg_vec))
+  }
+
+  fn serialize_messages(
+    &self,
+    msgs: &[ButtplugCurrentSpecClientMessage],
+    drop_unconvertible: bool,
+  ) -> Result<ButtplugSerializedMessage, ButtplugSerializerError> {
+    for msg in msgs {
+      if let ButtplugCurrentSpecClientMessage::RequestServerInfo(rsi) = msg {
+        *self
+          .message_version
+          .write()
+          .expect("Only locked for single accesses, should never be poisoned.") =
+          rsi.message_version();
+      }
+    }
+    match self.message_version() {
+      ButtplugMessageSpecVersion::Version0 => {
+        self.serialize_version::<ButtplugSpecV0ClientMessage>(msgs, drop_unconvertible)
+      }
+      ButtplugMessageSpecVersion::Version1 => {
+        self.serialize_version::<ButtplugSpecV1ClientMessage>(msgs, drop_unconvertible)
+      }
+      ButtplugMessageSpecVersion::Version2 => {
+        self.serialize_version::<ButtplugSpecV2ClientMessage>(msgs, drop_unconvertible)
+      }
+      ButtplugMessageSpecVersion::Version3 => Ok(self.serializer_impl.serialize(msgs)),
+    }
+  }
 }
 
 impl ButtplugMessageSerializer for ButtplugClientJSONSerializer {
@@ -317,18 +424,46 @@ impl ButtplugMessageSerializer for ButtplugClientJSONSerializer {
     &self,
     msg: &ButtplugSerializedMessage,
   ) -> Result<Vec<Self::Inbound>, ButtplugSerializerError> {
-    self.serializer_impl.deserialize(msg)
+    match self.message_version() {
+      ButtplugMessageSpecVersion::Version0 => {
+        self.deserialize_version::<ButtplugSpecV0ServerMessage>(msg)
+      }
+      ButtplugMessageSpecVersion::Version1 => {
+        self.deserialize_version::<ButtplugSpecV1ServerMessage>(msg)
+      }
+      ButtplugMessageSpecVersion::Version2 => {
+        self.deserialize_version::<ButtplugSpecV2ServerMessage>(msg)
+      }
+      ButtplugMessageSpecVersion::Version3 => self.serializer_impl.deserialize(msg),
+    }
+  }
+
+  // The client checks messages can be converted before sending them, so anything failing here has
+  // no way to reach the server.
+  fn serialize(&self, msgs: &[Self::Outbound]) -> ButtplugSerializedMessage {
+    self
+      .serialize_messages(msgs, true)
+      .expect("Unconvertible messages are dropped, so this can't fail.")
   }
 
-  fn serialize(&self, msg: &[Self::Outbound]) -> ButtplugSerializedMessage {
-    self.serializer_impl.serialize(msg)
+  fn try_serialize(
+    &self,
+    msgs: &[Self::Outbound],
+  ) -> Result<ButtplugSerializedMessage, ButtplugSerializerError> {
+    self.serialize_messages(msgs, false)
   }
 }
 
 #[cfg(test)]
 mod test {
   use super::*;
-  use crate::core::message::{RequestServerInfo, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION};
+  use crate::core::message::{
+    ActuatorType,
+    RequestServerInfo,
+    ScalarCmd,
+    ScalarSubcommand,
+    BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+  };
 
   #[test]
   fn test_correct_message_version() {
@@ -399,4 +534,47 @@ mod test {
       }
     }
   }
+
+  #[test]
+  fn test_client_unconvertible_message_fails_serialization() {
+    let serializer = ButtplugClientJSONSerializer::default();
+    serializer.serialize(&[RequestServerInfo::new(
+      "test client",
+      ButtplugMessageSpecVersion::Version0,
+    )
+    .into()]);
+    let scalar_cmd: ButtplugCurrentSpecClientMessage = ScalarCmd::new(
+      0,
+      vec![ScalarSubcommand::new(0, 0.5, ActuatorType::Constrict)],
+    )
+    .into();
+    assert!(matches!(
+      serializer.try_serialize(std::slice::from_ref(&scalar_cmd)),
+      Err(ButtplugSerializerError::JsonSerializerError(_))
+    ));
+    // Infallible serialization leaves the message out instead.
+    assert_eq!(
+      serializer.serialize(&[scalar_cmd]),
+      ButtplugSerializedMessage::Text("[]".to_owned())
+    );
+  }
+
+  #[test]
+  fn test_client_skips_messages_without_current_equivalent() {
+    let serializer = ButtplugClientJSONSerializer::default();
+    serializer.serialize(&[RequestServerInfo::new(
+      "test client",
+      ButtplugMessageSpecVersion::Version1,
+    )
+    .into()]);
+    let json = r#"[
+      {"Log": {"Id": 0, "LogLevel": "Info", "LogMessage": "Test"}},
+      {"Ok": {"Id": 2}}
+    ]"#;
+    let msgs = serializer
+      .deserialize(&ButtplugSerializedMessage::Text(json.to_owned()))
+      .expect("Test, assuming infallible.");
+    assert_eq!(msgs.len(), 1);
+    assert_eq!(msgs[0].id(), 2);
+  }
 }
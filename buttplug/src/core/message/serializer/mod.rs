@@ -64,4 +64,13 @@ pub trait ButtplugMessageSerializer: Default + Sync + Send {
     msg: &ButtplugSerializedMessage,
   ) -> ButtplugSerializerResult<Vec<Self::Inbound>>;
   fn serialize(&self, msg: &[Self::Outbound]) -> ButtplugSerializedMessage;
+  /// Same as [serialize](Self::serialize), but fails for messages it can't serialize instead of
+  /// leaving them out. Serializers that can always serialize their messages don't need to implement
+  /// this.
+  fn try_serialize(
+    &self,
+    msg: &[Self::Outbound],
+  ) -> ButtplugSerializerResult<ButtplugSerializedMessage> {
+    Ok(self.serialize(msg))
+  }
 }
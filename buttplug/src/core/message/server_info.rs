@@ -102,6 +102,14 @@ impl From<ServerInfo> for ServerInfoV0 {
   }
 }
 
+impl From<ServerInfoV0> for ServerInfo {
+  fn from(msg: ServerInfoV0) -> Self {
+    let mut out_msg = Self::new(&msg.server_name, msg.message_version, msg.max_ping_time);
+    out_msg.set_id(msg.id());
+    out_msg
+  }
+}
+
 impl ButtplugMessageValidator for ServerInfoV0 {
   fn is_valid(&self) -> Result<(), ButtplugMessageError> {
     self.is_system_id(self.id)
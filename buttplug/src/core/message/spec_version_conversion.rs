@@ -0,0 +1,421 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Conversions between the current message spec and older ones, for clients talking to servers that
+//! only support an older spec version.
+//!
+//! Client messages are converted down to the closest older message, e.g. vibration [ScalarCmd]s
+//! become [VibrateCmd] in v1/v2 and [SingleMotorVibrateCmd] in v0. Messages without an older
+//! equivalent fail with [ButtplugMessageError::VersionError]. Server messages are converted up, with
+//! device attributes only describing what the older spec can express.
+
+use super::*;
+
+fn version_error<T>(msg: &T, to_type: &str) -> ButtplugMessageError
+where
+  T: std::fmt::Debug,
+{
+  ButtplugMessageError::VersionError(
+    "ButtplugSpecV3ClientMessage".to_owned(),
+    format!("{:?}", msg),
+    to_type.to_owned(),
+  )
+}
+
+/// Vibration only [ScalarCmd]s can be sent as a [VibrateCmd]. Attributes converted from older specs
+/// list vibrators as the only scalar features, so scalar and vibrate indexes match.
+fn vibrate_cmd_from_scalar_cmd(msg: &ScalarCmd) -> Option<VibrateCmd> {
+  if msg
+    .scalars()
+    .iter()
+    .any(|scalar| scalar.actuator_type() != ActuatorType::Vibrate)
+  {
+    return None;
+  }
+  let mut vibrate_cmd = VibrateCmd::new(
+    msg.device_index(),
+    msg
+      .scalars()
+      .iter()
+      .map(|scalar| VibrateSubcommand::new(scalar.index(), scalar.scalar()))
+      .collect(),
+  );
+  vibrate_cmd.set_id(msg.id());
+  Some(vibrate_cmd)
+}
+
+/// [SingleMotorVibrateCmd] sets every vibrator to the same speed, so it can only stand in for
+/// commands that do the same.
+fn single_motor_vibrate_cmd_from_vibrate_cmd(msg: &VibrateCmd) -> Option<SingleMotorVibrateCmd> {
+  let speed = msg.speeds().first()?.speed();
+  if msg
+    .speeds()
+    .iter()
+    .any(|subcommand| subcommand.speed() != speed)
+  {
+    return None;
+  }
+  let mut single_motor_cmd = SingleMotorVibrateCmd::new(msg.device_index(), speed);
+  single_motor_cmd.set_id(msg.id());
+  Some(single_motor_cmd)
+}
+
+impl TryFrom<ButtplugSpecV3ClientMessage> for ButtplugSpecV2ClientMessage {
+  type Error = ButtplugMessageError;
+
+  fn try_from(msg: ButtplugSpecV3ClientMessage) -> Result<Self, ButtplugMessageError> {
+    let to_type = "ButtplugSpecV2ClientMessage";
+    match msg {
+      ButtplugSpecV3ClientMessage::RequestServerInfo(msg) => Ok(Self::RequestServerInfo(msg)),
+      ButtplugSpecV3ClientMessage::Ping(msg) => Ok(Self::Ping(msg)),
+      ButtplugSpecV3ClientMessage::StartScanning(msg) => Ok(Self::StartScanning(msg)),
+      ButtplugSpecV3ClientMessage::StopScanning(msg) => Ok(Self::StopScanning(msg)),
+      ButtplugSpecV3ClientMessage::RequestDeviceList(msg) => Ok(Self::RequestDeviceList(msg)),
+      ButtplugSpecV3ClientMessage::StopAllDevices(msg) => Ok(Self::StopAllDevices(msg)),
+      ButtplugSpecV3ClientMessage::VibrateCmd(msg) => Ok(Self::VibrateCmd(msg)),
+      ButtplugSpecV3ClientMessage::LinearCmd(msg) => Ok(Self::LinearCmd(msg)),
+      ButtplugSpecV3ClientMessage::RotateCmd(msg) => Ok(Self::RotateCmd(msg)),
+      ButtplugSpecV3ClientMessage::RawWriteCmd(msg) => Ok(Self::RawWriteCmd(msg)),
+      ButtplugSpecV3ClientMessage::RawReadCmd(msg) => Ok(Self::RawReadCmd(msg)),
+      ButtplugSpecV3ClientMessage::StopDeviceCmd(msg) => Ok(Self::StopDeviceCmd(msg)),
+      ButtplugSpecV3ClientMessage::RawSubscribeCmd(msg) => Ok(Self::RawSubscribeCmd(msg)),
+      ButtplugSpecV3ClientMessage::RawUnsubscribeCmd(msg) => Ok(Self::RawUnsubscribeCmd(msg)),
+      ButtplugSpecV3ClientMessage::ScalarCmd(msg) => vibrate_cmd_from_scalar_cmd(&msg)
+        .map(Self::VibrateCmd)
+        .ok_or_else(|| version_error(&msg, to_type)),
+      ButtplugSpecV3ClientMessage::SensorReadCmd(msg)
+        if *msg.sensor_type() == SensorType::Battery =>
+      {
+        let mut battery_cmd = BatteryLevelCmd::new(msg.device_index());
+        battery_cmd.set_id(msg.id());
+        Ok(Self::BatteryLevelCmd(battery_cmd))
+      }
+      msg => Err(version_error(&msg, to_type)),
+    }
+  }
+}
+
+impl TryFrom<ButtplugSpecV3ClientMessage> for ButtplugSpecV1ClientMessage {
+  type Error = ButtplugMessageError;
+
+  fn try_from(msg: ButtplugSpecV3ClientMessage) -> Result<Self, ButtplugMessageError> {
+    let to_type = "ButtplugSpecV1ClientMessage";
+    match msg {
+      ButtplugSpecV3ClientMessage::RequestServerInfo(msg) => Ok(Self::RequestServerInfo(msg)),
+      ButtplugSpecV3ClientMessage::Ping(msg) => Ok(Self::Ping(msg)),
+      ButtplugSpecV3ClientMessage::StartScanning(msg) => Ok(Self::StartScanning(msg)),
+      ButtplugSpecV3ClientMessage::StopScanning(msg) => Ok(Self::StopScanning(msg)),
+      ButtplugSpecV3ClientMessage::RequestDeviceList(msg) => Ok(Self::RequestDeviceList(msg)),
+      ButtplugSpecV3ClientMessage::StopAllDevices(msg) => Ok(Self::StopAllDevices(msg)),
+      ButtplugSpecV3ClientMessage::VibrateCmd(msg) => Ok(Self::VibrateCmd(msg)),
+      ButtplugSpecV3ClientMessage::LinearCmd(msg) => Ok(Self::LinearCmd(msg)),
+      ButtplugSpecV3ClientMessage::RotateCmd(msg) => Ok(Self::RotateCmd(msg)),
+      ButtplugSpecV3ClientMessage::StopDeviceCmd(msg) => Ok(Self::StopDeviceCmd(msg)),
+      ButtplugSpecV3ClientMessage::ScalarCmd(msg) => vibrate_cmd_from_scalar_cmd(&msg)
+        .map(Self::VibrateCmd)
+        .ok_or_else(|| version_error(&msg, to_type)),
+      msg => Err(version_error(&msg, to_type)),
+    }
+  }
+}
+
+impl TryFrom<ButtplugSpecV3ClientMessage> for ButtplugSpecV0ClientMessage {
+  type Error = ButtplugMessageError;
+
+  fn try_from(msg: ButtplugSpecV3ClientMessage) -> Result<Self, ButtplugMessageError> {
+    let to_type = "ButtplugSpecV0ClientMessage";
+    match msg {
+      ButtplugSpecV3ClientMessage::RequestServerInfo(msg) => Ok(Self::RequestServerInfo(msg)),
+      ButtplugSpecV3ClientMessage::Ping(msg) => Ok(Self::Ping(msg)),
+      ButtplugSpecV3ClientMessage::StartScanning(msg) => Ok(Self::StartScanning(msg)),
+      ButtplugSpecV3ClientMessage::StopScanning(msg) => Ok(Self::StopScanning(msg)),
+      ButtplugSpecV3ClientMessage::RequestDeviceList(msg) => Ok(Self::RequestDeviceList(msg)),
+      ButtplugSpecV3ClientMessage::StopAllDevices(msg) => Ok(Self::StopAllDevices(msg)),
+      ButtplugSpecV3ClientMessage::StopDeviceCmd(msg) => Ok(Self::StopDeviceCmd(msg)),
+      ButtplugSpecV3ClientMessage::VibrateCmd(msg) => {
+        single_motor_vibrate_cmd_from_vibrate_cmd(&msg)
+          .map(Self::SingleMotorVibrateCmd)
+          .ok_or_else(|| version_error(&msg, to_type))
+      }
+      ButtplugSpecV3ClientMessage::ScalarCmd(msg) => vibrate_cmd_from_scalar_cmd(&msg)
+        .and_then(|vibrate_cmd| single_motor_vibrate_cmd_from_vibrate_cmd(&vibrate_cmd))
+        .map(Self::SingleMotorVibrateCmd)
+        .ok_or_else(|| version_error(&msg, to_type)),
+      msg => Err(version_error(&msg, to_type)),
+    }
+  }
+}
+
+impl TryFrom<ButtplugSpecV2ServerMessage> for ButtplugSpecV3ServerMessage {
+  type Error = ButtplugMessageError;
+
+  fn try_from(msg: ButtplugSpecV2ServerMessage) -> Result<Self, ButtplugMessageError> {
+    match msg {
+      ButtplugSpecV2ServerMessage::Ok(msg) => Ok(Self::Ok(msg)),
+      ButtplugSpecV2ServerMessage::Error(msg) => Ok(Self::Error(msg)),
+      ButtplugSpecV2ServerMessage::ServerInfo(msg) => Ok(Self::ServerInfo(msg)),
+      ButtplugSpecV2ServerMessage::DeviceList(msg) => Ok(Self::DeviceList(msg.into())),
+      ButtplugSpecV2ServerMessage::DeviceAdded(msg) => Ok(Self::DeviceAdded(msg.into())),
+      ButtplugSpecV2ServerMessage::DeviceRemoved(msg) => Ok(Self::DeviceRemoved(msg)),
+      ButtplugSpecV2ServerMessage::ScanningFinished(msg) => Ok(Self::ScanningFinished(msg)),
+      ButtplugSpecV2ServerMessage::RawReading(msg) => Ok(Self::RawReading(msg)),
+      // Battery levels are read as the first sensor, see the ClientDeviceMessageAttributesV2
+      // conversion.
+      ButtplugSpecV2ServerMessage::BatteryLevelReading(msg) => {
+        let mut reading = SensorReading::new(
+          msg.device_index(),
+          0,
+          SensorType::Battery,
+          vec![(msg.battery_level() * 100.0).round() as i32],
+        );
+        reading.set_id(msg.id());
+        Ok(Self::SensorReading(reading))
+      }
+      msg => Err(ButtplugMessageError::VersionError(
+        "ButtplugSpecV2ServerMessage".to_owned(),
+        format!("{:?}", msg),
+        "ButtplugSpecV3ServerMessage".to_owned(),
+      )),
+    }
+  }
+}
+
+impl TryFrom<ButtplugSpecV1ServerMessage> for ButtplugSpecV3ServerMessage {
+  type Error = ButtplugMessageError;
+
+  fn try_from(msg: ButtplugSpecV1ServerMessage) -> Result<Self, ButtplugMessageError> {
+    match msg {
+      ButtplugSpecV1ServerMessage::Ok(msg) => Ok(Self::Ok(msg)),
+      ButtplugSpecV1ServerMessage::Error(msg) => Ok(Self::Error(msg.into())),
+      ButtplugSpecV1ServerMessage::ServerInfo(msg) => Ok(Self::ServerInfo(msg.into())),
+      ButtplugSpecV1ServerMessage::DeviceList(msg) => Ok(Self::DeviceList(msg.into())),
+      ButtplugSpecV1ServerMessage::DeviceAdded(msg) => Ok(Self::DeviceAdded(msg.into())),
+      ButtplugSpecV1ServerMessage::DeviceRemoved(msg) => Ok(Self::DeviceRemoved(msg)),
+      ButtplugSpecV1ServerMessage::ScanningFinished(msg) => Ok(Self::ScanningFinished(msg)),
+      msg => Err(ButtplugMessageError::VersionError(
+        "ButtplugSpecV1ServerMessage".to_owned(),
+        format!("{:?}", msg),
+        "ButtplugSpecV3ServerMessage".to_owned(),
+      )),
+    }
+  }
+}
+
+impl TryFrom<ButtplugSpecV0ServerMessage> for ButtplugSpecV3ServerMessage {
+  type Error = ButtplugMessageError;
+
+  fn try_from(msg: ButtplugSpecV0ServerMessage) -> Result<Self, ButtplugMessageError> {
+    match msg {
+      ButtplugSpecV0ServerMessage::Ok(msg) => Ok(Self::Ok(msg)),
+      ButtplugSpecV0ServerMessage::Error(msg) => Ok(Self::Error(msg.into())),
+      ButtplugSpecV0ServerMessage::ServerInfo(msg) => Ok(Self::ServerInfo(msg.into())),
+      ButtplugSpecV0ServerMessage::DeviceList(msg) => Ok(Self::DeviceList(msg.into())),
+      ButtplugSpecV0ServerMessage::DeviceAdded(msg) => Ok(Self::DeviceAdded(msg.into())),
+      ButtplugSpecV0ServerMessage::DeviceRemoved(msg) => Ok(Self::DeviceRemoved(msg)),
+      ButtplugSpecV0ServerMessage::ScanningFinished(msg) => Ok(Self::ScanningFinished(msg)),
+      msg => Err(ButtplugMessageError::VersionError(
+        "ButtplugSpecV0ServerMessage".to_owned(),
+        format!("{:?}", msg),
+        "ButtplugSpecV3ServerMessage".to_owned(),
+      )),
+    }
+  }
+}
+
+impl ButtplugSpecV3ClientMessage {
+  /// Converts the message to the form a server running `version` of the spec expects.
+  pub fn downgrade(
+    self,
+    version: ButtplugMessageSpecVersion,
+  ) -> Result<ButtplugClientMessage, ButtplugMessageError> {
+    Ok(match version {
+      ButtplugMessageSpecVersion::Version0 => ButtplugSpecV0ClientMessage::try_from(self)?.into(),
+      ButtplugMessageSpecVersion::Version1 => ButtplugSpecV1ClientMessage::try_from(self)?.into(),
+      ButtplugMessageSpecVersion::Version2 => ButtplugSpecV2ClientMessage::try_from(self)?.into(),
+      ButtplugMessageSpecVersion::Version3 => self.into(),
+    })
+  }
+}
+
+impl ButtplugSpecV3ServerMessage {
+  /// Converts a server message to the current spec, as a client would see it from a server running
+  /// `version` of the spec.
+  pub fn upgrade(
+    msg: ButtplugServerMessage,
+    version: ButtplugMessageSpecVersion,
+  ) -> Result<Self, ButtplugMessageError> {
+    match version {
+      ButtplugMessageSpecVersion::Version0 => {
+        ButtplugSpecV0ServerMessage::try_from(msg)?.try_into()
+      }
+      ButtplugMessageSpecVersion::Version1 => {
+        ButtplugSpecV1ServerMessage::try_from(msg)?.try_into()
+      }
+      ButtplugMessageSpecVersion::Version2 => {
+        ButtplugSpecV2ServerMessage::try_from(msg)?.try_into()
+      }
+      ButtplugMessageSpecVersion::Version3 => Self::try_from(msg),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_scalar_cmd_downgrade() {
+    let mut scalar_cmd = ScalarCmd::new(
+      2,
+      vec![
+        ScalarSubcommand::new(0, 0.5, ActuatorType::Vibrate),
+        ScalarSubcommand::new(1, 0.5, ActuatorType::Vibrate),
+      ],
+    );
+    scalar_cmd.set_id(5);
+    let msg = ButtplugSpecV3ClientMessage::from(scalar_cmd);
+
+    let mut vibrate_cmd = VibrateCmd::new(
+      2,
+      vec![
+        VibrateSubcommand::new(0, 0.5),
+        VibrateSubcommand::new(1, 0.5),
+      ],
+    );
+    vibrate_cmd.set_id(5);
+    assert_eq!(
+      ButtplugSpecV2ClientMessage::try_from(msg.clone()),
+      Ok(ButtplugSpecV2ClientMessage::VibrateCmd(vibrate_cmd.clone()))
+    );
+    assert_eq!(
+      ButtplugSpecV1ClientMessage::try_from(msg.clone()),
+      Ok(ButtplugSpecV1ClientMessage::VibrateCmd(vibrate_cmd))
+    );
+    let mut single_motor_cmd = SingleMotorVibrateCmd::new(2, 0.5);
+    single_motor_cmd.set_id(5);
+    assert_eq!(
+      ButtplugSpecV0ClientMessage::try_from(msg),
+      Ok(ButtplugSpecV0ClientMessage::SingleMotorVibrateCmd(
+        single_motor_cmd
+      ))
+    );
+  }
+
+  #[test]
+  fn test_unsupported_downgrades() {
+    let oscillate_cmd: ButtplugSpecV3ClientMessage = ScalarCmd::new(
+      0,
+      vec![ScalarSubcommand::new(0, 0.5, ActuatorType::Oscillate)],
+    )
+    .into();
+    assert!(ButtplugSpecV2ClientMessage::try_from(oscillate_cmd).is_err());
+    // V0 can only set all vibrators to the same speed.
+    let uneven_cmd: ButtplugSpecV3ClientMessage = VibrateCmd::new(
+      0,
+      vec![
+        VibrateSubcommand::new(0, 0.5),
+        VibrateSubcommand::new(1, 1.0),
+      ],
+    )
+    .into();
+    assert!(ButtplugSpecV1ClientMessage::try_from(uneven_cmd.clone()).is_ok());
+    assert!(ButtplugSpecV0ClientMessage::try_from(uneven_cmd).is_err());
+    let battery_cmd: ButtplugSpecV3ClientMessage =
+      SensorReadCmd::new(0, 0, SensorType::Battery).into();
+    assert!(matches!(
+      ButtplugSpecV2ClientMessage::try_from(battery_cmd.clone()),
+      Ok(ButtplugSpecV2ClientMessage::BatteryLevelCmd(_))
+    ));
+    assert!(matches!(
+      ButtplugSpecV1ClientMessage::try_from(battery_cmd),
+      Err(ButtplugMessageError::VersionError(..))
+    ));
+  }
+
+  #[test]
+  fn test_device_added_upgrade() {
+    let mut builder = ClientDeviceMessageAttributesBuilder::default();
+    builder.scalar_cmd(&[
+      ClientGenericDeviceMessageAttributes::new("Motor", 20, ActuatorType::Vibrate),
+      ClientGenericDeviceMessageAttributes::new("Motor", 10, ActuatorType::Vibrate),
+      ClientGenericDeviceMessageAttributes::new("Pump", 10, ActuatorType::Constrict),
+    ]);
+    builder.sensor_read_cmd(&[SensorDeviceMessageAttributes::new(
+      "Battery",
+      SensorType::Battery,
+      &[0..=100],
+    )]);
+    let attrs = builder.finish();
+    let device_added = DeviceAdded::new(1, "Test Device", &None, &None, &attrs);
+
+    let upgrade = |version| {
+      let msg = ButtplugSpecV3ServerMessage::upgrade(device_added.clone().into(), version)
+        .expect("Test, assuming infallible.");
+      if let ButtplugSpecV3ServerMessage::DeviceAdded(msg) = msg {
+        msg
+      } else {
+        panic!("Expected DeviceAdded, got {:?}", msg);
+      }
+    };
+
+    let v2 = upgrade(ButtplugMessageSpecVersion::Version2);
+    let scalars = v2
+      .device_messages()
+      .scalar_cmd()
+      .as_ref()
+      .expect("Test, assuming infallible.");
+    // Only vibrators can be expressed before v3.
+    assert_eq!(scalars.len(), 2);
+    assert_eq!(*scalars[1].step_count(), 10);
+    assert_eq!(*scalars[1].index(), 1);
+    assert_eq!(
+      *v2
+        .device_messages()
+        .sensor_read_cmd()
+        .as_ref()
+        .expect("Test, assuming infallible.")[0]
+        .sensor_type(),
+      SensorType::Battery
+    );
+
+    let v1 = upgrade(ButtplugMessageSpecVersion::Version1);
+    let scalars = v1
+      .device_messages()
+      .scalar_cmd()
+      .as_ref()
+      .expect("Test, assuming infallible.");
+    assert_eq!(scalars.len(), 2);
+    assert_eq!(*scalars[1].step_count(), 20);
+    assert!(v1.device_messages().sensor_read_cmd().is_none());
+
+    let v0 = upgrade(ButtplugMessageSpecVersion::Version0);
+    assert_eq!(v0.device_index(), 1);
+    assert_eq!(v0.device_name(), "Test Device");
+    assert_eq!(
+      v0.device_messages()
+        .scalar_cmd()
+        .as_ref()
+        .expect("Test, assuming infallible.")
+        .len(),
+      1
+    );
+  }
+
+  #[test]
+  fn test_battery_level_reading_upgrade() {
+    let mut reading = BatteryLevelReading::new(3, 0.5);
+    reading.set_id(7);
+    let mut expected = SensorReading::new(3, 0, SensorType::Battery, vec![50]);
+    expected.set_id(7);
+    assert_eq!(
+      ButtplugSpecV3ServerMessage::try_from(ButtplugSpecV2ServerMessage::BatteryLevelReading(
+        reading
+      )),
+      Ok(ButtplugSpecV3ServerMessage::SensorReading(expected))
+    );
+  }
+}
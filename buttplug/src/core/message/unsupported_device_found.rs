@@ -0,0 +1,78 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Notification that hardware was discovered by the server, but will not be connected.
+
+use super::*;
+use getset::{CopyGetters, Getters};
+#[cfg(feature = "serialize-json")]
+use serde::{Deserialize, Serialize};
+
+/// Reason a discovered piece of hardware was not connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub enum UnsupportedDeviceReason {
+  /// No protocol in the device configuration matched the hardware.
+  NoMatchingProtocol,
+  /// The hardware address is denied (or not allowed) by the user configuration.
+  Denied,
+}
+
+/// Sent by the server when reporting has been enabled via [RequestUnsupportedDeviceReports] and a
+/// communication manager finds hardware that will not be connected.
+///
+/// The specifier is the full communication specifier of the hardware as seen by the server (BLE
+/// name, advertised services and manufacturer data, USB VID/PID, etc...), in the same format used
+/// by device configuration files.
+#[derive(
+  Debug, ButtplugMessage, ButtplugMessageFinalizer, Clone, PartialEq, Eq, Getters, CopyGetters,
+)]
+#[cfg_attr(feature = "serialize-json", derive(Serialize, Deserialize))]
+pub struct UnsupportedDeviceFound {
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Id"))]
+  id: u32,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceName"))]
+  #[getset(get = "pub")]
+  device_name: String,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "DeviceAddress"))]
+  #[getset(get = "pub")]
+  device_address: String,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "CommunicationManager"))]
+  #[getset(get = "pub")]
+  communication_manager: String,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Reason"))]
+  #[getset(get_copy = "pub")]
+  reason: UnsupportedDeviceReason,
+  #[cfg_attr(feature = "serialize-json", serde(rename = "Specifier"))]
+  #[getset(get = "pub")]
+  specifier: serde_json::Value,
+}
+
+impl UnsupportedDeviceFound {
+  pub fn new(
+    device_name: &str,
+    device_address: &str,
+    communication_manager: &str,
+    reason: UnsupportedDeviceReason,
+    specifier: serde_json::Value,
+  ) -> Self {
+    Self {
+      id: 0,
+      device_name: device_name.to_owned(),
+      device_address: device_address.to_owned(),
+      communication_manager: communication_manager.to_owned(),
+      reason,
+      specifier,
+    }
+  }
+}
+
+impl ButtplugMessageValidator for UnsupportedDeviceFound {
+  fn is_valid(&self) -> Result<(), ButtplugMessageError> {
+    self.is_system_id(self.id)
+  }
+}
@@ -0,0 +1,647 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Client and server handles for the C API.
+
+use super::{
+  block_on,
+  enter_runtime,
+  ffi_handle,
+  ffi_status,
+  handle_arg,
+  str_arg,
+  to_c_string,
+  ButtplugFfiDevice,
+  ButtplugFfiSensorType,
+  ButtplugFfiStatus,
+  FfiError,
+};
+use crate::{
+  client::{ButtplugClient, ButtplugClientDevice, ButtplugClientDeviceEvent, ButtplugClientEvent},
+  core::{
+    connector::ButtplugInProcessClientConnectorBuilder,
+    message::ButtplugCurrentSpecServerMessage,
+  },
+  server::{ButtplugServer, ButtplugServerBuilder},
+  util::default_server_builder,
+};
+use futures::{FutureExt, StreamExt};
+use std::{
+  cell::Cell,
+  ffi::{c_char, c_void, CString},
+  sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// Type of event passed to a [ButtplugFfiEventCallback].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtplugFfiEventType {
+  /// A device was added. `device_index` is set, and `message` is the device name.
+  DeviceAdded,
+  /// A device was removed. `device_index` is set.
+  DeviceRemoved,
+  ScanningFinished,
+  /// Hardware was found that will not be connected. `message` is the hardware name.
+  UnsupportedDeviceFound,
+  ServerConnect,
+  ServerDisconnect,
+  PingTimeout,
+  /// An error was received that is not tied to a request. `message` is the error description.
+  Error,
+  /// A subscribed sensor sent a reading. `device_index`, `sensor_index`, `sensor_type`, `data` and
+  /// `data_len` are set.
+  SensorReading,
+  /// A subscribed raw endpoint sent data. `device_index`, `bytes` and `bytes_len` are set, and
+  /// `message` is the endpoint name.
+  RawReading,
+}
+
+/// Event passed to a [ButtplugFfiEventCallback]. Pointers in the event are only valid until the
+/// callback returns. Fields not used by the event type are zero or null.
+#[repr(C)]
+pub struct ButtplugFfiEvent {
+  pub event_type: ButtplugFfiEventType,
+  pub device_index: u32,
+  pub sensor_index: u32,
+  pub sensor_type: ButtplugFfiSensorType,
+  pub data: *const i32,
+  pub data_len: usize,
+  pub bytes: *const u8,
+  pub bytes_len: usize,
+  pub message: *const c_char,
+}
+
+/// Callback for client events. `user_data` is the pointer passed to
+/// [buttplug_client_set_event_callback].
+pub type ButtplugFfiEventCallback =
+  extern "C" fn(event: *const ButtplugFfiEvent, user_data: *mut c_void);
+
+/// Owned version of [ButtplugFfiEvent], for passing between tasks.
+enum FfiEvent {
+  DeviceAdded(u32, String),
+  DeviceRemoved(u32),
+  ScanningFinished,
+  UnsupportedDeviceFound(String),
+  ServerConnect,
+  ServerDisconnect,
+  PingTimeout,
+  Error(String),
+  SensorReading(u32, u32, ButtplugFfiSensorType, Vec<i32>),
+  RawReading(u32, String, Vec<u8>),
+}
+
+#[derive(Clone, Copy)]
+struct EventCallback {
+  callback: ButtplugFfiEventCallback,
+  user_data: *mut c_void,
+}
+
+// The user data pointer is only ever passed back to the callback, so it's up to the caller to make
+// sure it can be used from the callback thread.
+unsafe impl Send for EventCallback {
+}
+
+impl EventCallback {
+  fn call(&self, event: &FfiEvent) {
+    let message = match event {
+      FfiEvent::DeviceAdded(_, message)
+      | FfiEvent::UnsupportedDeviceFound(message)
+      | FfiEvent::Error(message)
+      | FfiEvent::RawReading(_, message, _) => {
+        Some(CString::new(message.replace('\0', "")).expect("Nulls were removed"))
+      }
+      _ => None,
+    };
+    let mut ffi_event = ButtplugFfiEvent {
+      event_type: ButtplugFfiEventType::ScanningFinished,
+      device_index: 0,
+      sensor_index: 0,
+      sensor_type: ButtplugFfiSensorType::Unknown,
+      data: std::ptr::null(),
+      data_len: 0,
+      bytes: std::ptr::null(),
+      bytes_len: 0,
+      message: message.as_ref().map_or(std::ptr::null(), |m| m.as_ptr()),
+    };
+    match event {
+      FfiEvent::DeviceAdded(index, _) => {
+        ffi_event.event_type = ButtplugFfiEventType::DeviceAdded;
+        ffi_event.device_index = *index;
+      }
+      FfiEvent::DeviceRemoved(index) => {
+        ffi_event.event_type = ButtplugFfiEventType::DeviceRemoved;
+        ffi_event.device_index = *index;
+      }
+      FfiEvent::ScanningFinished => ffi_event.event_type = ButtplugFfiEventType::ScanningFinished,
+      FfiEvent::UnsupportedDeviceFound(_) => {
+        ffi_event.event_type = ButtplugFfiEventType::UnsupportedDeviceFound
+      }
+      FfiEvent::ServerConnect => ffi_event.event_type = ButtplugFfiEventType::ServerConnect,
+      FfiEvent::ServerDisconnect => ffi_event.event_type = ButtplugFfiEventType::ServerDisconnect,
+      FfiEvent::PingTimeout => ffi_event.event_type = ButtplugFfiEventType::PingTimeout,
+      FfiEvent::Error(_) => ffi_event.event_type = ButtplugFfiEventType::Error,
+      FfiEvent::SensorReading(device_index, sensor_index, sensor_type, data) => {
+        ffi_event.event_type = ButtplugFfiEventType::SensorReading;
+        ffi_event.device_index = *device_index;
+        ffi_event.sensor_index = *sensor_index;
+        ffi_event.sensor_type = *sensor_type;
+        ffi_event.data = data.as_ptr();
+        ffi_event.data_len = data.len();
+      }
+      FfiEvent::RawReading(device_index, _, data) => {
+        ffi_event.event_type = ButtplugFfiEventType::RawReading;
+        ffi_event.device_index = *device_index;
+        ffi_event.bytes = data.as_ptr();
+        ffi_event.bytes_len = data.len();
+      }
+    }
+    (self.callback)(&ffi_event, self.user_data);
+  }
+}
+
+thread_local! {
+  // Address of the slot whose callback is running on this thread, if any.
+  static DISPATCHING_SLOT: Cell<usize> = const { Cell::new(0) };
+}
+
+#[derive(Default)]
+struct EventCallbackSlot {
+  callback: Mutex<Option<EventCallback>>,
+  // Held while the callback runs.
+  running: Mutex<()>,
+}
+
+impl EventCallbackSlot {
+  fn address(&self) -> usize {
+    self as *const Self as usize
+  }
+
+  fn dispatch(&self, event: &FfiEvent) {
+    let _running = self.running.lock().expect("Callback lock poisoned");
+    // The callback is copied out, rather than called with the lock held, as it may replace itself.
+    let callback = *self.callback.lock().expect("Callback lock poisoned");
+    if let Some(callback) = callback {
+      let previous = DISPATCHING_SLOT.with(|slot| slot.replace(self.address()));
+      callback.call(event);
+      DISPATCHING_SLOT.with(|slot| slot.set(previous));
+    }
+  }
+
+  /// Replaces the callback. Unless called from inside the callback itself, this waits for a running
+  /// callback to finish, so the old callback is never called after this returns.
+  fn set(&self, callback: Option<EventCallback>) {
+    let _running = (DISPATCHING_SLOT.with(Cell::get) != self.address())
+      .then(|| self.running.lock().expect("Callback lock poisoned"));
+    *self.callback.lock().expect("Callback lock poisoned") = callback;
+  }
+}
+
+/// Opaque client handle.
+pub struct ButtplugFfiClient {
+  client: Arc<ButtplugClient>,
+  callback: Arc<EventCallbackSlot>,
+  _event_task_guard: DropGuard,
+}
+
+impl ButtplugFfiClient {
+  fn new(client: ButtplugClient) -> Self {
+    let client = Arc::new(client);
+    let callback = Arc::new(EventCallbackSlot::default());
+    let token = CancellationToken::new();
+    let (event_sender, mut event_receiver) = mpsc::unbounded_channel();
+    // Events from the client and its devices are funneled through one dispatch task, so callbacks
+    // are never run concurrently.
+    let dispatch_callback = callback.clone();
+    let dispatch_token = token.child_token();
+    let _guard = enter_runtime();
+    tokio::spawn(async move {
+      loop {
+        select! {
+          event = event_receiver.recv().fuse() => match event {
+            Some(event) => dispatch_callback.dispatch(&event),
+            None => break,
+          },
+          _ = dispatch_token.cancelled().fuse() => break,
+        }
+      }
+    });
+    let mut client_events = client.event_stream();
+    let event_token = token.child_token();
+    tokio::spawn(async move {
+      loop {
+        let event = select! {
+          event = client_events.next().fuse() => match event {
+            Some(event) => event,
+            None => break,
+          },
+          _ = event_token.cancelled().fuse() => break,
+        };
+        let ffi_event = match event {
+          ButtplugClientEvent::DeviceAdded(device) => {
+            spawn_reading_forwarder(device.clone(), event_sender.clone(), event_token.clone());
+            FfiEvent::DeviceAdded(device.index(), device.name().clone())
+          }
+          ButtplugClientEvent::DeviceRemoved(device) => FfiEvent::DeviceRemoved(device.index()),
+          ButtplugClientEvent::ScanningFinished => FfiEvent::ScanningFinished,
+          ButtplugClientEvent::UnsupportedDeviceFound(msg) => {
+            FfiEvent::UnsupportedDeviceFound(msg.device_name().clone())
+          }
+          ButtplugClientEvent::ServerConnect => FfiEvent::ServerConnect,
+          ButtplugClientEvent::ServerDisconnect => FfiEvent::ServerDisconnect,
+          ButtplugClientEvent::PingTimeout => FfiEvent::PingTimeout,
+          ButtplugClientEvent::Error(e) => FfiEvent::Error(e.to_string()),
+        };
+        if event_sender.send(ffi_event).is_err() {
+          break;
+        }
+      }
+    });
+    Self {
+      client,
+      callback,
+      _event_task_guard: token.drop_guard(),
+    }
+  }
+}
+
+fn spawn_reading_forwarder(
+  device: Arc<ButtplugClientDevice>,
+  event_sender: mpsc::UnboundedSender<FfiEvent>,
+  token: CancellationToken,
+) {
+  let mut events = device.event_stream();
+  tokio::spawn(async move {
+    loop {
+      select! {
+        event = events.next().fuse() => match event {
+          Some(ButtplugClientDeviceEvent::Message(ButtplugCurrentSpecServerMessage::SensorReading(
+            reading,
+          ))) => {
+            let event = FfiEvent::SensorReading(
+              device.index(),
+              reading.sensor_index(),
+              reading.sensor_type().into(),
+              reading.data().clone(),
+            );
+            if event_sender.send(event).is_err() {
+              break;
+            }
+          }
+          Some(ButtplugClientDeviceEvent::Message(ButtplugCurrentSpecServerMessage::RawReading(
+            reading,
+          ))) => {
+            let event = FfiEvent::RawReading(
+              device.index(),
+              reading.endpoint().to_string(),
+              reading.data().clone(),
+            );
+            if event_sender.send(event).is_err() {
+              break;
+            }
+          }
+          Some(ButtplugClientDeviceEvent::Message(_)) => {}
+          _ => break,
+        },
+        _ = token.cancelled().fuse() => break,
+      }
+    }
+  });
+}
+
+/// Opaque server handle, for connecting a client to a server in the same process.
+pub struct ButtplugFfiServer {
+  server: ButtplugServer,
+}
+
+impl ButtplugFfiServer {
+  /// Creates a server handle from a server builder. This is for applications that embed the library
+  /// from Rust, and need to hand a server with custom device communication managers to C code.
+  /// Returns null on failure, setting the last error.
+  pub fn new_handle(builder: &mut ButtplugServerBuilder) -> *mut ButtplugFfiServer {
+    ffi_handle(|| {
+      // Device communication managers start tasks when the server is built.
+      let _guard = enter_runtime();
+      let server = builder.finish().map_err(|e| FfiError {
+        status: ButtplugFfiStatus::UnknownError,
+        message: e.to_string(),
+      })?;
+      Ok(ButtplugFfiServer { server })
+    })
+  }
+}
+
+/// Creates a server with all device communication managers that ship with the library and work on
+/// the current platform. Returns null on failure.
+#[no_mangle]
+pub extern "C" fn buttplug_server_new(allow_raw_messages: bool) -> *mut ButtplugFfiServer {
+  ButtplugFfiServer::new_handle(&mut default_server_builder(allow_raw_messages))
+}
+
+/// Frees a server handle that was not passed to [buttplug_client_connect_in_process].
+///
+/// # Safety
+///
+/// `server` must be null or a live server handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_server_free(server: *mut ButtplugFfiServer) {
+  if !server.is_null() {
+    let _guard = enter_runtime();
+    drop(Box::from_raw(server));
+  }
+}
+
+/// Creates a new, unconnected client. Returns null on failure.
+///
+/// # Safety
+///
+/// `name` must be a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_new(name: *const c_char) -> *mut ButtplugFfiClient {
+  ffi_handle(|| {
+    let name = str_arg(name, "name")?;
+    Ok(ButtplugFfiClient::new(ButtplugClient::new(name)))
+  })
+}
+
+/// Creates a new, unconnected client that presents an authentication token during the handshake.
+/// Returns null on failure.
+///
+/// # Safety
+///
+/// `name` and `auth_token` must be valid null terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_new_with_auth_token(
+  name: *const c_char,
+  auth_token: *const c_char,
+) -> *mut ButtplugFfiClient {
+  ffi_handle(|| {
+    let name = str_arg(name, "name")?;
+    let auth_token = str_arg(auth_token, "auth_token")?;
+    Ok(ButtplugFfiClient::new(ButtplugClient::new_with_auth_token(
+      name, auth_token,
+    )))
+  })
+}
+
+/// Frees a client handle, disconnecting it if needed. No callbacks will be run after this returns.
+/// Must not be called from inside an event callback for the same client.
+///
+/// # Safety
+///
+/// `client` must be null or a live client handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_free(client: *mut ButtplugFfiClient) {
+  if client.is_null() {
+    return;
+  }
+  let client = Box::from_raw(client);
+  client.callback.set(None);
+  if client.client.connected() {
+    let _ = block_on(client.client.disconnect());
+  }
+  let _guard = enter_runtime();
+  drop(client);
+}
+
+/// Sets the callback for client events, replacing any existing callback. Passing a null callback
+/// removes it. Can be called from inside an event callback, in which case the change applies from
+/// the next event.
+///
+/// # Safety
+///
+/// `client` must be a live client handle. `user_data` must be usable from the callback thread.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_set_event_callback(
+  client: *mut ButtplugFfiClient,
+  callback: Option<ButtplugFfiEventCallback>,
+  user_data: *mut c_void,
+) -> ButtplugFfiStatus {
+  ffi_status(|| {
+    let client = handle_arg(client, "client")?;
+    client.callback.set(callback.map(|callback| EventCallback {
+      callback,
+      user_data,
+    }));
+    Ok(())
+  })
+}
+
+/// Connects a client to a server in the same process. Takes ownership of the server handle, which
+/// is invalid after this call whether or not the connection succeeds.
+///
+/// # Safety
+///
+/// `client` must be a live client handle, and `server` a live server handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_connect_in_process(
+  client: *mut ButtplugFfiClient,
+  server: *mut ButtplugFfiServer,
+) -> ButtplugFfiStatus {
+  let server = (!server.is_null()).then(|| Box::from_raw(server));
+  ffi_status(|| {
+    let client = handle_arg(client, "client")?.client.clone();
+    let server = server.ok_or_else(|| FfiError::invalid_argument("server is null."))?;
+    let connector = ButtplugInProcessClientConnectorBuilder::default()
+      .server(server.server)
+      .finish();
+    block_on(async move { client.connect(connector).await })?;
+    Ok(())
+  })
+}
+
+/// Connects a client to a remote server over websockets, i.e. `ws://127.0.0.1:12345`. `wss://`
+/// addresses use TLS, with certificate verification unless `bypass_cert_verify` is set.
+///
+/// # Safety
+///
+/// `client` must be a live client handle, and `address` a valid null terminated string.
+#[cfg(feature = "websockets")]
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_connect_websocket(
+  client: *mut ButtplugFfiClient,
+  address: *const c_char,
+  bypass_cert_verify: bool,
+) -> ButtplugFfiStatus {
+  use crate::core::{
+    connector::{transport::ButtplugWebsocketClientTransport, ButtplugRemoteClientConnector},
+    message::serializer::ButtplugClientJSONSerializer,
+  };
+
+  ffi_status(|| {
+    let client = handle_arg(client, "client")?.client.clone();
+    let address = str_arg(address, "address")?;
+    let transport = if address.starts_with("wss://") {
+      ButtplugWebsocketClientTransport::new_secure_connector(address, bypass_cert_verify)
+    } else {
+      ButtplugWebsocketClientTransport::new_insecure_connector(address)
+    };
+    let connector = ButtplugRemoteClientConnector::<
+      ButtplugWebsocketClientTransport,
+      ButtplugClientJSONSerializer,
+    >::new(transport);
+    block_on(async move { client.connect(connector).await })?;
+    Ok(())
+  })
+}
+
+/// Disconnects a client from its server. The client handle stays valid, and can be reconnected.
+///
+/// # Safety
+///
+/// `client` must be a live client handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_disconnect(
+  client: *mut ButtplugFfiClient,
+) -> ButtplugFfiStatus {
+  ffi_status(|| {
+    let client = handle_arg(client, "client")?;
+    block_on(client.client.disconnect())?;
+    Ok(())
+  })
+}
+
+/// Returns true if the client is connected to a server.
+///
+/// # Safety
+///
+/// `client` must be null or a live client handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_connected(client: *const ButtplugFfiClient) -> bool {
+  client
+    .as_ref()
+    .is_some_and(|client| client.client.connected())
+}
+
+/// Returns the name of the connected server, or null if not connected. The string must be freed
+/// with [buttplug_free_string](super::buttplug_free_string).
+///
+/// # Safety
+///
+/// `client` must be null or a live client handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_server_name(
+  client: *const ButtplugFfiClient,
+) -> *mut c_char {
+  client
+    .as_ref()
+    .and_then(|client| client.client.server_name())
+    .map_or(std::ptr::null_mut(), |name| to_c_string(&name))
+}
+
+/// Starts scanning for devices. Devices are reported through the event callback.
+///
+/// # Safety
+///
+/// `client` must be a live client handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_start_scanning(
+  client: *mut ButtplugFfiClient,
+) -> ButtplugFfiStatus {
+  ffi_status(|| {
+    let client = handle_arg(client, "client")?;
+    block_on(client.client.start_scanning())?;
+    Ok(())
+  })
+}
+
+/// Stops scanning for devices.
+///
+/// # Safety
+///
+/// `client` must be a live client handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_stop_scanning(
+  client: *mut ButtplugFfiClient,
+) -> ButtplugFfiStatus {
+  ffi_status(|| {
+    let client = handle_arg(client, "client")?;
+    block_on(client.client.stop_scanning())?;
+    Ok(())
+  })
+}
+
+/// Stops all devices connected to the server.
+///
+/// # Safety
+///
+/// `client` must be a live client handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_stop_all_devices(
+  client: *mut ButtplugFfiClient,
+) -> ButtplugFfiStatus {
+  ffi_status(|| {
+    let client = handle_arg(client, "client")?;
+    block_on(client.client.stop_all_devices())?;
+    Ok(())
+  })
+}
+
+/// Pings the server. Only needed if the server was set up with a maximum ping time.
+///
+/// # Safety
+///
+/// `client` must be a live client handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_ping(client: *mut ButtplugFfiClient) -> ButtplugFfiStatus {
+  ffi_status(|| {
+    let client = handle_arg(client, "client")?;
+    block_on(client.client.ping())?;
+    Ok(())
+  })
+}
+
+/// Writes the indexes of connected devices to `indexes`, up to `capacity` entries, and returns the
+/// total number of connected devices. Call with a null `indexes` and zero `capacity` to get the
+/// count.
+///
+/// # Safety
+///
+/// `client` must be null or a live client handle. `indexes` must be null or valid for `capacity`
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_device_indexes(
+  client: *const ButtplugFfiClient,
+  indexes: *mut u32,
+  capacity: usize,
+) -> usize {
+  let Some(client) = client.as_ref() else {
+    return 0;
+  };
+  let mut device_indexes: Vec<u32> = client.client.devices().iter().map(|d| d.index()).collect();
+  device_indexes.sort_unstable();
+  if !indexes.is_null() {
+    for (i, index) in device_indexes.iter().take(capacity).enumerate() {
+      *indexes.add(i) = *index;
+    }
+  }
+  device_indexes.len()
+}
+
+/// Returns a handle to the device with the given index, or null if there is no such device. The
+/// handle must be freed with [buttplug_device_free](super::buttplug_device_free).
+///
+/// # Safety
+///
+/// `client` must be a live client handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_client_get_device(
+  client: *const ButtplugFfiClient,
+  device_index: u32,
+) -> *mut ButtplugFfiDevice {
+  ffi_handle(|| {
+    let client = handle_arg(client, "client")?;
+    client
+      .client
+      .devices()
+      .into_iter()
+      .find(|device| device.index() == device_index)
+      .map(ButtplugFfiDevice::new)
+      .ok_or_else(|| FfiError::invalid_argument(&format!("No device with index {}.", device_index)))
+  })
+}
@@ -0,0 +1,521 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Device handles for the C API.
+
+use super::{
+  block_on,
+  ffi_status,
+  handle_arg,
+  str_arg,
+  to_c_string,
+  ButtplugFfiActuatorType,
+  ButtplugFfiSensorType,
+  ButtplugFfiStatus,
+  FfiError,
+  FfiResult,
+};
+use crate::{
+  client::{ButtplugClientDevice, LinearCommand, RotateCommand, ScalarCommand, ScalarValueCommand},
+  core::message::{ClientGenericDeviceMessageAttributes, Endpoint},
+};
+use std::{collections::HashMap, ffi::c_char, str::FromStr, sync::Arc};
+
+/// Opaque device handle.
+pub struct ButtplugFfiDevice {
+  device: Arc<ButtplugClientDevice>,
+}
+
+impl ButtplugFfiDevice {
+  pub(crate) fn new(device: Arc<ButtplugClientDevice>) -> Self {
+    Self { device }
+  }
+}
+
+/// Runs a device command that returns a status.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+unsafe fn device_command(
+  device: *const ButtplugFfiDevice,
+  command: impl FnOnce(&ButtplugClientDevice) -> FfiResult,
+) -> ButtplugFfiStatus {
+  ffi_status(|| command(&handle_arg(device, "device")?.device))
+}
+
+/// # Safety
+///
+/// `endpoint` must be null or a valid null terminated string.
+unsafe fn endpoint_arg(endpoint: *const c_char) -> FfiResult<Endpoint> {
+  let endpoint = str_arg(endpoint, "endpoint")?;
+  Endpoint::from_str(endpoint)
+    .map_err(|_| FfiError::invalid_argument(&format!("{} is not a valid endpoint.", endpoint)))
+}
+
+fn scalar_attribute(
+  device: &ButtplugClientDevice,
+  feature_index: u32,
+) -> Option<ClientGenericDeviceMessageAttributes> {
+  device
+    .scalar_attributes()
+    .into_iter()
+    .nth(feature_index as usize)
+}
+
+/// Frees a device handle.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_free(device: *mut ButtplugFfiDevice) {
+  if !device.is_null() {
+    drop(Box::from_raw(device));
+  }
+}
+
+/// Returns the index of the device.
+///
+/// # Safety
+///
+/// `device` must be a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_index(device: *const ButtplugFfiDevice) -> u32 {
+  device.as_ref().map_or(0, |device| device.device.index())
+}
+
+/// Returns the name of the device. The string must be freed with
+/// [buttplug_free_string](super::buttplug_free_string).
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_name(device: *const ButtplugFfiDevice) -> *mut c_char {
+  device.as_ref().map_or(std::ptr::null_mut(), |device| {
+    to_c_string(device.device.name())
+  })
+}
+
+/// Returns the user configured display name of the device, or null if there isn't one. The string
+/// must be freed with [buttplug_free_string](super::buttplug_free_string).
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_display_name(
+  device: *const ButtplugFfiDevice,
+) -> *mut c_char {
+  device
+    .as_ref()
+    .and_then(|device| device.device.display_name().as_ref())
+    .map_or(std::ptr::null_mut(), |name| to_c_string(name))
+}
+
+/// Returns true if the device is still connected to the server.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_connected(device: *const ButtplugFfiDevice) -> bool {
+  device
+    .as_ref()
+    .is_some_and(|device| device.device.connected())
+}
+
+/// Returns the device's message attributes as JSON, in the same format as the `DeviceMessages`
+/// field of a DeviceAdded message. The string must be freed with
+/// [buttplug_free_string](super::buttplug_free_string).
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_message_attributes_json(
+  device: *const ButtplugFfiDevice,
+) -> *mut c_char {
+  device.as_ref().map_or(std::ptr::null_mut(), |device| {
+    to_c_string(
+      &serde_json::to_string(device.device.message_attributes())
+        .expect("Message attributes are always serializable"),
+    )
+  })
+}
+
+/// Returns the number of scalar features (vibrators, oscillators, etc...) on the device.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_scalar_count(device: *const ButtplugFfiDevice) -> u32 {
+  device
+    .as_ref()
+    .map_or(0, |device| device.device.scalar_attributes().len() as u32)
+}
+
+/// Returns the actuator type of a scalar feature, or Unknown if the feature doesn't exist.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_scalar_actuator_type(
+  device: *const ButtplugFfiDevice,
+  feature_index: u32,
+) -> ButtplugFfiActuatorType {
+  device
+    .as_ref()
+    .and_then(|device| scalar_attribute(&device.device, feature_index))
+    .map_or(ButtplugFfiActuatorType::Unknown, |attr| {
+      (*attr.actuator_type()).into()
+    })
+}
+
+/// Returns the number of steps a scalar feature supports, or 0 if the feature doesn't exist.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_scalar_step_count(
+  device: *const ButtplugFfiDevice,
+  feature_index: u32,
+) -> u32 {
+  device
+    .as_ref()
+    .and_then(|device| scalar_attribute(&device.device, feature_index))
+    .map_or(0, |attr| *attr.step_count())
+}
+
+/// Returns the number of rotation features on the device.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_rotate_count(device: *const ButtplugFfiDevice) -> u32 {
+  device.as_ref().map_or(0, |device| {
+    device
+      .device
+      .message_attributes()
+      .rotate_cmd()
+      .as_ref()
+      .map_or(0, |attrs| attrs.len() as u32)
+  })
+}
+
+/// Returns the number of linear features on the device.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_linear_count(device: *const ButtplugFfiDevice) -> u32 {
+  device
+    .as_ref()
+    .map_or(0, |device| device.device.linear_attributes().len() as u32)
+}
+
+/// Sets all vibration features of the device to `speed`, from 0.0 to 1.0.
+///
+/// # Safety
+///
+/// `device` must be a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_vibrate(
+  device: *const ButtplugFfiDevice,
+  speed: f64,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    Ok(block_on(
+      device.vibrate(&ScalarValueCommand::ScalarValue(speed)),
+    )?)
+  })
+}
+
+/// Sets all oscillation features of the device to `speed`, from 0.0 to 1.0.
+///
+/// # Safety
+///
+/// `device` must be a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_oscillate(
+  device: *const ButtplugFfiDevice,
+  speed: f64,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    Ok(block_on(
+      device.oscillate(&ScalarValueCommand::ScalarValue(speed)),
+    )?)
+  })
+}
+
+/// Sets a single scalar feature of the device to `scalar`, from 0.0 to 1.0.
+///
+/// # Safety
+///
+/// `device` must be a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_scalar(
+  device: *const ButtplugFfiDevice,
+  feature_index: u32,
+  scalar: f64,
+  actuator_type: ButtplugFfiActuatorType,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    let map = HashMap::from([(feature_index, (scalar, actuator_type.into()))]);
+    Ok(block_on(device.scalar(&ScalarCommand::ScalarMap(map)))?)
+  })
+}
+
+/// Sets a single rotation feature of the device to `speed`, from 0.0 to 1.0, in the given
+/// direction.
+///
+/// # Safety
+///
+/// `device` must be a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_rotate(
+  device: *const ButtplugFfiDevice,
+  feature_index: u32,
+  speed: f64,
+  clockwise: bool,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    let map = HashMap::from([(feature_index, (speed, clockwise))]);
+    Ok(block_on(device.rotate(&RotateCommand::RotateMap(map)))?)
+  })
+}
+
+/// Moves a single linear feature of the device to `position`, from 0.0 to 1.0, over `duration`
+/// milliseconds.
+///
+/// # Safety
+///
+/// `device` must be a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_linear(
+  device: *const ButtplugFfiDevice,
+  feature_index: u32,
+  duration: u32,
+  position: f64,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    let map = HashMap::from([(feature_index, (duration, position))]);
+    Ok(block_on(device.linear(&LinearCommand::LinearMap(map)))?)
+  })
+}
+
+/// Stops all features of the device.
+///
+/// # Safety
+///
+/// `device` must be a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_stop(
+  device: *const ButtplugFfiDevice,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| Ok(block_on(device.stop())?))
+}
+
+/// Returns true if the device can report its battery level.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_has_battery_level(
+  device: *const ButtplugFfiDevice,
+) -> bool {
+  device
+    .as_ref()
+    .is_some_and(|device| device.device.has_battery_level())
+}
+
+/// Reads the battery level of the device, from 0.0 to 1.0, into `level`.
+///
+/// # Safety
+///
+/// `device` must be a live device handle, and `level` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_battery_level(
+  device: *const ButtplugFfiDevice,
+  level: *mut f64,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    if level.is_null() {
+      return Err(FfiError::invalid_argument("level is null."));
+    }
+    *level = block_on(device.battery_level())?;
+    Ok(())
+  })
+}
+
+/// Returns true if the device can report its signal strength.
+///
+/// # Safety
+///
+/// `device` must be null or a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_has_rssi_level(device: *const ButtplugFfiDevice) -> bool {
+  device
+    .as_ref()
+    .is_some_and(|device| device.device.has_rssi_level())
+}
+
+/// Reads the signal strength of the device, in dBm, into `level`.
+///
+/// # Safety
+///
+/// `device` must be a live device handle, and `level` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_rssi_level(
+  device: *const ButtplugFfiDevice,
+  level: *mut i32,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    if level.is_null() {
+      return Err(FfiError::invalid_argument("level is null."));
+    }
+    *level = block_on(device.rssi_level())?;
+    Ok(())
+  })
+}
+
+/// Subscribes to a sensor. Readings are delivered as SensorReading events through the client's
+/// event callback.
+///
+/// # Safety
+///
+/// `device` must be a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_subscribe_sensor(
+  device: *const ButtplugFfiDevice,
+  sensor_index: u32,
+  sensor_type: ButtplugFfiSensorType,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    Ok(block_on(
+      device.subscribe_sensor(sensor_index, sensor_type.into()),
+    )?)
+  })
+}
+
+/// Unsubscribes from a sensor.
+///
+/// # Safety
+///
+/// `device` must be a live device handle.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_unsubscribe_sensor(
+  device: *const ButtplugFfiDevice,
+  sensor_index: u32,
+  sensor_type: ButtplugFfiSensorType,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    Ok(block_on(
+      device.unsubscribe_sensor(sensor_index, sensor_type.into()),
+    )?)
+  })
+}
+
+/// Writes raw data to a device endpoint (i.e. `tx`). Only works if the server allows raw messages.
+///
+/// # Safety
+///
+/// `device` must be a live device handle, `endpoint` a valid null terminated string, and `data`
+/// valid for `len` reads.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_raw_write(
+  device: *const ButtplugFfiDevice,
+  endpoint: *const c_char,
+  data: *const u8,
+  len: usize,
+  write_with_response: bool,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    let endpoint = endpoint_arg(endpoint)?;
+    if data.is_null() && len > 0 {
+      return Err(FfiError::invalid_argument("data is null."));
+    }
+    let data = if len == 0 {
+      &[]
+    } else {
+      std::slice::from_raw_parts(data, len)
+    };
+    Ok(block_on(device.raw_write(
+      endpoint,
+      data,
+      write_with_response,
+    ))?)
+  })
+}
+
+/// Reads raw data from a device endpoint. On success, `data` and `len` are set to a buffer that
+/// must be freed with [buttplug_free_bytes](super::buttplug_free_bytes).
+///
+/// # Safety
+///
+/// `device` must be a live device handle, `endpoint` a valid null terminated string, and `data` and
+/// `len` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_raw_read(
+  device: *const ButtplugFfiDevice,
+  endpoint: *const c_char,
+  expected_length: u32,
+  timeout: u32,
+  data: *mut *mut u8,
+  len: *mut usize,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    let endpoint = endpoint_arg(endpoint)?;
+    if data.is_null() || len.is_null() {
+      return Err(FfiError::invalid_argument("data or len is null."));
+    }
+    let reading = block_on(device.raw_read(endpoint, expected_length, timeout))?;
+    let reading = Box::into_raw(reading.into_boxed_slice());
+    *len = reading.len();
+    *data = reading as *mut u8;
+    Ok(())
+  })
+}
+
+/// Subscribes to raw notifications from a device endpoint.
+///
+/// # Safety
+///
+/// `device` must be a live device handle, and `endpoint` a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_raw_subscribe(
+  device: *const ButtplugFfiDevice,
+  endpoint: *const c_char,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    let endpoint = endpoint_arg(endpoint)?;
+    Ok(block_on(device.raw_subscribe(endpoint))?)
+  })
+}
+
+/// Unsubscribes from raw notifications from a device endpoint.
+///
+/// # Safety
+///
+/// `device` must be a live device handle, and `endpoint` a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_device_raw_unsubscribe(
+  device: *const ButtplugFfiDevice,
+  endpoint: *const c_char,
+) -> ButtplugFfiStatus {
+  device_command(device, |device| {
+    let endpoint = endpoint_arg(endpoint)?;
+    Ok(block_on(device.raw_unsubscribe(endpoint))?)
+  })
+}
@@ -0,0 +1,377 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! C API over the [client](crate::client) and [server](crate::server), for use from other languages.
+//!
+//! The C header for this API lives at `include/buttplug.h`, and is generated from this module via
+//! [cbindgen](https://github.com/mozilla/cbindgen) using the `cbindgen.toml` config in the crate
+//! root:
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --crate buttplug --output include/buttplug.h
+//! ```
+//!
+//! # Handles and Lifetimes
+//!
+//! Clients, servers and devices are passed across the API as opaque handles. Every handle returned
+//! from a `_new` or `_get` function is owned by the caller, and must be released with the matching
+//! `_free` function. Passing a handle to a `_free` function (or to
+//! [buttplug_client_connect_in_process], for servers) invalidates it. Device handles keep working
+//! after their client is freed, but all commands on them will fail.
+//!
+//! Strings returned by the API are owned by the caller and must be released with
+//! [buttplug_free_string]. Byte buffers must be released with [buttplug_free_bytes].
+//!
+//! # Errors
+//!
+//! Fallible functions return a [ButtplugFfiStatus]. On failure, a description of the error can be
+//! retrieved via [buttplug_last_error_message], which is kept per thread until the next failure.
+//! Functions that return handles return null on failure, and also set the last error.
+//!
+//! # Threading
+//!
+//! All calls block until the operation completes. Calls are safe from any thread, including from
+//! inside event callbacks. Event callbacks are run on an internal runtime thread, one at a time per
+//! client.
+
+mod client;
+mod device;
+
+pub use client::*;
+pub use device::*;
+
+use crate::{
+  client::ButtplugClientError,
+  core::{
+    errors::ButtplugError,
+    message::{ActuatorType, SensorType},
+  },
+};
+use once_cell::sync::Lazy;
+use std::{
+  cell::RefCell,
+  ffi::{c_char, CStr, CString},
+  future::Future,
+  panic::{catch_unwind, AssertUnwindSafe},
+};
+use tokio::runtime::{EnterGuard, Handle, Runtime, RuntimeFlavor};
+
+/// Runtime that runs all clients, servers and device commands created through the C API.
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+  tokio::runtime::Builder::new_multi_thread()
+    .enable_all()
+    .thread_name("buttplug-ffi")
+    .build()
+    .expect("FFI runtime creation should never fail")
+});
+
+thread_local! {
+  static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Result of a fallible C API call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtplugFfiStatus {
+  Ok = 0,
+  /// A handle or pointer was null, or a string was not valid UTF-8.
+  InvalidArgument,
+  /// The connection to the server failed or was lost.
+  ConnectorError,
+  HandshakeError,
+  MessageError,
+  PingError,
+  DeviceError,
+  UnknownError,
+  /// The library panicked during the call. The process should be considered unstable.
+  Panic,
+  /// The server didn't reply within the client's request timeout.
+  Timeout,
+}
+
+impl From<&ButtplugClientError> for ButtplugFfiStatus {
+  fn from(error: &ButtplugClientError) -> Self {
+    match error {
+      ButtplugClientError::ButtplugConnectorError(_) => ButtplugFfiStatus::ConnectorError,
+      ButtplugClientError::RequestTimeout(_) => ButtplugFfiStatus::Timeout,
+      ButtplugClientError::ButtplugError(error) => match error {
+        ButtplugError::ButtplugHandshakeError(_) => ButtplugFfiStatus::HandshakeError,
+        ButtplugError::ButtplugMessageError(_) => ButtplugFfiStatus::MessageError,
+        ButtplugError::ButtplugPingError(_) => ButtplugFfiStatus::PingError,
+        ButtplugError::ButtplugDeviceError(_) => ButtplugFfiStatus::DeviceError,
+        ButtplugError::ButtplugUnknownError(_) => ButtplugFfiStatus::UnknownError,
+      },
+    }
+  }
+}
+
+/// Actuator types, matching [ActuatorType].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtplugFfiActuatorType {
+  Unknown = 0,
+  Vibrate,
+  Rotate,
+  Oscillate,
+  Constrict,
+  Inflate,
+  Position,
+}
+
+impl From<ButtplugFfiActuatorType> for ActuatorType {
+  fn from(actuator_type: ButtplugFfiActuatorType) -> Self {
+    match actuator_type {
+      ButtplugFfiActuatorType::Unknown => ActuatorType::Unknown,
+      ButtplugFfiActuatorType::Vibrate => ActuatorType::Vibrate,
+      ButtplugFfiActuatorType::Rotate => ActuatorType::Rotate,
+      ButtplugFfiActuatorType::Oscillate => ActuatorType::Oscillate,
+      ButtplugFfiActuatorType::Constrict => ActuatorType::Constrict,
+      ButtplugFfiActuatorType::Inflate => ActuatorType::Inflate,
+      ButtplugFfiActuatorType::Position => ActuatorType::Position,
+    }
+  }
+}
+
+impl From<ActuatorType> for ButtplugFfiActuatorType {
+  fn from(actuator_type: ActuatorType) -> Self {
+    match actuator_type {
+      ActuatorType::Unknown => ButtplugFfiActuatorType::Unknown,
+      ActuatorType::Vibrate => ButtplugFfiActuatorType::Vibrate,
+      ActuatorType::Rotate => ButtplugFfiActuatorType::Rotate,
+      ActuatorType::Oscillate => ButtplugFfiActuatorType::Oscillate,
+      ActuatorType::Constrict => ButtplugFfiActuatorType::Constrict,
+      ActuatorType::Inflate => ButtplugFfiActuatorType::Inflate,
+      ActuatorType::Position => ButtplugFfiActuatorType::Position,
+    }
+  }
+}
+
+/// Sensor types, matching [SensorType].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtplugFfiSensorType {
+  Unknown = 0,
+  Battery,
+  RSSI,
+  Button,
+  Pressure,
+}
+
+impl From<ButtplugFfiSensorType> for SensorType {
+  fn from(sensor_type: ButtplugFfiSensorType) -> Self {
+    match sensor_type {
+      ButtplugFfiSensorType::Unknown => SensorType::Unknown,
+      ButtplugFfiSensorType::Battery => SensorType::Battery,
+      ButtplugFfiSensorType::RSSI => SensorType::RSSI,
+      ButtplugFfiSensorType::Button => SensorType::Button,
+      ButtplugFfiSensorType::Pressure => SensorType::Pressure,
+    }
+  }
+}
+
+impl From<SensorType> for ButtplugFfiSensorType {
+  fn from(sensor_type: SensorType) -> Self {
+    match sensor_type {
+      SensorType::Unknown => ButtplugFfiSensorType::Unknown,
+      SensorType::Battery => ButtplugFfiSensorType::Battery,
+      SensorType::RSSI => ButtplugFfiSensorType::RSSI,
+      SensorType::Button => ButtplugFfiSensorType::Button,
+      SensorType::Pressure => ButtplugFfiSensorType::Pressure,
+    }
+  }
+}
+
+/// Error raised inside a C API call, before it's stored as the thread's last error.
+pub(crate) struct FfiError {
+  status: ButtplugFfiStatus,
+  message: String,
+}
+
+impl FfiError {
+  pub(crate) fn invalid_argument(message: &str) -> Self {
+    Self {
+      status: ButtplugFfiStatus::InvalidArgument,
+      message: message.to_owned(),
+    }
+  }
+}
+
+impl From<ButtplugClientError> for FfiError {
+  fn from(error: ButtplugClientError) -> Self {
+    Self {
+      status: ButtplugFfiStatus::from(&error),
+      message: error.to_string(),
+    }
+  }
+}
+
+pub(crate) type FfiResult<T = ()> = Result<T, FfiError>;
+
+fn set_last_error(message: &str) {
+  // Interior nulls can't be represented in a C string, so drop them.
+  let message = CString::new(message.replace('\0', "")).expect("Nulls were removed");
+  LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+fn catch_ffi_panic<T>(call: impl FnOnce() -> FfiResult<T>) -> FfiResult<T> {
+  catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|_| {
+    Err(FfiError {
+      status: ButtplugFfiStatus::Panic,
+      message: "Buttplug library panicked during FFI call.".to_owned(),
+    })
+  })
+}
+
+/// Runs a status returning C API call, catching panics and storing errors.
+pub(crate) fn ffi_status(call: impl FnOnce() -> FfiResult) -> ButtplugFfiStatus {
+  match catch_ffi_panic(call) {
+    Ok(()) => ButtplugFfiStatus::Ok,
+    Err(error) => {
+      set_last_error(&error.message);
+      error.status
+    }
+  }
+}
+
+/// Runs a handle returning C API call, catching panics and storing errors. Returns null on failure.
+pub(crate) fn ffi_handle<T>(call: impl FnOnce() -> FfiResult<T>) -> *mut T {
+  match catch_ffi_panic(call) {
+    Ok(value) => Box::into_raw(Box::new(value)),
+    Err(error) => {
+      set_last_error(&error.message);
+      std::ptr::null_mut()
+    }
+  }
+}
+
+/// Runs a future to completion on the FFI runtime. Works from foreign threads, from the runtime's
+/// own threads (i.e. from inside an event callback), and from threads running some other runtime.
+pub(crate) fn block_on<F>(future: F) -> F::Output
+where
+  F: Future + Send + 'static,
+  F::Output: Send + 'static,
+{
+  match Handle::try_current() {
+    Err(_) => RUNTIME.block_on(future),
+    // Runtimes can't be blocked on from inside a runtime, but a multi threaded runtime can move its
+    // other tasks off this thread first, so they keep running while we wait.
+    Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+      tokio::task::block_in_place(|| RUNTIME.block_on(future))
+    }
+    // A current thread runtime has nowhere to move its tasks, so it's stuck until the future,
+    // running as a task on our runtime, finishes.
+    Ok(_) => {
+      let task = RUNTIME.spawn(future);
+      futures::executor::block_on(task).expect("Task only fails if it panics")
+    }
+  }
+}
+
+/// Enters the FFI runtime, for calls that need a runtime context to spawn tasks.
+pub(crate) fn enter_runtime() -> EnterGuard<'static> {
+  RUNTIME.enter()
+}
+
+/// Converts a C string argument to a Rust string.
+///
+/// # Safety
+///
+/// `value` must be null or point to a valid null terminated string.
+pub(crate) unsafe fn str_arg<'a>(value: *const c_char, name: &str) -> FfiResult<&'a str> {
+  if value.is_null() {
+    return Err(FfiError::invalid_argument(&format!("{} is null.", name)));
+  }
+  CStr::from_ptr(value)
+    .to_str()
+    .map_err(|_| FfiError::invalid_argument(&format!("{} is not valid UTF-8.", name)))
+}
+
+/// Converts a handle argument to a reference.
+///
+/// # Safety
+///
+/// `handle` must be null or a live handle returned by this API.
+pub(crate) unsafe fn handle_arg<'a, T>(handle: *const T, name: &str) -> FfiResult<&'a T> {
+  handle
+    .as_ref()
+    .ok_or_else(|| FfiError::invalid_argument(&format!("{} is null.", name)))
+}
+
+pub(crate) fn to_c_string(value: &str) -> *mut c_char {
+  CString::new(value.replace('\0', ""))
+    .expect("Nulls were removed")
+    .into_raw()
+}
+
+/// Returns the message of the last error that occurred on the calling thread, or null if there has
+/// not been one. The string is owned by the library, and is valid until the next failing call on
+/// the same thread.
+#[no_mangle]
+pub extern "C" fn buttplug_last_error_message() -> *const c_char {
+  LAST_ERROR.with(|last_error| {
+    last_error
+      .borrow()
+      .as_ref()
+      .map_or(std::ptr::null(), |message| message.as_ptr())
+  })
+}
+
+/// Frees a string returned by the API.
+///
+/// # Safety
+///
+/// `value` must be null or a string returned by this API that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_free_string(value: *mut c_char) {
+  if !value.is_null() {
+    drop(CString::from_raw(value));
+  }
+}
+
+/// Frees a byte buffer returned by the API.
+///
+/// # Safety
+///
+/// `data` must be null or a buffer returned by this API that has not already been freed, and
+/// `len` must be the length returned with it.
+#[no_mangle]
+pub unsafe extern "C" fn buttplug_free_bytes(data: *mut u8, len: usize) {
+  if !data.is_null() {
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)));
+  }
+}
+
+#[cfg(test)]
+mod test {
+  #[test]
+  fn test_header_declares_all_exports() {
+    // The header is generated, but checked in, so make sure it hasn't fallen behind the exports.
+    let header = include_str!("../../include/buttplug.h");
+    let sources = [
+      include_str!("mod.rs"),
+      include_str!("client.rs"),
+      include_str!("device.rs"),
+    ];
+    for source in sources {
+      for line in source.lines() {
+        let line = line.trim_start();
+        let Some(rest) = line
+          .strip_prefix("pub extern \"C\" fn ")
+          .or_else(|| line.strip_prefix("pub unsafe extern \"C\" fn "))
+        else {
+          continue;
+        };
+        let name = rest.split('(').next().expect("Always has a first element");
+        assert!(
+          header.contains(&format!("{}(", name)),
+          "{} is missing from include/buttplug.h",
+          name
+        );
+      }
+    }
+  }
+}
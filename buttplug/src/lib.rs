@@ -26,6 +26,9 @@
 //!   - Handles actual hardware connections and communication. If you want to add new devices or
 //!     protocols to Buttplug, or change how the system access devices, this is the module you'll be
 //!     working in.
+//! - [Testing](crate::testing)
+//!   - In-process test servers with fake devices, for testing applications built on Buttplug.
+//!     Only available with the `testing` feature.
 //! - [Util](crate::util)
 //!   - Utilities for all portions of the library that may not be specifically related to sex toy
 //!     functionality. This includes managers for different async runtimes, configuration file
@@ -44,6 +47,10 @@ extern crate tracing;
 #[cfg(feature = "client")]
 pub mod client;
 pub mod core;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 #[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod util;
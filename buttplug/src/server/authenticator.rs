@@ -0,0 +1,102 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Client authentication, run as part of the connection handshake.
+//!
+//! By default, any client that can reach a server (for instance, over a websocket port open on the
+//! local network) can connect to it and control devices. Setting a [ButtplugServerAuthenticator]
+//! on the [ButtplugServerBuilder](crate::server::ButtplugServerBuilder) makes the server check each
+//! [RequestServerInfo] handshake message, rejecting clients that fail the check with
+//! [ButtplugHandshakeError::AuthenticationFailed](crate::core::errors::ButtplugHandshakeError::AuthenticationFailed).
+//!
+//! Credentials are carried in the handshake message itself (see
+//! [ButtplugClient::new_with_auth_token](crate::client::ButtplugClient::new_with_auth_token)), so
+//! authentication works the same way across all connector transports. Transports that can carry
+//! credentials before the handshake, like the websocket server transport, can also check an
+//! `Authorization: Bearer` header with the same authenticator.
+
+use crate::core::message::RequestServerInfo;
+
+/// Decides whether a client is allowed to connect, based on the token it presents.
+pub trait ButtplugServerAuthenticator: Send + Sync {
+  /// Returns true if a client presenting the given token (or no token at all) should be allowed to
+  /// connect.
+  fn authenticate_token(&self, token: Option<&str>) -> bool;
+
+  /// Returns true if the client that sent the handshake should be allowed to connect.
+  fn authenticate(&self, request: &RequestServerInfo) -> bool {
+    self.authenticate_token(request.auth_token())
+  }
+}
+
+/// Extracts the token from an `Authorization: Bearer <token>` header value.
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+  let (scheme, token) = authorization.trim().split_once(' ')?;
+  scheme
+    .eq_ignore_ascii_case("bearer")
+    .then_some(token.trim())
+}
+
+/// Authenticator that requires clients to present a token shared ahead of time.
+pub struct SharedTokenAuthenticator {
+  token: String,
+}
+
+impl SharedTokenAuthenticator {
+  pub fn new(token: &str) -> Self {
+    Self {
+      token: token.to_owned(),
+    }
+  }
+}
+
+impl ButtplugServerAuthenticator for SharedTokenAuthenticator {
+  fn authenticate_token(&self, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+      return false;
+    };
+    // Compare every byte, so response timing doesn't leak how much of the token matched.
+    token.len() == self.token.len()
+      && token
+        .bytes()
+        .zip(self.token.bytes())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{bearer_token, ButtplugServerAuthenticator, SharedTokenAuthenticator};
+  use crate::core::message::{RequestServerInfo, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION};
+
+  #[test]
+  fn test_shared_token_authenticator() {
+    let authenticator = SharedTokenAuthenticator::new("secret");
+    let request = |token: Option<&str>| match token {
+      Some(token) => RequestServerInfo::new_with_auth_token(
+        "Test Client",
+        BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+        token,
+      ),
+      None => RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION),
+    };
+    assert!(authenticator.authenticate(&request(Some("secret"))));
+    assert!(!authenticator.authenticate(&request(Some("secreT"))));
+    assert!(!authenticator.authenticate(&request(Some("secret2"))));
+    assert!(!authenticator.authenticate(&request(Some(""))));
+    assert!(!authenticator.authenticate(&request(None)));
+  }
+
+  #[test]
+  fn test_bearer_token() {
+    assert_eq!(bearer_token("Bearer secret"), Some("secret"));
+    assert_eq!(bearer_token("bearer  secret "), Some("secret"));
+    assert_eq!(bearer_token("Basic c2VjcmV0"), None);
+    assert_eq!(bearer_token("secret"), None);
+  }
+}
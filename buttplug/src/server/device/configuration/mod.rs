@@ -137,10 +137,13 @@
 //! ### User Configurations
 //!
 
+mod output_mapping;
 mod server_device_message_attributes;
 pub mod specifier;
 pub use specifier::*;
 
+pub use output_mapping::{FeatureOutputMapping, OutputCurve};
+
 pub use server_device_message_attributes::{
   ServerDeviceMessageAttributes,
   ServerDeviceMessageAttributesBuilder,
@@ -377,6 +380,9 @@ pub struct DeviceConfigurationManagerBuilder {
   allow_raw_messages: bool,
   communication_specifiers: HashMap<String, Vec<ProtocolCommunicationSpecifier>>,
   protocol_attributes: HashMap<ProtocolAttributesIdentifier, ProtocolDeviceAttributes>,
+  /// Priorities for protocols, used to order connection attempts when multiple protocols match a
+  /// device. Protocols without a priority are treated as having a priority of 0.
+  protocol_priorities: HashMap<String, i32>,
   /// Map of protocol names to their respective protocol instance factories
   protocols: Vec<(String, Arc<dyn ProtocolIdentifierFactory>)>,
   /// Addresses of devices that we will only connect to, if this list is not empty. As these are
@@ -406,6 +412,12 @@ impl DeviceConfigurationManagerBuilder {
         .iter()
         .map(|(k, v)| (k.clone(), v.clone())),
     );
+    self.protocol_priorities.extend(
+      other
+        .protocol_priorities
+        .iter()
+        .map(|(k, v)| (k.clone(), *v)),
+    );
     self
       .protocols
       .extend(other.protocols.iter().map(|v| (v.clone())));
@@ -443,6 +455,15 @@ impl DeviceConfigurationManagerBuilder {
     self
   }
 
+  /// Set the priority of a protocol. When multiple protocols match a device, protocols with higher
+  /// priorities are tried first.
+  pub fn protocol_priority(&mut self, protocol_name: &str, priority: i32) -> &mut Self {
+    self
+      .protocol_priorities
+      .insert(protocol_name.to_owned(), priority);
+    self
+  }
+
   /// Add a protocol instance factory for a [ButtplugProtocol]
   pub fn protocol_factory<T>(&mut self, factory: T) -> &mut Self
   where
@@ -590,6 +611,7 @@ impl DeviceConfigurationManagerBuilder {
     Ok(DeviceConfigurationManager {
       allow_raw_messages: self.allow_raw_messages,
       communication_specifiers: self.communication_specifiers.clone(),
+      protocol_priorities: self.protocol_priorities.clone(),
       protocol_attributes: attribute_tree_map,
       protocol_map,
       allowed_addresses: self.allowed_addresses.clone(),
@@ -615,6 +637,7 @@ pub struct DeviceConfigurationManager {
   /// If true, add raw message support to connected devices
   allow_raw_messages: bool,
   communication_specifiers: HashMap<String, Vec<ProtocolCommunicationSpecifier>>,
+  protocol_priorities: HashMap<String, i32>,
   protocol_attributes: HashMap<ProtocolAttributesIdentifier, Arc<ProtocolDeviceAttributes>>,
   /// Map of protocol names to their respective protocol instance factories
   protocol_map: HashMap<String, Arc<dyn ProtocolIdentifierFactory>>,
@@ -677,6 +700,16 @@ impl DeviceConfigurationManager {
     }
   }
 
+  /// Returns a snapshot of all device indexes currently reserved, either via user configuration or
+  /// by devices that have connected during the lifetime of this manager.
+  pub fn reserved_indexes(&self) -> HashMap<ServerDeviceIdentifier, u32> {
+    self
+      .reserved_indexes
+      .iter()
+      .map(|pair| (pair.key().clone(), *pair.value()))
+      .collect()
+  }
+
   /// Provides read-only access to the internal protocol/identifier map. Mainly
   /// used for WebBluetooth filter construction, but could also be handy for
   /// listing capabilities in UI, etc.
@@ -686,6 +719,18 @@ impl DeviceConfigurationManager {
     self.communication_specifiers.clone()
   }
 
+  /// Returns the priority of a protocol, as set in the device configuration. Defaults to 0.
+  pub fn protocol_priority(&self, protocol_name: &str) -> i32 {
+    self
+      .protocol_priorities
+      .get(protocol_name)
+      .copied()
+      .unwrap_or(0)
+  }
+
+  /// Returns specializers for all protocols with a communication specifier matching the one given,
+  /// in the order connection should be attempted: highest protocol priority first, then best match
+  /// score. Protocols with equal priorities and scores are ordered by name.
   pub fn protocol_specializers(
     &self,
     specifier: &ProtocolCommunicationSpecifier,
@@ -694,29 +739,50 @@ impl DeviceConfigurationManager {
       "Looking for protocol that matches specifier: {:?}",
       specifier
     );
-    let mut specializers = vec![];
+    let mut matches = vec![];
     for (name, specifiers) in self.communication_specifiers.iter() {
-      if specifiers.contains(specifier) {
-        info!("Found protocol {:?} for specifier {:?}.", name, specifier);
-
-        if !self.protocol_map.contains_key(name) {
-          warn!(
-            "No protocol implementation for {:?} found for specifier {:?}.",
-            name, specifier
-          );
-          continue;
-        }
-        specializers.push(ProtocolSpecializer::new(
+      let Some(score) = specifiers
+        .iter()
+        .filter_map(|config_specifier| config_specifier.match_score(specifier))
+        .max()
+      else {
+        continue;
+      };
+      info!(
+        "Found protocol {:?} for specifier {:?} with score {}.",
+        name, specifier, score
+      );
+
+      if !self.protocol_map.contains_key(name) {
+        warn!(
+          "No protocol implementation for {:?} found for specifier {:?}.",
+          name, specifier
+        );
+        continue;
+      }
+      matches.push((score, name, specifiers));
+    }
+    matches.sort_by(|(score_a, name_a, _), (score_b, name_b, _)| {
+      self
+        .protocol_priority(name_b)
+        .cmp(&self.protocol_priority(name_a))
+        .then_with(|| score_b.cmp(score_a))
+        .then_with(|| name_a.cmp(name_b))
+    });
+    matches
+      .into_iter()
+      .map(|(_, name, specifiers)| {
+        ProtocolSpecializer::new(
+          name,
           specifiers.clone(),
           self
             .protocol_map
             .get(name)
             .expect("already checked existence")
             .create(),
-        ));
-      }
-    }
-    specializers
+        )
+      })
+      .collect()
   }
 
   pub fn protocol_device_attributes(
@@ -754,6 +820,33 @@ impl DeviceConfigurationManager {
 
     Some(flat_attrs)
   }
+
+  /// Resolve attributes for hardware that reports its own attributes (see
+  /// [Hardware::attributes](crate::server::device::hardware::Hardware::attributes)).
+  ///
+  /// The reported attributes replace any protocol or identifier configuration, but user
+  /// configurations for the device are still applied on top of them.
+  pub fn hardware_device_attributes(
+    &self,
+    identifier: &ServerDeviceIdentifier,
+    hardware_attributes: &ProtocolDeviceAttributes,
+    raw_endpoints: &[Endpoint],
+  ) -> ProtocolDeviceAttributes {
+    let mut flat_attrs = if let Some(attrs) = self.protocol_attributes.get(&identifier.into()) {
+      debug!("User device config found for {:?}", identifier);
+      attrs
+        .new_with_parent(Arc::new(hardware_attributes.clone()))
+        .flatten()
+    } else {
+      hardware_attributes.flatten()
+    };
+
+    if self.allow_raw_messages {
+      flat_attrs.add_raw_messages(raw_endpoints);
+    }
+
+    flat_attrs
+  }
 }
 
 #[cfg(test)]
@@ -815,33 +908,161 @@ mod test {
   #[test]
   fn test_config_equals() {
     let config = create_unit_test_dcm(false);
-    let spec = ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(
-      "LovenseDummyTestName",
-      &HashMap::new(),
-      &[],
-    ));
+    let spec = ProtocolCommunicationSpecifier::BluetoothLEAdvertisement(
+      BluetoothLEAdvertisement::new("LovenseDummyTestName", &HashMap::new(), &[]),
+    );
     assert!(!config.protocol_specializers(&spec).is_empty());
   }
 
   #[test]
   fn test_config_wildcard_equals() {
     let config = create_unit_test_dcm(false);
-    let spec = ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(
-      "LVS-Whatever",
-      &HashMap::new(),
-      &[],
-    ));
+    let spec = ProtocolCommunicationSpecifier::BluetoothLEAdvertisement(
+      BluetoothLEAdvertisement::new("LVS-Whatever", &HashMap::new(), &[]),
+    );
     assert!(!config.protocol_specializers(&spec).is_empty());
   }
 
+  #[test]
+  fn test_config_best_match_first() {
+    let mut builder = DeviceConfigurationManagerBuilder::default();
+    let wildcard_specifier =
+      ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new(
+        HashSet::from(["LVS-*".to_owned()]),
+        vec![],
+        HashSet::new(),
+        HashMap::new(),
+      ));
+    let exact_specifier = ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new(
+      HashSet::from(["LVS-Exact".to_owned()]),
+      vec![],
+      HashSet::new(),
+      HashMap::new(),
+    ));
+    builder.communication_specifier("lovense", wildcard_specifier.clone());
+    builder.communication_specifier("wevibe", exact_specifier.clone());
+    let dcm = builder.finish().unwrap();
+
+    let spec = ProtocolCommunicationSpecifier::BluetoothLEAdvertisement(
+      BluetoothLEAdvertisement::new("LVS-Exact", &HashMap::new(), &[]),
+    );
+    let specializers = dcm.protocol_specializers(&spec);
+    assert_eq!(specializers.len(), 2);
+    assert_eq!(*specializers[0].specifiers(), vec![exact_specifier]);
+    assert_eq!(
+      *specializers[1].specifiers(),
+      vec![wildcard_specifier.clone()]
+    );
+
+    // Explicit priorities take precedence over match scores.
+    builder.protocol_priority("lovense", 1);
+    let dcm = builder.finish().unwrap();
+    let specializers = dcm.protocol_specializers(&spec);
+    assert_eq!(specializers[0].protocol_name(), "lovense");
+    assert_eq!(specializers[1].protocol_name(), "wevibe");
+  }
+
+  #[test]
+  fn test_ble_specifier_matching() {
+    let pattern_score = |pattern: &str, name: &str| {
+      BluetoothLENamePattern::parse(pattern)
+        .unwrap()
+        .match_score(name)
+    };
+    assert!(pattern_score("LVS-*", "LVS-Edge").is_some());
+    assert!(pattern_score("*-Edge", "LVS-Edge").is_some());
+    assert!(pattern_score("L?S-*e", "LVS-Edge").is_some());
+    assert!(pattern_score("L?S-*e", "LVS-Edgy").is_none());
+    assert!(pattern_score("regex:^LVS-[A-Z]$", "LVS-P").is_some());
+    assert!(pattern_score("regex:^LVS-[A-Z]$", "LVS-PP").is_none());
+    assert!(BluetoothLENamePattern::parse("regex:(").is_err());
+    // Patterns are compiled when specifiers are loaded, so invalid ones fail the load.
+    assert!(serde_json::from_str::<BluetoothLESpecifier>(
+      r#"{"names": ["LVS-*", "regex:("], "services": {}}"#
+    )
+    .is_err());
+    let loaded: BluetoothLESpecifier =
+      serde_json::from_str(r#"{"names": ["LVS-*"], "services": {}}"#).unwrap();
+    assert!(loaded
+      .match_score(&BluetoothLEAdvertisement::new(
+        "LVS-Edge",
+        &HashMap::new(),
+        &[]
+      ))
+      .is_some());
+    assert!(pattern_score("LVS-Edge", "LVS-Edge") > pattern_score("LVS-*", "LVS-Edge"));
+    assert!(pattern_score("LVS-E*", "LVS-Edge") > pattern_score("LVS-*", "LVS-Edge"));
+
+    // Merging keeps every name with its own pattern, even after invalid patterns were dropped and
+    // across repeated merges.
+    let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+    let spec = |names| BluetoothLESpecifier::new(names, vec![], HashSet::new(), HashMap::new());
+    let mut merged = spec(names(&["Base0", "Base1", "Base2"]));
+    merged.merge(spec(names(&["User0", "User1", "User2"])));
+    merged.merge(spec(names(&[
+      "regex:(", "Lossy0", "regex:[", "Lossy1", "Lossy2", "Lossy3",
+    ])));
+    let mut merged_names: Vec<&str> = merged.names().names().collect();
+    merged_names.sort();
+    assert_eq!(
+      merged_names,
+      vec![
+        "Base0", "Base1", "Base2", "Lossy0", "Lossy1", "Lossy2", "Lossy3", "User0", "User1",
+        "User2"
+      ]
+    );
+    for name in merged_names {
+      assert_eq!(
+        merged.match_score(&BluetoothLEAdvertisement::new(name, &HashMap::new(), &[])),
+        Some(1000),
+        "{} should match its own pattern exactly",
+        name
+      );
+    }
+
+    // Config data is always the needle, even when it is longer than the advertised data.
+    let mut config_data = BluetoothLEManufacturerData::new(620, &Some(vec![0x01, 0x02, 0x03]));
+    let short_advertisement = HashMap::from([(620, vec![0x02])]);
+    let long_advertisement = HashMap::from([(620, vec![0xff, 0x01, 0x02, 0x03])]);
+    assert!(config_data.match_score(&short_advertisement).is_none());
+    assert!(config_data.match_score(&long_advertisement).is_some());
+    config_data.set_offset(Some(0));
+    assert!(config_data.match_score(&long_advertisement).is_none());
+    config_data.set_offset(Some(1));
+    config_data.set_mask(Some(vec![0xff, 0x00, 0xff]));
+    let masked_advertisement = HashMap::from([(620, vec![0xff, 0x01, 0xaa, 0x03])]);
+    assert!(config_data.match_score(&masked_advertisement).is_some());
+
+    let service = uuid::Uuid::from_u128(0x1234);
+    let mut specifier = BluetoothLESpecifier::new(
+      HashSet::from(["LVS-*".to_owned()]),
+      vec![],
+      HashSet::new(),
+      HashMap::new(),
+    );
+    specifier.set_required_advertised_services(HashSet::from([service]));
+    assert!(specifier
+      .match_score(&BluetoothLEAdvertisement::new(
+        "LVS-Edge",
+        &HashMap::new(),
+        &[]
+      ))
+      .is_none());
+    assert!(specifier
+      .match_score(&BluetoothLEAdvertisement::new(
+        "LVS-Edge",
+        &HashMap::new(),
+        &[service]
+      ))
+      .is_some());
+  }
+
   #[test]
   fn test_specific_device_config_creation() {
     let dcm = create_unit_test_dcm(false);
-    let spec = ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(
-      "LVS-Whatever",
-      &HashMap::new(),
-      &[],
-    ));
+    let spec = ProtocolCommunicationSpecifier::BluetoothLEAdvertisement(
+      BluetoothLEAdvertisement::new("LVS-Whatever", &HashMap::new(), &[]),
+    );
     assert!(!dcm.protocol_specializers(&spec).is_empty());
     let config = dcm
       .protocol_device_attributes(
@@ -872,11 +1093,9 @@ mod test {
   #[test]
   fn test_raw_device_config_creation() {
     let dcm = create_unit_test_dcm(true);
-    let spec = ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(
-      "LVS-Whatever",
-      &HashMap::new(),
-      &[],
-    ));
+    let spec = ProtocolCommunicationSpecifier::BluetoothLEAdvertisement(
+      BluetoothLEAdvertisement::new("LVS-Whatever", &HashMap::new(), &[]),
+    );
     assert!(!dcm.protocol_specializers(&spec).is_empty());
     let config = dcm
       .protocol_device_attributes(
@@ -900,11 +1119,9 @@ mod test {
   #[test]
   fn test_non_raw_device_config_creation() {
     let dcm = create_unit_test_dcm(false);
-    let spec = ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(
-      "LVS-Whatever",
-      &HashMap::new(),
-      &[],
-    ));
+    let spec = ProtocolCommunicationSpecifier::BluetoothLEAdvertisement(
+      BluetoothLEAdvertisement::new("LVS-Whatever", &HashMap::new(), &[]),
+    );
     assert!(!dcm.protocol_specializers(&spec).is_empty());
     let config = dcm
       .protocol_device_attributes(
@@ -0,0 +1,415 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! User configurable mapping of actuator values, applied before values are converted to steps.
+
+use crate::core::errors::ButtplugDeviceError;
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Serialize};
+
+/// Curve applied to a value after the dead zone is removed, and before inversion and scaling.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum OutputCurve {
+  /// Values pass through unchanged.
+  #[default]
+  Linear,
+  /// Values are raised to the given power. Exponents above 1.0 give finer control over low values,
+  /// exponents below 1.0 give finer control over high values.
+  Exponential(f64),
+  /// Values are interpolated between (input, output) points, ordered by input. Inputs outside the
+  /// points are clamped to the first or last output.
+  PiecewiseLinear(Vec<(f64, f64)>),
+}
+
+impl OutputCurve {
+  fn apply(&self, value: f64) -> f64 {
+    match self {
+      OutputCurve::Linear => value,
+      OutputCurve::Exponential(exponent) => value.powf(*exponent),
+      OutputCurve::PiecewiseLinear(points) => {
+        let Some(&(first_x, first_y)) = points.first() else {
+          return value;
+        };
+        if value <= first_x {
+          return first_y;
+        }
+        for window in points.windows(2) {
+          let ((x0, y0), (x1, y1)) = (window[0], window[1]);
+          if value <= x1 {
+            return y0 + (value - x0) / (x1 - x0) * (y1 - y0);
+          }
+        }
+        points.last().map(|(_, y)| *y).unwrap_or(value)
+      }
+    }
+  }
+
+  fn is_valid(&self) -> Result<(), String> {
+    match self {
+      OutputCurve::Linear => Ok(()),
+      OutputCurve::Exponential(exponent) => {
+        if exponent.is_finite() && *exponent > 0.0 {
+          Ok(())
+        } else {
+          Err(format!(
+            "Exponential curve exponent must be greater than 0, got {}.",
+            exponent
+          ))
+        }
+      }
+      OutputCurve::PiecewiseLinear(points) => {
+        if points.len() < 2 {
+          return Err("Piecewise linear curves need at least 2 points.".to_owned());
+        }
+        if points
+          .iter()
+          .any(|(x, y)| !is_unit_value(*x) || !is_unit_value(*y))
+        {
+          return Err("Piecewise linear curve points must be between 0.0 and 1.0.".to_owned());
+        }
+        if points.windows(2).any(|window| window[0].0 >= window[1].0) {
+          return Err(
+            "Piecewise linear curve points must be in increasing input order.".to_owned(),
+          );
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+// Floats are compared by their bits, so mappings (and the attributes holding them) can be Eq. This
+// only differs from == for NaN, which validation rejects, and for 0.0 vs -0.0.
+fn same_value(a: f64, b: f64) -> bool {
+  a.to_bits() == b.to_bits()
+}
+
+impl PartialEq for OutputCurve {
+  fn eq(&self, other: &Self) -> bool {
+    match (self, other) {
+      (OutputCurve::Linear, OutputCurve::Linear) => true,
+      (OutputCurve::Exponential(a), OutputCurve::Exponential(b)) => same_value(*a, *b),
+      (OutputCurve::PiecewiseLinear(a), OutputCurve::PiecewiseLinear(b)) => {
+        a.len() == b.len()
+          && a
+            .iter()
+            .zip(b)
+            .all(|((ax, ay), (bx, by))| same_value(*ax, *bx) && same_value(*ay, *by))
+      }
+      _ => false,
+    }
+  }
+}
+
+impl Eq for OutputCurve {
+}
+
+fn is_unit_value(value: f64) -> bool {
+  (0.0..=1.0).contains(&value)
+}
+
+fn default_max() -> f64 {
+  1.0
+}
+
+/// Per-feature mapping of actuator values, set in user device configs.
+///
+/// Incoming values go through the following steps, in order:
+///
+/// - Values at or below `dead_zone` are treated as 0.0, and the rest are stretched to cover 0.0 to
+///   1.0 again.
+/// - The `curve` is applied.
+/// - The value is inverted, if `invert` is set.
+/// - The value is scaled to the range `min` to `max`.
+///
+/// The result is then quantized to the feature's step range as usual.
+#[derive(Clone, Debug, Serialize, Deserialize, Getters, CopyGetters)]
+pub struct FeatureOutputMapping {
+  /// Output for the lowest running value. Useful for motors that don't move below a certain step.
+  #[getset(get_copy = "pub")]
+  #[serde(rename = "Min")]
+  #[serde(default)]
+  min: f64,
+  /// Output for the highest value.
+  #[getset(get_copy = "pub")]
+  #[serde(rename = "Max")]
+  #[serde(default = "default_max")]
+  max: f64,
+  /// Values at or below this are treated as 0.0.
+  #[getset(get_copy = "pub")]
+  #[serde(rename = "DeadZone")]
+  #[serde(default)]
+  dead_zone: f64,
+  #[getset(get_copy = "pub")]
+  #[serde(rename = "Invert")]
+  #[serde(default)]
+  invert: bool,
+  #[getset(get = "pub")]
+  #[serde(rename = "Curve")]
+  #[serde(default)]
+  curve: OutputCurve,
+}
+
+impl Default for FeatureOutputMapping {
+  fn default() -> Self {
+    Self {
+      min: 0.0,
+      max: default_max(),
+      dead_zone: 0.0,
+      invert: false,
+      curve: OutputCurve::default(),
+    }
+  }
+}
+
+impl PartialEq for FeatureOutputMapping {
+  fn eq(&self, other: &Self) -> bool {
+    same_value(self.min, other.min)
+      && same_value(self.max, other.max)
+      && same_value(self.dead_zone, other.dead_zone)
+      && self.invert == other.invert
+      && self.curve == other.curve
+  }
+}
+
+impl Eq for FeatureOutputMapping {
+}
+
+impl FeatureOutputMapping {
+  pub fn new(min: f64, max: f64, dead_zone: f64, invert: bool, curve: OutputCurve) -> Self {
+    Self {
+      min,
+      max,
+      dead_zone,
+      invert,
+      curve,
+    }
+  }
+
+  pub fn is_valid(&self) -> Result<(), ButtplugDeviceError> {
+    let check = || {
+      if !is_unit_value(self.min) || !is_unit_value(self.max) || self.min > self.max {
+        return Err(format!(
+          "Output mapping range must be 0.0 <= min <= max <= 1.0, got {} to {}.",
+          self.min, self.max
+        ));
+      }
+      if !(0.0..1.0).contains(&self.dead_zone) {
+        return Err(format!(
+          "Output mapping dead zone must be at least 0.0 and less than 1.0, got {}.",
+          self.dead_zone
+        ));
+      }
+      self.curve.is_valid()
+    };
+    check().map_err(ButtplugDeviceError::DeviceConfigurationError)
+  }
+
+  fn map(&self, value: f64, invert: bool) -> f64 {
+    let value = ((value.clamp(0.0, 1.0) - self.dead_zone) / (1.0 - self.dead_zone)).max(0.0);
+    let value = self.curve.apply(value).clamp(0.0, 1.0);
+    let value = if invert { 1.0 - value } else { value };
+    self.min + value * (self.max - self.min)
+  }
+
+  /// Maps a speed or intensity level. Stopped stays stopped, so levels of 0.0 (or inside the dead
+  /// zone) come out as 0.0 rather than `min`.
+  pub fn map_level(&self, level: f64) -> f64 {
+    if level <= 0.0 || level <= self.dead_zone {
+      0.0
+    } else {
+      self.map(level, self.invert)
+    }
+  }
+
+  /// Maps a rotation. Inverting a rotator reverses its direction, rather than its speed.
+  pub fn map_rotation(&self, speed: f64, clockwise: bool) -> (f64, bool) {
+    let speed = if speed <= 0.0 || speed <= self.dead_zone {
+      0.0
+    } else {
+      self.map(speed, false)
+    };
+    (speed, clockwise != self.invert)
+  }
+
+  /// Maps a position. Every position is mapped, so inverting swaps the ends of the axis.
+  pub fn map_position(&self, position: f64) -> f64 {
+    self.map(position, self.invert)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn assert_close(value: f64, expected: f64) {
+    assert!(
+      (value - expected).abs() < 0.0001,
+      "{} != {}",
+      value,
+      expected
+    );
+  }
+
+  #[test]
+  fn test_default_mapping_is_identity() {
+    let mapping = FeatureOutputMapping::default();
+    for value in [0.0, 0.25, 0.5, 1.0] {
+      assert_close(mapping.map_level(value), value);
+      assert_close(mapping.map_position(value), value);
+    }
+    assert_eq!(mapping.map_rotation(0.5, true), (0.5, true));
+  }
+
+  #[test]
+  fn test_min_max_and_dead_zone() {
+    let mapping = FeatureOutputMapping::new(0.2, 0.8, 0.1, false, OutputCurve::Linear);
+    assert_close(mapping.map_level(0.0), 0.0);
+    assert_close(mapping.map_level(0.1), 0.0);
+    // Just above the dead zone starts at the bottom of the range.
+    assert_close(mapping.map_level(0.1001), 0.2);
+    assert_close(mapping.map_level(0.55), 0.5);
+    assert_close(mapping.map_level(1.0), 0.8);
+    // Out of range input is clamped.
+    assert_close(mapping.map_level(2.0), 0.8);
+    // Positions don't have a stop value, so the dead zone maps to the bottom of the range.
+    assert_close(mapping.map_position(0.0), 0.2);
+  }
+
+  #[test]
+  fn test_inversion() {
+    let mapping = FeatureOutputMapping::new(0.0, 1.0, 0.0, true, OutputCurve::Linear);
+    assert_close(mapping.map_position(0.0), 1.0);
+    assert_close(mapping.map_position(0.25), 0.75);
+    assert_close(mapping.map_position(1.0), 0.0);
+    assert_close(mapping.map_level(0.0), 0.0);
+    assert_close(mapping.map_level(0.25), 0.75);
+    assert_eq!(mapping.map_rotation(0.25, true), (0.25, false));
+    assert_eq!(mapping.map_rotation(0.25, false), (0.25, true));
+  }
+
+  #[test]
+  fn test_exponential_curve() {
+    let mapping = FeatureOutputMapping::new(0.0, 1.0, 0.0, false, OutputCurve::Exponential(2.0));
+    assert_close(mapping.map_level(0.5), 0.25);
+    assert_close(mapping.map_level(1.0), 1.0);
+    let mapping = FeatureOutputMapping::new(0.5, 1.0, 0.0, false, OutputCurve::Exponential(0.5));
+    assert_close(mapping.map_level(0.25), 0.75);
+  }
+
+  #[test]
+  fn test_piecewise_linear_curve() {
+    let mapping = FeatureOutputMapping::new(
+      0.0,
+      1.0,
+      0.0,
+      false,
+      OutputCurve::PiecewiseLinear(vec![(0.2, 0.1), (0.5, 0.2), (1.0, 1.0)]),
+    );
+    assert_close(mapping.map_position(0.0), 0.1);
+    assert_close(mapping.map_position(0.2), 0.1);
+    assert_close(mapping.map_position(0.35), 0.15);
+    assert_close(mapping.map_position(0.75), 0.6);
+    assert_close(mapping.map_position(1.0), 1.0);
+  }
+
+  #[test]
+  fn test_mapping_validation() {
+    assert!(FeatureOutputMapping::default().is_valid().is_ok());
+    for invalid in [
+      FeatureOutputMapping::new(0.8, 0.2, 0.0, false, OutputCurve::Linear),
+      FeatureOutputMapping::new(0.0, 1.5, 0.0, false, OutputCurve::Linear),
+      FeatureOutputMapping::new(0.0, 1.0, 1.0, false, OutputCurve::Linear),
+      FeatureOutputMapping::new(0.0, 1.0, 0.0, false, OutputCurve::Exponential(0.0)),
+      FeatureOutputMapping::new(0.0, 1.0, 0.0, false, OutputCurve::Exponential(f64::NAN)),
+      FeatureOutputMapping::new(
+        0.0,
+        1.0,
+        0.0,
+        false,
+        OutputCurve::PiecewiseLinear(vec![(0.0, 0.0)]),
+      ),
+      FeatureOutputMapping::new(
+        0.0,
+        1.0,
+        0.0,
+        false,
+        OutputCurve::PiecewiseLinear(vec![(0.5, 0.0), (0.5, 1.0)]),
+      ),
+      FeatureOutputMapping::new(
+        0.0,
+        1.0,
+        0.0,
+        false,
+        OutputCurve::PiecewiseLinear(vec![(0.0, 0.0), (1.0, 2.0)]),
+      ),
+    ] {
+      assert!(
+        matches!(
+          invalid.is_valid(),
+          Err(ButtplugDeviceError::DeviceConfigurationError(_))
+        ),
+        "{:?}",
+        invalid
+      );
+    }
+  }
+
+  #[test]
+  fn test_mapping_equality() {
+    let curve = OutputCurve::PiecewiseLinear(vec![(0.0, 0.1), (1.0, 1.0)]);
+    let mapping = FeatureOutputMapping::new(0.2, 0.8, 0.1, false, curve.clone());
+    assert_eq!(
+      mapping,
+      FeatureOutputMapping::new(0.2, 0.8, 0.1, false, curve.clone())
+    );
+    assert_ne!(
+      mapping,
+      FeatureOutputMapping::new(0.2, 0.8, 0.1, true, curve.clone())
+    );
+    assert_ne!(
+      mapping,
+      FeatureOutputMapping::new(0.2, 0.9, 0.1, false, curve)
+    );
+    assert_ne!(
+      OutputCurve::PiecewiseLinear(vec![(0.0, 0.1), (1.0, 1.0)]),
+      OutputCurve::PiecewiseLinear(vec![(0.0, 0.1)])
+    );
+    assert_ne!(OutputCurve::Exponential(2.0), OutputCurve::Linear);
+    assert_eq!(OutputCurve::Exponential(2.0), OutputCurve::Exponential(2.0));
+  }
+
+  #[test]
+  fn test_mapping_json_round_trip() {
+    let mapping: FeatureOutputMapping = serde_json::from_str(
+      r#"{"Min": 0.25, "DeadZone": 0.1, "Curve": {"PiecewiseLinear": [[0.0, 0.0], [1.0, 1.0]]}}"#,
+    )
+    .expect("Test, assuming infallible.");
+    assert_eq!(
+      mapping,
+      FeatureOutputMapping::new(
+        0.25,
+        1.0,
+        0.1,
+        false,
+        OutputCurve::PiecewiseLinear(vec![(0.0, 0.0), (1.0, 1.0)])
+      )
+    );
+    let json = serde_json::to_string(&mapping).expect("Test, assuming infallible.");
+    assert_eq!(
+      serde_json::from_str::<FeatureOutputMapping>(&json).expect("Test, assuming infallible."),
+      mapping
+    );
+    let mapping: FeatureOutputMapping =
+      serde_json::from_str(r#"{"Invert": true, "Curve": {"Exponential": 2.0}}"#)
+        .expect("Test, assuming infallible.");
+    assert_eq!(
+      mapping,
+      FeatureOutputMapping::new(0.0, 1.0, 0.0, true, OutputCurve::Exponential(2.0))
+    );
+  }
+}
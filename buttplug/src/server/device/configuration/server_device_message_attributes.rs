@@ -3,6 +3,7 @@ use std::ops::RangeInclusive;
 use getset::{Getters, MutGetters, Setters};
 use serde::{Deserialize, Serialize};
 
+use super::FeatureOutputMapping;
 use crate::core::{
   errors::ButtplugDeviceError,
   message::{
@@ -225,6 +226,36 @@ impl From<ServerDeviceMessageAttributes> for ClientDeviceMessageAttributes {
   }
 }
 
+// Used when relaying devices from other servers, which only give us client attributes. Raw
+// messages are left off, as they would address the remote device's endpoints.
+impl From<ClientDeviceMessageAttributes> for ServerDeviceMessageAttributes {
+  fn from(attrs: ClientDeviceMessageAttributes) -> Self {
+    let mut builder = ServerDeviceMessageAttributesBuilder::default();
+    if let Some(scalar_cmd) = attrs.scalar_cmd() {
+      let commands: Vec<ServerGenericDeviceMessageAttributes> =
+        scalar_cmd.iter().map(|x| x.into()).collect();
+      builder.scalar_cmd(&commands);
+    }
+    if let Some(rotate_cmd) = attrs.rotate_cmd() {
+      let commands: Vec<ServerGenericDeviceMessageAttributes> =
+        rotate_cmd.iter().map(|x| x.into()).collect();
+      builder.rotate_cmd(&commands);
+    }
+    if let Some(linear_cmd) = attrs.linear_cmd() {
+      let commands: Vec<ServerGenericDeviceMessageAttributes> =
+        linear_cmd.iter().map(|x| x.into()).collect();
+      builder.linear_cmd(&commands);
+    }
+    if let Some(sensor_read_cmd) = attrs.sensor_read_cmd() {
+      builder.sensor_read_cmd(sensor_read_cmd);
+    }
+    if let Some(sensor_subscribe_cmd) = attrs.sensor_subscribe_cmd() {
+      builder.sensor_subscribe_cmd(sensor_subscribe_cmd);
+    }
+    builder.finish()
+  }
+}
+
 #[derive(Default)]
 pub struct ServerDeviceMessageAttributesBuilder {
   attrs: ServerDeviceMessageAttributes,
@@ -293,6 +324,12 @@ pub struct ServerGenericDeviceMessageAttributes {
   #[serde(skip_serializing)]
   #[getset(get = "pub", set = "pub")]
   step_range: RangeInclusive<u32>,
+  // Only set in user configs, and applied before values are converted to steps.
+  #[serde(rename = "OutputMapping")]
+  #[serde(default)]
+  #[serde(skip_serializing)]
+  #[getset(get = "pub", set = "pub")]
+  output_mapping: Option<FeatureOutputMapping>,
 }
 
 impl From<ServerGenericDeviceMessageAttributes> for ClientGenericDeviceMessageAttributes {
@@ -305,6 +342,16 @@ impl From<ServerGenericDeviceMessageAttributes> for ClientGenericDeviceMessageAt
   }
 }
 
+impl From<&ClientGenericDeviceMessageAttributes> for ServerGenericDeviceMessageAttributes {
+  fn from(attrs: &ClientGenericDeviceMessageAttributes) -> Self {
+    ServerGenericDeviceMessageAttributes::new(
+      attrs.feature_descriptor(),
+      &RangeInclusive::new(0, *attrs.step_count()),
+      *attrs.actuator_type(),
+    )
+  }
+}
+
 impl ServerGenericDeviceMessageAttributes {
   pub fn new(
     feature_descriptor: &str,
@@ -315,6 +362,7 @@ impl ServerGenericDeviceMessageAttributes {
       feature_descriptor: feature_descriptor.to_owned(),
       actuator_type,
       step_range: step_range.clone(),
+      output_mapping: None,
     }
   }
 
@@ -331,6 +379,8 @@ impl ServerGenericDeviceMessageAttributes {
         "Step range out of order for {}, must be start <= x <= end.",
         message_type
       )))
+    } else if let Some(output_mapping) = &self.output_mapping {
+      output_mapping.is_valid()
     } else {
       Ok(())
     }
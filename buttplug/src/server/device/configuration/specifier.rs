@@ -5,8 +5,9 @@
 // Licensed under the BSD 3-Clause license. See LICENSE file in the project root
 // for full license information.
 
-use crate::core::message::Endpoint;
+use crate::core::{errors::ButtplugDeviceError, message::Endpoint};
 use getset::{Getters, MutGetters, Setters};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
@@ -17,11 +18,188 @@ use uuid::Uuid;
 // gonna hurt anything and making a ton of serde attributes is just going to get
 // confusing (see the messages impl).
 
-#[derive(Serialize, Deserialize, Debug, Clone, Getters, MutGetters, Setters, Eq)]
+/// Name pattern for matching advertised Bluetooth LE device names.
+///
+/// Patterns are parsed from the strings in [BluetoothLESpecifier] name lists:
+///
+/// - `regex:<expression>` is matched as a regular expression against the full name.
+/// - `Name*` matches any name starting with `Name`.
+/// - `*Name` matches any name ending with `Name`.
+/// - Any other pattern containing `*` or `?` is matched as a glob, where `*` matches any run of
+///   characters and `?` matches a single character.
+/// - Everything else must match exactly.
+#[derive(Debug, Clone)]
+pub enum BluetoothLENamePattern {
+  Exact(String),
+  Prefix(String),
+  Suffix(String),
+  Glob(String, Regex),
+  Regex(Regex),
+}
+
+impl BluetoothLENamePattern {
+  pub fn parse(pattern: &str) -> Result<Self, ButtplugDeviceError> {
+    let invalid = |err: regex::Error| {
+      ButtplugDeviceError::DeviceConfigurationError(format!(
+        "Invalid BLE name pattern {}: {}",
+        pattern, err
+      ))
+    };
+    if let Some(expression) = pattern.strip_prefix("regex:") {
+      return Ok(Self::Regex(Regex::new(expression).map_err(invalid)?));
+    }
+    let is_wildcard = |c: char| c == '*' || c == '?';
+    if let Some(prefix) = pattern.strip_suffix('*') {
+      if !prefix.contains(is_wildcard) {
+        return Ok(Self::Prefix(prefix.to_owned()));
+      }
+    }
+    if let Some(suffix) = pattern.strip_prefix('*') {
+      if !suffix.contains(is_wildcard) {
+        return Ok(Self::Suffix(suffix.to_owned()));
+      }
+    }
+    if pattern.contains(is_wildcard) {
+      let mut expression = "^".to_owned();
+      for c in pattern.chars() {
+        match c {
+          '*' => expression.push_str(".*"),
+          '?' => expression.push('.'),
+          _ => expression.push_str(&regex::escape(&c.to_string())),
+        }
+      }
+      expression.push('$');
+      return Ok(Self::Glob(
+        pattern.to_owned(),
+        Regex::new(&expression).map_err(invalid)?,
+      ));
+    }
+    Ok(Self::Exact(pattern.to_owned()))
+  }
+
+  /// Returns a score for how specifically this pattern matches the name, or None if it does not
+  /// match. Exact matches always outrank wildcard matches, and longer literal prefixes/suffixes
+  /// outrank shorter ones.
+  pub fn match_score(&self, name: &str) -> Option<u32> {
+    let literal_score = |literal: &str| literal.chars().count().min(400) as u32;
+    match self {
+      Self::Exact(exact) => (exact == name).then_some(1000),
+      Self::Prefix(prefix) => name
+        .starts_with(prefix.as_str())
+        .then(|| 100 + literal_score(prefix)),
+      Self::Suffix(suffix) => name
+        .ends_with(suffix.as_str())
+        .then(|| 100 + literal_score(suffix)),
+      Self::Glob(pattern, regex) => regex
+        .is_match(name)
+        .then(|| 50 + literal_score(&pattern.replace(['*', '?'], ""))),
+      Self::Regex(regex) => regex.is_match(name).then_some(50),
+    }
+  }
+}
+
+/// Set of [BluetoothLENamePattern] strings from a [BluetoothLESpecifier], compiled once when the
+/// specifier is created or loaded, rather than on every advertisement. Serializes as the original
+/// pattern strings.
+#[derive(Debug, Clone, Default)]
+pub struct BluetoothLENamePatterns {
+  /// Compiled patterns, keyed by the pattern string they came from.
+  patterns: HashMap<String, BluetoothLENamePattern>,
+}
+
+impl BluetoothLENamePatterns {
+  /// Compiles a set of name patterns, failing on the first invalid pattern.
+  pub fn new(names: HashSet<String>) -> Result<Self, ButtplugDeviceError> {
+    let patterns = names
+      .into_iter()
+      .map(|name| BluetoothLENamePattern::parse(&name).map(|pattern| (name, pattern)))
+      .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok(Self { patterns })
+  }
+
+  /// Compiles a set of name patterns, logging and dropping any invalid patterns.
+  fn new_lossy(names: HashSet<String>) -> Self {
+    let patterns = names
+      .into_iter()
+      .filter_map(|name| match BluetoothLENamePattern::parse(&name) {
+        Ok(pattern) => Some((name, pattern)),
+        Err(err) => {
+          warn!("{}", err);
+          None
+        }
+      })
+      .collect();
+    Self { patterns }
+  }
+
+  /// Pattern strings in the set, in no particular order.
+  pub fn names(&self) -> impl Iterator<Item = &str> {
+    self.patterns.keys().map(|name| name.as_str())
+  }
+
+  /// Returns the best score of any pattern matching the name, or None if none match.
+  pub fn match_score(&self, name: &str) -> Option<u32> {
+    self
+      .patterns
+      .values()
+      .filter_map(|pattern| pattern.match_score(name))
+      .max()
+  }
+
+  fn merge(&mut self, other: BluetoothLENamePatterns) {
+    for (name, pattern) in other.patterns {
+      self.patterns.entry(name).or_insert(pattern);
+    }
+  }
+}
+
+impl PartialEq for BluetoothLENamePatterns {
+  fn eq(&self, other: &Self) -> bool {
+    self.patterns.len() == other.patterns.len()
+      && self
+        .patterns
+        .keys()
+        .all(|name| other.patterns.contains_key(name))
+  }
+}
+
+impl Eq for BluetoothLENamePatterns {
+}
+
+impl Serialize for BluetoothLENamePatterns {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: serde::Serializer,
+  {
+    serializer.collect_seq(self.patterns.keys())
+  }
+}
+
+impl<'de> Deserialize<'de> for BluetoothLENamePatterns {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: serde::Deserializer<'de>,
+  {
+    let names = HashSet::<String>::deserialize(deserializer)?;
+    BluetoothLENamePatterns::new(names).map_err(serde::de::Error::custom)
+  }
+}
+
+/// Manufacturer data requirement for Bluetooth LE advertisements.
+///
+/// If `data` is set, it must appear in the advertised manufacturer data for `company`, either at
+/// `offset` if that is set, or anywhere otherwise. If `mask` is set, it is ANDed with both the
+/// expected and advertised bytes before comparison, so only masked bits need to match.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Getters, MutGetters, Setters)]
 #[getset(get = "pub", set = "pub", get_mut = "pub(crate)")]
 pub struct BluetoothLEManufacturerData {
   company: u16,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
   data: Option<Vec<u8>>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  offset: Option<usize>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  mask: Option<Vec<u8>>,
 }
 
 impl BluetoothLEManufacturerData {
@@ -29,130 +207,100 @@ impl BluetoothLEManufacturerData {
     Self {
       company,
       data: data.clone(),
+      offset: None,
+      mask: None,
     }
   }
-}
-
-impl PartialEq for BluetoothLEManufacturerData {
-  fn eq(&self, other: &Self) -> bool {
-    if self.company != *other.company() {
-      return false;
-    }
-
-    // Only the deserialized device config can have none has a data value. If it does, that means
-    // all we care about is the company value, at which point we can return true here.
-    if self.data.is_none() || other.data.is_none() {
-      return true;
-    }
-
-    let data = self.data().as_ref().expect("Already checked existence");
-    let other_data = other.data().as_ref().expect("Already checked existence");
 
-    if data.len() == other_data.len() {
-      if *data == *other_data {
-        return true;
-      }
+  fn data_matches_at(&self, expected: &[u8], advertised: &[u8]) -> bool {
+    if advertised.len() < expected.len() {
       return false;
     }
+    expected.iter().enumerate().all(|(index, byte)| {
+      let mask = self
+        .mask
+        .as_ref()
+        .and_then(|mask| mask.get(index).copied())
+        .unwrap_or(0xff);
+      byte & mask == advertised[index] & mask
+    })
+  }
 
-    // If our data lengths are different, see if one is a subsequence of the other.
-    //
-    // Since we don't have the information to know if either self or other is what was loaded from
-    // the JSON file, we have to blindly guess for needle and haystack here based on length. This
-    // means if manufacturer data comes back from a device shorter than the data the device config
-    // expects, we could have a false match. This needs to be contexualized but that would require a
-    // large device config system rewrite at the moment, so we'll just live with the bug for now.
-    let needle = if data.len() < other_data.len() {
-      data
-    } else {
-      other_data
+  /// Returns a score for how specifically this requirement matches a device's advertised
+  /// manufacturer data, or None if it does not match.
+  pub fn match_score(&self, advertised: &HashMap<u16, Vec<u8>>) -> Option<u32> {
+    let advertised_data = advertised.get(&self.company)?;
+    let Some(expected) = &self.data else {
+      return Some(20);
     };
-    let mut haystack = if data.len() > other_data.len() {
-      data.as_slice()
+    let matched = if let Some(offset) = self.offset {
+      advertised_data.len() >= offset && self.data_matches_at(expected, &advertised_data[offset..])
     } else {
-      other_data.as_slice()
+      (0..=advertised_data.len().saturating_sub(expected.len()))
+        .any(|start| self.data_matches_at(expected, &advertised_data[start..]))
     };
-    while !haystack.is_empty() {
-      if haystack.starts_with(needle) {
-        return true;
-      }
-      haystack = &haystack[1..];
-    }
+    let offset_score = if self.offset.is_some() { 5 } else { 0 };
+    matched.then(|| 20 + 10 * expected.len().min(100) as u32 + offset_score)
+  }
+}
 
-    false
+/// Advertisement data received from a Bluetooth LE device.
+///
+/// This is the device side of Bluetooth LE matching, and is compared against the
+/// [BluetoothLESpecifier] instances loaded from device configurations.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct BluetoothLEAdvertisement {
+  name: String,
+  manufacturer_data: HashMap<u16, Vec<u8>>,
+  advertised_services: HashSet<Uuid>,
+}
+
+impl BluetoothLEAdvertisement {
+  pub fn new(
+    name: &str,
+    manufacturer_data: &HashMap<u16, Vec<u8>>,
+    advertised_services: &[Uuid],
+  ) -> Self {
+    Self {
+      name: name.to_owned(),
+      manufacturer_data: manufacturer_data.clone(),
+      advertised_services: advertised_services.iter().copied().collect(),
+    }
   }
 }
 
 /// Specifier for Bluetooth LE Devices
 ///
 /// Used by protocols for identifying bluetooth devices via their advertisements, as well as
-/// defining the services and characteristics they are expected to have.
-#[derive(Serialize, Deserialize, Debug, Clone, Getters, MutGetters, Setters)]
+/// defining the services and characteristics they are expected to have. Matching against a
+/// [BluetoothLEAdvertisement] succeeds if any name pattern, manufacturer data entry, or advertised
+/// service matches, and all required services are advertised.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Getters, MutGetters, Setters)]
 #[getset(get = "pub", set = "pub", get_mut = "pub(crate)")]
 pub struct BluetoothLESpecifier {
-  /// Set of expected advertised names for this device.
-  names: HashSet<String>,
+  /// Set of expected advertised name patterns for this device. See [BluetoothLENamePattern] for
+  /// the pattern format.
+  names: BluetoothLENamePatterns,
   /// Array of possible manufacturer data values.
   #[serde(default, rename = "manufacturer-data")]
   manufacturer_data: Vec<BluetoothLEManufacturerData>,
   /// Set of expected advertised services for this device.
   #[serde(default, rename = "advertised-services")]
   advertised_services: HashSet<Uuid>,
+  /// Set of services that must all be advertised for this device to match.
+  #[serde(
+    default,
+    rename = "required-advertised-services",
+    skip_serializing_if = "HashSet::is_empty"
+  )]
+  required_advertised_services: HashSet<Uuid>,
   /// Services we expect the device may have. More services may be listed in a specifier than any
   /// one device may have, but we expect at least one to be matched by a device in order to consider
   /// the device part of the protocol that has this specifier.
   services: HashMap<Uuid, HashMap<Endpoint, Uuid>>,
 }
 
-impl PartialEq for BluetoothLESpecifier {
-  fn eq(&self, other: &Self) -> bool {
-    // If names or manufacturer data are found, use those automatically.
-    if self.names.intersection(&other.names).count() > 0 {
-      return true;
-    }
-    // Otherwise, try wildcarded names.
-    for name in &self.names {
-      for other_name in &other.names {
-        let compare_name: &String;
-        let mut wildcard: String;
-        if name.ends_with('*') {
-          wildcard = name.clone();
-          compare_name = other_name;
-        } else if other_name.ends_with('*') {
-          wildcard = other_name.clone();
-          compare_name = name;
-        } else {
-          continue;
-        }
-        // Remove asterisk from the end of the wildcard
-        wildcard.pop();
-        if compare_name.starts_with(&wildcard) {
-          return true;
-        }
-      }
-    }
-
-    if !self.manufacturer_data.is_empty() && !other.manufacturer_data.is_empty() {
-      for data in &self.manufacturer_data {
-        if other.manufacturer_data.contains(data) {
-          return true;
-        }
-      }
-    }
-
-    if self
-      .advertised_services
-      .intersection(&other.advertised_services)
-      .count()
-      > 0
-    {
-      return true;
-    }
-
-    false
-  }
-}
-
 impl BluetoothLESpecifier {
   pub fn new(
     names: HashSet<String>,
@@ -161,48 +309,55 @@ impl BluetoothLESpecifier {
     services: HashMap<Uuid, HashMap<Endpoint, Uuid>>,
   ) -> Self {
     Self {
-      names,
+      names: BluetoothLENamePatterns::new_lossy(names),
       manufacturer_data,
       advertised_services,
+      required_advertised_services: HashSet::new(),
       services,
     }
   }
 
-  /// Creates a specifier from a BLE device advertisement.
-  pub fn new_from_device(
-    name: &str,
-    manufacturer_data: &HashMap<u16, Vec<u8>>,
-    advertised_services: &[Uuid],
-  ) -> BluetoothLESpecifier {
-    let mut name_set = HashSet::new();
-    name_set.insert(name.to_string());
-    let mut data_vec = vec![];
-    for (company, data) in manufacturer_data.iter() {
-      data_vec.push(BluetoothLEManufacturerData::new(
-        *company,
-        &Some(data.clone()),
-      ));
+  /// Returns a score for how well an advertisement matches this specifier, or None if it does not
+  /// match. Scores from name, manufacturer data and advertised service matches are summed, so
+  /// advertisements matching on more criteria score higher.
+  pub fn match_score(&self, advertisement: &BluetoothLEAdvertisement) -> Option<u32> {
+    if !self
+      .required_advertised_services
+      .is_subset(&advertisement.advertised_services)
+    {
+      return None;
     }
-    let service_set = HashSet::from_iter(advertised_services.iter().copied());
-    BluetoothLESpecifier {
-      names: name_set,
-      manufacturer_data: data_vec,
-      advertised_services: service_set,
-      services: HashMap::new(),
+    let name_score = self.names.match_score(&advertisement.name);
+    let manufacturer_data_score = self
+      .manufacturer_data
+      .iter()
+      .filter_map(|data| data.match_score(&advertisement.manufacturer_data))
+      .max();
+    let service_count = self
+      .advertised_services
+      .union(&self.required_advertised_services)
+      .filter(|service| advertisement.advertised_services.contains(service))
+      .count() as u32;
+    if name_score.is_none() && manufacturer_data_score.is_none() && service_count == 0 {
+      return None;
     }
+    Some(name_score.unwrap_or(0) + manufacturer_data_score.unwrap_or(0) + 10 * service_count)
   }
 
   /// Merge with another BLE specifier, used when loading user configs that extend a protocol
   /// definition.
   pub fn merge(&mut self, other: BluetoothLESpecifier) {
     // Add any new names.
-    self.names = self.names.union(&other.names).cloned().collect();
+    self.names.merge(other.names);
     // Add new services, overwrite matching services.
     self.advertised_services = self
       .advertised_services
       .union(&other.advertised_services)
       .cloned()
       .collect();
+    self
+      .required_advertised_services
+      .extend(other.required_advertised_services);
     self.services.extend(other.services);
   }
 }
@@ -232,6 +387,30 @@ impl PartialEq for LovenseConnectServiceSpecifier {
   }
 }
 
+/// Specifier for [Buttplug
+/// Federation](crate::server::device::hardware::communication::buttplug_federation) devices
+///
+/// Devices on remote Buttplug servers have no attributes to match on, as the federation
+/// communication manager relays device information from the remote server itself.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ButtplugFederationSpecifier {
+  // Needed for proper deserialization, but clippy will complain.
+  #[allow(dead_code)]
+  exists: bool,
+}
+
+impl Default for ButtplugFederationSpecifier {
+  fn default() -> Self {
+    Self { exists: true }
+  }
+}
+
+impl PartialEq for ButtplugFederationSpecifier {
+  fn eq(&self, _other: &Self) -> bool {
+    true
+  }
+}
+
 /// Specifier for [XInput](crate::server::device::communication_manager::xinput) devices
 ///
 /// Network based services, has no attributes because the
@@ -256,6 +435,30 @@ impl PartialEq for XInputSpecifier {
   }
 }
 
+/// Specifier for [evdev](crate::server::device::hardware::communication::evdev) force feedback
+/// devices
+///
+/// Has no attributes, as the evdev device communication manager only reports devices that support
+/// rumble effects, and they're all driven the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct EvdevSpecifier {
+  // Needed for deserialziation but unused.
+  #[allow(dead_code)]
+  exists: bool,
+}
+
+impl Default for EvdevSpecifier {
+  fn default() -> Self {
+    Self { exists: true }
+  }
+}
+
+impl PartialEq for EvdevSpecifier {
+  fn eq(&self, _other: &Self) -> bool {
+    true
+  }
+}
+
 /// Specifier for HID (USB, Bluetooth) devices
 ///
 /// Handles devices managed by the operating system's HID manager.
@@ -360,6 +563,30 @@ impl WebsocketSpecifier {
   }
 }
 
+/// Specifier for Network Device Manager devices
+///
+/// Network devices only report an identifier, either from their entry in the network device
+/// manager setup or from their discovery announcement, so names are all we can match on.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Getters, Setters, MutGetters)]
+#[getset(get = "pub", set = "pub")]
+pub struct NetworkSpecifier {
+  names: HashSet<String>,
+}
+
+impl PartialEq for NetworkSpecifier {
+  fn eq(&self, other: &Self) -> bool {
+    self.names.intersection(&other.names).count() > 0
+  }
+}
+
+impl NetworkSpecifier {
+  pub fn new(names: &[String]) -> NetworkSpecifier {
+    NetworkSpecifier {
+      names: names.iter().cloned().collect(),
+    }
+  }
+}
+
 /// Enum that covers all types of communication specifiers.
 ///
 /// Allows generalization of specifiers to handle checking for equality. Used for testing newly discovered
@@ -367,12 +594,16 @@ impl WebsocketSpecifier {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ProtocolCommunicationSpecifier {
   BluetoothLE(BluetoothLESpecifier),
+  BluetoothLEAdvertisement(BluetoothLEAdvertisement),
   HID(HIDSpecifier),
   USB(USBSpecifier),
   Serial(SerialSpecifier),
   XInput(XInputSpecifier),
+  Evdev(EvdevSpecifier),
   LovenseConnectService(LovenseConnectServiceSpecifier),
   Websocket(WebsocketSpecifier),
+  Network(NetworkSpecifier),
+  ButtplugFederation(ButtplugFederationSpecifier),
 }
 
 impl PartialEq for ProtocolCommunicationSpecifier {
@@ -382,12 +613,18 @@ impl PartialEq for ProtocolCommunicationSpecifier {
       (USB(self_spec), USB(other_spec)) => self_spec == other_spec,
       (Serial(self_spec), Serial(other_spec)) => self_spec == other_spec,
       (BluetoothLE(self_spec), BluetoothLE(other_spec)) => self_spec == other_spec,
+      (BluetoothLEAdvertisement(self_spec), BluetoothLEAdvertisement(other_spec)) => {
+        self_spec == other_spec
+      }
       (HID(self_spec), HID(other_spec)) => self_spec == other_spec,
       (XInput(self_spec), XInput(other_spec)) => self_spec == other_spec,
+      (Evdev(self_spec), Evdev(other_spec)) => self_spec == other_spec,
       (Websocket(self_spec), Websocket(other_spec)) => self_spec == other_spec,
+      (Network(self_spec), Network(other_spec)) => self_spec == other_spec,
       (LovenseConnectService(self_spec), LovenseConnectService(other_spec)) => {
         self_spec == other_spec
       }
+      (ButtplugFederation(self_spec), ButtplugFederation(other_spec)) => self_spec == other_spec,
       _ => false,
     }
   }
@@ -395,3 +632,23 @@ impl PartialEq for ProtocolCommunicationSpecifier {
 
 impl Eq for ProtocolCommunicationSpecifier {
 }
+
+impl ProtocolCommunicationSpecifier {
+  /// Returns a score for how well a specifier built from a discovered device matches this specifier
+  /// from the device configuration, or None if it does not match. Higher scores mean more specific
+  /// matches.
+  ///
+  /// Matching is directional: `self` is the configuration side, `device` is what the hardware
+  /// communication manager reported.
+  pub fn match_score(&self, device: &ProtocolCommunicationSpecifier) -> Option<u32> {
+    use ProtocolCommunicationSpecifier::*;
+    match (self, device) {
+      (BluetoothLE(config), BluetoothLEAdvertisement(advertisement)) => {
+        config.match_score(advertisement)
+      }
+      (BluetoothLE(_), _) | (BluetoothLEAdvertisement(_), _) => None,
+      // All other specifier types are exact matches.
+      (config, device) => (config == device).then_some(1),
+    }
+  }
+}
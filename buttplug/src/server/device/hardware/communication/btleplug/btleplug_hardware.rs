@@ -9,7 +9,7 @@ use crate::{
   core::{errors::ButtplugDeviceError, message::Endpoint},
   server::device::hardware::communication::HardwareSpecificError,
   server::device::{
-    configuration::{BluetoothLESpecifier, ProtocolCommunicationSpecifier},
+    configuration::{BluetoothLEAdvertisement, ProtocolCommunicationSpecifier},
     hardware::{
       Hardware,
       HardwareConnector,
@@ -90,7 +90,7 @@ impl<T: Peripheral> Debug for BtleplugHardwareConnector<T> {
 #[async_trait]
 impl<T: Peripheral> HardwareConnector for BtleplugHardwareConnector<T> {
   fn specifier(&self) -> ProtocolCommunicationSpecifier {
-    ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new_from_device(
+    ProtocolCommunicationSpecifier::BluetoothLEAdvertisement(BluetoothLEAdvertisement::new(
       &self.name,
       &self.manufacturer_data,
       &self.services,
@@ -127,17 +127,39 @@ impl<T: Peripheral> HardwareConnector for BtleplugHardwareConnector<T> {
   }
 }
 
+/// Disconnects the peripheral once the specializer and every hardware instance specialized from it
+/// have been dropped. This allows a protocol that fails to identify or initialize to drop its
+/// hardware without taking the connection away from the next protocol candidate.
+struct BtleplugConnectionGuard<T: Peripheral + 'static> {
+  device: T,
+}
+
+impl<T: Peripheral> Drop for BtleplugConnectionGuard<T> {
+  fn drop(&mut self) {
+    let device = self.device.clone();
+    async_manager::spawn(async move {
+      if let Err(e) = device.disconnect().await {
+        error!("Error disconnecting btleplug device: {:?}", e);
+      }
+    });
+  }
+}
+
 pub struct BtleplugHardwareSpecializer<T: Peripheral + 'static> {
   name: String,
   device: T,
   adapter: Adapter,
   requires_keepalive: bool,
+  connection: Arc<BtleplugConnectionGuard<T>>,
 }
 
 impl<T: Peripheral> BtleplugHardwareSpecializer<T> {
   pub(super) fn new(name: &str, device: T, adapter: Adapter, requires_keepalive: bool) -> Self {
     Self {
       name: name.to_owned(),
+      connection: Arc::new(BtleplugConnectionGuard {
+        device: device.clone(),
+      }),
       device,
       adapter,
       requires_keepalive,
@@ -200,8 +222,9 @@ impl<T: Peripheral> HardwareSpecializer for BtleplugHardwareSpecializer<T> {
       .await
       .expect("Should always be able to get notifications");
 
-    let device_internal_impl = BtlePlugHardware::new(
+    let device_internal_impl = BtlePlugHardware::with_connection(
       self.device.clone(),
+      self.connection.clone(),
       &self.name,
       self
         .adapter
@@ -232,12 +255,36 @@ pub struct BtlePlugHardware<T: Peripheral + 'static> {
   event_stream: broadcast::Sender<HardwareEvent>,
   endpoints: HashMap<Endpoint, Characteristic>,
   subscribed_endpoints: Arc<DashSet<Endpoint>>,
+  _connection: Arc<BtleplugConnectionGuard<T>>,
 }
 
 impl<T: Peripheral + 'static> BtlePlugHardware<T> {
   pub fn new(
     device: T,
     name: &str,
+    adapter_event_stream: Pin<Box<dyn Stream<Item = CentralEvent> + Send>>,
+    notification_stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
+    endpoints: HashMap<Endpoint, Characteristic>,
+    uuid_map: HashMap<Uuid, Endpoint>,
+  ) -> Self {
+    let connection = Arc::new(BtleplugConnectionGuard {
+      device: device.clone(),
+    });
+    Self::with_connection(
+      device,
+      connection,
+      name,
+      adapter_event_stream,
+      notification_stream,
+      endpoints,
+      uuid_map,
+    )
+  }
+
+  fn with_connection(
+    device: T,
+    connection: Arc<BtleplugConnectionGuard<T>>,
+    name: &str,
     mut adapter_event_stream: Pin<Box<dyn Stream<Item = CentralEvent> + Send>>,
     mut notification_stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
     endpoints: HashMap<Endpoint, Characteristic>,
@@ -318,6 +365,7 @@ impl<T: Peripheral + 'static> BtlePlugHardware<T> {
       endpoints,
       event_stream,
       subscribed_endpoints: Arc::new(DashSet::new()),
+      _connection: connection,
     }
   }
 }
@@ -495,14 +543,3 @@ impl<T: Peripheral + 'static> HardwareInternal for BtlePlugHardware<T> {
     .boxed()
   }
 }
-
-impl<T: Peripheral> Drop for BtlePlugHardware<T> {
-  fn drop(&mut self) {
-    let disconnect_fut = self.disconnect();
-    async_manager::spawn(async move {
-      if let Err(e) = disconnect_fut.await {
-        error!("Error disconnecting btleplug device: {:?}", e);
-      }
-    });
-  }
-}
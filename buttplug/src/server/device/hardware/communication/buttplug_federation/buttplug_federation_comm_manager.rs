@@ -0,0 +1,256 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::buttplug_federation_hardware::ButtplugFederationHardwareConnector;
+use crate::{
+  client::{ButtplugClient, ButtplugClientError, ButtplugClientEvent},
+  core::{
+    connector::ButtplugConnector,
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage},
+    ButtplugResultFuture,
+  },
+  server::device::hardware::communication::{
+    HardwareCommunicationManager,
+    HardwareCommunicationManagerBuilder,
+    HardwareCommunicationManagerEvent,
+  },
+  util::async_manager,
+};
+use futures::{future::BoxFuture, FutureExt, Stream, StreamExt};
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+use tokio::sync::{mpsc::Sender, Mutex};
+use tokio_util::sync::CancellationToken;
+
+type FederationConnectFn =
+  Box<dyn Fn(Arc<ButtplugClient>) -> BoxFuture<'static, Result<(), ButtplugClientError>> + Send>;
+
+fn client_error_to_buttplug_error(err: ButtplugClientError) -> ButtplugError {
+  match err {
+    ButtplugClientError::ButtplugError(err) => err,
+    ButtplugClientError::ButtplugConnectorError(err) => {
+      ButtplugDeviceError::DeviceConnectionError(err.to_string()).into()
+    }
+    err @ ButtplugClientError::RequestTimeout(_) => {
+      ButtplugDeviceError::DeviceCommunicationError(err.to_string()).into()
+    }
+  }
+}
+
+pub struct ButtplugFederationCommunicationManagerBuilder {
+  client_name: String,
+  connect: Option<FederationConnectFn>,
+}
+
+impl ButtplugFederationCommunicationManagerBuilder {
+  /// Creates a builder that will connect to a remote server using connectors made by
+  /// `connector_factory`. The connection is made when scanning is started, and made again with a
+  /// new connector if the remote server has disconnected since.
+  pub fn new<ConnectorType, FactoryType>(connector_factory: FactoryType) -> Self
+  where
+    ConnectorType: ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+      + 'static,
+    FactoryType: Fn() -> ConnectorType + Send + 'static,
+  {
+    Self {
+      client_name: "Buttplug Federation".to_owned(),
+      connect: Some(Box::new(move |client: Arc<ButtplugClient>| {
+        let connector = connector_factory();
+        async move { client.connect(connector).await }.boxed()
+      })),
+    }
+  }
+
+  /// Client name to send to the remote server during the handshake.
+  pub fn client_name(mut self, name: &str) -> Self {
+    self.client_name = name.to_owned();
+    self
+  }
+}
+
+impl HardwareCommunicationManagerBuilder for ButtplugFederationCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: Sender<HardwareCommunicationManagerEvent>,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    Box::new(ButtplugFederationCommunicationManager::new(
+      sender,
+      &self.client_name,
+      self.connect.take(),
+    ))
+  }
+}
+
+pub struct ButtplugFederationCommunicationManager {
+  sender: Sender<HardwareCommunicationManagerEvent>,
+  client: Arc<ButtplugClient>,
+  // Held while connecting, so concurrent scans don't connect twice.
+  connect: Arc<Mutex<Option<FederationConnectFn>>>,
+  scanning: Arc<AtomicBool>,
+  event_loop_token: CancellationToken,
+}
+
+impl ButtplugFederationCommunicationManager {
+  fn new(
+    sender: Sender<HardwareCommunicationManagerEvent>,
+    client_name: &str,
+    connect: Option<FederationConnectFn>,
+  ) -> Self {
+    Self {
+      sender,
+      client: Arc::new(ButtplugClient::new(client_name)),
+      connect: Arc::new(Mutex::new(connect)),
+      scanning: Arc::new(AtomicBool::new(false)),
+      event_loop_token: CancellationToken::new(),
+    }
+  }
+}
+
+async fn run_federation_event_loop(
+  event_stream: impl Stream<Item = ButtplugClientEvent>,
+  server_name: String,
+  sender: Sender<HardwareCommunicationManagerEvent>,
+  scanning: Arc<AtomicBool>,
+  token: CancellationToken,
+) {
+  futures::pin_mut!(event_stream);
+  loop {
+    let event = select! {
+      event = event_stream.next().fuse() => event,
+      _ = token.cancelled().fuse() => {
+        info!("Federation communication manager shutting down.");
+        return;
+      }
+    };
+    match event {
+      Some(ButtplugClientEvent::DeviceAdded(device)) => {
+        let address = format!("{}/{}", server_name, device.index());
+        debug!("Federated device {} found at {}", device.name(), address);
+        if sender
+          .send(HardwareCommunicationManagerEvent::DeviceFound {
+            name: device.name().clone(),
+            address: address.clone(),
+            creator: Box::new(ButtplugFederationHardwareConnector::new(&address, device)),
+          })
+          .await
+          .is_err()
+        {
+          error!("Device manager disappeared, exiting.");
+          return;
+        }
+      }
+      Some(ButtplugClientEvent::ScanningFinished) => {
+        // Only report the remote scan finishing if we were the ones that started it.
+        if !scanning.swap(false, Ordering::SeqCst) {
+          continue;
+        }
+        if sender
+          .send(HardwareCommunicationManagerEvent::ScanningFinished)
+          .await
+          .is_err()
+        {
+          error!("Device manager disappeared, exiting.");
+          return;
+        }
+      }
+      Some(ButtplugClientEvent::ServerDisconnect) | None => {
+        info!("Remote server disconnected, stopping federation event loop.");
+        scanning.store(false, Ordering::SeqCst);
+        return;
+      }
+      _ => {}
+    }
+  }
+}
+
+impl HardwareCommunicationManager for ButtplugFederationCommunicationManager {
+  fn name(&self) -> &'static str {
+    "ButtplugFederationCommunicationManager"
+  }
+
+  fn start_scanning(&mut self) -> ButtplugResultFuture {
+    let client = self.client.clone();
+    let connect = self.connect.clone();
+    let sender = self.sender.clone();
+    let scanning = self.scanning.clone();
+    let token = self.event_loop_token.child_token();
+    async move {
+      // The event stream needs to exist before connecting, as the handshake will announce devices
+      // already on the remote server.
+      let connect = connect.lock().await;
+      if let (Some(connect_fn), false) = (connect.as_ref(), client.connected()) {
+        let event_stream = client.event_stream();
+        connect_fn(client.clone())
+          .await
+          .map_err(client_error_to_buttplug_error)?;
+        let server_name = client.server_name().unwrap_or_default();
+        async_manager::spawn(run_federation_event_loop(
+          event_stream,
+          server_name,
+          sender,
+          scanning.clone(),
+          token,
+        ));
+      }
+      drop(connect);
+      if !client.connected() {
+        return Err(
+          ButtplugDeviceError::DeviceConnectionError(
+            "Federation communication manager is not connected to a remote server.".to_owned(),
+          )
+          .into(),
+        );
+      }
+      scanning.store(true, Ordering::SeqCst);
+      client
+        .start_scanning()
+        .await
+        .map_err(client_error_to_buttplug_error)
+    }
+    .boxed()
+  }
+
+  fn stop_scanning(&mut self) -> ButtplugResultFuture {
+    let client = self.client.clone();
+    async move {
+      if !client.connected() {
+        return Ok(());
+      }
+      client
+        .stop_scanning()
+        .await
+        .map_err(client_error_to_buttplug_error)
+    }
+    .boxed()
+  }
+
+  fn scanning_status(&self) -> bool {
+    self.scanning.load(Ordering::SeqCst)
+  }
+
+  // No restrictions since this is network not hardware.
+  fn can_scan(&self) -> bool {
+    true
+  }
+}
+
+impl Drop for ButtplugFederationCommunicationManager {
+  fn drop(&mut self) {
+    self.event_loop_token.cancel();
+    if self.client.connected() {
+      let client = self.client.clone();
+      async_manager::spawn(async move {
+        if let Err(err) = client.disconnect().await {
+          error!("Error disconnecting from remote server: {}", err);
+        }
+      });
+    }
+  }
+}
@@ -0,0 +1,304 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+  client::{
+    ButtplugClientDevice,
+    ButtplugClientDeviceEvent,
+    ButtplugClientError,
+    LinearCommand,
+    RotateCommand,
+    ScalarCommand,
+  },
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      ButtplugCurrentSpecServerMessage,
+      ButtplugDeviceCommandMessageUnion,
+      ButtplugDeviceMessage,
+      ButtplugMessage,
+      Endpoint,
+      SensorReading,
+    },
+  },
+  server::device::{
+    configuration::{
+      ButtplugFederationSpecifier,
+      ProtocolAttributesType,
+      ProtocolCommunicationSpecifier,
+      ProtocolDeviceAttributes,
+    },
+    hardware::{
+      GenericHardwareSpecializer,
+      Hardware,
+      HardwareConnector,
+      HardwareEvent,
+      HardwareInternal,
+      HardwareReadCmd,
+      HardwareReading,
+      HardwareSpecializer,
+      HardwareSubscribeCmd,
+      HardwareUnsubscribeCmd,
+      HardwareWriteCmd,
+    },
+  },
+  util::async_manager,
+};
+use async_trait::async_trait;
+use futures::{
+  future::{self, BoxFuture, FutureExt},
+  StreamExt,
+};
+use std::{
+  fmt::{self, Debug},
+  sync::Arc,
+};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+fn client_error_to_device_error(err: ButtplugClientError) -> ButtplugDeviceError {
+  match err {
+    ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(err)) => err,
+    err => ButtplugDeviceError::DeviceCommunicationError(err.to_string()),
+  }
+}
+
+pub struct ButtplugFederationHardwareConnector {
+  address: String,
+  device: Arc<ButtplugClientDevice>,
+}
+
+impl ButtplugFederationHardwareConnector {
+  pub(super) fn new(address: &str, device: Arc<ButtplugClientDevice>) -> Self {
+    Self {
+      address: address.to_owned(),
+      device,
+    }
+  }
+}
+
+impl Debug for ButtplugFederationHardwareConnector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("ButtplugFederationHardwareConnector")
+      .field("address", &self.address)
+      .finish()
+  }
+}
+
+#[async_trait]
+impl HardwareConnector for ButtplugFederationHardwareConnector {
+  fn specifier(&self) -> ProtocolCommunicationSpecifier {
+    ProtocolCommunicationSpecifier::ButtplugFederation(ButtplugFederationSpecifier::default())
+  }
+
+  async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
+    let name = self.device.name().clone();
+    let hardware_internal = ButtplugFederationHardware::new(&self.address, self.device.clone());
+    let mut hardware = Hardware::new(
+      &name,
+      &self.address,
+      &[Endpoint::Tx, Endpoint::Rx],
+      Box::new(hardware_internal),
+    );
+    // Remote devices have no entries in our device configuration, so the attributes reported by the
+    // remote server are carried on the hardware instead.
+    hardware.set_attributes(ProtocolDeviceAttributes::new(
+      ProtocolAttributesType::Identifier(name.clone()),
+      Some(name),
+      None,
+      self.device.message_attributes().clone().into(),
+      None,
+    ));
+    Ok(Box::new(GenericHardwareSpecializer::new(hardware)))
+  }
+}
+
+pub struct ButtplugFederationHardware {
+  address: String,
+  device: Arc<ButtplugClientDevice>,
+  event_sender: broadcast::Sender<HardwareEvent>,
+  listener_token: CancellationToken,
+}
+
+impl ButtplugFederationHardware {
+  fn new(address: &str, device: Arc<ButtplugClientDevice>) -> Self {
+    let (event_sender, _) = broadcast::channel(256);
+    let listener_token = CancellationToken::new();
+    let child_token = listener_token.child_token();
+    let sender_clone = event_sender.clone();
+    let address_clone = address.to_owned();
+    let mut device_events = device.event_stream();
+    async_manager::spawn(async move {
+      loop {
+        let event = select! {
+          event = device_events.next().fuse() => event,
+          _ = child_token.cancelled().fuse() => return,
+        };
+        match event {
+          Some(ButtplugClientDeviceEvent::Message(
+            ButtplugCurrentSpecServerMessage::SensorReading(reading),
+          )) => {
+            let data = serde_json::to_vec(&reading).expect("Type is always serializable");
+            let _ = sender_clone.send(HardwareEvent::Notification(
+              address_clone.clone(),
+              Endpoint::Rx,
+              data,
+            ));
+          }
+          Some(ButtplugClientDeviceEvent::Message(_)) => {}
+          Some(ButtplugClientDeviceEvent::DeviceRemoved)
+          | Some(ButtplugClientDeviceEvent::ClientDisconnect)
+          | None => {
+            info!(
+              "Federated device {} removed from remote server.",
+              address_clone
+            );
+            let _ = sender_clone.send(HardwareEvent::Disconnected(address_clone.clone()));
+            return;
+          }
+        }
+      }
+    });
+    Self {
+      address: address.to_owned(),
+      device,
+      event_sender,
+      listener_token,
+    }
+  }
+}
+
+impl HardwareInternal for ButtplugFederationHardware {
+  fn event_stream(&self) -> broadcast::Receiver<HardwareEvent> {
+    self.event_sender.subscribe()
+  }
+
+  fn disconnect(&self) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Ok(())).boxed()
+  }
+
+  // Sensors are read through SensorReadCmd writes, as a raw read can't say which sensor to read.
+  fn read_value(
+    &self,
+    _msg: &HardwareReadCmd,
+  ) -> BoxFuture<'static, Result<HardwareReading, ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Federated devices do not support raw reads".to_owned(),
+    )))
+    .boxed()
+  }
+
+  // Writes on Tx are device command messages, which are relayed through the matching client call.
+  // Sensor reads are answered with a reading notification on Rx, sent before the write finishes.
+  fn write_value(
+    &self,
+    msg: &HardwareWriteCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let message: ButtplugDeviceCommandMessageUnion = match serde_json::from_slice(msg.data()) {
+      Ok(message) => message,
+      Err(err) => {
+        return future::ready(Err(ButtplugDeviceError::DeviceCommunicationError(
+          err.to_string(),
+        )))
+        .boxed()
+      }
+    };
+    let device = &self.device;
+    let command = match message {
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => {
+        device.scalar(&ScalarCommand::ScalarMap(
+          msg
+            .scalars()
+            .iter()
+            .map(|s| (s.index(), (s.scalar(), s.actuator_type())))
+            .collect(),
+        ))
+      }
+      ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => {
+        device.rotate(&RotateCommand::RotateMap(
+          msg
+            .rotations()
+            .iter()
+            .map(|r| (r.index(), (r.speed(), r.clockwise())))
+            .collect(),
+        ))
+      }
+      ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => {
+        device.linear(&LinearCommand::LinearMap(
+          msg
+            .vectors()
+            .iter()
+            .map(|v| (v.index(), (v.duration(), v.position())))
+            .collect(),
+        ))
+      }
+      ButtplugDeviceCommandMessageUnion::StopDeviceCmd(_) => device.stop(),
+      ButtplugDeviceCommandMessageUnion::SensorReadCmd(msg) => {
+        let reading = device.read_sensor(*msg.sensor_index(), *msg.sensor_type());
+        let sender = self.event_sender.clone();
+        let address = self.address.clone();
+        return async move {
+          let data = reading.await.map_err(client_error_to_device_error)?;
+          let mut reading = SensorReading::new(
+            msg.device_index(),
+            *msg.sensor_index(),
+            *msg.sensor_type(),
+            data,
+          );
+          reading.set_id(msg.id());
+          let _ = sender.send(HardwareEvent::Notification(
+            address,
+            Endpoint::Rx,
+            serde_json::to_vec(&reading).expect("Type is always serializable"),
+          ));
+          Ok(())
+        }
+        .boxed();
+      }
+      ButtplugDeviceCommandMessageUnion::SensorSubscribeCmd(msg) => {
+        device.subscribe_sensor(*msg.sensor_index(), *msg.sensor_type())
+      }
+      ButtplugDeviceCommandMessageUnion::SensorUnsubscribeCmd(msg) => {
+        device.unsubscribe_sensor(*msg.sensor_index(), *msg.sensor_type())
+      }
+      msg => {
+        return future::ready(Err(ButtplugDeviceError::UnhandledCommand(format!(
+          "Federated devices cannot relay {:?}",
+          msg
+        ))))
+        .boxed()
+      }
+    };
+    async move { command.await.map_err(client_error_to_device_error) }.boxed()
+  }
+
+  fn subscribe(
+    &self,
+    _msg: &HardwareSubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Federated devices do not support raw subscriptions".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn unsubscribe(
+    &self,
+    _msg: &HardwareUnsubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Federated devices do not support raw subscriptions".to_owned(),
+    )))
+    .boxed()
+  }
+}
+
+impl Drop for ButtplugFederationHardware {
+  fn drop(&mut self) {
+    self.listener_token.cancel();
+  }
+}
@@ -0,0 +1,21 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Relay devices from another Buttplug server.
+//!
+//! The federation communication manager connects to another server as a client, and exposes each
+//! device on that server as local hardware using the `buttplug-federation` protocol. Scanning
+//! requests are passed on to the remote server, and devices disconnect locally when they are
+//! removed from the remote server or the remote connection drops.
+
+mod buttplug_federation_comm_manager;
+mod buttplug_federation_hardware;
+pub use buttplug_federation_comm_manager::{
+  ButtplugFederationCommunicationManager,
+  ButtplugFederationCommunicationManagerBuilder,
+};
+pub use buttplug_federation_hardware::ButtplugFederationHardware;
@@ -0,0 +1,125 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use getset::Getters;
+use std::io;
+
+/// Input device that supports rumble effects.
+#[derive(Debug, Clone, PartialEq, Eq, Getters)]
+#[getset(get = "pub")]
+pub struct EvdevDeviceInfo {
+  /// Path of the event device, i.e. `/dev/input/event5`. Used as the device address.
+  path: String,
+  /// Name the device reports.
+  name: String,
+}
+
+impl EvdevDeviceInfo {
+  pub fn new(path: &str, name: &str) -> Self {
+    Self {
+      path: path.to_owned(),
+      name: name.to_owned(),
+    }
+  }
+}
+
+/// Opened input device that rumble commands can be sent to.
+pub trait EvdevRumbleDevice: Send {
+  /// Play a rumble effect with the given motor magnitudes until the next call. Setting both
+  /// magnitudes to 0 stops the effect.
+  fn rumble(&mut self, strong_magnitude: u16, weak_magnitude: u16) -> io::Result<()>;
+}
+
+/// Access to input devices, used by the evdev communication manager for enumeration, connection and
+/// hot unplug detection.
+pub trait EvdevBackend: Send + Sync {
+  /// List all input devices that support rumble effects.
+  fn enumerate(&self) -> Vec<EvdevDeviceInfo>;
+
+  /// Open the device at the given path.
+  fn open(&self, path: &str) -> io::Result<Box<dyn EvdevRumbleDevice>>;
+
+  /// Returns true if the device at the given path is still plugged in.
+  fn is_present(&self, path: &str) -> bool {
+    self.enumerate().iter().any(|device| device.path() == path)
+  }
+}
+
+/// Backend that uses the kernel's event devices.
+#[cfg(target_os = "linux")]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SystemEvdevBackend {}
+
+#[cfg(target_os = "linux")]
+impl EvdevBackend for SystemEvdevBackend {
+  fn enumerate(&self) -> Vec<EvdevDeviceInfo> {
+    evdev::enumerate()
+      .filter(|(_, device)| {
+        device
+          .supported_ff()
+          .is_some_and(|ff| ff.contains(evdev::FFEffectType::FF_RUMBLE))
+      })
+      .map(|(path, device)| {
+        EvdevDeviceInfo::new(
+          &path.to_string_lossy(),
+          device.name().unwrap_or("Force Feedback Device"),
+        )
+      })
+      .collect()
+  }
+
+  fn open(&self, path: &str) -> io::Result<Box<dyn EvdevRumbleDevice>> {
+    Ok(Box::new(SystemEvdevRumbleDevice {
+      device: evdev::Device::open(path)?,
+      effect: None,
+    }))
+  }
+
+  fn is_present(&self, path: &str) -> bool {
+    std::path::Path::new(path).exists()
+  }
+}
+
+#[cfg(target_os = "linux")]
+struct SystemEvdevRumbleDevice {
+  device: evdev::Device,
+  // Uploaded on the first command, then updated in place.
+  effect: Option<evdev::FFEffect>,
+}
+
+#[cfg(target_os = "linux")]
+impl EvdevRumbleDevice for SystemEvdevRumbleDevice {
+  fn rumble(&mut self, strong_magnitude: u16, weak_magnitude: u16) -> io::Result<()> {
+    if strong_magnitude == 0 && weak_magnitude == 0 {
+      if let Some(effect) = &mut self.effect {
+        effect.stop()?;
+      }
+      return Ok(());
+    }
+    let data = evdev::FFEffectData {
+      direction: 0,
+      trigger: evdev::FFTrigger::default(),
+      // A zero length plays the effect until it's stopped.
+      replay: evdev::FFReplay {
+        length: 0,
+        delay: 0,
+      },
+      kind: evdev::FFEffectKind::Rumble {
+        strong_magnitude,
+        weak_magnitude,
+      },
+    };
+    let effect = match &mut self.effect {
+      Some(effect) => {
+        effect.update(data)?;
+        effect
+      }
+      None => self.effect.insert(self.device.upload_ff_effect(data)?),
+    };
+    effect.play(1)
+  }
+}
@@ -0,0 +1,108 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::{evdev_backend::EvdevBackend, evdev_hardware::EvdevHardwareConnector};
+use crate::{
+  core::errors::ButtplugDeviceError,
+  server::device::hardware::communication::{
+    HardwareCommunicationManager,
+    HardwareCommunicationManagerBuilder,
+    HardwareCommunicationManagerEvent,
+    TimedRetryCommunicationManager,
+    TimedRetryCommunicationManagerImpl,
+  },
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+#[derive(Default, Clone)]
+pub struct EvdevCommunicationManagerBuilder {
+  backend: Option<Arc<dyn EvdevBackend>>,
+}
+
+impl EvdevCommunicationManagerBuilder {
+  /// Use a different backend for input device access. If this isn't set, the system's event
+  /// devices are used on Linux, and scanning is unavailable elsewhere.
+  pub fn backend<T>(mut self, backend: T) -> Self
+  where
+    T: EvdevBackend + 'static,
+  {
+    self.backend = Some(Arc::new(backend));
+    self
+  }
+}
+
+impl HardwareCommunicationManagerBuilder for EvdevCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    #[cfg(target_os = "linux")]
+    let backend = Some(self.backend.clone().unwrap_or_else(|| {
+      Arc::new(super::evdev_backend::SystemEvdevBackend::default()) as Arc<dyn EvdevBackend>
+    }));
+    #[cfg(not(target_os = "linux"))]
+    let backend = self.backend.clone();
+    Box::new(TimedRetryCommunicationManager::new(
+      EvdevCommunicationManager::new(sender, backend),
+    ))
+  }
+}
+
+pub struct EvdevCommunicationManager {
+  sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
+  backend: Option<Arc<dyn EvdevBackend>>,
+}
+
+impl EvdevCommunicationManager {
+  fn new(
+    sender: mpsc::Sender<HardwareCommunicationManagerEvent>,
+    backend: Option<Arc<dyn EvdevBackend>>,
+  ) -> Self {
+    Self { sender, backend }
+  }
+}
+
+#[async_trait]
+impl TimedRetryCommunicationManagerImpl for EvdevCommunicationManager {
+  fn name(&self) -> &'static str {
+    "EvdevCommunicationManager"
+  }
+
+  async fn scan(&self) -> Result<(), ButtplugDeviceError> {
+    let Some(backend) = &self.backend else {
+      return Ok(());
+    };
+    trace!("Evdev manager scanning for devices");
+    for device in backend.enumerate() {
+      debug!(
+        "Evdev manager found device {} at {}",
+        device.name(),
+        device.path()
+      );
+      if self
+        .sender
+        .send(HardwareCommunicationManagerEvent::DeviceFound {
+          name: device.name().clone(),
+          address: device.path().clone(),
+          creator: Box::new(EvdevHardwareConnector::new(device, backend.clone())),
+        })
+        .await
+        .is_err()
+      {
+        error!("Error sending device found message from evdev.");
+        break;
+      }
+    }
+    Ok(())
+  }
+
+  fn can_scan(&self) -> bool {
+    self.backend.is_some()
+  }
+}
@@ -0,0 +1,200 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::evdev_backend::{EvdevBackend, EvdevDeviceInfo, EvdevRumbleDevice};
+use crate::{
+  core::{errors::ButtplugDeviceError, message::Endpoint},
+  server::device::{
+    configuration::{EvdevSpecifier, ProtocolCommunicationSpecifier},
+    hardware::{
+      communication::HardwareSpecificError,
+      GenericHardwareSpecializer,
+      Hardware,
+      HardwareConnector,
+      HardwareEvent,
+      HardwareInternal,
+      HardwareReadCmd,
+      HardwareReading,
+      HardwareSpecializer,
+      HardwareSubscribeCmd,
+      HardwareUnsubscribeCmd,
+      HardwareWriteCmd,
+    },
+  },
+  util::async_manager,
+};
+use async_trait::async_trait;
+use futures::future::{self, BoxFuture, FutureExt};
+use std::{
+  fmt::{self, Debug},
+  io,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+fn evdev_error(err: io::Error) -> ButtplugDeviceError {
+  ButtplugDeviceError::from(HardwareSpecificError::EvdevError(err.to_string()))
+}
+
+async fn check_device_presence(
+  path: String,
+  backend: Arc<dyn EvdevBackend>,
+  sender: broadcast::Sender<HardwareEvent>,
+  cancellation_token: CancellationToken,
+) {
+  loop {
+    if !backend.is_present(&path) {
+      info!("Evdev device {} has been unplugged.", path);
+      // If this fails, we don't care because we're exiting anyways.
+      let _ = sender.send(HardwareEvent::Disconnected(path));
+      return;
+    }
+    tokio::select! {
+      _ = cancellation_token.cancelled() => return,
+      _ = tokio::time::sleep(Duration::from_millis(500)) => continue
+    }
+  }
+}
+
+pub struct EvdevHardwareConnector {
+  info: EvdevDeviceInfo,
+  backend: Arc<dyn EvdevBackend>,
+}
+
+impl EvdevHardwareConnector {
+  pub(super) fn new(info: EvdevDeviceInfo, backend: Arc<dyn EvdevBackend>) -> Self {
+    Self { info, backend }
+  }
+}
+
+impl Debug for EvdevHardwareConnector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("EvdevHardwareConnector")
+      .field("info", &self.info)
+      .finish()
+  }
+}
+
+#[async_trait]
+impl HardwareConnector for EvdevHardwareConnector {
+  fn specifier(&self) -> ProtocolCommunicationSpecifier {
+    ProtocolCommunicationSpecifier::Evdev(EvdevSpecifier::default())
+  }
+
+  async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
+    let device = self.backend.open(self.info.path()).map_err(evdev_error)?;
+    let hardware_internal = EvdevHardware::new(self.info.path(), device, self.backend.clone());
+    let hardware = Hardware::new(
+      self.info.name(),
+      self.info.path(),
+      &[Endpoint::Tx],
+      Box::new(hardware_internal),
+    );
+    Ok(Box::new(GenericHardwareSpecializer::new(hardware)))
+  }
+}
+
+pub struct EvdevHardware {
+  device: Arc<Mutex<Box<dyn EvdevRumbleDevice>>>,
+  event_sender: broadcast::Sender<HardwareEvent>,
+  cancellation_token: CancellationToken,
+}
+
+impl EvdevHardware {
+  fn new(path: &str, device: Box<dyn EvdevRumbleDevice>, backend: Arc<dyn EvdevBackend>) -> Self {
+    let (device_event_sender, _) = broadcast::channel(256);
+    let token = CancellationToken::new();
+    async_manager::spawn(check_device_presence(
+      path.to_owned(),
+      backend,
+      device_event_sender.clone(),
+      token.child_token(),
+    ));
+    Self {
+      device: Arc::new(Mutex::new(device)),
+      event_sender: device_event_sender,
+      cancellation_token: token,
+    }
+  }
+
+  fn rumble(&self, strong_magnitude: u16, weak_magnitude: u16) -> Result<(), ButtplugDeviceError> {
+    self
+      .device
+      .lock()
+      .expect("Only locked for single calls, should never be poisoned.")
+      .rumble(strong_magnitude, weak_magnitude)
+      .map_err(evdev_error)
+  }
+}
+
+impl HardwareInternal for EvdevHardware {
+  fn event_stream(&self) -> broadcast::Receiver<HardwareEvent> {
+    self.event_sender.subscribe()
+  }
+
+  fn disconnect(&self) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    // Don't leave the device rumbling if it's still around. If it's been unplugged, there's nothing
+    // to stop.
+    let _ = self.rumble(0, 0);
+    self.cancellation_token.cancel();
+    future::ready(Ok(())).boxed()
+  }
+
+  fn read_value(
+    &self,
+    _msg: &HardwareReadCmd,
+  ) -> BoxFuture<'static, Result<HardwareReading, ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Evdev hardware does not support read".to_owned(),
+    )))
+    .boxed()
+  }
+
+  // Writes are the strong and weak magnitudes as little endian u16s, as packed by the evdev-rumble
+  // protocol.
+  fn write_value(
+    &self,
+    msg: &HardwareWriteCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let magnitude = |index: usize| {
+      msg
+        .data
+        .get(index * 2..index * 2 + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .unwrap_or(0)
+    };
+    future::ready(self.rumble(magnitude(0), magnitude(1))).boxed()
+  }
+
+  fn subscribe(
+    &self,
+    _msg: &HardwareSubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Evdev hardware does not support subscribe".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn unsubscribe(
+    &self,
+    _msg: &HardwareUnsubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Evdev hardware does not support unsubscribe".to_owned(),
+    )))
+    .boxed()
+  }
+}
+
+impl Drop for EvdevHardware {
+  fn drop(&mut self) {
+    self.cancellation_token.cancel();
+  }
+}
@@ -0,0 +1,25 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Rumble for Linux input devices with force feedback support.
+//!
+//! Gamepads on Linux expose force feedback through `/dev/input/event*` nodes. The evdev
+//! communication manager finds devices that support `FF_RUMBLE` effects, and exposes the strong and
+//! weak rumble magnitudes as two vibrators through the `evdev-rumble` protocol.
+//!
+//! Access to input devices goes through the [EvdevBackend] trait, so the manager can be driven by
+//! something other than real hardware (for instance, in tests). On Linux, the default backend talks
+//! to the kernel event devices directly.
+
+mod evdev_backend;
+mod evdev_comm_manager;
+mod evdev_hardware;
+#[cfg(target_os = "linux")]
+pub use evdev_backend::SystemEvdevBackend;
+pub use evdev_backend::{EvdevBackend, EvdevDeviceInfo, EvdevRumbleDevice};
+pub use evdev_comm_manager::{EvdevCommunicationManager, EvdevCommunicationManagerBuilder};
+pub use evdev_hardware::EvdevHardware;
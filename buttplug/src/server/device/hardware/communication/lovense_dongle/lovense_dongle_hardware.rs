@@ -15,7 +15,7 @@ use super::lovense_dongle_messages::{
 use crate::{
   core::{errors::ButtplugDeviceError, message::Endpoint},
   server::device::{
-    configuration::{BluetoothLESpecifier, ProtocolCommunicationSpecifier},
+    configuration::{BluetoothLEAdvertisement, ProtocolCommunicationSpecifier},
     hardware::{
       GenericHardwareSpecializer,
       Hardware,
@@ -74,8 +74,8 @@ impl LovenseDongleHardwareConnector {
       // when we get the device, we can set up as we need.
       //
       // Hacky, but it works.
-      specifier: ProtocolCommunicationSpecifier::BluetoothLE(
-        BluetoothLESpecifier::new_from_device("LVS-DongleDevice", &HashMap::new(), &[]),
+      specifier: ProtocolCommunicationSpecifier::BluetoothLEAdvertisement(
+        BluetoothLEAdvertisement::new("LVS-DongleDevice", &HashMap::new(), &[]),
       ),
       id: id.to_string(),
       device_outgoing,
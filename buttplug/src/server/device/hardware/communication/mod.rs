@@ -6,8 +6,12 @@
 // for full license information.
 
 // Network DCMs work on all platforms
+#[cfg(feature = "federation-manager")]
+pub mod buttplug_federation;
 #[cfg(feature = "lovense-connect-service-manager")]
 pub mod lovense_connect_service;
+#[cfg(feature = "network-manager")]
+pub mod network;
 #[cfg(feature = "websocket-server-manager")]
 pub mod websocket_server;
 
@@ -46,6 +50,11 @@ pub mod hid;
 #[cfg(all(feature = "xinput-manager", target_os = "windows"))]
 pub mod xinput;
 
+// Evdev access sits behind a backend trait, so the manager builds everywhere but only has a system
+// backend on Linux.
+#[cfg(feature = "evdev-manager")]
+pub mod evdev;
+
 use crate::{
   core::{errors::ButtplugDeviceError, ButtplugResultFuture},
   server::device::hardware::HardwareConnector,
@@ -114,6 +123,9 @@ pub enum HardwareSpecificError {
   ))]
   #[error("Serial error: {0}")]
   SerialError(String),
+  #[cfg(feature = "evdev-manager")]
+  #[error("Evdev error: {0}")]
+  EvdevError(String),
 }
 
 #[async_trait]
@@ -0,0 +1,35 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Connect to DIY devices that listen on TCP or UDP ports.
+//!
+//! Unlike the websocket server communication manager, where devices connect to us, the network
+//! communication manager connects out to devices. Devices can either be listed ahead of time, or
+//! announce themselves while scanning by sending a JSON packet to the discovery port over UDP
+//! (usually as a broadcast):
+//!
+//! ```json
+//! { "identifier": "DIY Vibe", "port": 8000, "transport": "tcp" }
+//! ```
+//!
+//! The device address is the source address of the announcement, with the port replaced by the one
+//! in the packet. In both cases the identifier is matched against `network` specifiers in the
+//! device configuration to select a protocol.
+//!
+//! Writes go straight to the socket, and data received from the device is available through reads
+//! and subscriptions on the Rx endpoint.
+
+mod network_comm_manager;
+mod network_hardware;
+pub use network_comm_manager::{
+  NetworkCommunicationManager,
+  NetworkCommunicationManagerBuilder,
+  NetworkDeviceAnnouncement,
+  NetworkDeviceInfo,
+  NetworkTransport,
+};
+pub use network_hardware::NetworkHardware;
@@ -0,0 +1,296 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::network_hardware::NetworkHardwareConnector;
+use crate::{
+  core::ButtplugResultFuture,
+  server::device::hardware::communication::{
+    HardwareCommunicationManager,
+    HardwareCommunicationManagerBuilder,
+    HardwareCommunicationManagerEvent,
+  },
+  util::async_manager,
+};
+use futures::FutureExt;
+use getset::{CopyGetters, Getters};
+use serde::{Deserialize, Serialize};
+use std::{
+  fmt,
+  net::{Ipv4Addr, SocketAddr},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+};
+use tokio::{
+  net::UdpSocket,
+  sync::{mpsc::Sender, Mutex},
+};
+use tokio_util::sync::CancellationToken;
+
+/// Socket type used to talk to a network device.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkTransport {
+  Tcp,
+  Udp,
+}
+
+impl fmt::Display for NetworkTransport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      NetworkTransport::Tcp => write!(f, "tcp"),
+      NetworkTransport::Udp => write!(f, "udp"),
+    }
+  }
+}
+
+/// Where to find a network device, and the identifier used to match it to a protocol.
+#[derive(Debug, Clone, Getters, CopyGetters)]
+pub struct NetworkDeviceInfo {
+  #[getset(get = "pub")]
+  identifier: String,
+  #[getset(get_copy = "pub")]
+  address: SocketAddr,
+  #[getset(get_copy = "pub")]
+  transport: NetworkTransport,
+}
+
+impl NetworkDeviceInfo {
+  pub fn new(identifier: &str, address: SocketAddr, transport: NetworkTransport) -> Self {
+    Self {
+      identifier: identifier.to_owned(),
+      address,
+      transport,
+    }
+  }
+
+  /// Address reported to the device manager, which includes the transport so the same host and
+  /// port can be used over both TCP and UDP.
+  pub fn device_address(&self) -> String {
+    format!("{}://{}", self.transport, self.address)
+  }
+}
+
+// Packet format received on the discovery port.
+#[derive(Serialize, Deserialize, Debug, Clone, Getters, CopyGetters)]
+pub struct NetworkDeviceAnnouncement {
+  #[getset(get = "pub")]
+  identifier: String,
+  #[getset(get_copy = "pub")]
+  port: u16,
+  #[getset(get_copy = "pub")]
+  transport: NetworkTransport,
+}
+
+impl NetworkDeviceAnnouncement {
+  pub fn new(identifier: &str, port: u16, transport: NetworkTransport) -> Self {
+    Self {
+      identifier: identifier.to_owned(),
+      port,
+      transport,
+    }
+  }
+}
+
+#[derive(Default, Clone)]
+pub struct NetworkCommunicationManagerBuilder {
+  devices: Vec<NetworkDeviceInfo>,
+  discovery_port: Option<u16>,
+}
+
+impl NetworkCommunicationManagerBuilder {
+  /// Add a device to connect to whenever scanning is started.
+  pub fn device(mut self, device: NetworkDeviceInfo) -> Self {
+    self.devices.push(device);
+    self
+  }
+
+  /// Listen for device announcements on this UDP port while scanning.
+  pub fn discovery_port(mut self, port: u16) -> Self {
+    self.discovery_port = Some(port);
+    self
+  }
+}
+
+impl HardwareCommunicationManagerBuilder for NetworkCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: Sender<HardwareCommunicationManagerEvent>,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    Box::new(NetworkCommunicationManager::new(
+      sender,
+      self.devices.clone(),
+      self.discovery_port,
+    ))
+  }
+}
+
+pub struct NetworkCommunicationManager {
+  sender: Sender<HardwareCommunicationManagerEvent>,
+  devices: Vec<NetworkDeviceInfo>,
+  discovery_port: Option<u16>,
+  scanning: Arc<AtomicBool>,
+  scanning_token: Option<CancellationToken>,
+  // Held by the running scan task, so a new scan doesn't try to bind the discovery port before a
+  // stopped one has let go of it.
+  scan_lock: Arc<Mutex<()>>,
+}
+
+impl NetworkCommunicationManager {
+  fn new(
+    sender: Sender<HardwareCommunicationManagerEvent>,
+    devices: Vec<NetworkDeviceInfo>,
+    discovery_port: Option<u16>,
+  ) -> Self {
+    Self {
+      sender,
+      devices,
+      discovery_port,
+      scanning: Arc::new(AtomicBool::new(false)),
+      scanning_token: None,
+      scan_lock: Arc::new(Mutex::new(())),
+    }
+  }
+}
+
+async fn send_device_found(
+  sender: &Sender<HardwareCommunicationManagerEvent>,
+  device: NetworkDeviceInfo,
+) -> bool {
+  debug!(
+    "Network device {} found at {}",
+    device.identifier(),
+    device.device_address()
+  );
+  sender
+    .send(HardwareCommunicationManagerEvent::DeviceFound {
+      name: device.identifier().clone(),
+      address: device.device_address(),
+      creator: Box::new(NetworkHardwareConnector::new(device)),
+    })
+    .await
+    .is_ok()
+}
+
+async fn run_discovery_loop(
+  port: u16,
+  sender: &Sender<HardwareCommunicationManagerEvent>,
+  token: CancellationToken,
+) {
+  let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).await {
+    Ok(socket) => socket,
+    Err(err) => {
+      error!(
+        "Cannot bind network device discovery port {}: {:?}",
+        port, err
+      );
+      return;
+    }
+  };
+  let mut buf = vec![0u8; 1024];
+  loop {
+    let (len, source) = select! {
+      result = socket.recv_from(&mut buf).fuse() => match result {
+        Ok(result) => result,
+        Err(err) => {
+          error!("Error receiving network device announcement: {:?}", err);
+          continue;
+        }
+      },
+      _ = token.cancelled().fuse() => return,
+    };
+    let announcement: NetworkDeviceAnnouncement = match serde_json::from_slice(&buf[..len]) {
+      Ok(announcement) => announcement,
+      Err(err) => {
+        debug!("Ignoring invalid announcement from {}: {}", source, err);
+        continue;
+      }
+    };
+    let device = NetworkDeviceInfo::new(
+      announcement.identifier(),
+      SocketAddr::new(source.ip(), announcement.port()),
+      announcement.transport(),
+    );
+    if !send_device_found(sender, device).await {
+      error!("Device manager disappeared, exiting.");
+      return;
+    }
+  }
+}
+
+impl HardwareCommunicationManager for NetworkCommunicationManager {
+  fn name(&self) -> &'static str {
+    "NetworkCommunicationManager"
+  }
+
+  fn start_scanning(&mut self) -> ButtplugResultFuture {
+    if self.scanning.swap(true, Ordering::SeqCst) {
+      return async move { Ok(()) }.boxed();
+    }
+    debug!("Network manager scanning for devices.");
+    let token = CancellationToken::new();
+    let child_token = token.child_token();
+    self.scanning_token = Some(token);
+    let sender = self.sender.clone();
+    let devices = self.devices.clone();
+    let discovery_port = self.discovery_port;
+    let scanning = self.scanning.clone();
+    let scan_lock = self.scan_lock.clone();
+    async_manager::spawn(async move {
+      let _scan_guard = scan_lock.lock().await;
+      // Configured devices are reported every scan, the device manager will ignore any we're
+      // already connected to. Connection happens when the device manager asks for it.
+      for device in devices {
+        if !send_device_found(&sender, device).await {
+          error!("Device manager disappeared, exiting.");
+          return;
+        }
+      }
+      if let Some(port) = discovery_port {
+        run_discovery_loop(port, &sender, child_token.clone()).await;
+      }
+      // A stopped scan was already marked as finished, and a new one may have started since.
+      if !child_token.is_cancelled() {
+        scanning.store(false, Ordering::SeqCst);
+      }
+      if sender
+        .send(HardwareCommunicationManagerEvent::ScanningFinished)
+        .await
+        .is_err()
+      {
+        error!("Device manager disappeared, exiting.");
+      }
+    });
+    async move { Ok(()) }.boxed()
+  }
+
+  fn stop_scanning(&mut self) -> ButtplugResultFuture {
+    if let Some(token) = self.scanning_token.take() {
+      token.cancel();
+    }
+    self.scanning.store(false, Ordering::SeqCst);
+    async move { Ok(()) }.boxed()
+  }
+
+  fn scanning_status(&self) -> bool {
+    self.scanning.load(Ordering::SeqCst)
+  }
+
+  // No restrictions since this is network not hardware.
+  fn can_scan(&self) -> bool {
+    true
+  }
+}
+
+impl Drop for NetworkCommunicationManager {
+  fn drop(&mut self) {
+    if let Some(token) = self.scanning_token.take() {
+      token.cancel();
+    }
+  }
+}
@@ -0,0 +1,333 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use super::network_comm_manager::{NetworkDeviceInfo, NetworkTransport};
+use crate::{
+  core::{errors::ButtplugDeviceError, message::Endpoint},
+  server::device::{
+    configuration::{NetworkSpecifier, ProtocolCommunicationSpecifier},
+    hardware::{
+      GenericHardwareSpecializer,
+      Hardware,
+      HardwareConnector,
+      HardwareEvent,
+      HardwareInternal,
+      HardwareReadCmd,
+      HardwareReading,
+      HardwareSpecializer,
+      HardwareSubscribeCmd,
+      HardwareUnsubscribeCmd,
+      HardwareWriteCmd,
+    },
+  },
+  util::async_manager,
+};
+use async_trait::async_trait;
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+};
+use std::{
+  fmt::{self, Debug},
+  net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::{TcpStream, UdpSocket},
+  sync::{
+    broadcast,
+    mpsc::{channel, Receiver, Sender},
+  },
+};
+use tokio_util::sync::CancellationToken;
+
+// Shared state for the connection loops, which own the socket.
+struct NetworkConnectionContext {
+  address: String,
+  event_sender: broadcast::Sender<HardwareEvent>,
+  incoming_broadcaster: broadcast::Sender<Vec<u8>>,
+  subscribed: Arc<AtomicBool>,
+}
+
+impl NetworkConnectionContext {
+  fn received(&self, data: Vec<u8>) {
+    if self.subscribed.load(Ordering::SeqCst) {
+      // We don't really care if there's no one to send the notification to here.
+      let _ = self.event_sender.send(HardwareEvent::Notification(
+        self.address.clone(),
+        Endpoint::Rx,
+        data.clone(),
+      ));
+    }
+    // If no one is reading, ignore output.
+    let _ = self.incoming_broadcaster.send(data);
+  }
+}
+
+async fn run_tcp_connection_loop(
+  context: NetworkConnectionContext,
+  mut stream: TcpStream,
+  mut outgoing_receiver: Receiver<Vec<u8>>,
+  token: CancellationToken,
+) {
+  let mut buf = vec![0u8; 1024];
+  loop {
+    select! {
+      msg = outgoing_receiver.recv().fuse() => {
+        let Some(data) = msg else {
+          info!("Network hardware dropped, closing TCP connection.");
+          break;
+        };
+        if let Err(err) = stream.write_all(&data).await {
+          error!("Cannot write to TCP device, considering connection closed: {:?}", err);
+          break;
+        }
+      }
+      result = stream.read(&mut buf).fuse() => match result {
+        Ok(0) => {
+          info!("TCP device closed connection.");
+          break;
+        }
+        Ok(len) => context.received(buf[..len].to_vec()),
+        Err(err) => {
+          error!("Error reading from TCP device, considering connection closed: {:?}", err);
+          break;
+        }
+      },
+      _ = token.cancelled().fuse() => break,
+    }
+  }
+  // Drop the error if no one receives the message, we're exiting anyways.
+  let _ = context
+    .event_sender
+    .send(HardwareEvent::Disconnected(context.address.clone()));
+  debug!("Exiting TCP device control loop.");
+}
+
+async fn run_udp_connection_loop(
+  context: NetworkConnectionContext,
+  socket: UdpSocket,
+  mut outgoing_receiver: Receiver<Vec<u8>>,
+  token: CancellationToken,
+) {
+  let mut buf = vec![0u8; 1024];
+  loop {
+    select! {
+      msg = outgoing_receiver.recv().fuse() => {
+        let Some(data) = msg else {
+          info!("Network hardware dropped, closing UDP socket.");
+          break;
+        };
+        if let Err(err) = socket.send(&data).await {
+          error!("Cannot send to UDP device: {:?}", err);
+        }
+      }
+      result = socket.recv(&mut buf).fuse() => match result {
+        Ok(len) => context.received(buf[..len].to_vec()),
+        // UDP has no connection to lose, so errors (usually ICMP port unreachable replies to a
+        // previous send) are not fatal.
+        Err(err) => debug!("Error receiving from UDP device: {:?}", err),
+      },
+      _ = token.cancelled().fuse() => break,
+    }
+  }
+  let _ = context
+    .event_sender
+    .send(HardwareEvent::Disconnected(context.address.clone()));
+  debug!("Exiting UDP device control loop.");
+}
+
+pub struct NetworkHardwareConnector {
+  info: NetworkDeviceInfo,
+}
+
+impl NetworkHardwareConnector {
+  pub(super) fn new(info: NetworkDeviceInfo) -> Self {
+    Self { info }
+  }
+}
+
+impl Debug for NetworkHardwareConnector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("NetworkHardwareConnector")
+      .field("info", &self.info)
+      .finish()
+  }
+}
+
+#[async_trait]
+impl HardwareConnector for NetworkHardwareConnector {
+  fn specifier(&self) -> ProtocolCommunicationSpecifier {
+    ProtocolCommunicationSpecifier::Network(NetworkSpecifier::new(&[self
+      .info
+      .identifier()
+      .clone()]))
+  }
+
+  async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
+    let address = self.info.device_address();
+    let (outgoing_sender, outgoing_receiver) = channel(256);
+    let (incoming_broadcaster, _) = broadcast::channel(256);
+    let (event_sender, _) = broadcast::channel(256);
+    let subscribed = Arc::new(AtomicBool::new(false));
+    let token = CancellationToken::new();
+    let context = NetworkConnectionContext {
+      address: address.clone(),
+      event_sender: event_sender.clone(),
+      incoming_broadcaster: incoming_broadcaster.clone(),
+      subscribed: subscribed.clone(),
+    };
+    let connection_error = |err: std::io::Error| {
+      ButtplugDeviceError::DeviceConnectionError(format!(
+        "Cannot connect to network device {}: {}",
+        address, err
+      ))
+    };
+    match self.info.transport() {
+      NetworkTransport::Tcp => {
+        let stream = TcpStream::connect(self.info.address())
+          .await
+          .map_err(connection_error)?;
+        async_manager::spawn(run_tcp_connection_loop(
+          context,
+          stream,
+          outgoing_receiver,
+          token.child_token(),
+        ));
+      }
+      NetworkTransport::Udp => {
+        let local_address = if self.info.address().is_ipv4() {
+          SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        } else {
+          SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        };
+        let socket = UdpSocket::bind(local_address)
+          .await
+          .map_err(connection_error)?;
+        socket
+          .connect(self.info.address())
+          .await
+          .map_err(connection_error)?;
+        async_manager::spawn(run_udp_connection_loop(
+          context,
+          socket,
+          outgoing_receiver,
+          token.child_token(),
+        ));
+      }
+    }
+    let hardware_internal = NetworkHardware {
+      outgoing_sender,
+      incoming_broadcaster,
+      event_sender,
+      subscribed,
+      connection_token: token,
+    };
+    let hardware = Hardware::new(
+      self.info.identifier(),
+      &address,
+      &[Endpoint::Rx, Endpoint::Tx],
+      Box::new(hardware_internal),
+    );
+    Ok(Box::new(GenericHardwareSpecializer::new(hardware)))
+  }
+}
+
+pub struct NetworkHardware {
+  outgoing_sender: Sender<Vec<u8>>,
+  incoming_broadcaster: broadcast::Sender<Vec<u8>>,
+  event_sender: broadcast::Sender<HardwareEvent>,
+  subscribed: Arc<AtomicBool>,
+  connection_token: CancellationToken,
+}
+
+impl HardwareInternal for NetworkHardware {
+  fn event_stream(&self) -> broadcast::Receiver<HardwareEvent> {
+    self.event_sender.subscribe()
+  }
+
+  fn disconnect(&self) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    self.connection_token.cancel();
+    future::ready(Ok(())).boxed()
+  }
+
+  // Network devices don't have addressable values, so reads return the next packet received from
+  // the device, waiting up to the read timeout if one is given.
+  fn read_value(
+    &self,
+    msg: &HardwareReadCmd,
+  ) -> BoxFuture<'static, Result<HardwareReading, ButtplugDeviceError>> {
+    let mut receiver = self.incoming_broadcaster.subscribe();
+    let timeout_ms = msg.timeout_ms();
+    async move {
+      let read = async move {
+        receiver.recv().await.map_err(|_| {
+          ButtplugDeviceError::DeviceCommunicationError(
+            "Network device disconnected while reading.".to_owned(),
+          )
+        })
+      };
+      let data = if timeout_ms > 0 {
+        tokio::time::timeout(Duration::from_millis(timeout_ms as u64), read)
+          .await
+          .map_err(|_| {
+            ButtplugDeviceError::DeviceCommunicationError(
+              "Timed out waiting for network device data.".to_owned(),
+            )
+          })??
+      } else {
+        read.await?
+      };
+      Ok(HardwareReading::new(Endpoint::Rx, &data))
+    }
+    .boxed()
+  }
+
+  fn write_value(
+    &self,
+    msg: &HardwareWriteCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    let sender = self.outgoing_sender.clone();
+    let data = msg.data.clone();
+    async move {
+      sender.send(data).await.map_err(|err| {
+        ButtplugDeviceError::DeviceCommunicationError(format!(
+          "Could not write value to network device: {}",
+          err
+        ))
+      })
+    }
+    .boxed()
+  }
+
+  fn subscribe(
+    &self,
+    _msg: &HardwareSubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    self.subscribed.store(true, Ordering::SeqCst);
+    future::ready(Ok(())).boxed()
+  }
+
+  fn unsubscribe(
+    &self,
+    _msg: &HardwareUnsubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    self.subscribed.store(false, Ordering::SeqCst);
+    future::ready(Ok(())).boxed()
+  }
+}
+
+impl Drop for NetworkHardware {
+  fn drop(&mut self) {
+    self.connection_token.cancel();
+  }
+}
@@ -7,7 +7,7 @@ use crate::{
     errors::ButtplugDeviceError,
     message::{Endpoint, RawReadCmd, RawReading, RawSubscribeCmd, RawUnsubscribeCmd, RawWriteCmd},
   },
-  server::device::configuration::ProtocolCommunicationSpecifier,
+  server::device::configuration::{ProtocolCommunicationSpecifier, ProtocolDeviceAttributes},
 };
 use async_trait::async_trait;
 use futures::future::BoxFuture;
@@ -242,10 +242,12 @@ pub struct Hardware {
   /// Communication endpoints
   endpoints: Vec<Endpoint>,
   /// Internal implementation details
-  internal_impl: Box<dyn HardwareInternal>,
+  internal_impl: Arc<dyn HardwareInternal>,
   /// Requires a keepalive signal to be sent by the Server Device class
   #[getset(get_copy = "pub")]
   requires_keepalive: bool,
+  /// Attributes reported by the hardware itself, for hardware that can describe its own features
+  attributes: Option<ProtocolDeviceAttributes>,
   last_write_time: Arc<RwLock<Instant>>,
 }
 
@@ -260,8 +262,23 @@ impl Hardware {
       name: name.to_owned(),
       address: address.to_owned(),
       endpoints: endpoints.into(),
-      internal_impl,
+      internal_impl: internal_impl.into(),
       requires_keepalive: false,
+      attributes: None,
+      last_write_time: Arc::new(RwLock::new(Instant::now())),
+    }
+  }
+
+  /// Create another handle to the same underlying hardware, so it can be handed out again if a
+  /// protocol fails to connect with it.
+  fn share(&self) -> Self {
+    Self {
+      name: self.name.clone(),
+      address: self.address.clone(),
+      endpoints: self.endpoints.clone(),
+      internal_impl: self.internal_impl.clone(),
+      requires_keepalive: self.requires_keepalive,
+      attributes: self.attributes.clone(),
       last_write_time: Arc::new(RwLock::new(Instant::now())),
     }
   }
@@ -274,6 +291,18 @@ impl Hardware {
     self.requires_keepalive = true;
   }
 
+  /// Set the attributes the hardware reports about itself. Used by communication managers that
+  /// learn device features at runtime (i.e. from a remote server) instead of from the device
+  /// configuration file.
+  pub fn set_attributes(&mut self, attributes: ProtocolDeviceAttributes) {
+    self.attributes = Some(attributes);
+  }
+
+  /// Returns the attributes the hardware reports about itself, if any.
+  pub fn attributes(&self) -> Option<&ProtocolDeviceAttributes> {
+    self.attributes.as_ref()
+  }
+
   /// Returns the device name
   pub fn name(&self) -> &str {
     &self.name
@@ -413,14 +442,12 @@ pub trait HardwareSpecializer: Sync + Send {
 
 /// Used in cases where there's nothing to specialize for the protocol.
 pub struct GenericHardwareSpecializer {
-  hardware: Option<Hardware>,
+  hardware: Hardware,
 }
 
 impl GenericHardwareSpecializer {
   pub fn new(hardware: Hardware) -> Self {
-    Self {
-      hardware: Some(hardware),
-    }
+    Self { hardware }
   }
 }
 
@@ -430,6 +457,9 @@ impl HardwareSpecializer for GenericHardwareSpecializer {
     &mut self,
     _: &[ProtocolCommunicationSpecifier],
   ) -> Result<Hardware, ButtplugDeviceError> {
-    Ok(self.hardware.take().expect("This should only be run once"))
+    // Every protocol candidate gets a handle to the same hardware, so a candidate that fails after
+    // specialization doesn't disconnect the device out from under the next one. The connection
+    // closes once the specializer and all handles are dropped.
+    Ok(self.hardware.share())
   }
 }
@@ -0,0 +1,325 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Protocol for devices relayed from another Buttplug server.
+//!
+//! Commands are converted back to their normalized message form and written to the Tx endpoint as
+//! JSON [ButtplugDeviceCommandMessageUnion] messages, which the federation hardware sends on to the
+//! remote server. Sensor readings arrive as JSON [SensorReading] notifications on Rx. Readings for
+//! a [SensorReadCmd](message::SensorReadCmd) carry the id of the command, and readings from
+//! subscriptions have an id of 0.
+
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{
+      self,
+      ActuatorType,
+      ButtplugDeviceCommandMessageUnion,
+      ButtplugMessage,
+      ButtplugServerDeviceMessage,
+      ButtplugServerMessage,
+      Endpoint,
+      RotateCmd,
+      RotationSubcommand,
+      ScalarCmd,
+      ScalarSubcommand,
+      SensorReading,
+    },
+  },
+  server::device::{
+    configuration::{
+      ProtocolAttributesType,
+      ProtocolDeviceAttributes,
+      ServerGenericDeviceMessageAttributes,
+    },
+    hardware::{Hardware, HardwareCommand, HardwareEvent, HardwareWriteCmd},
+    protocol::{
+      generic_protocol_initializer_setup,
+      ProtocolHandler,
+      ProtocolIdentifier,
+      ProtocolInitializer,
+    },
+    ServerDeviceIdentifier,
+  },
+  util::{async_manager, stream::convert_broadcast_receiver_to_stream},
+};
+use async_trait::async_trait;
+use dashmap::DashSet;
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+  StreamExt,
+};
+use std::{pin::Pin, sync::Arc};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+generic_protocol_initializer_setup!(ButtplugFederation, "buttplug-federation");
+
+#[derive(Default)]
+pub struct ButtplugFederationInitializer {}
+
+#[async_trait]
+impl ProtocolInitializer for ButtplugFederationInitializer {
+  async fn initialize(
+    &mut self,
+    hardware: Arc<Hardware>,
+    attributes: &ProtocolDeviceAttributes,
+  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
+    let message_attributes = attributes.message_attributes();
+    let protocol = ButtplugFederation::new(
+      step_counts(message_attributes.scalar_cmd()),
+      step_counts(message_attributes.rotate_cmd()),
+    );
+
+    // Forward readings for subscribed sensors for as long as the hardware is around.
+    let mut hardware_stream = hardware.event_stream();
+    let sender = protocol.event_stream.clone();
+    let sensors = protocol.subscribed_sensors.clone();
+    async_manager::spawn(async move {
+      while let Ok(event) = hardware_stream.recv().await {
+        match event {
+          HardwareEvent::Notification(_, Endpoint::Rx, data) => {
+            let reading = match serde_json::from_slice::<SensorReading>(&data) {
+              Ok(reading) => reading,
+              Err(err) => {
+                error!("Cannot parse federated sensor reading: {}", err);
+                continue;
+              }
+            };
+            if reading.id() != 0 || !sensors.contains(&reading.sensor_index()) {
+              continue;
+            }
+            // The reading still has the remote device index, which the device manager replaces.
+            // No receivers just means nothing is listening to the device yet.
+            let _ = sender.send(reading.into());
+          }
+          HardwareEvent::Disconnected(_) => return,
+          _ => {}
+        }
+      }
+    });
+
+    Ok(Arc::new(protocol))
+  }
+}
+
+pub struct ButtplugFederation {
+  scalar_step_counts: Vec<u32>,
+  rotate_step_counts: Vec<u32>,
+  subscribed_sensors: Arc<DashSet<u32>>,
+  event_stream: broadcast::Sender<ButtplugServerDeviceMessage>,
+}
+
+impl ButtplugFederation {
+  fn new(scalar_step_counts: Vec<u32>, rotate_step_counts: Vec<u32>) -> Self {
+    let (sender, _) = broadcast::channel(256);
+    Self {
+      scalar_step_counts,
+      rotate_step_counts,
+      subscribed_sensors: Arc::new(DashSet::new()),
+      event_stream: sender,
+    }
+  }
+
+  fn remote_command(
+    &self,
+    message: ButtplugDeviceCommandMessageUnion,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    Ok(vec![HardwareWriteCmd::new(
+      Endpoint::Tx,
+      serde_json::to_vec(&message).expect("Type is always serializable"),
+      false,
+    )
+    .into()])
+  }
+}
+
+fn step_counts(attributes: &Option<Vec<ServerGenericDeviceMessageAttributes>>) -> Vec<u32> {
+  attributes
+    .iter()
+    .flatten()
+    .map(|attr| attr.step_count())
+    .collect()
+}
+
+// Steps were generated from the remote attributes, so going back to a float is lossless.
+fn step_to_value(step_counts: &[u32], index: usize, step: u32) -> f64 {
+  let step_count = step_counts.get(index).copied().unwrap_or(1).max(1);
+  step as f64 / step_count as f64
+}
+
+impl ProtocolHandler for ButtplugFederation {
+  fn handle_scalar_cmd(
+    &self,
+    commands: &[Option<(ActuatorType, u32)>],
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    let scalars = commands
+      .iter()
+      .enumerate()
+      .filter_map(|(index, command)| {
+        command.map(|(actuator, step)| {
+          ScalarSubcommand::new(
+            index as u32,
+            step_to_value(&self.scalar_step_counts, index, step),
+            actuator,
+          )
+        })
+      })
+      .collect();
+    self.remote_command(ScalarCmd::new(0, scalars).into())
+  }
+
+  fn handle_rotate_cmd(
+    &self,
+    commands: &[Option<(u32, bool)>],
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    let rotations = commands
+      .iter()
+      .enumerate()
+      .filter_map(|(index, command)| {
+        command.map(|(step, clockwise)| {
+          RotationSubcommand::new(
+            index as u32,
+            step_to_value(&self.rotate_step_counts, index, step),
+            clockwise,
+          )
+        })
+      })
+      .collect();
+    self.remote_command(RotateCmd::new(0, rotations).into())
+  }
+
+  fn handle_linear_cmd(
+    &self,
+    message: message::LinearCmd,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    self.remote_command(message.into())
+  }
+
+  fn handle_sensor_read_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: message::SensorReadCmd,
+  ) -> BoxFuture<'_, Result<ButtplugServerMessage, ButtplugDeviceError>> {
+    // Subscribe before sending, as the hardware sends the reading before the write finishes.
+    let mut hardware_stream = device.event_stream();
+    let commands = self.remote_command(message.clone().into());
+    async move {
+      for command in commands? {
+        device.parse_message(&command).await?;
+      }
+      loop {
+        match hardware_stream.recv().await {
+          Ok(HardwareEvent::Notification(_, Endpoint::Rx, data)) => {
+            match serde_json::from_slice::<SensorReading>(&data) {
+              Ok(reading) if reading.id() == message.id() => return Ok(reading.into()),
+              Ok(_) => {}
+              Err(err) => error!("Cannot parse federated sensor reading: {}", err),
+            }
+          }
+          Ok(HardwareEvent::Disconnected(_)) | Err(RecvError::Closed) => {
+            return Err(ButtplugDeviceError::DeviceNotConnected(
+              "Federated device disconnected during sensor read".to_owned(),
+            ))
+          }
+          Ok(_) | Err(RecvError::Lagged(_)) => {}
+        }
+      }
+    }
+    .boxed()
+  }
+
+  fn handle_sensor_subscribe_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: message::SensorSubscribeCmd,
+  ) -> BoxFuture<'_, Result<ButtplugServerMessage, ButtplugDeviceError>> {
+    if self.subscribed_sensors.contains(message.sensor_index()) {
+      return future::ready(Ok(message::Ok::new(message.id()).into())).boxed();
+    }
+    let commands = self.remote_command(message.clone().into());
+    let sensors = self.subscribed_sensors.clone();
+    async move {
+      for command in commands? {
+        device.parse_message(&command).await?;
+      }
+      sensors.insert(*message.sensor_index());
+      Ok(message::Ok::new(message.id()).into())
+    }
+    .boxed()
+  }
+
+  fn handle_sensor_unsubscribe_cmd(
+    &self,
+    device: Arc<Hardware>,
+    message: message::SensorUnsubscribeCmd,
+  ) -> BoxFuture<'_, Result<ButtplugServerMessage, ButtplugDeviceError>> {
+    if !self.subscribed_sensors.contains(message.sensor_index()) {
+      return future::ready(Ok(message::Ok::new(message.id()).into())).boxed();
+    }
+    let commands = self.remote_command(message.clone().into());
+    let sensors = self.subscribed_sensors.clone();
+    async move {
+      sensors.remove(message.sensor_index());
+      for command in commands? {
+        device.parse_message(&command).await?;
+      }
+      Ok(message::Ok::new(message.id()).into())
+    }
+    .boxed()
+  }
+
+  fn event_stream(
+    &self,
+  ) -> Pin<Box<dyn futures::Stream<Item = ButtplugServerDeviceMessage> + Send>> {
+    convert_broadcast_receiver_to_stream(self.event_stream.subscribe()).boxed()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn written_message(commands: Vec<HardwareCommand>) -> ButtplugDeviceCommandMessageUnion {
+    assert_eq!(commands.len(), 1);
+    if let HardwareCommand::Write(cmd) = &commands[0] {
+      assert_eq!(cmd.endpoint(), Endpoint::Tx);
+      serde_json::from_slice(cmd.data()).expect("Test, assuming infallible.")
+    } else {
+      panic!("Expected a write command, got {:?}", commands[0]);
+    }
+  }
+
+  #[test]
+  fn test_scalar_steps_convert_back_to_values() {
+    let protocol = ButtplugFederation::new(vec![20, 4], vec![]);
+    let commands = protocol
+      .handle_scalar_cmd(&[None, Some((ActuatorType::Oscillate, 1))])
+      .expect("Test, assuming infallible.");
+    assert_eq!(
+      written_message(commands),
+      ScalarCmd::new(
+        0,
+        vec![ScalarSubcommand::new(1, 0.25, ActuatorType::Oscillate)]
+      )
+      .into()
+    );
+  }
+
+  #[test]
+  fn test_rotate_steps_convert_back_to_values() {
+    let protocol = ButtplugFederation::new(vec![], vec![10]);
+    let commands = protocol
+      .handle_rotate_cmd(&[Some((5, false))])
+      .expect("Test, assuming infallible.");
+    assert_eq!(
+      written_message(commands),
+      RotateCmd::new(0, vec![RotationSubcommand::new(0, 0.5, false)]).into()
+    );
+  }
+}
@@ -0,0 +1,48 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{ActuatorType, Endpoint},
+  },
+  server::device::{
+    hardware::{HardwareCommand, HardwareWriteCmd},
+    protocol::{generic_protocol_setup, ProtocolHandler},
+  },
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+
+generic_protocol_setup!(EvdevRumble, "evdev-rumble");
+
+#[derive(Default)]
+pub struct EvdevRumble {}
+
+impl ProtocolHandler for EvdevRumble {
+  fn needs_full_command_set(&self) -> bool {
+    true
+  }
+
+  fn handle_scalar_cmd(
+    &self,
+    cmds: &[Option<(ActuatorType, u32)>],
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    // Rumble effects always carry both magnitudes, so pack the strong and weak motor values
+    // together and let the hardware update the effect in one go.
+    let mut cmd = vec![];
+    for value in cmds.iter().take(2) {
+      let magnitude = value.map(|(_, magnitude)| magnitude).unwrap_or(0);
+      if cmd.write_u16::<LittleEndian>(magnitude as u16).is_err() {
+        return Err(ButtplugDeviceError::ProtocolSpecificError(
+          "EvdevRumble".to_owned(),
+          "Cannot convert rumble magnitude for processing".to_owned(),
+        ));
+      }
+    }
+    Ok(vec![HardwareWriteCmd::new(Endpoint::Tx, cmd, false).into()])
+  }
+}
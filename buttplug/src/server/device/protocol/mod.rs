@@ -16,9 +16,11 @@ pub mod fleshlight_launch_helper;
 pub mod adrienlastic;
 pub mod aneros;
 pub mod ankni;
+pub mod buttplug_federation;
 pub mod buttplug_passthru;
 pub mod cachito;
 pub mod cowgirl;
+pub mod evdev_rumble;
 pub mod foreo;
 pub mod fox;
 pub mod fredorch;
@@ -184,6 +186,10 @@ pub fn get_default_protocol_map() -> HashMap<String, Arc<dyn ProtocolIdentifierF
     adrienlastic::setup::AdrienLasticIdentifierFactory::default(),
   );
   add_to_protocol_map(&mut map, aneros::setup::AnerosIdentifierFactory::default());
+  add_to_protocol_map(
+    &mut map,
+    buttplug_federation::setup::ButtplugFederationIdentifierFactory::default(),
+  );
   add_to_protocol_map(
     &mut map,
     buttplug_passthru::setup::ButtplugPassthruIdentifierFactory::default(),
@@ -215,6 +221,10 @@ pub fn get_default_protocol_map() -> HashMap<String, Arc<dyn ProtocolIdentifierF
   );
 
   add_to_protocol_map(&mut map, ankni::setup::AnkniIdentifierFactory::default());
+  add_to_protocol_map(
+    &mut map,
+    evdev_rumble::setup::EvdevRumbleIdentifierFactory::default(),
+  );
   add_to_protocol_map(&mut map, foreo::setup::ForeoIdentifierFactory::default());
   add_to_protocol_map(&mut map, fox::setup::FoxIdentifierFactory::default());
   add_to_protocol_map(
@@ -463,21 +473,28 @@ fn print_type_of<T>(_: &T) -> &'static str {
 }
 
 pub struct ProtocolSpecializer {
+  protocol_name: String,
   specifiers: Vec<ProtocolCommunicationSpecifier>,
   identifier: Box<dyn ProtocolIdentifier>,
 }
 
 impl ProtocolSpecializer {
   pub fn new(
+    protocol_name: &str,
     specifiers: Vec<ProtocolCommunicationSpecifier>,
     identifier: Box<dyn ProtocolIdentifier>,
   ) -> Self {
     Self {
+      protocol_name: protocol_name.to_owned(),
       specifiers,
       identifier,
     }
   }
 
+  pub fn protocol_name(&self) -> &str {
+    &self.protocol_name
+  }
+
   pub fn specifiers(&self) -> &Vec<ProtocolCommunicationSpecifier> {
     &self.specifiers
   }
@@ -13,7 +13,12 @@ use std::{
 
 use crate::{
   core::{
-    errors::{ButtplugDeviceError, ButtplugError},
+    errors::{
+      ButtplugDeviceError,
+      ButtplugError,
+      ProtocolConnectionAttempt,
+      ProtocolConnectionStage,
+    },
     message::{
       self,
       ActuatorType,
@@ -25,21 +30,31 @@ use crate::{
       ButtplugServerDeviceMessage,
       ButtplugServerMessage,
       Endpoint,
+      LinearCmd,
       RSSILevelReading,
       RawReading,
       RawSubscribeCmd,
+      RotateCmd,
+      RotationSubcommand,
       ScalarCmd,
       ScalarSubcommand,
       SensorDeviceMessageAttributes,
       SensorReadCmd,
       SensorType,
+      VectorSubcommand,
     },
     ButtplugResultFuture,
   },
   server::{
     device::{
       configuration::{DeviceConfigurationManager, ProtocolAttributesType},
-      hardware::{Hardware, HardwareCommand, HardwareConnector, HardwareEvent},
+      hardware::{
+        Hardware,
+        HardwareCommand,
+        HardwareConnector,
+        HardwareEvent,
+        HardwareSpecializer,
+      },
       protocol::ProtocolHandler,
     },
     ButtplugServerResultFuture,
@@ -55,7 +70,11 @@ use tokio::sync::RwLock;
 use tokio_stream::StreamExt;
 
 use super::{
-  configuration::{ProtocolDeviceAttributes, ServerDeviceMessageAttributes},
+  configuration::{
+    ProtocolDeviceAttributes,
+    ServerDeviceMessageAttributes,
+    ServerGenericDeviceMessageAttributes,
+  },
   hardware::HardwareWriteCmd,
   protocol::{
     generic_command_manager::GenericCommandManager,
@@ -98,6 +117,57 @@ impl ServerDeviceIdentifier {
   }
 }
 
+/// Run a single protocol candidate through specialization, identification and initialization.
+async fn connect_with_protocol(
+  device_config_manager: &DeviceConfigurationManager,
+  hardware_specializer: &mut dyn HardwareSpecializer,
+  protocol_specializer: ProtocolSpecializer,
+) -> Result<ServerDevice, (ProtocolConnectionStage, ButtplugDeviceError)> {
+  let hardware = Arc::new(
+    hardware_specializer
+      .specialize(protocol_specializer.specifiers())
+      .await
+      .map_err(|e| (ProtocolConnectionStage::Specialization, e))?,
+  );
+
+  let mut protocol_identifier_stage = protocol_specializer.identify();
+  let (identifier, mut protocol_initializer) = protocol_identifier_stage
+    .identify(hardware.clone())
+    .await
+    .map_err(|e| (ProtocolConnectionStage::Identification, e))?;
+
+  // Check in the DeviceConfigurationManager to make sure we have attributes
+  // for this device. Hardware that reports its own attributes (i.e. devices on
+  // remote servers) only needs user configurations applied.
+  let attrs = if let Some(hardware_attributes) = hardware.attributes() {
+    Some(device_config_manager.hardware_device_attributes(
+      &identifier,
+      hardware_attributes,
+      &hardware.endpoints(),
+    ))
+  } else {
+    device_config_manager.protocol_device_attributes(&identifier, &hardware.endpoints())
+  }
+  .ok_or_else(|| {
+    (
+      ProtocolConnectionStage::AttributeLookup,
+      ButtplugDeviceError::DeviceConfigurationError(format!(
+        "No viable protocol attributes for hardware {:?}.",
+        identifier
+      )),
+    )
+  })?;
+
+  // If we have attributes, go ahead and initialize, handing us back our hardware instance that
+  // is now ready to use with the protocol handler.
+  let handler = protocol_initializer
+    .initialize(hardware.clone(), &attrs)
+    .await
+    .map_err(|e| (ProtocolConnectionStage::Initialization, e))?;
+
+  Ok(ServerDevice::new(identifier, handler, hardware, &attrs))
+}
+
 pub(super) async fn build_server_device(
   device_config_manager: Arc<DeviceConfigurationManager>,
   mut hardware_connector: Box<dyn HardwareConnector>,
@@ -112,63 +182,47 @@ pub(super) async fn build_server_device(
   // info to actually do something after we connect. So go ahead and connect.
   let mut hardware_specializer = hardware_connector.connect().await?;
 
-  // We can't run these in parallel because we need to only accept one specializer.
-  let mut protocol_identifier = None;
-  let mut hardware_out = None;
+  // Protocol specializers arrive in priority order. We can't run these in parallel because we need
+  // to only accept one protocol, so try them one at a time, falling back to the next candidate if
+  // any stage fails. Note that a failed identify/initialize may have already sent commands to the
+  // device, so later candidates will need to be able to cope with that.
+  let mut attempts = vec![];
+  let mut connected_device = None;
   for protocol_specializer in protocol_specializers {
-    if let Ok(specialized_hardware) = hardware_specializer
-      .specialize(protocol_specializer.specifiers())
-      .await
+    let protocol = protocol_specializer.protocol_name().to_owned();
+    info!("Attempting to connect device using protocol {}.", protocol);
+    match connect_with_protocol(
+      &device_config_manager,
+      hardware_specializer.as_mut(),
+      protocol_specializer,
+    )
+    .await
     {
-      protocol_identifier = Some(protocol_specializer.identify());
-      hardware_out = Some(specialized_hardware);
-      break;
+      Ok(device) => {
+        info!("Device connected using protocol {}.", protocol);
+        connected_device = Some(device);
+        break;
+      }
+      Err((stage, error)) => {
+        warn!(
+          "Protocol {} failed during {}, trying next candidate: {}",
+          protocol, stage, error
+        );
+        attempts.push(ProtocolConnectionAttempt {
+          protocol,
+          stage,
+          error,
+        });
+      }
     }
   }
 
-  if protocol_identifier.is_none() {
-    return Err(ButtplugDeviceError::DeviceConfigurationError(
-      "No protocols with viable communication matches for hardware.".to_owned(),
-    ));
-  }
-
-  let mut protocol_identifier_stage = protocol_identifier.unwrap();
-  let hardware = Arc::new(hardware_out.unwrap());
-
-  let (identifier, mut protocol_initializer) =
-    protocol_identifier_stage.identify(hardware.clone()).await?;
-
-  // Now we have an identifier. After this point, if anything fails, consider it a complete
-  // connection failure, as identify may have already run commands on the device, and therefore
-  // put it in an unknown state if anything fails.
-
-  // Check in the DeviceConfigurationManager to make sure we have attributes
-  // for this device.
-  let attrs = if let Some(attrs) =
-    device_config_manager.protocol_device_attributes(&identifier, &hardware.endpoints())
-  {
-    attrs
-  } else {
-    return Err(ButtplugDeviceError::DeviceConfigurationError(format!(
-      "No protocols with viable protocol attributes for hardware {:?}.",
-      identifier
-    )));
+  let Some(device) = connected_device else {
+    return Err(ButtplugDeviceError::ProtocolConnectionFailed(attempts));
   };
 
-  // If we have attributes, go ahead and initialize, handing us back our hardware instance that
-  // is now ready to use with the protocol handler.
-
-  // Build the server device and return.
-
-  let handler = protocol_initializer
-    .initialize(hardware.clone(), &attrs)
-    .await?;
-
-  let requires_keepalive = hardware.requires_keepalive();
-  let strategy = handler.keepalive_strategy();
-
-  // We now have fully initialized hardware, return a server device.
-  let device = ServerDevice::new(identifier, handler, hardware, &attrs);
+  let requires_keepalive = device.hardware.requires_keepalive();
+  let strategy = device.handler.keepalive_strategy();
 
   // If we need a keepalive with a packet replay, set this up via stopping the device on connect.
   if requires_keepalive
@@ -449,6 +503,7 @@ impl ServerDevice {
     if let Err(err) = self.supports_message(&command_message) {
       return future::ready(Err(err)).boxed();
     }
+    let command_message = self.apply_output_mappings(command_message);
 
     // If a handler implements handle message, bypass all of our parsing and let it do its own
     // thing. This should be a very rare thing.
@@ -556,6 +611,75 @@ impl ServerDevice {
     }
   }
 
+  /// Applies any user configured output mappings to generic actuator commands. This happens before
+  /// the generic command manager converts values to steps, so mapped values still respect step
+  /// ranges. Features without mappings, or with out of range indexes, are passed through as is.
+  fn apply_output_mappings(
+    &self,
+    command_message: ButtplugDeviceCommandMessageUnion,
+  ) -> ButtplugDeviceCommandMessageUnion {
+    let attributes = self.attributes.message_attributes();
+    let mapping = |features: &Option<Vec<ServerGenericDeviceMessageAttributes>>, index: u32| {
+      features
+        .as_ref()
+        .and_then(|features| features.get(index as usize))
+        .and_then(|feature| feature.output_mapping().clone())
+    };
+    match command_message {
+      ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => {
+        let scalars = msg
+          .scalars()
+          .iter()
+          .map(|cmd| match mapping(attributes.scalar_cmd(), cmd.index()) {
+            Some(mapping) => ScalarSubcommand::new(
+              cmd.index(),
+              mapping.map_level(cmd.scalar()),
+              cmd.actuator_type(),
+            ),
+            None => cmd.clone(),
+          })
+          .collect();
+        let mut mapped_msg = ScalarCmd::new(msg.device_index(), scalars);
+        mapped_msg.set_id(msg.id());
+        mapped_msg.into()
+      }
+      ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => {
+        let rotations = msg
+          .rotations()
+          .iter()
+          .map(|cmd| match mapping(attributes.rotate_cmd(), cmd.index()) {
+            Some(mapping) => {
+              let (speed, clockwise) = mapping.map_rotation(cmd.speed(), cmd.clockwise());
+              RotationSubcommand::new(cmd.index(), speed, clockwise)
+            }
+            None => cmd.clone(),
+          })
+          .collect();
+        let mut mapped_msg = RotateCmd::new(msg.device_index(), rotations);
+        mapped_msg.set_id(msg.id());
+        mapped_msg.into()
+      }
+      ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => {
+        let vectors = msg
+          .vectors()
+          .iter()
+          .map(|cmd| match mapping(attributes.linear_cmd(), cmd.index()) {
+            Some(mapping) => VectorSubcommand::new(
+              cmd.index(),
+              cmd.duration(),
+              mapping.map_position(cmd.position()),
+            ),
+            None => cmd.clone(),
+          })
+          .collect();
+        let mut mapped_msg = LinearCmd::new(msg.device_index(), vectors);
+        mapped_msg.set_id(msg.id());
+        mapped_msg.into()
+      }
+      msg => msg,
+    }
+  }
+
   fn handle_hardware_commands(&self, commands: Vec<HardwareCommand>) -> ButtplugServerResultFuture {
     let hardware = self.hardware.clone();
     let keepalive_type = self.handler.keepalive_strategy();
@@ -882,3 +1006,180 @@ impl ServerDevice {
     .boxed()
   }
 }
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::server::device::{
+    configuration::{ProtocolCommunicationSpecifier, WebsocketSpecifier},
+    hardware::{
+      GenericHardwareSpecializer,
+      HardwareInternal,
+      HardwareReadCmd,
+      HardwareReading,
+      HardwareSubscribeCmd,
+      HardwareUnsubscribeCmd,
+    },
+    protocol::{
+      GenericProtocolIdentifier,
+      ProtocolIdentifier,
+      ProtocolInitializer,
+      ProtocolSpecializer,
+    },
+  };
+  use async_trait::async_trait;
+  use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+  use tokio::sync::broadcast;
+
+  struct CountingHardware {
+    event_sender: broadcast::Sender<HardwareEvent>,
+    writes: Arc<AtomicUsize>,
+    dropped: Arc<AtomicBool>,
+  }
+
+  impl Drop for CountingHardware {
+    fn drop(&mut self) {
+      self.dropped.store(true, Ordering::SeqCst);
+    }
+  }
+
+  impl HardwareInternal for CountingHardware {
+    fn disconnect(&self) -> futures::future::BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+      future::ready(Ok(())).boxed()
+    }
+
+    fn event_stream(&self) -> broadcast::Receiver<HardwareEvent> {
+      self.event_sender.subscribe()
+    }
+
+    fn read_value(
+      &self,
+      msg: &HardwareReadCmd,
+    ) -> futures::future::BoxFuture<'static, Result<HardwareReading, ButtplugDeviceError>> {
+      future::ready(Ok(HardwareReading::new(msg.endpoint(), &[]))).boxed()
+    }
+
+    fn write_value(
+      &self,
+      _: &HardwareWriteCmd,
+    ) -> futures::future::BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+      self.writes.fetch_add(1, Ordering::SeqCst);
+      future::ready(Ok(())).boxed()
+    }
+
+    fn subscribe(
+      &self,
+      _: &HardwareSubscribeCmd,
+    ) -> futures::future::BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+      future::ready(Ok(())).boxed()
+    }
+
+    fn unsubscribe(
+      &self,
+      _: &HardwareUnsubscribeCmd,
+    ) -> futures::future::BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+      future::ready(Ok(())).boxed()
+    }
+  }
+
+  #[derive(Debug)]
+  struct CountingHardwareConnector {
+    writes: Arc<AtomicUsize>,
+    dropped: Arc<AtomicBool>,
+  }
+
+  #[async_trait]
+  impl HardwareConnector for CountingHardwareConnector {
+    fn specifier(&self) -> ProtocolCommunicationSpecifier {
+      ProtocolCommunicationSpecifier::Websocket(WebsocketSpecifier::new(&vec![
+        "Counting Device".to_owned()
+      ]))
+    }
+
+    async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
+      let (event_sender, _) = broadcast::channel(256);
+      let mut hardware = Hardware::new(
+        "Counting Device",
+        "counting-device",
+        &[Endpoint::Tx],
+        Box::new(CountingHardware {
+          event_sender,
+          writes: self.writes.clone(),
+          dropped: self.dropped.clone(),
+        }),
+      );
+      hardware.set_attributes(ProtocolDeviceAttributes::new(
+        ProtocolAttributesType::Identifier("Counting Device".to_owned()),
+        Some("Counting Device".to_owned()),
+        None,
+        ServerDeviceMessageAttributes::default(),
+        None,
+      ));
+      Ok(Box::new(GenericHardwareSpecializer::new(hardware)))
+    }
+  }
+
+  struct WriteThenFailIdentifier {}
+
+  #[async_trait]
+  impl ProtocolIdentifier for WriteThenFailIdentifier {
+    async fn identify(
+      &mut self,
+      hardware: Arc<Hardware>,
+    ) -> Result<(ServerDeviceIdentifier, Box<dyn ProtocolInitializer>), ButtplugDeviceError> {
+      hardware
+        .write_value(&HardwareWriteCmd::new(Endpoint::Tx, vec![0], false))
+        .await?;
+      Err(ButtplugDeviceError::ProtocolSpecificError(
+        "write-then-fail".to_owned(),
+        "Identification always fails".to_owned(),
+      ))
+    }
+  }
+
+  #[derive(Default)]
+  struct NoopProtocol {}
+
+  impl ProtocolHandler for NoopProtocol {
+  }
+
+  #[tokio::test]
+  async fn test_generic_hardware_protocol_fallback() {
+    let writes = Arc::new(AtomicUsize::new(0));
+    let dropped = Arc::new(AtomicBool::new(false));
+    let connector = CountingHardwareConnector {
+      writes: writes.clone(),
+      dropped: dropped.clone(),
+    };
+    let specializers = vec![
+      ProtocolSpecializer::new(
+        "write-then-fail",
+        vec![],
+        Box::new(WriteThenFailIdentifier {}),
+      ),
+      ProtocolSpecializer::new(
+        "noop",
+        vec![],
+        Box::new(GenericProtocolIdentifier::new(
+          Arc::new(NoopProtocol::default()),
+          "noop",
+        )),
+      ),
+    ];
+    let device = build_server_device(
+      Arc::new(DeviceConfigurationManager::default()),
+      Box::new(connector),
+      specializers,
+    )
+    .await
+    .expect("Second protocol should connect after the first fails.");
+    assert_eq!(device.identifier().protocol(), "noop");
+    assert_eq!(device.name(), "Counting Device");
+    // The failed candidate talked to the same hardware, and dropping its handle must not have torn
+    // down the hardware the connected device is now using.
+    assert_eq!(writes.load(Ordering::SeqCst), 1);
+    assert!(!dropped.load(Ordering::SeqCst));
+    drop(device);
+    assert!(dropped.load(Ordering::SeqCst));
+  }
+}
@@ -27,6 +27,7 @@ use crate::{
   server::{
     device::{
       configuration::{
+        DeviceConfigurationManager,
         DeviceConfigurationManagerBuilder,
         ProtocolAttributesIdentifier,
         ProtocolCommunicationSpecifier,
@@ -48,7 +49,9 @@ use crate::{
 use dashmap::DashMap;
 use futures::{
   future::{self, FutureExt},
+  stream,
   Stream,
+  StreamExt,
 };
 use getset::Getters;
 use std::{
@@ -59,6 +62,7 @@ use std::{
   },
 };
 use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::CancellationToken;
 
 #[derive(Debug)]
@@ -144,6 +148,13 @@ impl ServerDeviceManagerBuilder {
     self
   }
 
+  pub fn protocol_priority(&mut self, protocol_name: &str, priority: i32) -> &mut Self {
+    self
+      .configuration_manager_builder
+      .protocol_priority(protocol_name, priority);
+    self
+  }
+
   pub fn skip_default_protocols(&mut self) -> &mut Self {
     self.configuration_manager_builder.skip_default_protocols();
     self
@@ -155,16 +166,21 @@ impl ServerDeviceManagerBuilder {
   }
 
   pub fn finish(&mut self) -> Result<ServerDeviceManager, ButtplugServerError> {
-    let config_mgr = self
-      .configuration_manager_builder
-      .finish()
-      .map_err(ButtplugServerError::DeviceConfigurationManagerError)?;
+    let config_mgr = Arc::new(
+      self
+        .configuration_manager_builder
+        .finish()
+        .map_err(ButtplugServerError::DeviceConfigurationManagerError)?,
+    );
 
     let (device_command_sender, device_command_receiver) = mpsc::channel(256);
-    let (device_event_sender, device_event_receiver) = mpsc::channel(256);
     let mut comm_managers: Vec<Box<dyn HardwareCommunicationManager>> = Vec::new();
+    // Each comm manager gets its own channel, so the event loop knows which manager found which
+    // device.
+    let mut device_event_receivers = Vec::new();
     for builder in &mut self.comm_managers {
-      let comm_mgr = builder.finish(device_event_sender.clone());
+      let (device_event_sender, device_event_receiver) = mpsc::channel(256);
+      let comm_mgr = builder.finish(device_event_sender);
 
       if comm_managers
         .iter()
@@ -177,6 +193,12 @@ impl ServerDeviceManagerBuilder {
         );
       }
 
+      let comm_mgr_name = comm_mgr.name();
+      device_event_receivers.push(
+        ReceiverStream::new(device_event_receiver)
+          .map(move |event| (comm_mgr_name, event))
+          .boxed(),
+      );
       comm_managers.push(comm_mgr);
     }
 
@@ -206,35 +228,43 @@ impl ServerDeviceManagerBuilder {
     let loop_cancellation_token = CancellationToken::new();
 
     let output_sender = broadcast::channel(255).0;
+    let report_unsupported_devices = Arc::new(AtomicBool::new(false));
 
     let mut event_loop = ServerDeviceManagerEventLoop::new(
       comm_managers,
-      config_mgr,
+      config_mgr.clone(),
       devices.clone(),
       loop_cancellation_token.child_token(),
       output_sender.clone(),
-      device_event_receiver,
+      stream::select_all(device_event_receivers),
       device_command_receiver,
+      report_unsupported_devices.clone(),
     );
     async_manager::spawn(async move {
       event_loop.run().await;
     });
     Ok(ServerDeviceManager {
+      config: config_mgr,
       devices,
       device_command_sender,
       loop_cancellation_token,
       running: Arc::new(AtomicBool::new(true)),
       output_sender,
+      report_unsupported_devices,
     })
   }
 }
 
 pub struct ServerDeviceManager {
+  config: Arc<DeviceConfigurationManager>,
   devices: Arc<DashMap<u32, Arc<ServerDevice>>>,
   device_command_sender: mpsc::Sender<DeviceManagerCommand>,
   loop_cancellation_token: CancellationToken,
   running: Arc<AtomicBool>,
   output_sender: broadcast::Sender<ButtplugServerMessage>,
+  /// If true, hardware that is found but will not be connected is reported via
+  /// [UnsupportedDeviceFound](crate::core::message::UnsupportedDeviceFound) events.
+  report_unsupported_devices: Arc<AtomicBool>,
 }
 
 impl ServerDeviceManager {
@@ -332,6 +362,12 @@ impl ServerDeviceManager {
       ButtplugDeviceManagerMessageUnion::StopAllDevices(_) => self.stop_all_devices(),
       ButtplugDeviceManagerMessageUnion::StartScanning(_) => self.start_scanning(),
       ButtplugDeviceManagerMessageUnion::StopScanning(_) => self.stop_scanning(),
+      ButtplugDeviceManagerMessageUnion::RequestUnsupportedDeviceReports(msg) => {
+        self
+          .report_unsupported_devices
+          .store(msg.enabled(), Ordering::SeqCst);
+        future::ready(Ok(message::Ok::default().into())).boxed()
+      }
     }
   }
 
@@ -350,6 +386,12 @@ impl ServerDeviceManager {
     }
   }
 
+  /// Returns the device configuration manager used to identify and configure devices for this
+  /// manager.
+  pub fn device_configuration_manager(&self) -> Arc<DeviceConfigurationManager> {
+    self.config.clone()
+  }
+
   pub fn device_info(&self, index: u32) -> Option<ServerDeviceInfo> {
     self.devices.get(&index).map(|device| ServerDeviceInfo {
       identifier: device.value().identifier().clone(),
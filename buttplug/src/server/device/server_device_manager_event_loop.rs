@@ -6,9 +6,17 @@
 // for full license information.
 
 use crate::{
-  core::message::{ButtplugServerMessage, DeviceAdded, DeviceRemoved, ScanningFinished},
+  core::message::{
+    ButtplugDeviceMessage,
+    ButtplugServerMessage,
+    DeviceAdded,
+    DeviceRemoved,
+    ScanningFinished,
+    UnsupportedDeviceFound,
+    UnsupportedDeviceReason,
+  },
   server::device::{
-    configuration::DeviceConfigurationManager,
+    configuration::{DeviceConfigurationManager, ProtocolCommunicationSpecifier},
     hardware::communication::{HardwareCommunicationManager, HardwareCommunicationManagerEvent},
     server_device::build_server_device,
     ServerDevice,
@@ -17,8 +25,16 @@ use crate::{
   util::async_manager,
 };
 use dashmap::{DashMap, DashSet};
-use futures::{future, FutureExt, StreamExt};
-use std::sync::Arc;
+use futures::{
+  future,
+  stream::{BoxStream, SelectAll},
+  FutureExt,
+  StreamExt,
+};
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
 use tokio::sync::{broadcast, mpsc};
 use tokio_util::sync::CancellationToken;
 use tracing;
@@ -36,8 +52,10 @@ pub(super) struct ServerDeviceManagerEventLoop {
   /// whoever owns the Buttplug Server.
   server_sender: broadcast::Sender<ButtplugServerMessage>,
   /// As the device manager owns the Device Communication Managers, it will have
-  /// a receiver that the comm managers all send thru.
-  device_comm_receiver: mpsc::Receiver<HardwareCommunicationManagerEvent>,
+  /// a receiver that the comm managers all send thru, tagged with the name of the
+  /// sending manager.
+  device_comm_receiver:
+    SelectAll<BoxStream<'static, (&'static str, HardwareCommunicationManagerEvent)>>,
   /// Sender for device events, passed to new devices when they are created.
   device_event_sender: mpsc::Sender<ServerDeviceEvent>,
   /// Receiver for device events, which the event loops to handle events.
@@ -51,22 +69,28 @@ pub(super) struct ServerDeviceManagerEventLoop {
   connecting_devices: Arc<DashSet<String>>,
   /// Cancellation token for the event loop
   loop_cancellation_token: CancellationToken,
+  /// If true, emit UnsupportedDeviceFound events for hardware we won't connect to.
+  report_unsupported_devices: Arc<AtomicBool>,
 }
 
 impl ServerDeviceManagerEventLoop {
+  #[allow(clippy::too_many_arguments)]
   pub fn new(
     comm_managers: Vec<Box<dyn HardwareCommunicationManager>>,
-    device_config_manager: DeviceConfigurationManager,
+    device_config_manager: Arc<DeviceConfigurationManager>,
     device_map: Arc<DashMap<u32, Arc<ServerDevice>>>,
     loop_cancellation_token: CancellationToken,
     server_sender: broadcast::Sender<ButtplugServerMessage>,
-    device_comm_receiver: mpsc::Receiver<HardwareCommunicationManagerEvent>,
+    device_comm_receiver: SelectAll<
+      BoxStream<'static, (&'static str, HardwareCommunicationManagerEvent)>,
+    >,
     device_command_receiver: mpsc::Receiver<DeviceManagerCommand>,
+    report_unsupported_devices: Arc<AtomicBool>,
   ) -> Self {
     let (device_event_sender, device_event_receiver) = mpsc::channel(256);
     Self {
       comm_managers,
-      device_config_manager: Arc::new(device_config_manager),
+      device_config_manager,
       server_sender,
       device_map,
       device_comm_receiver,
@@ -77,6 +101,31 @@ impl ServerDeviceManagerEventLoop {
       scanning_started: false,
       connecting_devices: Arc::new(DashSet::new()),
       loop_cancellation_token,
+      report_unsupported_devices,
+    }
+  }
+
+  fn report_unsupported_device(
+    &self,
+    name: &str,
+    address: &str,
+    comm_manager_name: &str,
+    reason: UnsupportedDeviceReason,
+    specifier: &ProtocolCommunicationSpecifier,
+  ) {
+    if !self.report_unsupported_devices.load(Ordering::SeqCst) {
+      return;
+    }
+    let specifier = match serde_json::to_value(specifier) {
+      Ok(value) => value,
+      Err(e) => {
+        error!("Cannot serialize specifier {:?}: {}", specifier, e);
+        return;
+      }
+    };
+    let msg = UnsupportedDeviceFound::new(name, address, comm_manager_name, reason, specifier);
+    if self.server_sender.send(msg.into()).is_err() {
+      debug!("Server not currently available, dropping Unsupported Device Found event.");
     }
   }
 
@@ -118,7 +167,11 @@ impl ServerDeviceManagerEventLoop {
     future::join_all(fut_vec).await;
   }
 
-  async fn handle_device_communication(&mut self, event: HardwareCommunicationManagerEvent) {
+  async fn handle_device_communication(
+    &mut self,
+    comm_manager_name: &'static str,
+    event: HardwareCommunicationManagerEvent,
+  ) {
     match event {
       HardwareCommunicationManagerEvent::ScanningFinished => {
         debug!(
@@ -148,6 +201,13 @@ impl ServerDeviceManagerEventLoop {
         info!("Device {} ({}) found.", name, address);
         // Make sure the device isn't on the deny list, or is on the allow list if anything is on it.
         if !self.device_config_manager.address_allowed(&address) {
+          self.report_unsupported_device(
+            &name,
+            &address,
+            comm_manager_name,
+            UnsupportedDeviceReason::Denied,
+            &creator.specifier(),
+          );
           return;
         }
         debug!(
@@ -189,6 +249,13 @@ impl ServerDeviceManagerEventLoop {
               creator.specifier()
             )
           );
+          self.report_unsupported_device(
+            &name,
+            &address,
+            comm_manager_name,
+            UnsupportedDeviceReason::NoMatchingProtocol,
+            &creator.specifier(),
+          );
           return;
         }
 
@@ -324,7 +391,16 @@ impl ServerDeviceManagerEventLoop {
           }
         }
       }
-      ServerDeviceEvent::Notification(_, message) => {
+      ServerDeviceEvent::Notification(identifier, mut message) => {
+        // Not every notification source knows the index its device was given (raw readings and
+        // federated sensor readings don't), so notifications are addressed here.
+        if let Some(entry) = self
+          .device_map
+          .iter()
+          .find(|entry| *entry.value().identifier() == identifier)
+        {
+          message.set_device_index(*entry.key());
+        }
         if self.server_sender.send(message.into()).is_err() {
           debug!("Server not currently available, dropping Device Added event.");
         }
@@ -336,10 +412,10 @@ impl ServerDeviceManagerEventLoop {
     debug!("Starting Device Manager Loop");
     loop {
       tokio::select! {
-        device_comm_msg = self.device_comm_receiver.recv() => {
-          if let Some(msg) = device_comm_msg {
-            trace!("Got device communication message {:?}", msg);
-            self.handle_device_communication(msg).await;
+        device_comm_msg = self.device_comm_receiver.next() => {
+          if let Some((comm_manager_name, msg)) = device_comm_msg {
+            trace!("Got device communication message {:?} from {}", msg, comm_manager_name);
+            self.handle_device_communication(comm_manager_name, msg).await;
           } else {
             break;
           }
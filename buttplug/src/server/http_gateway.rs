@@ -0,0 +1,500 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! HTTP REST and Server-Sent Events gateway for a [ButtplugServer].
+//!
+//! For programs that can't hold a stateful websocket session (shell scripts, home automation
+//! systems, etc...), the gateway exposes a running server over plain HTTP. The gateway acts as the
+//! server's client: it runs the handshake (and ping, if required) itself, then translates requests
+//! into Buttplug messages. Responses and events are JSON serialized spec messages, the same as would
+//! be sent over a websocket connection.
+//!
+//! | Method | Path                                  | Body                        | Message sent         |
+//! |--------|---------------------------------------|-----------------------------|----------------------|
+//! | GET    | `/devices`                            |                             | RequestDeviceList    |
+//! | GET    | `/devices/:index`                     |                             | RequestDeviceList    |
+//! | POST   | `/devices/:index/scalar`              | Array of ScalarSubcommand   | ScalarCmd            |
+//! | POST   | `/devices/:index/rotate`              | Array of RotationSubcommand | RotateCmd            |
+//! | POST   | `/devices/:index/linear`              | Array of VectorSubcommand   | LinearCmd            |
+//! | POST   | `/devices/:index/stop`                |                             | StopDeviceCmd        |
+//! | POST   | `/devices/:index/sensors/subscribe`   | `SensorIndex`, `SensorType` | SensorSubscribeCmd   |
+//! | POST   | `/devices/:index/sensors/unsubscribe` | `SensorIndex`, `SensorType` | SensorUnsubscribeCmd |
+//! | POST   | `/devices/stop`                       |                             | StopAllDevices       |
+//! | POST   | `/scanning/start`                     |                             | StartScanning        |
+//! | POST   | `/scanning/stop`                      |                             | StopScanning         |
+//! | GET    | `/events`                             |                             |                      |
+//!
+//! `GET /devices/:index` returns the device's entry from the device list. `GET /events` is a
+//! Server-Sent Events stream of DeviceAdded, DeviceRemoved, ScanningFinished and SensorReading
+//! messages, with the message type as the event name. Failed requests return the server's Error
+//! message with a 4xx status.
+//!
+//! If an authenticator is set on the [ButtplugHttpGatewayBuilder], every request must carry an
+//! `Authorization: Bearer <token>` header it accepts, or is rejected with 401 Unauthorized. The
+//! gateway refuses to listen on all interfaces without an authenticator.
+
+use super::{
+  authenticator::{bearer_token, ButtplugServerAuthenticator},
+  ButtplugServer,
+};
+use crate::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError, ButtplugHandshakeError},
+    message::{
+      self,
+      ButtplugClientMessage,
+      ButtplugMessage,
+      ButtplugServerMessage,
+      ButtplugSpecV3ServerMessage,
+      ErrorCode,
+      LinearCmd,
+      RequestDeviceList,
+      RequestServerInfo,
+      RotateCmd,
+      RotationSubcommand,
+      ScalarCmd,
+      ScalarSubcommand,
+      SensorSubscribeCmd,
+      SensorType,
+      SensorUnsubscribeCmd,
+      StartScanning,
+      StopAllDevices,
+      StopDeviceCmd,
+      StopScanning,
+      VectorSubcommand,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    },
+  },
+  util::{async_manager, sleep},
+};
+use axum::{
+  body::Body,
+  extract::{Path, State},
+  http::{
+    header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    Request,
+    StatusCode,
+  },
+  middleware::{self, Next},
+  response::{
+    sse::{Event, KeepAlive, Sse},
+    IntoResponse,
+    Response,
+  },
+  routing::{get, post},
+  Json,
+  Router,
+};
+use futures::{FutureExt, Stream, StreamExt};
+use serde::Deserialize;
+use std::{
+  convert::Infallible,
+  fmt,
+  net::SocketAddr,
+  sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+  },
+  time::Duration,
+};
+use thiserror::Error;
+use tokio_util::sync::CancellationToken;
+
+/// Error enum for the HTTP gateway.
+#[derive(Error, Debug)]
+pub enum ButtplugHttpGatewayError {
+  /// The server rejected the gateway's handshake.
+  #[error("Gateway handshake with server failed: {0}")]
+  HandshakeFailed(ButtplugError),
+  /// The HTTP listener could not be started.
+  #[error("Could not listen on {0}: {1}")]
+  ListenError(SocketAddr, String),
+  /// Listening on anything other than loopback requires an authenticator.
+  #[error("Refusing to listen on {0} without an authenticator")]
+  AuthenticatorRequired(SocketAddr),
+  /// The HTTP server stopped with an error.
+  #[error("HTTP server error: {0}")]
+  ServerError(String),
+}
+
+#[derive(Clone)]
+pub struct ButtplugHttpGatewayBuilder {
+  /// If true, listens all on available interfaces. Otherwise, only listens on 127.0.0.1.
+  listen_on_all_interfaces: bool,
+  /// Port for listening for HTTP connections.
+  port: u16,
+  /// Token presented during the handshake, for servers that require authentication.
+  auth_token: Option<String>,
+  /// If set, HTTP requests must carry a bearer token this accepts.
+  authenticator: Option<Arc<dyn ButtplugServerAuthenticator>>,
+}
+
+impl Default for ButtplugHttpGatewayBuilder {
+  fn default() -> Self {
+    Self {
+      listen_on_all_interfaces: false,
+      port: 12346,
+      auth_token: None,
+      authenticator: None,
+    }
+  }
+}
+
+impl fmt::Debug for ButtplugHttpGatewayBuilder {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // Never print the token into logs.
+    f.debug_struct("ButtplugHttpGatewayBuilder")
+      .field("listen_on_all_interfaces", &self.listen_on_all_interfaces)
+      .field("port", &self.port)
+      .field("authenticator", &self.authenticator.is_some())
+      .finish_non_exhaustive()
+  }
+}
+
+impl ButtplugHttpGatewayBuilder {
+  pub fn listen_on_all_interfaces(&mut self, listen_on_all_interfaces: bool) -> &mut Self {
+    self.listen_on_all_interfaces = listen_on_all_interfaces;
+    self
+  }
+
+  pub fn port(&mut self, port: u16) -> &mut Self {
+    self.port = port;
+    self
+  }
+
+  pub fn auth_token(&mut self, auth_token: &str) -> &mut Self {
+    self.auth_token = Some(auth_token.to_owned());
+    self
+  }
+
+  /// Require every HTTP request to carry an `Authorization: Bearer <token>` header that the
+  /// authenticator accepts.
+  pub fn authenticator<T>(&mut self, authenticator: T) -> &mut Self
+  where
+    T: ButtplugServerAuthenticator + 'static,
+  {
+    self.authenticator = Some(Arc::new(authenticator));
+    self
+  }
+
+  pub fn finish(&self, server: ButtplugServer) -> ButtplugHttpGateway {
+    let ip = if self.listen_on_all_interfaces {
+      [0, 0, 0, 0]
+    } else {
+      [127, 0, 0, 1]
+    };
+    ButtplugHttpGateway {
+      server: Arc::new(server),
+      address: SocketAddr::from((ip, self.port)),
+      auth_token: self.auth_token.clone(),
+      authenticator: self.authenticator.clone(),
+    }
+  }
+}
+
+/// Serves the HTTP API for a [ButtplugServer]. Created via [ButtplugHttpGatewayBuilder].
+pub struct ButtplugHttpGateway {
+  server: Arc<ButtplugServer>,
+  address: SocketAddr,
+  auth_token: Option<String>,
+  authenticator: Option<Arc<dyn ButtplugServerAuthenticator>>,
+}
+
+struct GatewayState {
+  server: Arc<ButtplugServer>,
+  /// Buttplug messages sent from the gateway need unique, non-system ids.
+  message_id: AtomicU32,
+}
+
+impl GatewayState {
+  async fn send(&self, mut msg: ButtplugClientMessage) -> Result<ButtplugServerMessage, Response> {
+    msg.set_id(self.message_id.fetch_add(1, Ordering::SeqCst));
+    self.server.parse_message(msg).await.map_err(error_response)
+  }
+}
+
+/// Converts a server message into a JSON response, using the current spec message format.
+fn message_response(status: StatusCode, msg: ButtplugServerMessage) -> Response {
+  match ButtplugSpecV3ServerMessage::try_from(msg) {
+    Ok(msg) => (status, Json(msg)).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+  }
+}
+
+fn error_response(error: message::Error) -> Response {
+  let status = match error.original_error() {
+    ButtplugError::ButtplugDeviceError(ButtplugDeviceError::DeviceNotAvailable(_)) => {
+      StatusCode::NOT_FOUND
+    }
+    _ if error.error_code() == ErrorCode::ErrorUnknown => StatusCode::INTERNAL_SERVER_ERROR,
+    _ => StatusCode::BAD_REQUEST,
+  };
+  message_response(status, error.into())
+}
+
+fn ok_response(result: Result<ButtplugServerMessage, Response>) -> Response {
+  match result {
+    Ok(msg) => message_response(StatusCode::OK, msg),
+    Err(response) => response,
+  }
+}
+
+async fn require_bearer_token(
+  State(authenticator): State<Arc<dyn ButtplugServerAuthenticator>>,
+  request: Request<Body>,
+  next: Next<Body>,
+) -> Response {
+  let token = request
+    .headers()
+    .get(AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(bearer_token);
+  if authenticator.authenticate_token(token) {
+    next.run(request).await
+  } else {
+    (
+      StatusCode::UNAUTHORIZED,
+      [(WWW_AUTHENTICATE, "Bearer")],
+      "Unauthorized",
+    )
+      .into_response()
+  }
+}
+
+#[derive(Deserialize)]
+struct SensorRequest {
+  #[serde(rename = "SensorIndex")]
+  sensor_index: u32,
+  #[serde(rename = "SensorType")]
+  sensor_type: SensorType,
+}
+
+async fn list_devices(State(state): State<Arc<GatewayState>>) -> Response {
+  ok_response(state.send(RequestDeviceList::default().into()).await)
+}
+
+async fn get_device(
+  State(state): State<Arc<GatewayState>>,
+  Path(device_index): Path<u32>,
+) -> Response {
+  let device_list = match state.send(RequestDeviceList::default().into()).await {
+    Ok(ButtplugServerMessage::DeviceList(device_list)) => device_list,
+    Ok(msg) => return message_response(StatusCode::INTERNAL_SERVER_ERROR, msg),
+    Err(response) => return response,
+  };
+  match device_list
+    .devices()
+    .iter()
+    .find(|device| device.device_index() == device_index)
+  {
+    Some(device) => Json(device).into_response(),
+    None => error_response(
+      ButtplugError::from(ButtplugDeviceError::DeviceNotAvailable(device_index)).into(),
+    ),
+  }
+}
+
+async fn scalar_cmd(
+  State(state): State<Arc<GatewayState>>,
+  Path(device_index): Path<u32>,
+  Json(scalars): Json<Vec<ScalarSubcommand>>,
+) -> Response {
+  ok_response(
+    state
+      .send(ScalarCmd::new(device_index, scalars).into())
+      .await,
+  )
+}
+
+async fn rotate_cmd(
+  State(state): State<Arc<GatewayState>>,
+  Path(device_index): Path<u32>,
+  Json(rotations): Json<Vec<RotationSubcommand>>,
+) -> Response {
+  ok_response(
+    state
+      .send(RotateCmd::new(device_index, rotations).into())
+      .await,
+  )
+}
+
+async fn linear_cmd(
+  State(state): State<Arc<GatewayState>>,
+  Path(device_index): Path<u32>,
+  Json(vectors): Json<Vec<VectorSubcommand>>,
+) -> Response {
+  ok_response(
+    state
+      .send(LinearCmd::new(device_index, vectors).into())
+      .await,
+  )
+}
+
+async fn stop_device(
+  State(state): State<Arc<GatewayState>>,
+  Path(device_index): Path<u32>,
+) -> Response {
+  ok_response(state.send(StopDeviceCmd::new(device_index).into()).await)
+}
+
+async fn sensor_subscribe(
+  State(state): State<Arc<GatewayState>>,
+  Path(device_index): Path<u32>,
+  Json(request): Json<SensorRequest>,
+) -> Response {
+  ok_response(
+    state
+      .send(SensorSubscribeCmd::new(device_index, request.sensor_index, request.sensor_type).into())
+      .await,
+  )
+}
+
+async fn sensor_unsubscribe(
+  State(state): State<Arc<GatewayState>>,
+  Path(device_index): Path<u32>,
+  Json(request): Json<SensorRequest>,
+) -> Response {
+  ok_response(
+    state
+      .send(
+        SensorUnsubscribeCmd::new(device_index, request.sensor_index, request.sensor_type).into(),
+      )
+      .await,
+  )
+}
+
+async fn stop_all_devices(State(state): State<Arc<GatewayState>>) -> Response {
+  ok_response(state.send(StopAllDevices::default().into()).await)
+}
+
+async fn start_scanning(State(state): State<Arc<GatewayState>>) -> Response {
+  ok_response(state.send(StartScanning::default().into()).await)
+}
+
+async fn stop_scanning(State(state): State<Arc<GatewayState>>) -> Response {
+  ok_response(state.send(StopScanning::default().into()).await)
+}
+
+async fn events(
+  State(state): State<Arc<GatewayState>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+  let stream = state.server.event_stream().filter_map(|msg| async move {
+    let event_name = match &msg {
+      ButtplugServerMessage::DeviceAdded(_) => "DeviceAdded",
+      ButtplugServerMessage::DeviceRemoved(_) => "DeviceRemoved",
+      ButtplugServerMessage::ScanningFinished(_) => "ScanningFinished",
+      ButtplugServerMessage::SensorReading(_) => "SensorReading",
+      _ => return None,
+    };
+    let msg = ButtplugSpecV3ServerMessage::try_from(msg).ok()?;
+    match Event::default().event(event_name).json_data(msg) {
+      Ok(event) => Some(Ok(event)),
+      Err(e) => {
+        error!("Cannot serialize gateway event: {:?}", e);
+        None
+      }
+    }
+  });
+  Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+impl ButtplugHttpGateway {
+  /// Returns the server the gateway is serving.
+  pub fn server(&self) -> Arc<ButtplugServer> {
+    self.server.clone()
+  }
+
+  /// Runs the handshake with the server, then serves HTTP requests until an error occurs.
+  pub async fn run(&self) -> Result<(), ButtplugHttpGatewayError> {
+    if self.authenticator.is_none() && !self.address.ip().is_loopback() {
+      return Err(ButtplugHttpGatewayError::AuthenticatorRequired(
+        self.address,
+      ));
+    }
+    let request_server_info = match &self.auth_token {
+      Some(auth_token) => RequestServerInfo::new_with_auth_token(
+        "Buttplug HTTP Gateway",
+        BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+        auth_token,
+      ),
+      None => RequestServerInfo::new(
+        "Buttplug HTTP Gateway",
+        BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+      ),
+    };
+    let max_ping_time = match self.server.parse_message(request_server_info.into()).await {
+      Ok(ButtplugServerMessage::ServerInfo(server_info)) => server_info.max_ping_time(),
+      Ok(msg) => {
+        return Err(ButtplugHttpGatewayError::HandshakeFailed(
+          ButtplugHandshakeError::UnexpectedHandshakeMessageReceived(format!("{:?}", msg)).into(),
+        ))
+      }
+      Err(e) => {
+        return Err(ButtplugHttpGatewayError::HandshakeFailed(
+          e.original_error(),
+        ))
+      }
+    };
+
+    let state = Arc::new(GatewayState {
+      server: self.server.clone(),
+      message_id: AtomicU32::new(1),
+    });
+
+    // HTTP clients come and go, so the gateway keeps the server's ping timer fed for them.
+    let ping_token = CancellationToken::new();
+    let _ping_guard = ping_token.clone().drop_guard();
+    if max_ping_time > 0 {
+      let state = state.clone();
+      async_manager::spawn(async move {
+        let interval = Duration::from_millis((max_ping_time / 2).max(1) as u64);
+        loop {
+          select! {
+            _ = ping_token.cancelled().fuse() => return,
+            _ = sleep(interval).fuse() => {
+              if state.send(message::Ping::default().into()).await.is_err() {
+                error!("HTTP gateway ping failed, stopping ping loop.");
+                return;
+              }
+            }
+          }
+        }
+      });
+    }
+
+    let mut app = Router::new()
+      .route("/devices", get(list_devices))
+      .route("/devices/stop", post(stop_all_devices))
+      .route("/devices/:index", get(get_device))
+      .route("/devices/:index/scalar", post(scalar_cmd))
+      .route("/devices/:index/rotate", post(rotate_cmd))
+      .route("/devices/:index/linear", post(linear_cmd))
+      .route("/devices/:index/stop", post(stop_device))
+      .route("/devices/:index/sensors/subscribe", post(sensor_subscribe))
+      .route(
+        "/devices/:index/sensors/unsubscribe",
+        post(sensor_unsubscribe),
+      )
+      .route("/scanning/start", post(start_scanning))
+      .route("/scanning/stop", post(stop_scanning))
+      .route("/events", get(events));
+    if let Some(authenticator) = &self.authenticator {
+      app = app.route_layer(middleware::from_fn_with_state(
+        authenticator.clone(),
+        require_bearer_token,
+      ));
+    }
+    let app = app.with_state(state);
+
+    info!("HTTP gateway listening on {}", self.address);
+    axum::Server::try_bind(&self.address)
+      .map_err(|e| ButtplugHttpGatewayError::ListenError(self.address, e.to_string()))?
+      .serve(app.into_make_service())
+      .await
+      .map_err(|e| ButtplugHttpGatewayError::ServerError(e.to_string()))
+  }
+}
@@ -45,9 +45,13 @@
 //!   - If the server object is dropped, all devices are stopped and disconnected as part
 //!     of the [DeviceManager] teardown.
 
+pub mod authenticator;
 pub mod device;
+#[cfg(feature = "http-gateway")]
+pub mod http_gateway;
 mod ping_timer;
 
+use self::authenticator::ButtplugServerAuthenticator;
 use self::device::{
   configuration::{
     ProtocolAttributesIdentifier,
@@ -69,7 +73,9 @@ use crate::{
       ButtplugDeviceCommandMessageUnion,
       ButtplugDeviceManagerMessageUnion,
       ButtplugMessage,
+      ButtplugMessageSpecVersion,
       ButtplugServerMessage,
+      RequestUnsupportedDeviceReports,
       StopAllDevices,
       StopScanning,
       BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
@@ -77,7 +83,11 @@ use crate::{
   },
   util::{
     async_manager,
-    device_configuration::{load_protocol_configs, DEVICE_CONFIGURATION_JSON},
+    device_configuration::{
+      load_protocol_configs,
+      migrate_device_config_json,
+      DEVICE_CONFIGURATION_JSON,
+    },
     stream::convert_broadcast_receiver_to_stream,
   },
 };
@@ -136,8 +146,15 @@ pub struct ButtplugServerBuilder {
   device_configuration_json: Option<String>,
   /// JSON string, with the contents of the User Device Configuration file
   user_device_configuration_json: Option<String>,
+  /// If true, configuration files from older major versions are migrated to the current version
+  /// while loading, instead of being rejected.
+  migrate_device_configurations: bool,
   /// Device manager builder for the server
   device_manager_builder: ServerDeviceManagerBuilder,
+  /// If set, clients must pass authentication during the handshake before they can connect.
+  authenticator: Option<Arc<dyn ButtplugServerAuthenticator>>,
+  /// Newest message spec version clients can connect with.
+  max_message_version: ButtplugMessageSpecVersion,
 }
 
 impl Default for ButtplugServerBuilder {
@@ -147,7 +164,10 @@ impl Default for ButtplugServerBuilder {
       max_ping_time: None,
       device_configuration_json: Some(DEVICE_CONFIGURATION_JSON.to_owned()),
       user_device_configuration_json: None,
+      migrate_device_configurations: false,
       device_manager_builder: ServerDeviceManagerBuilder::default(),
+      authenticator: None,
+      max_message_version: BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
     }
   }
 }
@@ -184,6 +204,32 @@ impl ButtplugServerBuilder {
     self
   }
 
+  /// Migrate device configuration files from older major versions while loading, instead of
+  /// failing on a version mismatch. Migrated documents are only used in memory, use
+  /// [migrate_device_config_json] to get a copy to write back to disk.
+  pub fn migrate_device_configurations(&mut self) -> &mut Self {
+    self.migrate_device_configurations = true;
+    self
+  }
+
+  /// Require clients to pass authentication during the handshake. Clients that fail are rejected
+  /// with [ButtplugHandshakeError::AuthenticationFailed].
+  pub fn authenticator<T>(&mut self, authenticator: T) -> &mut Self
+  where
+    T: ButtplugServerAuthenticator + 'static,
+  {
+    self.authenticator = Some(Arc::new(authenticator));
+    self
+  }
+
+  /// Limit the server to an older message spec version. Clients asking for a newer version are
+  /// rejected with [ButtplugHandshakeError::MessageSpecVersionMismatch], which carries this version
+  /// so they can retry with it. Mostly useful for testing clients against older servers.
+  pub fn max_message_version(&mut self, version: ButtplugMessageSpecVersion) -> &mut Self {
+    self.max_message_version = version;
+    self
+  }
+
   pub fn comm_manager<T>(&mut self, builder: T) -> &mut Self
   where
     T: HardwareCommunicationManagerBuilder + 'static,
@@ -239,6 +285,13 @@ impl ButtplugServerBuilder {
     self
   }
 
+  pub fn protocol_priority(&mut self, protocol_name: &str, priority: i32) -> &mut Self {
+    self
+      .device_manager_builder
+      .protocol_priority(protocol_name, priority);
+    self
+  }
+
   pub fn skip_default_protocols(&mut self) -> &mut Self {
     self.device_manager_builder.skip_default_protocols();
     self
@@ -257,9 +310,41 @@ impl ButtplugServerBuilder {
 
     // First, try loading our configs. If this doesn't work, nothing else will, so get it out of
     // the way first.
+    let (device_configuration_json, user_device_configuration_json) =
+      if self.migrate_device_configurations {
+        let migrate = |config: &Option<String>| -> Result<Option<String>, ButtplugServerError> {
+          config
+            .as_ref()
+            .map(|config| {
+              let (migrated, report) = migrate_device_config_json(config)
+                .map_err(ButtplugServerError::DeviceConfigurationManagerError)?;
+              if report.migrated() {
+                info!(
+                  "Migrated device configuration from version {} to {}",
+                  report.from_version(),
+                  report.to_version()
+                );
+                for change in report.changes() {
+                  info!("Device configuration migration: {}", change);
+                }
+              }
+              Ok(migrated)
+            })
+            .transpose()
+        };
+        (
+          migrate(&self.device_configuration_json)?,
+          migrate(&self.user_device_configuration_json)?,
+        )
+      } else {
+        (
+          self.device_configuration_json.clone(),
+          self.user_device_configuration_json.clone(),
+        )
+      };
     let dcm_builder = load_protocol_configs(
-      self.device_configuration_json.clone(),
-      self.user_device_configuration_json.clone(),
+      device_configuration_json,
+      user_device_configuration_json,
       false,
     )
     .map_err(ButtplugServerError::DeviceConfigurationManagerError)?;
@@ -315,6 +400,8 @@ impl ButtplugServerBuilder {
       ping_timer,
       connected,
       output_sender,
+      authenticator: self.authenticator.clone(),
+      max_message_version: self.max_message_version,
     })
   }
 }
@@ -342,6 +429,10 @@ pub struct ButtplugServer {
   /// Broadcaster for server events. Receivers for this are handed out through the
   /// [ButtplugServer::event_stream()] method.
   output_sender: broadcast::Sender<ButtplugServerMessage>,
+  /// If set, checks clients during the handshake.
+  authenticator: Option<Arc<dyn ButtplugServerAuthenticator>>,
+  /// Newest message spec version clients can connect with.
+  max_message_version: ButtplugMessageSpecVersion,
 }
 
 impl std::fmt::Debug for ButtplugServer {
@@ -395,6 +486,10 @@ impl ButtplugServer {
     let stop_fut = self.parse_message(ButtplugClientMessage::StopAllDevices(
       StopAllDevices::default(),
     ));
+    let stop_reports_fut =
+      self.parse_message(ButtplugClientMessage::RequestUnsupportedDeviceReports(
+        RequestUnsupportedDeviceReports::new(false),
+      ));
     let connected = self.connected.clone();
     async move {
       connected.store(false, Ordering::SeqCst);
@@ -404,6 +499,7 @@ impl ButtplugServer {
       let _ = stop_scanning_fut.await;
       info!("Server disconnected, stopping all devices...");
       let _ = stop_fut.await;
+      let _ = stop_reports_fut.await;
       Ok(())
     }
     .boxed()
@@ -491,9 +587,18 @@ impl ButtplugServer {
       msg.client_name(),
       msg.message_version()
     );
-    if BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION < msg.message_version() {
+    if let Some(authenticator) = &self.authenticator {
+      if !authenticator.authenticate(&msg) {
+        warn!(
+          "Client {} failed authentication, rejecting connection.",
+          msg.client_name()
+        );
+        return ButtplugHandshakeError::AuthenticationFailed.into();
+      }
+    }
+    if self.max_message_version < msg.message_version() {
       return ButtplugHandshakeError::MessageSpecVersionMismatch(
-        BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+        self.max_message_version,
         msg.message_version(),
       )
       .into();
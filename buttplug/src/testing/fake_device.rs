@@ -0,0 +1,565 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Fake devices for [ButtplugTestServerBuilder][super::ButtplugTestServerBuilder].
+//!
+//! Fake devices report their own attributes and connect through the testing module's own protocol,
+//! which records commands on the [FakeDevice] as normalized device command messages rather than
+//! writing protocol specific bytes to the hardware. The hardware itself only reports disconnections.
+
+use super::fake_protocol::fake_device_specifier;
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{
+      ActuatorType,
+      ButtplugDeviceCommandMessageUnion,
+      SensorDeviceMessageAttributes,
+      SensorReading,
+      SensorType,
+    },
+  },
+  server::device::{
+    configuration::{
+      ProtocolAttributesType,
+      ProtocolCommunicationSpecifier,
+      ProtocolDeviceAttributes,
+      ServerDeviceMessageAttributes,
+      ServerDeviceMessageAttributesBuilder,
+      ServerGenericDeviceMessageAttributes,
+    },
+    hardware::{
+      GenericHardwareSpecializer,
+      Hardware,
+      HardwareConnector,
+      HardwareEvent,
+      HardwareInternal,
+      HardwareReadCmd,
+      HardwareReading,
+      HardwareSpecializer,
+      HardwareSubscribeCmd,
+      HardwareUnsubscribeCmd,
+      HardwareWriteCmd,
+    },
+  },
+  util::sleep,
+};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+};
+use std::{
+  fmt::{self, Debug},
+  ops::RangeInclusive,
+  sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+    Mutex,
+  },
+  time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, Notify};
+
+/// Used to give every fake device a unique address, across all test servers in a process.
+static NEXT_FAKE_DEVICE_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Describes a fake device to add to a [ButtplugTestServerBuilder][super::ButtplugTestServerBuilder].
+///
+/// Features are indexed in the order they are added, separately for each message type, the same
+/// way clients see them.
+#[derive(Clone, Debug)]
+pub struct FakeDeviceBuilder {
+  name: String,
+  display_name: Option<String>,
+  scalar_cmd: Vec<ServerGenericDeviceMessageAttributes>,
+  rotate_cmd: Vec<ServerGenericDeviceMessageAttributes>,
+  linear_cmd: Vec<ServerGenericDeviceMessageAttributes>,
+  sensor_read_cmd: Vec<SensorDeviceMessageAttributes>,
+  sensor_subscribe_cmd: Vec<SensorDeviceMessageAttributes>,
+}
+
+impl FakeDeviceBuilder {
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.to_owned(),
+      display_name: None,
+      scalar_cmd: vec![],
+      rotate_cmd: vec![],
+      linear_cmd: vec![],
+      sensor_read_cmd: vec![],
+      sensor_subscribe_cmd: vec![],
+    }
+  }
+
+  pub fn display_name(&mut self, display_name: &str) -> &mut Self {
+    self.display_name = Some(display_name.to_owned());
+    self
+  }
+
+  /// Adds a ScalarCmd feature with the given actuator type and number of steps.
+  pub fn scalar(&mut self, actuator_type: ActuatorType, step_count: u32) -> &mut Self {
+    self
+      .scalar_cmd
+      .push(ServerGenericDeviceMessageAttributes::new(
+        "N/A",
+        &RangeInclusive::new(0, step_count),
+        actuator_type,
+      ));
+    self
+  }
+
+  /// Adds a RotateCmd feature with the given number of steps.
+  pub fn rotate(&mut self, step_count: u32) -> &mut Self {
+    self
+      .rotate_cmd
+      .push(ServerGenericDeviceMessageAttributes::new(
+        "N/A",
+        &RangeInclusive::new(0, step_count),
+        ActuatorType::Rotate,
+      ));
+    self
+  }
+
+  /// Adds a LinearCmd feature with the given number of steps.
+  pub fn linear(&mut self, step_count: u32) -> &mut Self {
+    self
+      .linear_cmd
+      .push(ServerGenericDeviceMessageAttributes::new(
+        "N/A",
+        &RangeInclusive::new(0, step_count),
+        ActuatorType::Position,
+      ));
+    self
+  }
+
+  /// Adds a sensor that can be read via SensorReadCmd, with one range per value it returns.
+  pub fn sensor_read(
+    &mut self,
+    sensor_type: SensorType,
+    sensor_range: &[RangeInclusive<u32>],
+  ) -> &mut Self {
+    self
+      .sensor_read_cmd
+      .push(SensorDeviceMessageAttributes::new_with_index(
+        "N/A",
+        sensor_type,
+        sensor_range,
+        self.sensor_read_cmd.len() as u32,
+      ));
+    self
+  }
+
+  /// Adds a sensor that can be subscribed to via SensorSubscribeCmd, with one range per value it
+  /// emits.
+  pub fn sensor_subscribe(
+    &mut self,
+    sensor_type: SensorType,
+    sensor_range: &[RangeInclusive<u32>],
+  ) -> &mut Self {
+    self
+      .sensor_subscribe_cmd
+      .push(SensorDeviceMessageAttributes::new_with_index(
+        "N/A",
+        sensor_type,
+        sensor_range,
+        self.sensor_subscribe_cmd.len() as u32,
+      ));
+    self
+  }
+
+  /// Adds a readable battery sensor, reporting percentages in the range [0, 100].
+  pub fn battery(&mut self) -> &mut Self {
+    self.sensor_read(SensorType::Battery, &[RangeInclusive::new(0, 100)])
+  }
+
+  fn message_attributes(&self) -> ServerDeviceMessageAttributes {
+    let mut builder = ServerDeviceMessageAttributesBuilder::default();
+    if !self.scalar_cmd.is_empty() {
+      builder.scalar_cmd(&self.scalar_cmd);
+    }
+    if !self.rotate_cmd.is_empty() {
+      builder.rotate_cmd(&self.rotate_cmd);
+    }
+    if !self.linear_cmd.is_empty() {
+      builder.linear_cmd(&self.linear_cmd);
+    }
+    if !self.sensor_read_cmd.is_empty() {
+      builder.sensor_read_cmd(&self.sensor_read_cmd);
+    }
+    if !self.sensor_subscribe_cmd.is_empty() {
+      builder.sensor_subscribe_cmd(&self.sensor_subscribe_cmd);
+    }
+    builder.finish()
+  }
+
+  pub(super) fn finish(&self) -> FakeDevice {
+    let (event_sender, _) = broadcast::channel(256);
+    let (reading_sender, _) = broadcast::channel(256);
+    FakeDevice {
+      shared: Arc::new(FakeDeviceShared {
+        address: format!(
+          "fake-device-{}",
+          NEXT_FAKE_DEVICE_ID.fetch_add(1, Ordering::SeqCst)
+        ),
+        builder: self.clone(),
+        connected: AtomicBool::new(false),
+        commands: Mutex::new(vec![]),
+        command_notifier: Notify::new(),
+        sensor_values: DashMap::new(),
+        event_sender,
+        reading_sender,
+      }),
+    }
+  }
+}
+
+struct FakeDeviceShared {
+  address: String,
+  builder: FakeDeviceBuilder,
+  connected: AtomicBool,
+  commands: Mutex<Vec<ButtplugDeviceCommandMessageUnion>>,
+  command_notifier: Notify,
+  /// Values returned for SensorReadCmd, keyed by sensor index.
+  sensor_values: DashMap<u32, Vec<i32>>,
+  event_sender: broadcast::Sender<HardwareEvent>,
+  /// Readings from SensorSubscribeCmd sensors, filtered by the protocol down to subscribed sensors.
+  reading_sender: broadcast::Sender<SensorReading>,
+}
+
+/// Handle to a fake device on a test server.
+///
+/// Records every command the device receives, and allows injecting sensor readings and
+/// disconnections. Commands arrive as the server hands them to the protocol, so only features whose
+/// values changed are included, and levels are rounded to the feature's step count. Commands are
+/// recorded before the client's call returns, so they can be checked right after awaiting it.
+#[derive(Clone)]
+pub struct FakeDevice {
+  shared: Arc<FakeDeviceShared>,
+}
+
+impl FakeDevice {
+  pub fn name(&self) -> &str {
+    &self.shared.builder.name
+  }
+
+  /// Address the device is reported with. Unique within the process.
+  pub fn address(&self) -> &str {
+    &self.shared.address
+  }
+
+  /// True if the device is currently connected to the server.
+  pub fn connected(&self) -> bool {
+    self.shared.connected.load(Ordering::SeqCst)
+  }
+
+  /// All commands received so far, in order.
+  pub fn commands(&self) -> Vec<ButtplugDeviceCommandMessageUnion> {
+    self.lock_commands().clone()
+  }
+
+  pub fn last_command(&self) -> Option<ButtplugDeviceCommandMessageUnion> {
+    self.lock_commands().last().cloned()
+  }
+
+  /// Forgets all commands received so far.
+  pub fn clear_commands(&self) {
+    self.lock_commands().clear();
+  }
+
+  /// Levels received by the ScalarCmd feature at `index`, in order.
+  pub fn scalar_levels(&self, index: u32) -> Vec<f64> {
+    self
+      .lock_commands()
+      .iter()
+      .filter_map(|command| match command {
+        ButtplugDeviceCommandMessageUnion::ScalarCmd(msg) => msg
+          .scalars()
+          .iter()
+          .find(|scalar| scalar.index() == index)
+          .map(|scalar| scalar.scalar()),
+        _ => None,
+      })
+      .collect()
+  }
+
+  /// Speeds and directions (true for clockwise) received by the RotateCmd feature at `index`, in
+  /// order.
+  pub fn rotations(&self, index: u32) -> Vec<(f64, bool)> {
+    self
+      .lock_commands()
+      .iter()
+      .filter_map(|command| match command {
+        ButtplugDeviceCommandMessageUnion::RotateCmd(msg) => msg
+          .rotations()
+          .iter()
+          .find(|rotation| rotation.index() == index)
+          .map(|rotation| (rotation.speed(), rotation.clockwise())),
+        _ => None,
+      })
+      .collect()
+  }
+
+  /// Durations in milliseconds and positions received by the LinearCmd feature at `index`, in
+  /// order.
+  pub fn linear_movements(&self, index: u32) -> Vec<(u32, f64)> {
+    self
+      .lock_commands()
+      .iter()
+      .filter_map(|command| match command {
+        ButtplugDeviceCommandMessageUnion::LinearCmd(msg) => msg
+          .vectors()
+          .iter()
+          .find(|vector| vector.index() == index)
+          .map(|vector| (vector.duration(), vector.position())),
+        _ => None,
+      })
+      .collect()
+  }
+
+  /// Waits until at least `count` commands have been received, or `timeout` passes. Returns the
+  /// commands received either way. Useful for commands sent from a background task, like ramps.
+  pub async fn wait_for_commands(
+    &self,
+    count: usize,
+    timeout: Duration,
+  ) -> Vec<ButtplugDeviceCommandMessageUnion> {
+    let deadline = Instant::now() + timeout;
+    loop {
+      // Register for the notification before checking, so a command arriving in between isn't
+      // missed.
+      let notified = self.shared.command_notifier.notified();
+      if self.lock_commands().len() >= count {
+        break;
+      }
+      let remaining = deadline.saturating_duration_since(Instant::now());
+      if remaining.is_zero() {
+        break;
+      }
+      select! {
+        _ = notified.fuse() => {},
+        _ = sleep(remaining).fuse() => {},
+      }
+    }
+    self.commands()
+  }
+
+  /// Panics if the commands received so far don't match `expected` exactly.
+  #[track_caller]
+  pub fn assert_received(&self, expected: &[ButtplugDeviceCommandMessageUnion]) {
+    let commands = self.commands();
+    assert_eq!(
+      commands,
+      expected,
+      "Commands received by fake device {} don't match.",
+      self.name()
+    );
+  }
+
+  /// Sets the values returned when the SensorReadCmd sensor at `sensor_index` is read. Reads of
+  /// sensors without values fail.
+  pub fn set_sensor_value(&self, sensor_index: u32, data: Vec<i32>) {
+    self.shared.sensor_values.insert(sensor_index, data);
+  }
+
+  /// Sets the battery level, in the range [0.0, 1.0], for the device's battery sensor.
+  ///
+  /// Panics if the device has no readable battery sensor.
+  #[track_caller]
+  pub fn set_battery_level(&self, level: f64) {
+    let (index, sensor) = self
+      .shared
+      .builder
+      .sensor_read_cmd
+      .iter()
+      .enumerate()
+      .find(|(_, sensor)| *sensor.sensor_type() == SensorType::Battery)
+      .unwrap_or_else(|| panic!("Fake device {} has no battery sensor.", self.name()));
+    let max = sensor
+      .sensor_range()
+      .first()
+      .map_or(100, |range| *range.end());
+    self.set_sensor_value(index as u32, vec![(level * max as f64).round() as i32]);
+  }
+
+  /// Emits a reading from the SensorSubscribeCmd sensor at `sensor_index`. Readings are only passed
+  /// on to clients while the sensor is subscribed.
+  ///
+  /// Panics if the device has no subscribable sensor at `sensor_index`.
+  #[track_caller]
+  pub fn emit_sensor_reading(&self, sensor_index: u32, data: Vec<i32>) {
+    let sensor_type = self
+      .shared
+      .builder
+      .sensor_subscribe_cmd
+      .get(sensor_index as usize)
+      .map(|sensor| *sensor.sensor_type())
+      .unwrap_or_else(|| {
+        panic!(
+          "Fake device {} has no subscribable sensor at index {}.",
+          self.name(),
+          sensor_index
+        )
+      });
+    let reading = SensorReading::new(0, sensor_index, sensor_type, data);
+    // No receivers just means the device isn't connected yet.
+    let _ = self.shared.reading_sender.send(reading);
+  }
+
+  /// Disconnects the device, as if it had been turned off or gone out of range. The device will be
+  /// found again on the next scan.
+  pub fn disconnect(&self) {
+    self.shared.disconnect();
+  }
+
+  fn lock_commands(&self) -> std::sync::MutexGuard<'_, Vec<ButtplugDeviceCommandMessageUnion>> {
+    self
+      .shared
+      .commands
+      .lock()
+      .expect("Only locked for single updates, should never be poisoned.")
+  }
+
+  pub(super) fn record_command(&self, command: ButtplugDeviceCommandMessageUnion) {
+    self.lock_commands().push(command);
+    self.shared.command_notifier.notify_waiters();
+  }
+
+  pub(super) fn sensor_value(&self, sensor_index: u32) -> Option<Vec<i32>> {
+    self
+      .shared
+      .sensor_values
+      .get(&sensor_index)
+      .map(|data| data.value().clone())
+  }
+
+  pub(super) fn sensor_readings(&self) -> broadcast::Receiver<SensorReading> {
+    self.shared.reading_sender.subscribe()
+  }
+
+  pub(super) fn connector(&self) -> FakeDeviceHardwareConnector {
+    FakeDeviceHardwareConnector {
+      device: self.clone(),
+    }
+  }
+}
+
+impl FakeDeviceShared {
+  fn disconnect(&self) {
+    self.connected.store(false, Ordering::SeqCst);
+    let _ = self
+      .event_sender
+      .send(HardwareEvent::Disconnected(self.address.clone()));
+  }
+}
+
+impl Debug for FakeDevice {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("FakeDevice")
+      .field("name", &self.name())
+      .field("address", &self.address())
+      .field("connected", &self.connected())
+      .finish()
+  }
+}
+
+pub(super) struct FakeDeviceHardwareConnector {
+  device: FakeDevice,
+}
+
+impl Debug for FakeDeviceHardwareConnector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("FakeDeviceHardwareConnector")
+      .field("address", &self.device.address())
+      .finish()
+  }
+}
+
+#[async_trait]
+impl HardwareConnector for FakeDeviceHardwareConnector {
+  fn specifier(&self) -> ProtocolCommunicationSpecifier {
+    fake_device_specifier()
+  }
+
+  async fn connect(&mut self) -> Result<Box<dyn HardwareSpecializer>, ButtplugDeviceError> {
+    let shared = self.device.shared.clone();
+    shared.connected.store(true, Ordering::SeqCst);
+    let builder = &shared.builder;
+    let mut hardware = Hardware::new(
+      &builder.name,
+      &shared.address,
+      &[],
+      Box::new(FakeDeviceHardware {
+        shared: shared.clone(),
+      }),
+    );
+    hardware.set_attributes(ProtocolDeviceAttributes::new(
+      ProtocolAttributesType::Identifier(builder.name.clone()),
+      Some(builder.name.clone()),
+      builder.display_name.clone(),
+      builder.message_attributes(),
+      None,
+    ));
+    Ok(Box::new(GenericHardwareSpecializer::new(hardware)))
+  }
+}
+
+struct FakeDeviceHardware {
+  shared: Arc<FakeDeviceShared>,
+}
+
+impl HardwareInternal for FakeDeviceHardware {
+  fn event_stream(&self) -> broadcast::Receiver<HardwareEvent> {
+    self.shared.event_sender.subscribe()
+  }
+
+  fn disconnect(&self) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    self.shared.disconnect();
+    future::ready(Ok(())).boxed()
+  }
+
+  fn read_value(
+    &self,
+    _msg: &HardwareReadCmd,
+  ) -> BoxFuture<'static, Result<HardwareReading, ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Fake devices do not support raw reads".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn write_value(
+    &self,
+    _msg: &HardwareWriteCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Fake devices do not support raw writes".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn subscribe(
+    &self,
+    _msg: &HardwareSubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Fake devices do not support raw subscriptions".to_owned(),
+    )))
+    .boxed()
+  }
+
+  fn unsubscribe(
+    &self,
+    _msg: &HardwareUnsubscribeCmd,
+  ) -> BoxFuture<'static, Result<(), ButtplugDeviceError>> {
+    future::ready(Err(ButtplugDeviceError::UnhandledCommand(
+      "Fake devices do not support raw subscriptions".to_owned(),
+    )))
+    .boxed()
+  }
+}
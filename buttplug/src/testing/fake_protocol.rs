@@ -0,0 +1,297 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! Protocol for [FakeDevice]s.
+//!
+//! Rather than sending anything to the hardware, the protocol converts commands back to their
+//! normalized message form and records them on the fake device. Sensor reads are answered from the
+//! values set on the device, and readings emitted by the device are passed on while their sensor is
+//! subscribed.
+
+use super::fake_device::FakeDevice;
+use crate::{
+  core::{
+    errors::ButtplugDeviceError,
+    message::{
+      self,
+      ActuatorType,
+      ButtplugDeviceMessage,
+      ButtplugMessage,
+      ButtplugServerDeviceMessage,
+      ButtplugServerMessage,
+      RotateCmd,
+      RotationSubcommand,
+      ScalarCmd,
+      ScalarSubcommand,
+      SensorReading,
+    },
+  },
+  server::device::{
+    configuration::{
+      ProtocolAttributesType,
+      ProtocolCommunicationSpecifier,
+      ProtocolDeviceAttributes,
+      ServerGenericDeviceMessageAttributes,
+      WebsocketSpecifier,
+    },
+    hardware::{Hardware, HardwareCommand},
+    protocol::{
+      ProtocolHandler,
+      ProtocolIdentifier,
+      ProtocolIdentifierFactory,
+      ProtocolInitializer,
+    },
+    ServerDeviceIdentifier,
+  },
+  util::stream::convert_broadcast_receiver_to_stream,
+};
+use async_trait::async_trait;
+use dashmap::DashSet;
+use futures::{
+  future::{self, BoxFuture},
+  FutureExt,
+  StreamExt,
+};
+use std::{collections::HashMap, pin::Pin, sync::Arc};
+
+pub(super) const FAKE_DEVICE_PROTOCOL: &str = "buttplug-testing-fake-device";
+
+/// Specifier that fake devices connect with, and that the fake protocol is registered for.
+///
+/// Websocket specifiers match on name alone, so one named after the protocol only ever matches fake
+/// devices, without the testing feature needing its own kind of specifier.
+pub(super) fn fake_device_specifier() -> ProtocolCommunicationSpecifier {
+  ProtocolCommunicationSpecifier::Websocket(WebsocketSpecifier::new(&vec![
+    FAKE_DEVICE_PROTOCOL.to_owned()
+  ]))
+}
+
+/// Hands each connecting fake device's handle to the protocol, looked up by address.
+pub(super) struct FakeDeviceIdentifierFactory {
+  devices: Arc<HashMap<String, FakeDevice>>,
+}
+
+impl FakeDeviceIdentifierFactory {
+  pub(super) fn new(devices: &[FakeDevice]) -> Self {
+    Self {
+      devices: Arc::new(
+        devices
+          .iter()
+          .map(|device| (device.address().to_owned(), device.clone()))
+          .collect(),
+      ),
+    }
+  }
+}
+
+impl ProtocolIdentifierFactory for FakeDeviceIdentifierFactory {
+  fn identifier(&self) -> &str {
+    FAKE_DEVICE_PROTOCOL
+  }
+
+  fn create(&self) -> Box<dyn ProtocolIdentifier> {
+    Box::new(FakeDeviceIdentifier {
+      devices: self.devices.clone(),
+    })
+  }
+}
+
+struct FakeDeviceIdentifier {
+  devices: Arc<HashMap<String, FakeDevice>>,
+}
+
+#[async_trait]
+impl ProtocolIdentifier for FakeDeviceIdentifier {
+  async fn identify(
+    &mut self,
+    hardware: Arc<Hardware>,
+  ) -> Result<(ServerDeviceIdentifier, Box<dyn ProtocolInitializer>), ButtplugDeviceError> {
+    let device = self
+      .devices
+      .get(hardware.address())
+      .cloned()
+      .ok_or_else(|| {
+        ButtplugDeviceError::DeviceConfigurationError(format!(
+          "{} is not a fake device on this server",
+          hardware.address()
+        ))
+      })?;
+    Ok((
+      ServerDeviceIdentifier::new(
+        hardware.address(),
+        FAKE_DEVICE_PROTOCOL,
+        &ProtocolAttributesType::Identifier(hardware.name().to_owned()),
+      ),
+      Box::new(FakeDeviceInitializer { device }),
+    ))
+  }
+}
+
+struct FakeDeviceInitializer {
+  device: FakeDevice,
+}
+
+#[async_trait]
+impl ProtocolInitializer for FakeDeviceInitializer {
+  async fn initialize(
+    &mut self,
+    _hardware: Arc<Hardware>,
+    attributes: &ProtocolDeviceAttributes,
+  ) -> Result<Arc<dyn ProtocolHandler>, ButtplugDeviceError> {
+    let message_attributes = attributes.message_attributes();
+    Ok(Arc::new(FakeDeviceProtocol {
+      device: self.device.clone(),
+      scalar_step_counts: step_counts(message_attributes.scalar_cmd()),
+      rotate_step_counts: step_counts(message_attributes.rotate_cmd()),
+      subscribed_sensors: Arc::new(DashSet::new()),
+    }))
+  }
+}
+
+struct FakeDeviceProtocol {
+  device: FakeDevice,
+  scalar_step_counts: Vec<u32>,
+  rotate_step_counts: Vec<u32>,
+  subscribed_sensors: Arc<DashSet<u32>>,
+}
+
+fn step_counts(attributes: &Option<Vec<ServerGenericDeviceMessageAttributes>>) -> Vec<u32> {
+  attributes
+    .iter()
+    .flatten()
+    .map(|attr| attr.step_count())
+    .collect()
+}
+
+// Steps were generated from the fake device's own attributes, so going back to a float is
+// lossless.
+fn step_to_value(step_counts: &[u32], index: usize, step: u32) -> f64 {
+  let step_count = step_counts.get(index).copied().unwrap_or(1).max(1);
+  step as f64 / step_count as f64
+}
+
+impl ProtocolHandler for FakeDeviceProtocol {
+  fn handle_scalar_cmd(
+    &self,
+    commands: &[Option<(ActuatorType, u32)>],
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    let scalars = commands
+      .iter()
+      .enumerate()
+      .filter_map(|(index, command)| {
+        command.map(|(actuator, step)| {
+          ScalarSubcommand::new(
+            index as u32,
+            step_to_value(&self.scalar_step_counts, index, step),
+            actuator,
+          )
+        })
+      })
+      .collect();
+    self
+      .device
+      .record_command(ScalarCmd::new(0, scalars).into());
+    Ok(vec![])
+  }
+
+  fn handle_rotate_cmd(
+    &self,
+    commands: &[Option<(u32, bool)>],
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    let rotations = commands
+      .iter()
+      .enumerate()
+      .filter_map(|(index, command)| {
+        command.map(|(step, clockwise)| {
+          RotationSubcommand::new(
+            index as u32,
+            step_to_value(&self.rotate_step_counts, index, step),
+            clockwise,
+          )
+        })
+      })
+      .collect();
+    self
+      .device
+      .record_command(RotateCmd::new(0, rotations).into());
+    Ok(vec![])
+  }
+
+  fn handle_linear_cmd(
+    &self,
+    message: message::LinearCmd,
+  ) -> Result<Vec<HardwareCommand>, ButtplugDeviceError> {
+    self.device.record_command(message.into());
+    Ok(vec![])
+  }
+
+  fn handle_sensor_read_cmd(
+    &self,
+    _device: Arc<Hardware>,
+    message: message::SensorReadCmd,
+  ) -> BoxFuture<'_, Result<ButtplugServerMessage, ButtplugDeviceError>> {
+    let sensor_index = *message.sensor_index();
+    let result = self
+      .device
+      .sensor_value(sensor_index)
+      .ok_or_else(|| {
+        ButtplugDeviceError::DeviceCommunicationError(format!(
+          "No value set for sensor {} of fake device {}",
+          sensor_index,
+          self.device.name()
+        ))
+      })
+      .map(|data| {
+        let mut reading = SensorReading::new(
+          message.device_index(),
+          sensor_index,
+          *message.sensor_type(),
+          data,
+        );
+        reading.set_id(message.id());
+        reading.into()
+      });
+    future::ready(result).boxed()
+  }
+
+  fn handle_sensor_subscribe_cmd(
+    &self,
+    _device: Arc<Hardware>,
+    message: message::SensorSubscribeCmd,
+  ) -> BoxFuture<'_, Result<ButtplugServerMessage, ButtplugDeviceError>> {
+    if self.subscribed_sensors.insert(*message.sensor_index()) {
+      self.device.record_command(message.clone().into());
+    }
+    future::ready(Ok(message::Ok::new(message.id()).into())).boxed()
+  }
+
+  fn handle_sensor_unsubscribe_cmd(
+    &self,
+    _device: Arc<Hardware>,
+    message: message::SensorUnsubscribeCmd,
+  ) -> BoxFuture<'_, Result<ButtplugServerMessage, ButtplugDeviceError>> {
+    if self
+      .subscribed_sensors
+      .remove(message.sensor_index())
+      .is_some()
+    {
+      self.device.record_command(message.clone().into());
+    }
+    future::ready(Ok(message::Ok::new(message.id()).into())).boxed()
+  }
+
+  fn event_stream(
+    &self,
+  ) -> Pin<Box<dyn futures::Stream<Item = ButtplugServerDeviceMessage> + Send>> {
+    let sensors = self.subscribed_sensors.clone();
+    // The readings have a device index of 0, which the device manager replaces.
+    convert_broadcast_receiver_to_stream(self.device.sensor_readings())
+      .filter(move |reading| future::ready(sensors.contains(&reading.sensor_index())))
+      .map(|reading| reading.into())
+      .boxed()
+  }
+}
@@ -0,0 +1,184 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+//! In-process test servers with fake devices, for testing applications built on Buttplug.
+//!
+//! A [ButtplugTestServerBuilder] builds a regular [ButtplugServer] whose only hardware is a set of
+//! [FakeDevice]s. Fake devices have whatever features they are described with, show up when the
+//! client starts scanning, and record every command sent to them so tests can check what an
+//! application did.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! use buttplug::{
+//!   client::{ButtplugClient, ScalarValueCommand},
+//!   core::message::ActuatorType,
+//!   testing::{ButtplugTestServerBuilder, FakeDeviceBuilder},
+//! };
+//!
+//! let mut builder = ButtplugTestServerBuilder::default();
+//! let fake_device = builder.add_device(FakeDeviceBuilder::new("Vibrator").scalar(ActuatorType::Vibrate, 20));
+//! let client = ButtplugClient::new("Test Client");
+//! client.connect(builder.connector()?).await?;
+//! client.start_scanning().await?;
+//! // ... wait for the device to be added, then run the application code under test ...
+//! # let device = client.devices()[0].clone();
+//! device.vibrate(&ScalarValueCommand::ScalarValue(0.5)).await?;
+//! assert_eq!(fake_device.scalar_levels(0), vec![0.5]);
+//! # Ok(())
+//! # }
+//! ```
+
+mod fake_device;
+mod fake_protocol;
+
+pub use fake_device::{FakeDevice, FakeDeviceBuilder};
+use fake_protocol::{fake_device_specifier, FakeDeviceIdentifierFactory, FAKE_DEVICE_PROTOCOL};
+
+use crate::{
+  core::{
+    connector::{ButtplugInProcessClientConnector, ButtplugInProcessClientConnectorBuilder},
+    ButtplugResultFuture,
+  },
+  server::{
+    device::hardware::communication::{
+      HardwareCommunicationManager,
+      HardwareCommunicationManagerBuilder,
+      HardwareCommunicationManagerEvent,
+    },
+    ButtplugServer,
+    ButtplugServerBuilder,
+    ButtplugServerError,
+  },
+};
+use futures::{future, FutureExt};
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc,
+};
+use tokio::sync::mpsc::Sender;
+
+/// Builds in-process servers with fake devices.
+#[derive(Default)]
+pub struct ButtplugTestServerBuilder {
+  server_builder: ButtplugServerBuilder,
+  devices: Vec<FakeDevice>,
+}
+
+impl ButtplugTestServerBuilder {
+  /// Adds a fake device to the server, returning the handle used to inspect and control it.
+  pub fn add_device(&mut self, device: &FakeDeviceBuilder) -> FakeDevice {
+    let device = device.finish();
+    self.devices.push(device.clone());
+    device
+  }
+
+  /// Builder for the underlying server, for settings like the server name or ping time.
+  pub fn server_builder(&mut self) -> &mut ButtplugServerBuilder {
+    &mut self.server_builder
+  }
+
+  pub fn finish(&mut self) -> Result<ButtplugServer, ButtplugServerError> {
+    let devices = std::mem::take(&mut self.devices);
+    self
+      .server_builder
+      .protocol_factory(FakeDeviceIdentifierFactory::new(&devices))
+      .communication_specifier(FAKE_DEVICE_PROTOCOL, fake_device_specifier())
+      .comm_manager(FakeDeviceCommunicationManagerBuilder { devices });
+    self.server_builder.finish()
+  }
+
+  /// Builds the server and wraps it in a connector, ready to pass to
+  /// [ButtplugClient::connect][crate::client::ButtplugClient::connect].
+  pub fn connector(&mut self) -> Result<ButtplugInProcessClientConnector, ButtplugServerError> {
+    Ok(
+      ButtplugInProcessClientConnectorBuilder::default()
+        .server(self.finish()?)
+        .finish(),
+    )
+  }
+}
+
+struct FakeDeviceCommunicationManagerBuilder {
+  devices: Vec<FakeDevice>,
+}
+
+impl HardwareCommunicationManagerBuilder for FakeDeviceCommunicationManagerBuilder {
+  fn finish(
+    &mut self,
+    sender: Sender<HardwareCommunicationManagerEvent>,
+  ) -> Box<dyn HardwareCommunicationManager> {
+    Box::new(FakeDeviceCommunicationManager {
+      sender,
+      devices: std::mem::take(&mut self.devices),
+      is_scanning: Arc::new(AtomicBool::new(false)),
+    })
+  }
+}
+
+/// Reports every fake device that isn't connected whenever scanning starts.
+struct FakeDeviceCommunicationManager {
+  sender: Sender<HardwareCommunicationManagerEvent>,
+  devices: Vec<FakeDevice>,
+  is_scanning: Arc<AtomicBool>,
+}
+
+impl HardwareCommunicationManager for FakeDeviceCommunicationManager {
+  fn name(&self) -> &'static str {
+    "FakeDeviceCommunicationManager"
+  }
+
+  fn start_scanning(&mut self) -> ButtplugResultFuture {
+    let devices: Vec<FakeDevice> = self
+      .devices
+      .iter()
+      .filter(|device| !device.connected())
+      .cloned()
+      .collect();
+    let sender = self.sender.clone();
+    let is_scanning = self.is_scanning.clone();
+    async move {
+      is_scanning.store(true, Ordering::SeqCst);
+      for device in devices {
+        if sender
+          .send(HardwareCommunicationManagerEvent::DeviceFound {
+            name: device.name().to_owned(),
+            address: device.address().to_owned(),
+            creator: Box::new(device.connector()),
+          })
+          .await
+          .is_err()
+        {
+          error!("Device manager disappeared, exiting.");
+          break;
+        }
+      }
+      is_scanning.store(false, Ordering::SeqCst);
+      if sender
+        .send(HardwareCommunicationManagerEvent::ScanningFinished)
+        .await
+        .is_err()
+      {
+        error!("Error sending scanning finished. Scanning may not register as finished now!");
+      }
+      Ok(())
+    }
+    .boxed()
+  }
+
+  fn stop_scanning(&mut self) -> ButtplugResultFuture {
+    future::ready(Ok(())).boxed()
+  }
+
+  fn scanning_status(&self) -> bool {
+    self.is_scanning.load(Ordering::SeqCst)
+  }
+
+  fn can_scan(&self) -> bool {
+    true
+  }
+}
@@ -7,14 +7,18 @@
 
 use super::json::JSONValidator;
 use crate::{
-  core::errors::ButtplugDeviceError,
+  core::{errors::ButtplugDeviceError, message::ButtplugDeviceMessageType},
   server::device::{
     configuration::{
       BluetoothLESpecifier,
+      ButtplugFederationSpecifier,
       DeviceConfigurationManager,
       DeviceConfigurationManagerBuilder,
+      EvdevSpecifier,
+      FeatureOutputMapping,
       HIDSpecifier,
       LovenseConnectServiceSpecifier,
+      NetworkSpecifier,
       ProtocolAttributesIdentifier,
       ProtocolAttributesType,
       ProtocolCommunicationSpecifier,
@@ -26,10 +30,12 @@ use crate::{
       XInputSpecifier,
     },
     ServerDeviceIdentifier,
+    ServerDeviceManager,
   },
 };
 use getset::{CopyGetters, Getters, MutGetters, Setters};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use std::{collections::HashMap, fmt::Display, ops::RangeInclusive};
 
 pub static DEVICE_CONFIGURATION_JSON: &str =
@@ -123,14 +129,24 @@ pub struct ProtocolDefinition {
   #[serde(skip_serializing_if = "Option::is_none")]
   xinput: Option<XInputSpecifier>,
   #[serde(skip_serializing_if = "Option::is_none")]
+  evdev: Option<EvdevSpecifier>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   websocket: Option<WebsocketSpecifier>,
   #[serde(skip_serializing_if = "Option::is_none")]
+  network: Option<NetworkSpecifier>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   #[serde(rename = "lovense-connect-service")]
   lovense_connect_service: Option<LovenseConnectServiceSpecifier>,
   #[serde(skip_serializing_if = "Option::is_none")]
+  #[serde(rename = "buttplug-federation")]
+  buttplug_federation: Option<ButtplugFederationSpecifier>,
+  #[serde(skip_serializing_if = "Option::is_none")]
   defaults: Option<ProtocolAttributes>,
   #[serde(default)]
   configurations: Vec<ProtocolAttributes>,
+  /// Priority used to order connection attempts when multiple protocols match a device.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  priority: Option<i32>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Default, Getters, Setters, MutGetters)]
@@ -201,6 +217,7 @@ struct ExternalDeviceConfiguration {
   reserved_indexes: HashMap<u32, ServerDeviceIdentifier>,
   protocol_specifiers: HashMap<String, Vec<ProtocolCommunicationSpecifier>>,
   protocol_attributes: HashMap<ProtocolAttributesIdentifier, ProtocolDeviceAttributes>,
+  protocol_priorities: HashMap<String, i32>,
   user_configs: HashMap<ServerDeviceIdentifier, ProtocolDeviceAttributes>,
 }
 
@@ -229,14 +246,25 @@ impl From<ProtocolDefinition> for ProtocolDeviceConfiguration {
     if let Some(xinput) = &protocol_def.xinput {
       specifiers.push(ProtocolCommunicationSpecifier::XInput(*xinput));
     }
+    if let Some(evdev) = &protocol_def.evdev {
+      specifiers.push(ProtocolCommunicationSpecifier::Evdev(*evdev));
+    }
     if let Some(websocket) = &protocol_def.websocket {
       specifiers.push(ProtocolCommunicationSpecifier::Websocket(websocket.clone()));
     }
+    if let Some(network) = &protocol_def.network {
+      specifiers.push(ProtocolCommunicationSpecifier::Network(network.clone()));
+    }
     if let Some(lcs) = &protocol_def.lovense_connect_service {
       specifiers.push(ProtocolCommunicationSpecifier::LovenseConnectService(
         lcs.clone(),
       ));
     }
+    if let Some(federation) = &protocol_def.buttplug_federation {
+      specifiers.push(ProtocolCommunicationSpecifier::ButtplugFederation(
+        federation.clone(),
+      ));
+    }
 
     let mut configurations = HashMap::new();
 
@@ -311,6 +339,9 @@ fn add_user_configs_to_protocol(
       if let Some(websocket) = &protocol_def.websocket {
         base_protocol_def.push(ProtocolCommunicationSpecifier::Websocket(websocket.clone()));
       }
+      if let Some(network) = &protocol_def.network {
+        base_protocol_def.push(ProtocolCommunicationSpecifier::Network(network.clone()));
+      }
     }
   }
   if let Some(user_device_configs) = user_config_def.user_device_configs() {
@@ -347,7 +378,7 @@ fn add_user_configs_to_protocol(
   }
 }
 
-#[derive(Deserialize, Serialize, Debug, CopyGetters)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, CopyGetters)]
 #[getset(get_copy = "pub", get_mut = "pub")]
 pub struct ConfigVersion {
   pub major: u32,
@@ -467,7 +498,11 @@ fn load_protocol_configs_internal(
 
   // Iterate through all of the protocols in the main config first and build up a map of protocol
   // name to ProtocolDeviceConfiguration structs.
+  let mut protocol_priorities = HashMap::new();
   for (protocol_name, protocol_def) in main_config.protocols.unwrap_or_default() {
+    if let Some(priority) = protocol_def.priority {
+      protocol_priorities.insert(protocol_name.clone(), priority);
+    }
     let protocol_device_config: ProtocolDeviceConfiguration = protocol_def.into();
     protocol_specifiers.insert(
       protocol_name.clone(),
@@ -482,6 +517,7 @@ fn load_protocol_configs_internal(
   let mut external_config = ExternalDeviceConfiguration {
     protocol_specifiers,
     protocol_attributes,
+    protocol_priorities,
     ..Default::default()
   };
 
@@ -527,6 +563,10 @@ pub fn load_protocol_configs(
     }
   }
 
+  for (name, priority) in external_config.protocol_priorities() {
+    dcm_builder.protocol_priority(name, *priority);
+  }
+
   for (ident, attributes) in external_config.protocol_attributes() {
     dcm_builder.protocol_attributes(ident.clone(), attributes.clone());
   }
@@ -538,11 +578,746 @@ pub fn load_protocol_configs(
   Ok(dcm_builder)
 }
 
-pub fn load_user_configs(user_config_str: &str) -> UserConfigDefinition {
-  load_protocol_config_from_json(user_config_str, true)
-    .unwrap()
-    .user_configs
-    .unwrap()
+pub fn load_user_configs(
+  user_config_str: &str,
+) -> Result<UserConfigDefinition, ButtplugDeviceError> {
+  Ok(
+    load_protocol_config_from_json(user_config_str, true)?
+      .user_configs
+      .unwrap_or_default(),
+  )
+}
+
+fn config_error<T: Display>(err: T) -> ButtplugDeviceError {
+  ButtplugDeviceError::DeviceConfigurationError(format!("{}", err))
+}
+
+/// Summary of the changes made while migrating a configuration document to the current format.
+#[derive(Debug, Clone, Getters)]
+#[getset(get = "pub")]
+pub struct ConfigMigrationReport {
+  /// Version of the document before migration.
+  from_version: ConfigVersion,
+  /// Version of the document after migration.
+  to_version: ConfigVersion,
+  /// Human readable descriptions of changes, including any values that had to be dropped because
+  /// they have no equivalent in newer formats.
+  changes: Vec<String>,
+}
+
+impl ConfigMigrationReport {
+  /// True if the document needed migration steps to reach the current major version.
+  pub fn migrated(&self) -> bool {
+    self.from_version.major != self.to_version.major
+  }
+}
+
+type ConfigMigrationFn =
+  fn(&mut Map<String, Value>, &mut Vec<String>) -> Result<(), ButtplugDeviceError>;
+
+/// A single migration step, moving a document from `from_major` to `from_major + 1`.
+struct ConfigMigration {
+  from_major: u32,
+  migrate: ConfigMigrationFn,
+}
+
+static CONFIG_MIGRATIONS: &[ConfigMigration] = &[ConfigMigration {
+  from_major: 1,
+  migrate: migrate_config_v1_to_v2,
+}];
+
+/// Version 1 configs stored actuator messages as feature counts and step counts. Convert those to
+/// the per-feature attribute lists used in version 2.
+fn migrate_v1_protocol_messages(
+  context: &str,
+  messages: &Map<String, Value>,
+  changes: &mut Vec<String>,
+) -> Map<String, Value> {
+  let mut new_messages = Map::new();
+  for (message_name, attrs) in messages {
+    let (new_name, actuator_type) = match message_name.as_str() {
+      "VibrateCmd" => ("ScalarCmd", "Vibrate"),
+      "RotateCmd" => ("RotateCmd", "Rotate"),
+      "LinearCmd" => ("LinearCmd", "Position"),
+      "BatteryLevelCmd" | "RSSILevelCmd" => {
+        let (sensor_type, range) = if message_name == "BatteryLevelCmd" {
+          ("Battery", json!([0, 100]))
+        } else {
+          ("RSSI", json!([-128, 0]))
+        };
+        new_messages
+          .entry("SensorReadCmd")
+          .or_insert_with(|| json!([]))
+          .as_array_mut()
+          .expect("We just created this as an array.")
+          .push(json!({
+            "SensorType": sensor_type,
+            "FeatureDescriptor": sensor_type,
+            "SensorRange": [range]
+          }));
+        changes.push(format!(
+          "{}: converted {} to SensorReadCmd.",
+          context, message_name
+        ));
+        continue;
+      }
+      "StopDeviceCmd"
+      | "LovenseCmd"
+      | "VorzeA10CycloneCmd"
+      | "KiirooCmd"
+      | "SingleMotorVibrateCmd"
+      | "FleshlightLaunchFW12Cmd" => {
+        new_messages.insert(message_name.clone(), json!({}));
+        continue;
+      }
+      _ => {
+        changes.push(format!(
+          "{}: dropped {}, which has no version 2 equivalent.",
+          context, message_name
+        ));
+        continue;
+      }
+    };
+    let feature_count = attrs
+      .get("FeatureCount")
+      .and_then(|count| count.as_u64())
+      .unwrap_or(0) as usize;
+    let step_counts = attrs
+      .get("StepCount")
+      .and_then(|steps| steps.as_array())
+      .cloned()
+      .unwrap_or_default();
+    // User configs only stored step ranges, protocol configs stored feature and step counts.
+    let step_ranges: Vec<Value> = if let Some(ranges) = attrs.get("StepRange") {
+      ranges.as_array().cloned().unwrap_or_default()
+    } else {
+      (0..feature_count.max(step_counts.len()))
+        .map(|index| {
+          json!([
+            0,
+            step_counts
+              .get(index)
+              .and_then(|steps| steps.as_u64())
+              .unwrap_or(0)
+          ])
+        })
+        .collect()
+    };
+    if step_ranges.is_empty() {
+      changes.push(format!(
+        "{}: dropped {}, as it had no features.",
+        context, message_name
+      ));
+      continue;
+    }
+    let features = new_messages
+      .entry(new_name)
+      .or_insert_with(|| json!([]))
+      .as_array_mut()
+      .expect("We just created this as an array.");
+    for step_range in step_ranges {
+      features.push(json!({
+        "ActuatorType": actuator_type,
+        "StepRange": step_range
+      }));
+    }
+    if message_name != new_name {
+      changes.push(format!(
+        "{}: converted {} to {} with ActuatorType {}.",
+        context, message_name, new_name, actuator_type
+      ));
+    }
+  }
+  new_messages
+}
+
+fn migrate_v1_protocol_attributes(context: &str, attrs: &mut Value, changes: &mut Vec<String>) {
+  let Some(attrs) = attrs.as_object_mut() else {
+    return;
+  };
+  // Names used to be a map of locale to name. Version 2 only has a single name.
+  if let Some(Value::Object(names)) = attrs.get("name") {
+    let name = names
+      .get("en-us")
+      .or_else(|| names.values().next())
+      .cloned()
+      .unwrap_or_else(|| Value::from(""));
+    attrs.insert("name".to_owned(), name);
+    changes.push(format!(
+      "{}: converted localized names to a single name.",
+      context
+    ));
+  }
+  if let Some(Value::Object(messages)) = attrs.get("messages") {
+    let messages = migrate_v1_protocol_messages(context, messages, changes);
+    attrs.insert("messages".to_owned(), Value::Object(messages));
+  }
+}
+
+fn migrate_config_v1_to_v2(
+  document: &mut Map<String, Value>,
+  changes: &mut Vec<String>,
+) -> Result<(), ButtplugDeviceError> {
+  if let Some(Value::Object(protocols)) = document.get_mut("protocols") {
+    for (protocol_name, protocol) in protocols.iter_mut() {
+      if let Some(defaults) = protocol.get_mut("defaults") {
+        migrate_v1_protocol_attributes(protocol_name, defaults, changes);
+      }
+      if let Some(Value::Array(configurations)) = protocol.get_mut("configurations") {
+        for configuration in configurations {
+          migrate_v1_protocol_attributes(protocol_name, configuration, changes);
+        }
+      }
+    }
+  }
+
+  if let Some(user_config) = document.remove("user-config") {
+    changes.push("Renamed user-config section to user-configs.".to_owned());
+    document.insert("user-configs".to_owned(), user_config);
+  }
+  if let Some(Value::Object(user_configs)) = document.get_mut("user-configs") {
+    // Version 1 device configs were keyed by address alone. Version 2 requires the protocol as well,
+    // so we can only carry over entries that happen to list it.
+    if let Some(Value::Object(devices)) = user_configs.remove("devices") {
+      let mut new_devices = vec![];
+      for (address, mut config) in devices {
+        let Some(config_obj) = config.as_object_mut() else {
+          changes.push(format!(
+            "{}: dropped malformed user device config.",
+            address
+          ));
+          continue;
+        };
+        let Some(protocol) = config_obj.remove("protocol") else {
+          changes.push(format!(
+            "{}: dropped user device config, as it does not specify a protocol.",
+            address
+          ));
+          continue;
+        };
+        if let Some(index) = config_obj.remove("reserved-index") {
+          config_obj.insert("index".to_owned(), index);
+        }
+        if let Some(Value::Object(messages)) = config_obj.remove("messages") {
+          let messages = migrate_v1_protocol_messages(&address, &messages, changes);
+          // Only actuator messages are configurable by users.
+          let messages: Map<String, Value> = messages
+            .into_iter()
+            .filter(|(name, _)| matches!(name.as_str(), "ScalarCmd" | "RotateCmd" | "LinearCmd"))
+            .collect();
+          if !messages.is_empty() {
+            config_obj.insert("messages".to_owned(), Value::Object(messages));
+          }
+        }
+        new_devices.push(json!({
+          "identifier": {
+            "address": address,
+            "protocol": protocol
+          },
+          "config": config
+        }));
+      }
+      user_configs.insert("devices".to_owned(), Value::Array(new_devices));
+    }
+  }
+  Ok(())
+}
+
+fn document_config_version(
+  document: &Map<String, Value>,
+) -> Result<ConfigVersion, ButtplugDeviceError> {
+  match document.get("version") {
+    // Version 1 files used a single incrementing integer for their version.
+    Some(Value::Number(_)) => Ok(ConfigVersion { major: 1, minor: 0 }),
+    Some(version @ Value::Object(_)) => {
+      serde_json::from_value(version.clone()).map_err(config_error)
+    }
+    _ => Err(config_error(
+      "Device configuration file does not have a valid version.",
+    )),
+  }
+}
+
+/// Upgrade a protocol or user device configuration document to the major version used by this
+/// library, one major version at a time.
+///
+/// Returns the migrated document along with a report of what was changed. Documents that are
+/// already at the current major version are returned unchanged. Documents with a newer major
+/// version than this library understands will return an error.
+pub fn migrate_device_config_json(
+  config_str: &str,
+) -> Result<(String, ConfigMigrationReport), ButtplugDeviceError> {
+  let mut document = match serde_json::from_str::<Value>(config_str).map_err(config_error)? {
+    Value::Object(document) => document,
+    _ => return Err(config_error("Device configuration root is not an object.")),
+  };
+  let from_version = document_config_version(&document)?;
+  let internal_version = get_internal_config_version();
+  if from_version.major > internal_version.major {
+    return Err(config_error(format!(
+      "Device configuration file major version {} is newer than internal major version {}, cannot migrate.",
+      from_version, internal_version
+    )));
+  }
+  if from_version.major == internal_version.major {
+    let report = ConfigMigrationReport {
+      from_version,
+      to_version: from_version,
+      changes: vec![],
+    };
+    return Ok((config_str.to_owned(), report));
+  }
+
+  let mut changes = vec![];
+  let mut version = from_version;
+  while version.major < internal_version.major {
+    let migration = CONFIG_MIGRATIONS
+      .iter()
+      .find(|migration| migration.from_major == version.major)
+      .ok_or_else(|| {
+        config_error(format!(
+          "No migration available from device configuration major version {}.",
+          version.major
+        ))
+      })?;
+    (migration.migrate)(&mut document, &mut changes)?;
+    version = ConfigVersion {
+      major: version.major + 1,
+      minor: 0,
+    };
+    changes.push(format!(
+      "Migrated from major version {} to {}.",
+      version.major - 1,
+      version.major
+    ));
+  }
+  document.insert(
+    "version".to_owned(),
+    serde_json::to_value(version).map_err(config_error)?,
+  );
+
+  let migrated_str = serde_json::to_string_pretty(&document).map_err(config_error)?;
+  JSONValidator::new(DEVICE_CONFIGURATION_JSON_SCHEMA)
+    .validate(&migrated_str)
+    .map_err(|err| {
+      config_error(format!(
+        "Migrated device configuration is not valid: {}",
+        err
+      ))
+    })?;
+  Ok((
+    migrated_str,
+    ConfigMigrationReport {
+      from_version,
+      to_version: version,
+      changes,
+    },
+  ))
+}
+
+/// Converts the user configurable portions of a message attribute set (generic actuator commands,
+/// their step ranges and output mappings) into the JSON format used in user config files.
+///
+/// [ServerDeviceMessageAttributes] can't be used with serde directly here, as its serialized form
+/// is meant for the base device config and skips step ranges.
+fn user_message_attributes_to_json(attrs: &ServerDeviceMessageAttributes) -> Value {
+  let mut messages = Map::new();
+  for (message_type, features) in [
+    (ButtplugDeviceMessageType::ScalarCmd, attrs.scalar_cmd()),
+    (ButtplugDeviceMessageType::RotateCmd, attrs.rotate_cmd()),
+    (ButtplugDeviceMessageType::LinearCmd, attrs.linear_cmd()),
+  ] {
+    if let Some(features) = features {
+      let features: Vec<Value> = features
+        .iter()
+        .map(|feature| {
+          let mut feature_json = json!({
+            "FeatureDescriptor": feature.feature_descriptor(),
+            "ActuatorType": feature.actuator_type(),
+            "StepRange": [feature.step_range().start(), feature.step_range().end()],
+          });
+          if let Some(output_mapping) = feature.output_mapping() {
+            feature_json["OutputMapping"] = json!(output_mapping);
+          }
+          feature_json
+        })
+        .collect();
+      messages.insert(message_type.to_string(), Value::Array(features));
+    }
+  }
+  Value::Object(messages)
+}
+
+/// Loads, edits and saves user device configuration files.
+///
+/// The store keeps the whole JSON document it was loaded from, and only rewrites the values that
+/// are changed through its API. This means fields the library doesn't know about (either from newer
+/// config versions, or from other applications sharing the file) survive a load/save round trip.
+///
+/// All device entries are keyed by [UserConfigDeviceIdentifier]. Every edit is validated against
+/// the device configuration schema when the store is saved via [UserConfigStore::to_json].
+#[derive(Debug, Clone)]
+pub struct UserConfigStore {
+  document: Value,
+}
+
+impl Default for UserConfigStore {
+  fn default() -> Self {
+    Self {
+      document: json!({
+        "version": get_internal_config_version(),
+        "user-configs": {}
+      }),
+    }
+  }
+}
+
+impl UserConfigStore {
+  /// Load a store from a user configuration JSON string. The string is validated against the device
+  /// configuration schema before being accepted.
+  pub fn from_json(config_str: &str) -> Result<Self, ButtplugDeviceError> {
+    JSONValidator::new(DEVICE_CONFIGURATION_JSON_SCHEMA)
+      .validate(config_str)
+      .map_err(config_error)?;
+    let document: Value = serde_json::from_str(config_str).map_err(config_error)?;
+    Ok(Self { document })
+  }
+
+  /// Serialize the store to a JSON string, failing if the result would not pass schema validation.
+  pub fn to_json(&self) -> Result<String, ButtplugDeviceError> {
+    let config_str = serde_json::to_string_pretty(&self.document).map_err(config_error)?;
+    JSONValidator::new(DEVICE_CONFIGURATION_JSON_SCHEMA)
+      .validate(&config_str)
+      .map_err(config_error)?;
+    Ok(config_str)
+  }
+
+  /// Returns the typed form of the user configs in this store.
+  pub fn user_configs(&self) -> Result<UserConfigDefinition, ButtplugDeviceError> {
+    match self.document.get("user-configs") {
+      Some(user_configs) => serde_json::from_value(user_configs.clone()).map_err(config_error),
+      None => Ok(UserConfigDefinition::default()),
+    }
+  }
+
+  /// Returns the identifiers of all devices that have configurations in this store.
+  pub fn device_identifiers(&self) -> Result<Vec<UserConfigDeviceIdentifier>, ButtplugDeviceError> {
+    Ok(
+      self
+        .user_configs()?
+        .user_device_configs()
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|pair| pair.identifier)
+        .collect(),
+    )
+  }
+
+  /// Returns the configuration for a device, if one exists.
+  pub fn device_config(
+    &self,
+    identifier: &UserConfigDeviceIdentifier,
+  ) -> Result<Option<UserDeviceConfig>, ButtplugDeviceError> {
+    Ok(
+      self
+        .user_configs()?
+        .user_device_configs()
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|pair| pair.identifier == *identifier)
+        .map(|pair| pair.config),
+    )
+  }
+
+  fn device_entries(&mut self) -> Result<&mut Vec<Value>, ButtplugDeviceError> {
+    let root = self
+      .document
+      .as_object_mut()
+      .ok_or_else(|| config_error("User configuration root is not an object."))?;
+    let devices = root
+      .entry("user-configs")
+      .or_insert_with(|| json!({}))
+      .as_object_mut()
+      .ok_or_else(|| config_error("user-configs entry is not an object."))?
+      .entry("devices")
+      .or_insert_with(|| json!([]));
+    devices
+      .as_array_mut()
+      .ok_or_else(|| config_error("user-configs devices entry is not an array."))
+  }
+
+  fn device_entry_position(
+    entries: &[Value],
+    identifier: &UserConfigDeviceIdentifier,
+  ) -> Option<usize> {
+    entries.iter().position(|entry| {
+      entry
+        .get("identifier")
+        .cloned()
+        .and_then(|ident| serde_json::from_value::<UserConfigDeviceIdentifier>(ident).ok())
+        .is_some_and(|ident| ident == *identifier)
+    })
+  }
+
+  /// Returns the raw JSON config object for a device, creating an empty one if it doesn't exist.
+  fn device_config_object(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+  ) -> Result<&mut Map<String, Value>, ButtplugDeviceError> {
+    let entries = self.device_entries()?;
+    let position = match Self::device_entry_position(entries, identifier) {
+      Some(position) => position,
+      None => {
+        entries.push(json!({
+          "identifier": identifier,
+          "config": {}
+        }));
+        entries.len() - 1
+      }
+    };
+    entries[position]
+      .as_object_mut()
+      .ok_or_else(|| config_error("User device config entry is not an object."))?
+      .entry("config")
+      .or_insert_with(|| json!({}))
+      .as_object_mut()
+      .ok_or_else(|| config_error("User device config is not an object."))
+  }
+
+  /// Update the configuration for a device, creating it if it doesn't exist.
+  ///
+  /// Only values that are changed by `update` are rewritten in the underlying document.
+  pub fn update_device_config<F>(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+    update: F,
+  ) -> Result<(), ButtplugDeviceError>
+  where
+    F: FnOnce(&mut UserDeviceConfig),
+  {
+    let old_config = self.device_config(identifier)?.unwrap_or_default();
+    let mut new_config = old_config.clone();
+    update(&mut new_config);
+
+    let config_object = self.device_config_object(identifier)?;
+    let mut set_or_remove = |key: &str, value: Option<Value>| {
+      if let Some(value) = value {
+        config_object.insert(key.to_owned(), value);
+      } else {
+        config_object.remove(key);
+      }
+    };
+    set_or_remove(
+      "display-name",
+      new_config.display_name.clone().map(Value::from),
+    );
+    set_or_remove("allow", new_config.allow.map(Value::from));
+    set_or_remove("deny", new_config.deny.map(Value::from));
+    set_or_remove("index", new_config.index.map(Value::from));
+    if old_config.messages != new_config.messages {
+      set_or_remove(
+        "messages",
+        new_config
+          .messages
+          .as_ref()
+          .map(user_message_attributes_to_json),
+      );
+    }
+    Ok(())
+  }
+
+  /// Remove the configuration for a device, returning it if it existed.
+  pub fn remove_device_config(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+  ) -> Result<Option<UserDeviceConfig>, ButtplugDeviceError> {
+    let config = self.device_config(identifier)?;
+    if config.is_some() {
+      let entries = self.device_entries()?;
+      if let Some(position) = Self::device_entry_position(entries, identifier) {
+        entries.remove(position);
+      }
+    }
+    Ok(config)
+  }
+
+  /// Set or clear the user defined display name for a device.
+  pub fn set_display_name(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+    display_name: Option<String>,
+  ) -> Result<(), ButtplugDeviceError> {
+    self.update_device_config(identifier, |config| config.display_name = display_name)
+  }
+
+  /// Set whether a device is on the allow list.
+  pub fn set_allow(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+    allow: bool,
+  ) -> Result<(), ButtplugDeviceError> {
+    self.update_device_config(identifier, |config| config.allow = allow.then_some(true))
+  }
+
+  /// Set whether a device is on the deny list.
+  pub fn set_deny(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+    deny: bool,
+  ) -> Result<(), ButtplugDeviceError> {
+    self.update_device_config(identifier, |config| config.deny = deny.then_some(true))
+  }
+
+  /// Set or clear the reserved device index for a device. Fails if the index is already reserved by
+  /// another device in this store.
+  pub fn set_index(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+    index: Option<u32>,
+  ) -> Result<(), ButtplugDeviceError> {
+    if let Some(index) = index {
+      if let Some(pair) = self
+        .user_configs()?
+        .user_device_configs()
+        .clone()
+        .unwrap_or_default()
+        .iter()
+        .find(|pair| pair.identifier != *identifier && pair.config.index == Some(index))
+      {
+        return Err(config_error(format!(
+          "Device index {} is already reserved for {:?}.",
+          index, pair.identifier
+        )));
+      }
+    }
+    self.update_device_config(identifier, |config| config.index = index)
+  }
+
+  /// Set the step range limit for a single feature of a device's configured messages.
+  ///
+  /// The device must already have a user configured message attribute list for the message type,
+  /// as user configs require the full feature description (actuator type, etc...) to be valid.
+  pub fn set_step_range(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+    message_type: ButtplugDeviceMessageType,
+    feature_index: usize,
+    step_range: RangeInclusive<u32>,
+  ) -> Result<(), ButtplugDeviceError> {
+    if step_range.is_empty() {
+      return Err(config_error(format!(
+        "Step range out of order for {}, must be start <= x <= end.",
+        message_type
+      )));
+    }
+    self
+      .user_feature_object(identifier, message_type, feature_index)?
+      .insert(
+        "StepRange".to_owned(),
+        json!([step_range.start(), step_range.end()]),
+      );
+    Ok(())
+  }
+
+  /// Set or clear the output mapping for a single feature of a device's configured messages.
+  ///
+  /// As with [UserConfigStore::set_step_range], the device must already have a user configured
+  /// message attribute list for the message type.
+  pub fn set_output_mapping(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+    message_type: ButtplugDeviceMessageType,
+    feature_index: usize,
+    output_mapping: Option<FeatureOutputMapping>,
+  ) -> Result<(), ButtplugDeviceError> {
+    let feature = self.user_feature_object(identifier, message_type, feature_index)?;
+    if let Some(output_mapping) = output_mapping {
+      output_mapping.is_valid()?;
+      feature.insert("OutputMapping".to_owned(), json!(output_mapping));
+    } else {
+      feature.remove("OutputMapping");
+    }
+    Ok(())
+  }
+
+  /// Returns the raw JSON object for a user configured actuator feature.
+  fn user_feature_object(
+    &mut self,
+    identifier: &UserConfigDeviceIdentifier,
+    message_type: ButtplugDeviceMessageType,
+    feature_index: usize,
+  ) -> Result<&mut Map<String, Value>, ButtplugDeviceError> {
+    if !matches!(
+      message_type,
+      ButtplugDeviceMessageType::ScalarCmd
+        | ButtplugDeviceMessageType::RotateCmd
+        | ButtplugDeviceMessageType::LinearCmd
+    ) {
+      return Err(config_error(format!(
+        "{} does not have user configurable features.",
+        message_type
+      )));
+    }
+    if self.device_config(identifier)?.is_none() {
+      return Err(config_error(format!(
+        "No user config exists for {:?}.",
+        identifier
+      )));
+    }
+    self
+      .device_config_object(identifier)?
+      .get_mut("messages")
+      .and_then(|messages| messages.get_mut(message_type.to_string()))
+      .and_then(|features| features.get_mut(feature_index))
+      .and_then(|feature| feature.as_object_mut())
+      .ok_or_else(|| {
+        config_error(format!(
+          "No user configured {} feature at index {} for {:?}.",
+          message_type, feature_index, identifier
+        ))
+      })
+  }
+
+  /// Add all devices known to a device manager (either via its device configuration or by having
+  /// connected during this session) to the store, along with their reserved device indexes.
+  ///
+  /// Existing entries keep their settings, other than their index, which is updated to match the
+  /// device manager.
+  pub fn add_known_devices(
+    &mut self,
+    device_manager: &ServerDeviceManager,
+  ) -> Result<(), ButtplugDeviceError> {
+    let dcm = device_manager.device_configuration_manager();
+    let mut reserved_indexes: Vec<(ServerDeviceIdentifier, u32)> =
+      dcm.reserved_indexes().into_iter().collect();
+    reserved_indexes.sort_by_key(|(_, index)| *index);
+    for (server_identifier, index) in reserved_indexes {
+      let display_name = dcm
+        .protocol_device_attributes(&server_identifier, &[])
+        .and_then(|attrs| attrs.display_name());
+      let identifier: UserConfigDeviceIdentifier = server_identifier.into();
+      // Indexes that have been reused by a different device in the manager shouldn't conflict with
+      // what we're about to write, so clear them from other entries first.
+      for other in self.device_identifiers()? {
+        if other != identifier
+          && self
+            .device_config(&other)?
+            .is_some_and(|config| config.index == Some(index))
+        {
+          self.update_device_config(&other, |config| config.index = None)?;
+        }
+      }
+      self.update_device_config(&identifier, |config| {
+        config.index = Some(index);
+        if config.display_name.is_none() {
+          config.display_name = display_name;
+        }
+      })?;
+    }
+    Ok(())
+  }
 }
 
 pub fn create_test_dcm(allow_raw_messages: bool) -> DeviceConfigurationManager {
@@ -17,16 +17,14 @@ pub mod logging;
 pub mod stream;
 
 #[cfg(not(feature = "wasm"))]
-pub use tokio::time::sleep;
+pub use tokio::time::{sleep, Instant};
 #[cfg(feature = "wasm")]
-pub use wasmtimer::tokio::sleep;
+pub use wasmtimer::{std::Instant, tokio::sleep};
 
+#[cfg(feature = "server")]
+use crate::server::ButtplugServerBuilder;
 #[cfg(all(feature = "server", feature = "client"))]
-use crate::{
-  client::ButtplugClient,
-  core::connector::ButtplugInProcessClientConnectorBuilder,
-  server::ButtplugServerBuilder,
-};
+use crate::{client::ButtplugClient, core::connector::ButtplugInProcessClientConnectorBuilder};
 
 /// Convenience function for creating in-process connectors.
 ///
@@ -64,6 +62,22 @@ use crate::{
 /// `run()` method to pass it in.
 #[cfg(all(feature = "server", feature = "client"))]
 pub async fn in_process_client(client_name: &str, allow_raw_messages: bool) -> ButtplugClient {
+  let server = default_server_builder(allow_raw_messages).finish().unwrap();
+  let connector = ButtplugInProcessClientConnectorBuilder::default()
+    .server(server)
+    .finish();
+  let client = ButtplugClient::new(client_name);
+  client.connect(connector).await.unwrap();
+  client
+}
+
+/// Creates a [ButtplugServerBuilder] with all device managers that ship with the library and work
+/// on the current platform added to it already.
+///
+/// This is the server setup used by [in_process_client], for when the server needs to be built or
+/// connected separately. The same caveats apply: the set of device managers may change at any time.
+#[cfg(feature = "server")]
+pub fn default_server_builder(allow_raw_messages: bool) -> ButtplugServerBuilder {
   let mut server_builder = ButtplugServerBuilder::default();
 
   #[cfg(all(
@@ -120,11 +134,5 @@ pub async fn in_process_client(client_name: &str, allow_raw_messages: bool) -> B
   if allow_raw_messages {
     server_builder.allow_raw_messages();
   }
-  let server = server_builder.finish().unwrap();
-  let connector = ButtplugInProcessClientConnectorBuilder::default()
-    .server(server)
-    .finish();
-  let client = ButtplugClient::new(client_name);
-  client.connect(connector).await.unwrap();
-  client
+  server_builder
 }
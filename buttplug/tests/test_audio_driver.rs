@@ -0,0 +1,240 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+#[cfg(all(feature = "audio-driver", feature = "testing"))]
+mod audio_driver_tests {
+  use buttplug::{
+    client::{
+      audio_driver::{
+        envelope::{AudioEnvelopeFollower, AudioEnvelopeSettings},
+        wav::WavAudio,
+        AudioDriverTarget,
+        ButtplugAudioDriver,
+        ButtplugAudioDriverBuilder,
+      },
+      ButtplugClient,
+      ButtplugClientEvent,
+      DeviceGroupMembership,
+    },
+    core::message::ActuatorType,
+    testing::{ButtplugTestServerBuilder, FakeDevice, FakeDeviceBuilder},
+  };
+  use futures::StreamExt;
+  use instant::Instant;
+  use std::{f64::consts::PI, sync::Arc, time::Duration};
+  use tokio::time::timeout;
+
+  const SAMPLE_RATE: u32 = 8000;
+
+  /// Builds a 400Hz tone, with one amplitude per 100ms window.
+  fn synthetic_wav(amplitudes: &[f64]) -> WavAudio {
+    let window = SAMPLE_RATE as usize / 10;
+    let samples = amplitudes
+      .iter()
+      .flat_map(|amplitude| {
+        (0..window).map(move |i| {
+          (amplitude * (2.0 * PI * 400.0 * i as f64 / SAMPLE_RATE as f64).sin()) as f32
+        })
+      })
+      .collect::<Vec<f32>>();
+    // Round trip through a file written by another encoder, so the WAV decoding is covered too.
+    let path = std::env::temp_dir().join(format!(
+      "buttplug-audio-test-{}-{}.wav",
+      std::process::id(),
+      amplitudes.len()
+    ));
+    let spec = hound::WavSpec {
+      channels: 1,
+      sample_rate: SAMPLE_RATE,
+      bits_per_sample: 16,
+      sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).expect("Test, assuming infallible.");
+    for sample in samples {
+      writer
+        .write_sample((sample * i16::MAX as f32).round() as i16)
+        .expect("Test, assuming infallible.");
+    }
+    writer.finalize().expect("Test, assuming infallible.");
+    let audio = WavAudio::read_file(&path).expect("Test, assuming infallible.");
+    let _ = std::fs::remove_file(&path);
+    audio
+  }
+
+  fn envelope_settings() -> AudioEnvelopeSettings {
+    // A full scale sine wave comes out as 1.0.
+    let mut settings = AudioEnvelopeSettings::default();
+    settings.update_rate(10.0).gain(2f64.sqrt());
+    settings
+  }
+
+  fn assert_levels(levels: &[f64], expected: &[f64]) {
+    assert_eq!(levels.len(), expected.len(), "{:?}", levels);
+    for (level, expected) in levels.iter().zip(expected) {
+      assert!(
+        (level - expected).abs() < 0.001,
+        "{:?} != {:?}",
+        levels,
+        expected
+      );
+    }
+  }
+
+  async fn test_driver(targets: Vec<AudioDriverTarget>) -> (ButtplugAudioDriver, FakeDevice) {
+    let mut builder = ButtplugTestServerBuilder::default();
+    let fake_device = builder.add_device(
+      FakeDeviceBuilder::new("Audio Toy")
+        .scalar(ActuatorType::Vibrate, 20)
+        .scalar(ActuatorType::Vibrate, 20)
+        .scalar(ActuatorType::Oscillate, 10),
+    );
+    let client = Arc::new(ButtplugClient::new("Test Client"));
+    let mut event_stream = client.event_stream();
+    client
+      .connect(builder.connector().expect("Test, assuming infallible."))
+      .await
+      .expect("Test, assuming infallible.");
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    loop {
+      if let ButtplugClientEvent::DeviceAdded(_) =
+        timeout(Duration::from_secs(5), event_stream.next())
+          .await
+          .expect("Device never showed up.")
+          .expect("Test, assuming infallible.")
+      {
+        break;
+      }
+    }
+    let mut driver_builder = ButtplugAudioDriverBuilder::default();
+    driver_builder
+      .envelope(&envelope_settings())
+      .sample_rate(SAMPLE_RATE);
+    for target in targets {
+      driver_builder.target(target);
+    }
+    (driver_builder.finish(client), fake_device)
+  }
+
+  #[test]
+  fn test_audio_envelope_from_wav() {
+    let audio = synthetic_wav(&[0.0, 0.5, 0.5, 1.0, 0.25, 0.0]);
+    assert_eq!(audio.sample_rate(), SAMPLE_RATE);
+    assert_eq!(audio.frames(), 4800);
+    let mut follower = AudioEnvelopeFollower::new(&envelope_settings(), audio.sample_rate());
+    assert_levels(
+      &follower.process(&audio.mono_samples()),
+      &[0.0, 0.5, 0.5, 1.0, 0.25, 0.0],
+    );
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_audio_driver_pushed_samples() {
+    let (driver, fake_device) = test_driver(vec![
+      AudioDriverTarget::new(
+        DeviceGroupMembership::ActuatorType(ActuatorType::Vibrate),
+        ActuatorType::Vibrate,
+        Some(0),
+      ),
+      AudioDriverTarget::new(
+        DeviceGroupMembership::Name("Audio Toy".to_owned()),
+        ActuatorType::Oscillate,
+        None,
+      ),
+    ])
+    .await;
+    let audio = synthetic_wav(&[0.5, 0.5, 1.0, 0.0, 0.0]);
+    let samples = audio.mono_samples();
+    let mut levels = vec![];
+    // Push in buffers that don't line up with windows, in real time, like a capture device would.
+    for buffer in samples.chunks(300) {
+      if let Some(level) = driver.push_samples(buffer).await {
+        levels.push(level);
+      }
+      tokio::time::advance(Duration::from_micros(37500)).await;
+    }
+    assert_levels(&levels, &[0.5, 0.5, 1.0, 0.0, 0.0]);
+    // Repeated levels aren't resent, and features not targeted are left alone.
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5, 1.0, 0.0]);
+    assert!(fake_device.scalar_levels(1).is_empty());
+    assert_eq!(fake_device.scalar_levels(2), vec![0.5, 1.0, 0.0]);
+
+    fake_device.clear_commands();
+    assert_levels(
+      &[driver
+        .push_samples(&samples[..800])
+        .await
+        .expect("Test, assuming infallible.")],
+      &[0.5],
+    );
+    driver.stop().await;
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5, 0.0]);
+    assert_eq!(fake_device.scalar_levels(2), vec![0.5, 0.0]);
+  }
+
+  #[tokio::test(start_paused = true)]
+  async fn test_audio_driver_push_rate_limit() {
+    let (driver, fake_device) = test_driver(vec![AudioDriverTarget::new(
+      DeviceGroupMembership::ActuatorType(ActuatorType::Vibrate),
+      ActuatorType::Vibrate,
+      Some(0),
+    )])
+    .await;
+    let audio = synthetic_wav(&[0.5, 1.0, 0.25]);
+    let samples = audio.mono_samples();
+    // Windows pushed faster than real time are computed, but only the first is sent right away.
+    let mut levels = vec![];
+    for window in samples.chunks(800) {
+      levels.push(
+        driver
+          .push_samples(window)
+          .await
+          .expect("Test, assuming infallible."),
+      );
+    }
+    assert_levels(&levels, &[0.5, 1.0, 0.25]);
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5]);
+
+    // The latest level is held until an update window has passed, then sent by the next push.
+    tokio::time::advance(Duration::from_millis(50)).await;
+    assert_eq!(driver.push_samples(&[]).await, None);
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5]);
+    tokio::time::advance(Duration::from_millis(50)).await;
+    assert_eq!(driver.push_samples(&[]).await, None);
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5, 0.25]);
+
+    // Stopping drops any held level.
+    driver
+      .push_samples(&samples[800..1600])
+      .await
+      .expect("Test, assuming infallible.");
+    driver.stop().await;
+    tokio::time::advance(Duration::from_millis(100)).await;
+    driver.push_samples(&[]).await;
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5, 0.25, 0.0]);
+  }
+
+  #[tokio::test]
+  async fn test_audio_driver_play_wav() {
+    let (driver, fake_device) = test_driver(vec![AudioDriverTarget::new(
+      DeviceGroupMembership::ActuatorType(ActuatorType::Vibrate),
+      ActuatorType::Vibrate,
+      None,
+    )])
+    .await;
+    let audio = synthetic_wav(&[0.25, 0.75, 0.75]);
+    let start_time = Instant::now();
+    driver.play_wav(&audio).await;
+    // Playback runs in real time.
+    assert!(start_time.elapsed() >= Duration::from_millis(300));
+    for feature in 0..2 {
+      assert_eq!(fake_device.scalar_levels(feature), vec![0.25, 0.75, 0.0]);
+    }
+  }
+}
@@ -0,0 +1,230 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(all(feature = "blocking-client", feature = "server"))]
+mod blocking_client_tests {
+  use super::util::{
+    test_device_manager::TestDeviceIdentifier,
+    TestDeviceChannelHost,
+    TestDeviceCommunicationManagerBuilder,
+    TestHardwareEvent,
+  };
+  use buttplug::{
+    client::{
+      blocking::{
+        ButtplugBlockingClient,
+        ButtplugBlockingClientDevice,
+        ButtplugBlockingClientEvent,
+        ButtplugBlockingClientEventIter,
+      },
+      ButtplugClientDeviceEvent,
+      ScalarValueCommand,
+    },
+    core::{
+      connector::{
+        ButtplugConnector,
+        ButtplugConnectorError,
+        ButtplugConnectorResultFuture,
+        ButtplugInProcessClientConnectorBuilder,
+      },
+      message::{ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage, Endpoint},
+    },
+    server::{
+      device::hardware::{HardwareCommand, HardwareWriteCmd},
+      ButtplugServerBuilder,
+    },
+  };
+  use futures::future::BoxFuture;
+  use std::{sync::mpsc, thread, time::Duration};
+  use tokio::sync::mpsc::Sender;
+
+  const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+  #[derive(Default)]
+  struct ButtplugFailingConnector {}
+
+  impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+    for ButtplugFailingConnector
+  {
+    fn connect(
+      &mut self,
+      _: Sender<ButtplugCurrentSpecServerMessage>,
+    ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+      ButtplugConnectorError::ConnectorNotConnected.into()
+    }
+
+    fn disconnect(&self) -> ButtplugConnectorResultFuture {
+      ButtplugConnectorError::ConnectorNotConnected.into()
+    }
+
+    fn send(&self, _msg: ButtplugCurrentSpecClientMessage) -> ButtplugConnectorResultFuture {
+      panic!("Should never be called")
+    }
+  }
+
+  fn connect_client(client: &ButtplugBlockingClient, mut server_builder: ButtplugServerBuilder) {
+    let connector = {
+      // The server spawns its tasks on creation, so it needs to be built on the client's runtime.
+      let _guard = client.enter();
+      ButtplugInProcessClientConnectorBuilder::default()
+        .server(server_builder.finish().expect("Test, assuming infallible."))
+        .finish()
+    };
+    assert!(!client.connected());
+    client
+      .connect(connector)
+      .expect("Test, assuming infallible.");
+    assert!(client.connected());
+  }
+
+  fn test_client() -> ButtplugBlockingClient {
+    let client = ButtplugBlockingClient::new("Test Client");
+    connect_client(&client, ButtplugServerBuilder::default());
+    client
+  }
+
+  fn test_client_with_device() -> (ButtplugBlockingClient, TestDeviceChannelHost) {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    let device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+    let client = ButtplugBlockingClient::new("Test Client");
+    let mut server_builder = ButtplugServerBuilder::default();
+    server_builder.comm_manager(builder);
+    connect_client(&client, server_builder);
+    (client, device)
+  }
+
+  fn wait_for_device(events: &mut ButtplugBlockingClientEventIter) -> ButtplugBlockingClientDevice {
+    loop {
+      match events
+        .next_timeout(EVENT_TIMEOUT)
+        .expect("Device never showed up.")
+      {
+        ButtplugBlockingClientEvent::DeviceAdded(device) => return device,
+        _ => continue,
+      }
+    }
+  }
+
+  #[test]
+  fn test_blocking_failing_connection() {
+    let client = ButtplugBlockingClient::new("Test Client");
+    assert!(client.connect(ButtplugFailingConnector::default()).is_err());
+    assert!(!client.connected());
+  }
+
+  #[test]
+  fn test_blocking_connect_init() {
+    let client = test_client();
+    assert_eq!(client.server_name(), Some("Buttplug Server".to_owned()));
+  }
+
+  #[test]
+  fn test_blocking_disconnect_status() {
+    let client = test_client();
+    assert!(client.disconnect().is_ok());
+    assert!(!client.connected());
+    assert!(client.disconnect().is_err());
+  }
+
+  #[test]
+  fn test_blocking_ping() {
+    let client = ButtplugBlockingClient::new("Test Client");
+    let mut server_builder = ButtplugServerBuilder::default();
+    server_builder.max_ping_time(200);
+    connect_client(&client, server_builder);
+    assert!(client.ping().is_ok());
+    thread::sleep(Duration::from_millis(800));
+    assert!(client.ping().is_err());
+  }
+
+  #[test]
+  fn test_blocking_scanning_finished() {
+    let (client, _) = test_client_with_device();
+    let mut events = client.event_iter();
+    assert!(client.start_scanning().is_ok());
+    loop {
+      match events
+        .next_timeout(EVENT_TIMEOUT)
+        .expect("Scanning never finished.")
+      {
+        ButtplugBlockingClientEvent::ScanningFinished => break,
+        _ => continue,
+      }
+    }
+  }
+
+  #[test]
+  fn test_blocking_device_commands_and_removal() {
+    let (client, mut test_device) = test_client_with_device();
+    let mut events = client.event_iter();
+    client.start_scanning().expect("Test, assuming infallible.");
+    let device = wait_for_device(&mut events);
+    assert_eq!(device.name(), "Aneros Vivi");
+    assert_eq!(client.devices(), vec![device.clone()]);
+    assert!(device.connected());
+
+    device
+      .vibrate(&ScalarValueCommand::ScalarValue(0.5))
+      .expect("Test, assuming infallible.");
+    for expected in [vec![0xF1, 64], vec![0xF2, 64]] {
+      assert_eq!(
+        test_device
+          .receiver
+          .blocking_recv()
+          .expect("Test, assuming infallible."),
+        HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, expected, false))
+      );
+    }
+    assert!(device.battery_level().is_err());
+
+    let mut device_events = device.event_iter();
+    test_device
+      .sender
+      .blocking_send(TestHardwareEvent::Disconnect)
+      .expect("Test, assuming infallible.");
+    loop {
+      match device_events
+        .next_timeout(EVENT_TIMEOUT)
+        .expect("Device was never removed.")
+      {
+        ButtplugClientDeviceEvent::DeviceRemoved => break,
+        _ => continue,
+      }
+    }
+    assert!(!device.connected());
+  }
+
+  #[test]
+  fn test_blocking_event_callback() {
+    let (client, _test_device) = test_client_with_device();
+    let (sender, receiver) = mpsc::channel();
+    client.on_event(move |event| {
+      if let ButtplugBlockingClientEvent::DeviceAdded(device) = event {
+        // Blocking calls work from inside callbacks.
+        let result = device.vibrate(&ScalarValueCommand::ScalarValue(1.0));
+        let _ = sender.send((device, result.is_ok()));
+      }
+    });
+    client.start_scanning().expect("Test, assuming infallible.");
+    let (device, vibrated) = receiver
+      .recv_timeout(EVENT_TIMEOUT)
+      .expect("Callback was never called.");
+    assert_eq!(device.name(), "Aneros Vivi");
+    assert!(vibrated);
+  }
+
+  // Applications that already run a runtime may still call into the blocking client, as long as
+  // they're fine with blocking that runtime's thread.
+  #[tokio::test(flavor = "multi_thread")]
+  async fn test_blocking_client_inside_runtime() {
+    let client = test_client();
+    assert!(client.stop_all_devices().is_ok());
+    assert!(client.disconnect().is_ok());
+  }
+}
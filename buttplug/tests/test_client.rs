@@ -6,7 +6,13 @@
 // for full license information.
 
 mod util;
-use util::{test_client, test_client_with_delayed_device_manager, test_client_with_device};
+use util::{
+  test_client,
+  test_client_with_delayed_device_manager,
+  test_client_with_device,
+  test_device_manager::TestDeviceIdentifier,
+  TestDeviceCommunicationManagerBuilder,
+};
 extern crate buttplug;
 extern crate tracing;
 
@@ -20,9 +26,13 @@ use buttplug::{
       ButtplugInProcessClientConnectorBuilder,
     },
     errors::{ButtplugDeviceError, ButtplugError},
-    message::{ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage},
+    message::{
+      ButtplugCurrentSpecClientMessage,
+      ButtplugCurrentSpecServerMessage,
+      UnsupportedDeviceReason,
+    },
   },
-  server::ButtplugServerBuilder,
+  server::{authenticator::SharedTokenAuthenticator, ButtplugServerBuilder},
   util::async_manager,
 };
 
@@ -148,6 +158,61 @@ async fn test_client_scanning_finished() {
   ));
 }
 
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_unsupported_device_found() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _device = builder.add_test_device(&TestDeviceIdentifier::new("Not A Real Toy", None));
+  let mut server_builder = ButtplugServerBuilder::default();
+  server_builder.comm_manager(builder);
+  let connector = ButtplugInProcessClientConnectorBuilder::default()
+    .server(server_builder.finish().expect("Test, assuming infallible."))
+    .finish();
+  let client = ButtplugClient::new("Test Client");
+  client
+    .connect(connector)
+    .await
+    .expect("Test, assuming infallible.");
+  let mut recv = client.event_stream();
+  assert!(client.report_unsupported_devices(true).await.is_ok());
+  assert!(client.start_scanning().await.is_ok());
+  while let Some(event) = recv.next().await {
+    if let ButtplugClientEvent::UnsupportedDeviceFound(msg) = event {
+      assert_eq!(msg.device_name(), "Not A Real Toy");
+      assert_eq!(msg.reason(), UnsupportedDeviceReason::NoMatchingProtocol);
+      return;
+    }
+  }
+  panic!("Unsupported device was never reported.");
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_auth_token() {
+  let connector = || {
+    ButtplugInProcessClientConnectorBuilder::default()
+      .server(
+        ButtplugServerBuilder::default()
+          .authenticator(SharedTokenAuthenticator::new("secret"))
+          .finish()
+          .expect("Test, assuming infallible."),
+      )
+      .finish()
+  };
+  let client = ButtplugClient::new("Test Client");
+  assert!(client.connect(connector()).await.is_err());
+  assert!(!client.connected());
+  let client = ButtplugClient::new_with_auth_token("Test Client", "wrong");
+  assert!(client.connect(connector()).await.is_err());
+  assert!(!client.connected());
+  let client = ButtplugClient::new_with_auth_token("Test Client", "secret");
+  client
+    .connect(connector())
+    .await
+    .expect("Test, assuming infallible.");
+  assert!(client.connected());
+}
+
 #[cfg(feature = "server")]
 #[tokio::test]
 async fn test_client_ping() {
@@ -8,21 +8,47 @@
 mod util;
 use buttplug::{
   client::{
+    ButtplugClient,
+    ButtplugClientDevice,
     ButtplugClientDeviceEvent,
     ButtplugClientError,
     ButtplugClientEvent,
+    RampEasing,
+    ScalarCommand,
     ScalarValueCommand,
   },
   core::{
+    connector::ButtplugInProcessClientConnectorBuilder,
     errors::{ButtplugDeviceError, ButtplugError, ButtplugMessageError},
-    message::{self, ButtplugClientMessage, ClientDeviceMessageAttributes},
+    message::{
+      self,
+      ActuatorType,
+      ButtplugClientMessage,
+      ButtplugDeviceMessageType,
+      ClientDeviceMessageAttributes,
+      Endpoint,
+      SensorType,
+    },
+  },
+  server::{
+    device::hardware::{HardwareCommand, HardwareWriteCmd},
+    ButtplugServerBuilder,
   },
   util::async_manager,
 };
 use futures::StreamExt;
-use std::{sync::Arc, time::Duration};
-use tokio::time::sleep;
-use util::{test_client_with_device, test_device_manager::TestHardwareEvent};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::mpsc, time::sleep};
+use util::{
+  test_client_with_device,
+  test_device_manager::{
+    TestDeviceCommunicationManagerBuilder,
+    TestDeviceIdentifier,
+    TestHardwareEvent,
+    TestHardwareNotification,
+  },
+  wait_for_device_added,
+};
 
 #[cfg(feature = "server")]
 #[tokio::test]
@@ -278,6 +304,377 @@ async fn test_client_repeated_deviceremoved_message() {
   ));
 }
 
+async fn wait_for_client_device(client: &ButtplugClient) -> Arc<ButtplugClientDevice> {
+  let mut event_stream = client.event_stream();
+  client
+    .start_scanning()
+    .await
+    .expect("Test, assuming infallible.");
+  wait_for_device_added(&mut event_stream).await
+}
+
+// Collects the levels written for the first vibrator of the test device (an Aneros Vivi, which
+// writes [0xF1, level]), until `last_level` is written.
+async fn collect_vibrator_levels(
+  receiver: &mut mpsc::Receiver<HardwareCommand>,
+  last_level: u8,
+) -> Vec<u8> {
+  let mut levels = vec![];
+  loop {
+    let command = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+      .await
+      .expect("Ramp never reached its target.")
+      .expect("Test, assuming infallible.");
+    if let HardwareCommand::Write(write) = command {
+      assert_eq!(
+        write.data()[0],
+        0xF1,
+        "Only the first vibrator should move."
+      );
+      levels.push(write.data()[1]);
+      if write.data()[1] == last_level {
+        return levels;
+      }
+    }
+  }
+}
+
+fn feature_zero(level: f64) -> ScalarValueCommand {
+  ScalarValueCommand::ScalarValueMap(HashMap::from([(0, level)]))
+}
+
+// Runs on paused time, so ramps move in exact update intervals.
+#[cfg(feature = "server")]
+#[tokio::test(start_paused = true)]
+async fn test_client_device_ramp() {
+  let (client, mut device) = test_client_with_device().await;
+  let test_device = wait_for_client_device(&client).await;
+
+  test_device
+    .ramp_vibrate(
+      &feature_zero(1.0),
+      Duration::from_millis(300),
+      RampEasing::Linear,
+    )
+    .await
+    .expect("Test, assuming infallible.");
+  let levels = collect_vibrator_levels(&mut device.receiver, 127).await;
+  // One update per 20ms interval, each crossing into a new step.
+  assert_eq!(
+    levels,
+    vec![9, 17, 26, 34, 43, 51, 60, 68, 77, 85, 94, 102, 111, 119, 127]
+  );
+
+  // Ramps start from the last commanded level.
+  test_device
+    .ramp_vibrate(
+      &feature_zero(0.5),
+      Duration::from_millis(200),
+      RampEasing::EaseInOut,
+    )
+    .await
+    .expect("Test, assuming infallible.");
+  let levels = collect_vibrator_levels(&mut device.receiver, 64).await;
+  assert_eq!(levels, vec![126, 122, 116, 107, 96, 84, 75, 69, 65, 64]);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test(start_paused = true)]
+async fn test_client_device_ramp_cancelled_by_command() {
+  let (client, mut device) = test_client_with_device().await;
+  let test_device = wait_for_client_device(&client).await;
+
+  let ramp = test_device.ramp_vibrate(
+    &feature_zero(1.0),
+    Duration::from_secs(10),
+    RampEasing::Linear,
+  );
+  sleep(Duration::from_millis(200)).await;
+  test_device
+    .vibrate(&feature_zero(0.0))
+    .await
+    .expect("Test, assuming infallible.");
+  tokio::time::timeout(Duration::from_secs(1), ramp)
+    .await
+    .expect("Ramp was not cancelled.")
+    .expect("Test, assuming infallible.");
+  // Ramp updates up to the cancelling command, then only that command.
+  assert_eq!(
+    collect_vibrator_levels(&mut device.receiver, 0).await,
+    vec![1, 2, 3, 0]
+  );
+  sleep(Duration::from_millis(200)).await;
+  assert!(
+    device.receiver.try_recv().is_err(),
+    "Ramp kept running after cancellation."
+  );
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_ramp_invalid_target() {
+  let (client, _device) = test_client_with_device().await;
+  let test_device = wait_for_client_device(&client).await;
+  assert!(matches!(
+    test_device
+      .ramp_vibrate(
+        &ScalarValueCommand::ScalarValue(2.0),
+        Duration::from_millis(100),
+        RampEasing::Linear
+      )
+      .await
+      .unwrap_err(),
+    ButtplugClientError::ButtplugError(ButtplugError::ButtplugMessageError(
+      ButtplugMessageError::InvalidMessageContents(..)
+    ))
+  ));
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_feature_handles() {
+  let (client, mut device) = test_client_with_device().await;
+  let test_device = wait_for_client_device(&client).await;
+
+  let actuators = test_device.scalar_actuators();
+  assert_eq!(actuators.len(), 2);
+  for (index, actuator) in actuators.iter().enumerate() {
+    assert_eq!(actuator.index(), index as u32);
+    assert_eq!(actuator.actuator_type(), ActuatorType::Vibrate);
+    assert_eq!(actuator.step_count(), 127);
+  }
+  assert!(test_device.rotate_actuators().is_empty());
+  assert!(test_device.linear_actuators().is_empty());
+  assert!(test_device.sensors().is_empty());
+
+  // Driving the second vibrator through its handle only touches that vibrator.
+  actuators[1]
+    .scalar(0.5)
+    .await
+    .expect("Test, assuming infallible.");
+  assert_eq!(
+    device
+      .receiver
+      .recv()
+      .await
+      .expect("Test, assuming infallible."),
+    HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF2, 64], false))
+  );
+
+  // Same wire message as addressing the feature by index.
+  test_device
+    .scalar(&ScalarCommand::ScalarMap(HashMap::from([(
+      0,
+      (0.5, ActuatorType::Vibrate),
+    )])))
+    .await
+    .expect("Test, assuming infallible.");
+  actuators[0]
+    .scalar(1.0)
+    .await
+    .expect("Test, assuming infallible.");
+  for expected in [vec![0xF1, 64], vec![0xF1, 127]] {
+    assert_eq!(
+      device
+        .receiver
+        .recv()
+        .await
+        .expect("Test, assuming infallible."),
+      HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, expected, false))
+    );
+  }
+
+  assert!(matches!(
+    actuators[0].scalar(1.5).await.unwrap_err(),
+    ButtplugClientError::ButtplugError(ButtplugError::ButtplugMessageError(
+      ButtplugMessageError::InvalidMessageContents(..)
+    ))
+  ));
+}
+
+// Regression test for rotate_attributes reporting the device's LinearCmd attributes.
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_rotate_attributes() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _device = builder.add_test_device(&TestDeviceIdentifier::new("CycSA", None));
+  let mut server_builder = ButtplugServerBuilder::default();
+  server_builder.comm_manager(builder);
+  let client = ButtplugClient::new("Test Client");
+  client
+    .connect(
+      ButtplugInProcessClientConnectorBuilder::default()
+        .server(server_builder.finish().expect("Test, assuming infallible."))
+        .finish(),
+    )
+    .await
+    .expect("Test, assuming infallible.");
+  let test_device = wait_for_client_device(&client).await;
+  assert_eq!(test_device.name(), "Vorze A10 Cyclone SA");
+
+  let rotate_attributes = test_device.rotate_attributes();
+  assert_eq!(rotate_attributes.len(), 1);
+  assert_eq!(*rotate_attributes[0].step_count(), 99);
+  assert_eq!(rotate_attributes[0].actuator_type(), &ActuatorType::Rotate);
+  assert!(test_device.linear_attributes().is_empty());
+  assert_eq!(test_device.rotate_actuators().len(), 1);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_state() {
+  let (client, device) = test_client_with_device().await;
+  let test_device = wait_for_client_device(&client).await;
+  let mut state_stream = test_device.state_stream();
+  assert_eq!(test_device.state().scalars(), &vec![None, None]);
+  assert!(test_device.state().rotations().is_empty());
+  assert!(test_device.state().linear().is_empty());
+
+  test_device
+    .vibrate(&feature_zero(0.5))
+    .await
+    .expect("Test, assuming infallible.");
+  assert_eq!(test_device.state().scalars(), &vec![Some(0.5), None]);
+  assert_eq!(
+    state_stream
+      .next()
+      .await
+      .expect("Test, assuming infallible.")
+      .scalars(),
+    &vec![Some(0.5), None]
+  );
+
+  // Commands the server rejects don't change the state.
+  assert!(test_device
+    .vibrate(&ScalarValueCommand::ScalarValue(2.0))
+    .await
+    .is_err());
+  assert_eq!(test_device.state().scalars(), &vec![Some(0.5), None]);
+
+  test_device
+    .stop()
+    .await
+    .expect("Test, assuming infallible.");
+  assert_eq!(test_device.state().scalars(), &vec![None, None]);
+  assert_eq!(
+    state_stream
+      .next()
+      .await
+      .expect("Test, assuming infallible.")
+      .scalars(),
+    &vec![None, None]
+  );
+
+  test_device
+    .vibrate(&feature_zero(1.0))
+    .await
+    .expect("Test, assuming infallible.");
+  device
+    .sender
+    .send(TestHardwareEvent::Disconnect)
+    .await
+    .expect("Test, assuming infallible.");
+  // Skip the updates from the vibrate command.
+  while let Some(state) = state_stream.next().await {
+    if state.scalars() == &vec![None, None] {
+      break;
+    }
+  }
+  assert!(!test_device.connected());
+  assert_eq!(test_device.state().scalars(), &vec![None, None]);
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_client_device_state_sensor_readings() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let mut device = builder.add_test_device(&TestDeviceIdentifier::new("LVS-Test", None));
+  let mut server_builder = ButtplugServerBuilder::default();
+  server_builder.comm_manager(builder);
+  let client = ButtplugClient::new("Test Client");
+  client
+    .connect(
+      ButtplugInProcessClientConnectorBuilder::default()
+        .server(server_builder.finish().expect("Test, assuming infallible."))
+        .finish(),
+    )
+    .await
+    .expect("Test, assuming infallible.");
+  let mut event_stream = client.event_stream();
+  client
+    .start_scanning()
+    .await
+    .expect("Test, assuming infallible.");
+
+  // Lovense devices identify themselves before being added.
+  assert!(matches!(
+    device.receiver.recv().await,
+    Some(HardwareCommand::Subscribe(_))
+  ));
+  assert_eq!(
+    device.receiver.recv().await,
+    Some(HardwareCommand::Write(HardwareWriteCmd::new(
+      Endpoint::Tx,
+      b"DeviceType;".to_vec(),
+      false
+    )))
+  );
+  device
+    .sender
+    .send(TestHardwareEvent::Notifications(vec![
+      TestHardwareNotification {
+        endpoint: Endpoint::Rx,
+        data: b"Z:11:0082059AD3BD;".to_vec(),
+      },
+    ]))
+    .await
+    .expect("Test, assuming infallible.");
+  let test_device = wait_for_device_added(&mut event_stream).await;
+  assert!(test_device.state().sensor_readings().is_empty());
+
+  let battery = async_manager::spawn_with_handle({
+    let test_device = test_device.clone();
+    async move { test_device.battery_level().await }
+  })
+  .expect("Test, assuming infallible.");
+  assert_eq!(
+    device.receiver.recv().await,
+    Some(HardwareCommand::Write(HardwareWriteCmd::new(
+      Endpoint::Tx,
+      b"Battery;".to_vec(),
+      false
+    )))
+  );
+  device
+    .sender
+    .send(TestHardwareEvent::Notifications(vec![
+      TestHardwareNotification {
+        endpoint: Endpoint::Rx,
+        data: b"90;".to_vec(),
+      },
+    ]))
+    .await
+    .expect("Test, assuming infallible.");
+  assert_eq!(battery.await.expect("Test, assuming infallible."), 0.9);
+  assert_eq!(
+    test_device.state().sensor_reading(
+      ButtplugDeviceMessageType::SensorReadCmd,
+      0,
+      SensorType::Battery
+    ),
+    Some(&vec![90])
+  );
+  // Subscription readings are kept apart from reads of the same index.
+  assert_eq!(
+    test_device.state().sensor_reading(
+      ButtplugDeviceMessageType::SensorSubscribeCmd,
+      0,
+      SensorType::Battery
+    ),
+    None
+  );
+}
+
 // TODO Test invalid messages to device
 // TODO Test invalid parameters in message
 // TODO Test device invalidation across client connections (i.e. a device shouldn't be allowed to reconnect even if index is the same)
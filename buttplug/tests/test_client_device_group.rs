@@ -0,0 +1,222 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "server")]
+mod client_device_group_tests {
+  use super::util::{
+    test_device_manager::TestDeviceIdentifier,
+    TestDeviceChannelHost,
+    TestDeviceCommunicationManagerBuilder,
+    TestHardwareEvent,
+  };
+  use buttplug::{
+    client::{
+      ButtplugClient,
+      ButtplugClientDevice,
+      ButtplugClientDeviceGroup,
+      ButtplugClientEvent,
+      DeviceGroupMembership,
+      RotateCommand,
+      ScalarCommand,
+      ScalarValueCommand,
+    },
+    core::{
+      connector::ButtplugInProcessClientConnectorBuilder,
+      message::{ActuatorType, Endpoint},
+    },
+    server::{
+      device::hardware::{HardwareCommand, HardwareWriteCmd},
+      ButtplugServerBuilder,
+    },
+  };
+  use futures::StreamExt;
+  use std::{collections::HashMap, sync::Arc, time::Duration};
+  use tokio::time::{sleep, timeout};
+
+  async fn test_client_with_devices() -> (
+    ButtplugClient,
+    Vec<Arc<ButtplugClientDevice>>,
+    Vec<TestDeviceChannelHost>,
+  ) {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    let hosts = vec![
+      builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None)),
+      builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None)),
+    ];
+    let mut server_builder = ButtplugServerBuilder::default();
+    server_builder.comm_manager(builder);
+    let client = ButtplugClient::new("Test Client");
+    client
+      .connect(
+        ButtplugInProcessClientConnectorBuilder::default()
+          .server(server_builder.finish().expect("Test, assuming infallible."))
+          .finish(),
+      )
+      .await
+      .expect("Test, assuming infallible.");
+    let mut event_stream = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let mut devices = vec![];
+    while devices.len() < hosts.len() {
+      if let ButtplugClientEvent::DeviceAdded(device) =
+        timeout(Duration::from_secs(5), event_stream.next())
+          .await
+          .expect("Devices never showed up.")
+          .expect("Test, assuming infallible.")
+      {
+        devices.push(device);
+      }
+    }
+    devices.sort_by_key(|device| device.index());
+    (client, devices, hosts)
+  }
+
+  // Group membership is updated on its own task, so give it a moment to catch up.
+  async fn wait_for_group_len(group: &ButtplugClientDeviceGroup, len: usize) {
+    timeout(Duration::from_secs(5), async {
+      while group.len() != len {
+        sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .expect("Group membership never updated.");
+  }
+
+  fn vibrator_write(feature: u8, level: u8) -> HardwareCommand {
+    HardwareCommand::Write(HardwareWriteCmd::new(
+      Endpoint::Tx,
+      vec![0xF1 + feature, level],
+      false,
+    ))
+  }
+
+  // Finds the test device host that receives the next write, returning its index in `hosts`.
+  async fn next_write_host(
+    hosts: &mut [TestDeviceChannelHost],
+    expected: HardwareCommand,
+  ) -> usize {
+    timeout(Duration::from_secs(5), async {
+      loop {
+        for (index, host) in hosts.iter_mut().enumerate() {
+          if let Ok(command) = host.receiver.try_recv() {
+            assert_eq!(command, expected);
+            return index;
+          }
+        }
+        sleep(Duration::from_millis(10)).await;
+      }
+    })
+    .await
+    .expect("Write never happened.")
+  }
+
+  #[tokio::test]
+  async fn test_device_group_follows_devices() {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    let host = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+    let mut server_builder = ButtplugServerBuilder::default();
+    server_builder.comm_manager(builder);
+    let client = ButtplugClient::new("Test Client");
+    client
+      .connect(
+        ButtplugInProcessClientConnectorBuilder::default()
+          .server(server_builder.finish().expect("Test, assuming infallible."))
+          .finish(),
+      )
+      .await
+      .expect("Test, assuming infallible.");
+
+    // Groups can be created before any devices show up.
+    let vibrators = client.device_group(DeviceGroupMembership::ActuatorType(ActuatorType::Vibrate));
+    let rotators = client.device_group(DeviceGroupMembership::ActuatorType(ActuatorType::Rotate));
+    let by_name = client.device_group(DeviceGroupMembership::Name("Aneros Vivi".to_owned()));
+    assert!(vibrators.is_empty());
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    wait_for_group_len(&vibrators, 1).await;
+    wait_for_group_len(&by_name, 1).await;
+    assert!(rotators.is_empty());
+
+    host
+      .sender
+      .send(TestHardwareEvent::Disconnect)
+      .await
+      .expect("Test, assuming infallible.");
+    wait_for_group_len(&vibrators, 0).await;
+    wait_for_group_len(&by_name, 0).await;
+  }
+
+  #[tokio::test]
+  async fn test_device_group_commands() {
+    let (client, devices, mut hosts) = test_client_with_devices().await;
+
+    let all = client.device_group(DeviceGroupMembership::predicate(|_| true));
+    assert_eq!(all.devices(), devices);
+    let first = client.device_group(DeviceGroupMembership::devices(&devices[..1]));
+    assert_eq!(first.devices(), devices[..1].to_vec());
+    assert!(first.contains(&devices[0]));
+    assert!(!first.contains(&devices[1]));
+
+    // Broadcast only goes to group members.
+    first
+      .vibrate(&ScalarValueCommand::ScalarValue(0.5))
+      .await
+      .expect("Test, assuming infallible.");
+    let first_host = next_write_host(&mut hosts, vibrator_write(0, 64)).await;
+    assert_eq!(
+      next_write_host(&mut hosts, vibrator_write(1, 64)).await,
+      first_host
+    );
+    sleep(Duration::from_millis(100)).await;
+    assert!(hosts
+      .iter_mut()
+      .all(|host| host.receiver.try_recv().is_err()));
+
+    // Per-member mapping, skipping members the mapping returns None for.
+    let second_index = devices[1].index();
+    all
+      .scalar_with(|device| {
+        (device.index() == second_index)
+          .then(|| ScalarCommand::ScalarMap(HashMap::from([(0, (1.0, ActuatorType::Vibrate))])))
+      })
+      .await
+      .expect("Test, assuming infallible.");
+    assert_ne!(
+      next_write_host(&mut hosts, vibrator_write(0, 127)).await,
+      first_host
+    );
+
+    // Members without rotators are skipped, rather than failing.
+    all
+      .rotate(&RotateCommand::Rotate(0.5, true))
+      .await
+      .expect("Test, assuming infallible.");
+
+    // Failures are reported per device.
+    let err = all
+      .vibrate(&ScalarValueCommand::ScalarValue(2.0))
+      .await
+      .unwrap_err();
+    assert_eq!(*err.member_count(), 2);
+    let mut failed: Vec<Arc<ButtplugClientDevice>> = err
+      .failures()
+      .iter()
+      .map(|(device, _)| device.clone())
+      .collect();
+    failed.sort_by_key(|device| device.index());
+    assert_eq!(failed, devices);
+
+    all.stop().await.expect("Test, assuming infallible.");
+  }
+}
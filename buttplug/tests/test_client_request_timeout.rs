@@ -0,0 +1,252 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "server")]
+mod client_request_timeout_tests {
+  use super::util::{
+    test_device_manager::TestDeviceIdentifier,
+    wait_for_device_added,
+    TestDeviceCommunicationManagerBuilder,
+  };
+  use buttplug::{
+    client::{ButtplugClient, ButtplugClientError, ScalarValueCommand},
+    core::{
+      connector::{
+        ButtplugConnector,
+        ButtplugConnectorError,
+        ButtplugConnectorResultFuture,
+        ButtplugInProcessClientConnector,
+        ButtplugInProcessClientConnectorBuilder,
+      },
+      message::{
+        ButtplugCurrentSpecClientMessage,
+        ButtplugCurrentSpecServerMessage,
+        ButtplugMessage,
+      },
+    },
+    server::ButtplugServerBuilder,
+    util::async_manager,
+  };
+  use futures::{future::BoxFuture, StreamExt};
+  use std::{
+    sync::{
+      atomic::{AtomicBool, Ordering},
+      Arc,
+      Mutex,
+    },
+    time::Duration,
+  };
+  use tokio::{
+    sync::mpsc::{self, Sender},
+    time::timeout,
+  };
+
+  /// Connector that drops replies from the server while told to hold them, keeping them around so
+  /// they can be delivered late. Events are always passed on.
+  struct ButtplugReplyHoldingConnector {
+    connector: ButtplugInProcessClientConnector,
+    control: ReplyHoldingControl,
+  }
+
+  #[derive(Clone)]
+  struct ReplyHoldingControl {
+    hold_replies: Arc<AtomicBool>,
+    held_replies: Arc<Mutex<Vec<ButtplugCurrentSpecServerMessage>>>,
+    client_sender: Arc<Mutex<Option<Sender<ButtplugCurrentSpecServerMessage>>>>,
+  }
+
+  impl ReplyHoldingControl {
+    fn hold_replies(&self, hold: bool) {
+      self.hold_replies.store(hold, Ordering::SeqCst);
+    }
+
+    fn held_count(&self) -> usize {
+      self.held_replies.lock().unwrap().len()
+    }
+
+    async fn release_replies(&self) {
+      let replies: Vec<ButtplugCurrentSpecServerMessage> =
+        self.held_replies.lock().unwrap().drain(..).collect();
+      let sender = self
+        .client_sender
+        .lock()
+        .unwrap()
+        .clone()
+        .expect("Connector should be connected.");
+      for reply in replies {
+        sender
+          .send(reply)
+          .await
+          .expect("Test, assuming infallible.");
+      }
+    }
+  }
+
+  impl ButtplugReplyHoldingConnector {
+    fn new(connector: ButtplugInProcessClientConnector) -> (Self, ReplyHoldingControl) {
+      let control = ReplyHoldingControl {
+        hold_replies: Arc::new(AtomicBool::new(false)),
+        held_replies: Arc::new(Mutex::new(vec![])),
+        client_sender: Arc::new(Mutex::new(None)),
+      };
+      (
+        Self {
+          connector,
+          control: control.clone(),
+        },
+        control,
+      )
+    }
+  }
+
+  impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+    for ButtplugReplyHoldingConnector
+  {
+    fn connect(
+      &mut self,
+      message_sender: Sender<ButtplugCurrentSpecServerMessage>,
+    ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+      let (server_sender, mut server_receiver) =
+        mpsc::channel::<ButtplugCurrentSpecServerMessage>(256);
+      *self.control.client_sender.lock().unwrap() = Some(message_sender.clone());
+      let hold_replies = self.control.hold_replies.clone();
+      let held_replies = self.control.held_replies.clone();
+      async_manager::spawn(async move {
+        while let Some(msg) = server_receiver.recv().await {
+          if msg.id() != 0 && hold_replies.load(Ordering::SeqCst) {
+            held_replies.lock().unwrap().push(msg);
+          } else if message_sender.send(msg).await.is_err() {
+            return;
+          }
+        }
+      });
+      self.connector.connect(server_sender)
+    }
+
+    fn disconnect(&self) -> ButtplugConnectorResultFuture {
+      self.connector.disconnect()
+    }
+
+    fn send(&self, msg: ButtplugCurrentSpecClientMessage) -> ButtplugConnectorResultFuture {
+      self.connector.send(msg)
+    }
+  }
+
+  async fn test_client_with_held_replies(
+    builder: TestDeviceCommunicationManagerBuilder,
+  ) -> (ButtplugClient, ReplyHoldingControl) {
+    let mut server_builder = ButtplugServerBuilder::default();
+    server_builder.comm_manager(builder);
+    let (connector, control) = ButtplugReplyHoldingConnector::new(
+      ButtplugInProcessClientConnectorBuilder::default()
+        .server(server_builder.finish().expect("Test, assuming infallible."))
+        .finish(),
+    );
+    let client = ButtplugClient::new("Test Client");
+    client
+      .connect(connector)
+      .await
+      .expect("Test, assuming infallible.");
+    (client, control)
+  }
+
+  #[tokio::test]
+  async fn test_client_request_timeout() {
+    let (client, control) =
+      test_client_with_held_replies(TestDeviceCommunicationManagerBuilder::default()).await;
+    assert_eq!(client.request_timeout(), None);
+    client.set_request_timeout(Some(Duration::from_millis(100)));
+    assert_eq!(client.request_timeout(), Some(Duration::from_millis(100)));
+
+    control.hold_replies(true);
+    let err = timeout(Duration::from_secs(5), client.stop_all_devices())
+      .await
+      .expect("Request should have timed out on its own.")
+      .unwrap_err();
+    assert!(matches!(
+      err,
+      ButtplugClientError::RequestTimeout(duration) if duration == Duration::from_millis(100)
+    ));
+    assert_eq!(control.held_count(), 1);
+
+    // A reply arriving after its timeout is dropped, rather than surfacing as an event.
+    let mut event_stream = client.event_stream();
+    control.hold_replies(false);
+    control.release_replies().await;
+    assert!(timeout(Duration::from_millis(200), event_stream.next())
+      .await
+      .is_err());
+
+    // The client keeps working once replies come through again.
+    assert!(client.stop_all_devices().await.is_ok());
+    client.set_request_timeout(None);
+    assert!(client.stop_all_devices().await.is_ok());
+  }
+
+  #[tokio::test]
+  async fn test_client_device_request_timeout() {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    let _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+    let (client, control) = test_client_with_held_replies(builder).await;
+    let mut event_stream = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let device = wait_for_device_added(&mut event_stream).await;
+
+    // Per-device timeouts apply even when the client has none.
+    let impatient_device = device.with_request_timeout(Duration::from_millis(50));
+    assert_eq!(impatient_device, *device);
+    control.hold_replies(true);
+    let err = timeout(
+      Duration::from_secs(5),
+      impatient_device.vibrate(&ScalarValueCommand::ScalarValue(0.5)),
+    )
+    .await
+    .expect("Request should have timed out on its own.")
+    .unwrap_err();
+    assert!(matches!(err, ButtplugClientError::RequestTimeout(_)));
+    // Timed out commands were never acknowledged, so aren't part of the device state.
+    assert_eq!(*device.state().scalars(), vec![None, None]);
+
+    // Without a timeout, the original device waits for the reply.
+    let vibrate = device.vibrate(&ScalarValueCommand::ScalarValue(0.5));
+    let waiting = tokio::spawn(vibrate);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    control.hold_replies(false);
+    control.release_replies().await;
+    assert!(timeout(Duration::from_secs(5), waiting)
+      .await
+      .expect("Held reply should resolve the request.")
+      .expect("Test, assuming infallible.")
+      .is_ok());
+    assert_eq!(*device.state().scalars(), vec![Some(0.5), Some(0.5)]);
+
+    // Stops that time out weren't acknowledged either, so the device state is kept.
+    control.hold_replies(true);
+    client.set_request_timeout(Some(Duration::from_millis(50)));
+    assert!(matches!(
+      impatient_device.stop().await,
+      Err(ButtplugClientError::RequestTimeout(_))
+    ));
+    assert!(matches!(
+      client.stop_all_devices().await,
+      Err(ButtplugClientError::RequestTimeout(_))
+    ));
+    assert_eq!(*device.state().scalars(), vec![Some(0.5), Some(0.5)]);
+    control.hold_replies(false);
+    control.release_replies().await;
+    client
+      .stop_all_devices()
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(*device.state().scalars(), vec![None, None]);
+  }
+}
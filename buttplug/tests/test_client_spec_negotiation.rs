@@ -0,0 +1,190 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+#[cfg(feature = "testing")]
+mod client_spec_negotiation_tests {
+  use buttplug::{
+    client::{
+      ButtplugClient,
+      ButtplugClientDevice,
+      ButtplugClientError,
+      ButtplugClientEvent,
+      LinearCommand,
+      RotateCommand,
+      ScalarCommand,
+      ScalarValueCommand,
+    },
+    core::{
+      errors::{ButtplugError, ButtplugMessageError},
+      message::{ActuatorType, ButtplugMessageSpecVersion},
+    },
+    testing::{ButtplugTestServerBuilder, FakeDevice, FakeDeviceBuilder},
+  };
+  use futures::StreamExt;
+  use std::{collections::HashMap, sync::Arc, time::Duration};
+  use tokio::time::timeout;
+
+  async fn connect_with_max_version(
+    version: ButtplugMessageSpecVersion,
+  ) -> (ButtplugClient, Arc<ButtplugClientDevice>, FakeDevice) {
+    let mut builder = ButtplugTestServerBuilder::default();
+    builder.server_builder().max_message_version(version);
+    let fake_device = builder.add_device(
+      FakeDeviceBuilder::new("Old Toy")
+        .scalar(ActuatorType::Vibrate, 20)
+        .scalar(ActuatorType::Vibrate, 10)
+        .scalar(ActuatorType::Oscillate, 10)
+        .rotate(10)
+        .linear(100)
+        .battery(),
+    );
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    client
+      .connect(builder.connector().expect("Test, assuming infallible."))
+      .await
+      .expect("Test, assuming infallible.");
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    loop {
+      if let ButtplugClientEvent::DeviceAdded(device) =
+        timeout(Duration::from_secs(5), event_stream.next())
+          .await
+          .expect("Device never showed up.")
+          .expect("Test, assuming infallible.")
+      {
+        return (client, device, fake_device);
+      }
+    }
+  }
+
+  fn assert_version_error<T>(result: Result<T, ButtplugClientError>) {
+    assert!(
+      matches!(
+        result,
+        Err(ButtplugClientError::ButtplugError(
+          ButtplugError::ButtplugMessageError(ButtplugMessageError::VersionError(..))
+        ))
+      ),
+      "Expected a version error"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_current_spec_server() {
+    let (client, device, _) =
+      connect_with_max_version(ButtplugMessageSpecVersion::Version3).await;
+    assert_eq!(
+      client.server_message_version(),
+      Some(ButtplugMessageSpecVersion::Version3)
+    );
+    assert_eq!(device.oscillate_attributes().len(), 1);
+    client.disconnect().await.expect("Test, assuming infallible.");
+    assert_eq!(client.server_message_version(), None);
+  }
+
+  #[tokio::test]
+  async fn test_spec_v2_server() {
+    let (client, device, fake_device) =
+      connect_with_max_version(ButtplugMessageSpecVersion::Version2).await;
+    assert_eq!(
+      client.server_message_version(),
+      Some(ButtplugMessageSpecVersion::Version2)
+    );
+    // Only vibrators can be described as scalar features before v3.
+    let vibrators = device.vibrate_attributes();
+    assert_eq!(vibrators.len(), 2);
+    assert_eq!(*vibrators[1].step_count(), 10);
+    assert!(device.oscillate_attributes().is_empty());
+    assert_eq!(*device.rotate_attributes()[0].step_count(), 10);
+    assert!(device.has_battery_level());
+
+    // ScalarCmd goes out as VibrateCmd.
+    device
+      .scalar(&ScalarCommand::ScalarMap(HashMap::from([
+        (0, (0.5, ActuatorType::Vibrate)),
+        (1, (1.0, ActuatorType::Vibrate)),
+      ])))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5]);
+    assert_eq!(fake_device.scalar_levels(1), vec![1.0]);
+    device
+      .rotate(&RotateCommand::Rotate(0.3, true))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(fake_device.rotations(0), vec![(0.3, true)]);
+
+    // SensorReadCmd goes out as BatteryLevelCmd.
+    fake_device.set_battery_level(0.75);
+    assert_eq!(
+      device
+        .battery_level()
+        .await
+        .expect("Test, assuming infallible."),
+      0.75
+    );
+
+    assert_version_error(client.report_unsupported_devices(true).await);
+    device.stop().await.expect("Test, assuming infallible.");
+  }
+
+  #[tokio::test]
+  async fn test_spec_v1_server() {
+    let (client, device, fake_device) =
+      connect_with_max_version(ButtplugMessageSpecVersion::Version1).await;
+    assert_eq!(
+      client.server_message_version(),
+      Some(ButtplugMessageSpecVersion::Version1)
+    );
+    // V1 doesn't report step counts or sensors.
+    let vibrators = device.vibrate_attributes();
+    assert_eq!(vibrators.len(), 2);
+    assert_eq!(*vibrators[1].step_count(), 20);
+    assert_eq!(device.linear_attributes().len(), 1);
+    assert!(!device.has_battery_level());
+
+    device
+      .vibrate(&ScalarValueCommand::ScalarValueVec(vec![0.25, 0.5]))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(fake_device.scalar_levels(0), vec![0.25]);
+    assert_eq!(fake_device.scalar_levels(1), vec![0.5]);
+    device
+      .linear(&LinearCommand::Linear(500, 0.25))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(fake_device.linear_movements(0), vec![(500, 0.25)]);
+    assert!(device.battery_level().await.is_err());
+    assert_version_error(client.report_unsupported_devices(true).await);
+  }
+
+  #[tokio::test]
+  async fn test_spec_v0_server() {
+    let (client, device, fake_device) =
+      connect_with_max_version(ButtplugMessageSpecVersion::Version0).await;
+    assert_eq!(
+      client.server_message_version(),
+      Some(ButtplugMessageSpecVersion::Version0)
+    );
+    // SingleMotorVibrateCmd drives every vibrator at once, so it shows up as one feature.
+    assert_eq!(device.vibrate_attributes().len(), 1);
+    assert!(device.rotate_attributes().is_empty());
+    assert!(device.linear_attributes().is_empty());
+
+    device
+      .vibrate(&ScalarValueCommand::ScalarValue(0.5))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5]);
+    assert_eq!(fake_device.scalar_levels(1), vec![0.5]);
+    device.stop().await.expect("Test, assuming infallible.");
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5, 0.0]);
+  }
+}
@@ -213,3 +213,373 @@ async fn test_invalid_step_range_device_config_wrong_order() {
     .finish()
     .is_ok());
 }
+
+const USER_CONFIG_STORE_JSON: &str = r#"
+{
+  "version": {
+    "major": 2,
+    "minor": 999
+  },
+  "user-configs": {
+    "devices": [
+      {
+        "identifier": {
+          "address": "UserConfigTest",
+          "protocol": "lovense",
+          "identifier": "F"
+        },
+        "config": {
+          "display-name": "Lovense Name Test",
+          "messages": {
+            "ScalarCmd": [
+              {
+                "StepRange": [0, 10],
+                "ActuatorType": "Oscillate",
+                "FeatureDescriptor": "Fucking Machine Oscillation Speed",
+                "FeatureOrder": 3
+              }
+            ]
+          }
+        }
+      }
+    ]
+  }
+}
+"#;
+
+#[cfg(feature = "server")]
+fn lovense_user_config_identifier(
+) -> buttplug::util::device_configuration::UserConfigDeviceIdentifier {
+  buttplug::util::device_configuration::UserConfigDeviceIdentifier {
+    address: "UserConfigTest".to_owned(),
+    protocol: "lovense".to_owned(),
+    identifier: Some("F".to_owned()),
+  }
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_user_config_store_round_trip() {
+  use buttplug::{
+    core::message::ButtplugDeviceMessageType,
+    util::device_configuration::UserConfigStore,
+  };
+  let ident = lovense_user_config_identifier();
+  let mut store = UserConfigStore::from_json(USER_CONFIG_STORE_JSON).unwrap();
+  assert_eq!(store.device_identifiers().unwrap(), vec![ident.clone()]);
+  store
+    .set_display_name(&ident, Some("Renamed".to_owned()))
+    .unwrap();
+  store.set_index(&ident, Some(3)).unwrap();
+  store
+    .set_step_range(&ident, ButtplugDeviceMessageType::ScalarCmd, 0, 2..=8)
+    .unwrap();
+  let output = store.to_json().unwrap();
+
+  // Output should be loadable by the server, and keep fields we don't edit directly.
+  ButtplugServerBuilder::default()
+    .user_device_configuration_json(Some(output.clone()))
+    .finish()
+    .unwrap();
+  let value: serde_json::Value = serde_json::from_str(&output).unwrap();
+  let feature = &value["user-configs"]["devices"][0]["config"]["messages"]["ScalarCmd"][0];
+  assert_eq!(feature["FeatureOrder"], 3);
+  assert_eq!(feature["StepRange"], serde_json::json!([2, 8]));
+
+  let reloaded = UserConfigStore::from_json(&output).unwrap();
+  let config = reloaded.device_config(&ident).unwrap().unwrap();
+  assert_eq!(*config.display_name(), Some("Renamed".to_owned()));
+  assert_eq!(*config.index(), Some(3));
+
+  let mut store = reloaded;
+  assert!(store.remove_device_config(&ident).unwrap().is_some());
+  assert!(store.device_config(&ident).unwrap().is_none());
+  assert!(UserConfigStore::from_json(&store.to_json().unwrap()).is_ok());
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_user_config_store_invalid_edits() {
+  use buttplug::{
+    core::message::ButtplugDeviceMessageType,
+    util::device_configuration::UserConfigStore,
+  };
+  assert!(UserConfigStore::from_json("{ \"version\": \"2\" }").is_err());
+  assert!(UserConfigStore::from_json("not json").is_err());
+
+  let ident = lovense_user_config_identifier();
+  let other = buttplug::util::device_configuration::UserConfigDeviceIdentifier {
+    address: "OtherAddress".to_owned(),
+    protocol: "lovense".to_owned(),
+    identifier: None,
+  };
+  let mut store = UserConfigStore::from_json(USER_CONFIG_STORE_JSON).unwrap();
+  store.set_index(&ident, Some(1)).unwrap();
+  assert!(store.set_index(&other, Some(1)).is_err());
+  assert!(store
+    .set_step_range(&ident, ButtplugDeviceMessageType::ScalarCmd, 1, 0..=5)
+    .is_err());
+  assert!(store
+    .set_step_range(&other, ButtplugDeviceMessageType::ScalarCmd, 0, 0..=5)
+    .is_err());
+  #[allow(clippy::reversed_empty_ranges)]
+  let reversed = 5..=0;
+  assert!(store
+    .set_step_range(&ident, ButtplugDeviceMessageType::ScalarCmd, 0, reversed)
+    .is_err());
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_user_config_store_output_mapping() {
+  use buttplug::{
+    core::message::ButtplugDeviceMessageType,
+    server::device::configuration::{FeatureOutputMapping, OutputCurve},
+    util::device_configuration::UserConfigStore,
+  };
+  let ident = lovense_user_config_identifier();
+  let mapping = FeatureOutputMapping::new(
+    0.2,
+    0.9,
+    0.05,
+    true,
+    OutputCurve::PiecewiseLinear(vec![(0.0, 0.0), (0.5, 0.25), (1.0, 1.0)]),
+  );
+  let mut store = UserConfigStore::from_json(USER_CONFIG_STORE_JSON).unwrap();
+  store
+    .set_output_mapping(
+      &ident,
+      ButtplugDeviceMessageType::ScalarCmd,
+      0,
+      Some(mapping.clone()),
+    )
+    .unwrap();
+  let output = store.to_json().unwrap();
+  ButtplugServerBuilder::default()
+    .user_device_configuration_json(Some(output.clone()))
+    .finish()
+    .unwrap();
+  let value: serde_json::Value = serde_json::from_str(&output).unwrap();
+  let feature = &value["user-configs"]["devices"][0]["config"]["messages"]["ScalarCmd"][0];
+  assert_eq!(feature["OutputMapping"]["Invert"], true);
+  assert_eq!(
+    feature["OutputMapping"]["Curve"],
+    serde_json::json!({"PiecewiseLinear": [[0.0, 0.0], [0.5, 0.25], [1.0, 1.0]]})
+  );
+
+  let scalar_mapping = |store: &UserConfigStore| {
+    store
+      .device_config(&ident)
+      .unwrap()
+      .unwrap()
+      .messages()
+      .as_ref()
+      .and_then(|messages| messages.scalar_cmd().clone())
+      .unwrap()[0]
+      .output_mapping()
+      .clone()
+  };
+  let mut store = UserConfigStore::from_json(&output).unwrap();
+  assert_eq!(scalar_mapping(&store), Some(mapping));
+  store
+    .set_output_mapping(&ident, ButtplugDeviceMessageType::ScalarCmd, 0, None)
+    .unwrap();
+  let reloaded = UserConfigStore::from_json(&store.to_json().unwrap()).unwrap();
+  assert_eq!(scalar_mapping(&reloaded), None);
+
+  // Invalid mappings are rejected by the store, and by the server when loaded from JSON.
+  assert!(store
+    .set_output_mapping(
+      &ident,
+      ButtplugDeviceMessageType::ScalarCmd,
+      0,
+      Some(FeatureOutputMapping::new(
+        0.9,
+        0.2,
+        0.0,
+        false,
+        OutputCurve::Linear
+      )),
+    )
+    .is_err());
+  let invalid_json = output.replace("\"Min\": 0.2", "\"Min\": 0.95");
+  assert_ne!(invalid_json, output);
+  assert!(ButtplugServerBuilder::default()
+    .user_device_configuration_json(Some(invalid_json))
+    .finish()
+    .is_err());
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_user_config_store_add_known_devices() {
+  use buttplug::{
+    server::device::{configuration::ProtocolAttributesType, ServerDeviceIdentifier},
+    util::device_configuration::UserConfigStore,
+  };
+  let server_ident = ServerDeviceIdentifier::new(
+    "KnownDeviceAddress",
+    "lovense",
+    &ProtocolAttributesType::Identifier("W".to_owned()),
+  );
+  let server = ButtplugServerBuilder::default()
+    .reserved_index(&server_ident, 7)
+    .finish()
+    .unwrap();
+  let mut store = UserConfigStore::default();
+  store.add_known_devices(&server.device_manager()).unwrap();
+  let config = store
+    .device_config(&server_ident.into())
+    .unwrap()
+    .expect("Device should be added to store");
+  assert_eq!(*config.index(), Some(7));
+  assert!(UserConfigStore::from_json(&store.to_json().unwrap()).is_ok());
+}
+
+const V1_USER_CONFIG_JSON: &str = r#"
+{
+  "version": 63,
+  "user-config": {
+    "devices": {
+      "MigratedAddress": {
+        "protocol": "lovense",
+        "allow": true,
+        "reserved-index": 4,
+        "messages": {
+          "VibrateCmd": {
+            "StepRange": [
+              [0, 10]
+            ]
+          }
+        }
+      },
+      "NoProtocolAddress": {
+        "deny": true
+      }
+    }
+  }
+}
+"#;
+
+const V1_PROTOCOL_CONFIG_JSON: &str = r#"
+{
+  "version": 63,
+  "protocols": {
+    "lovense": {
+      "btle": {
+        "names": [
+          "LVS-*"
+        ],
+        "services": {
+          "0000fff0-0000-1000-8000-00805f9b34fb": {
+            "tx": "0000fff2-0000-1000-8000-00805f9b34fb",
+            "rx": "0000fff1-0000-1000-8000-00805f9b34fb"
+          }
+        }
+      },
+      "defaults": {
+        "name": {
+          "en-us": "Lovense Device"
+        },
+        "messages": {
+          "VibrateCmd": {
+            "FeatureCount": 1,
+            "StepCount": [20]
+          },
+          "BatteryLevelCmd": {},
+          "StopDeviceCmd": {}
+        }
+      },
+      "configurations": [
+        {
+          "identifier": ["P"],
+          "name": {
+            "en-us": "Lovense Edge"
+          },
+          "messages": {
+            "VibrateCmd": {
+              "FeatureCount": 2,
+              "StepCount": [20, 20]
+            }
+          }
+        }
+      ]
+    }
+  }
+}
+"#;
+
+#[cfg(feature = "server")]
+#[test]
+fn test_migrate_v1_user_config() {
+  use buttplug::util::device_configuration::{migrate_device_config_json, UserConfigStore};
+  let (migrated, report) = migrate_device_config_json(V1_USER_CONFIG_JSON).unwrap();
+  assert!(report.migrated());
+  assert_eq!(report.from_version().major, 1);
+  assert_eq!(report.to_version().major, 2);
+  assert!(report
+    .changes()
+    .iter()
+    .any(|change| change.contains("NoProtocolAddress")));
+
+  let store = UserConfigStore::from_json(&migrated).unwrap();
+  let ident = buttplug::util::device_configuration::UserConfigDeviceIdentifier {
+    address: "MigratedAddress".to_owned(),
+    protocol: "lovense".to_owned(),
+    identifier: None,
+  };
+  assert_eq!(store.device_identifiers().unwrap(), vec![ident.clone()]);
+  let config = store.device_config(&ident).unwrap().unwrap();
+  assert_eq!(*config.allow(), Some(true));
+  assert_eq!(*config.index(), Some(4));
+  let value: serde_json::Value = serde_json::from_str(&migrated).unwrap();
+  assert_eq!(
+    value["user-configs"]["devices"][0]["config"]["messages"]["ScalarCmd"][0],
+    serde_json::json!({"ActuatorType": "Vibrate", "StepRange": [0, 10]})
+  );
+}
+
+#[cfg(feature = "server")]
+#[tokio::test]
+async fn test_migrate_v1_protocol_config() {
+  use buttplug::util::device_configuration::migrate_device_config_json;
+  let (migrated, report) = migrate_device_config_json(V1_PROTOCOL_CONFIG_JSON).unwrap();
+  assert!(report.migrated());
+  let value: serde_json::Value = serde_json::from_str(&migrated).unwrap();
+  let lovense = &value["protocols"]["lovense"];
+  assert_eq!(lovense["defaults"]["name"], "Lovense Device");
+  assert_eq!(
+    lovense["configurations"][0]["messages"]["ScalarCmd"]
+      .as_array()
+      .unwrap()
+      .len(),
+    2
+  );
+  assert_eq!(
+    lovense["defaults"]["messages"]["SensorReadCmd"][0]["SensorType"],
+    "Battery"
+  );
+
+  // The old document is rejected unless migration is requested.
+  assert!(ButtplugServerBuilder::default()
+    .device_configuration_json(Some(V1_PROTOCOL_CONFIG_JSON.to_owned()))
+    .finish()
+    .is_err());
+  ButtplugServerBuilder::default()
+    .device_configuration_json(Some(V1_PROTOCOL_CONFIG_JSON.to_owned()))
+    .user_device_configuration_json(Some(V1_USER_CONFIG_JSON.to_owned()))
+    .migrate_device_configurations()
+    .finish()
+    .unwrap();
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_migrate_current_and_future_config() {
+  use buttplug::util::device_configuration::migrate_device_config_json;
+  let (migrated, report) = migrate_device_config_json(BASE_VALID_VERSION_CONFIG_JSON).unwrap();
+  assert!(!report.migrated());
+  assert!(report.changes().is_empty());
+  assert_eq!(migrated, BASE_VALID_VERSION_CONFIG_JSON);
+  assert!(migrate_device_config_json(BASE_INVALID_VERSION_CONFIG_JSON).is_err());
+}
@@ -34,6 +34,7 @@ async fn load_test_case(test_file: &str) -> DeviceTestCase {
 #[test_case("test_lovense_battery.yaml" ; "Lovense Protocol - Lovense Battery (Default Devices)")]
 #[test_case("test_lovense_battery_non_default.yaml" ; "Lovense Protocol - Lovense Battery (Non-Default Devices)")]
 #[test_case("test_lovense_ridge_user_config.yaml" ; "Lovense Protocol - Lovense Ridge (User Config)")]
+#[test_case("test_lovense_nora_output_mapping.yaml" ; "Lovense Protocol - Lovense Nora (Output Mapping)")]
 #[test_case("test_lovense_flexer_fw2.yaml" ; "Lovense Protocol - Flexer FW2")]
 #[test_case("test_lovense_flexer_fw3.yaml" ; "Lovense Protocol - Flexer FW3")]
 #[test_case("test_lovense_edge.yaml" ; "Lovense Protocol - Edge")]
@@ -113,6 +114,7 @@ async fn test_device_protocols_embedded_v3(test_file: &str) {
 #[test_case("test_lovense_battery.yaml" ; "Lovense Protocol - Lovense Battery (Default Devices)")]
 #[test_case("test_lovense_battery_non_default.yaml" ; "Lovense Protocol - Lovense Battery (Non-Default Devices)")]
 #[test_case("test_lovense_ridge_user_config.yaml" ; "Lovense Protocol - Lovense Ridge (User Config)")]
+#[test_case("test_lovense_nora_output_mapping.yaml" ; "Lovense Protocol - Lovense Nora (Output Mapping)")]
 #[test_case("test_lovense_flexer_fw2.yaml" ; "Lovense Protocol - Flexer FW2")]
 #[test_case("test_lovense_flexer_fw3.yaml" ; "Lovense Protocol - Flexer FW3")]
 #[test_case("test_lovense_edge.yaml" ; "Lovense Protocol - Edge")]
@@ -0,0 +1,126 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "evdev-manager")]
+mod evdev_comm_manager_tests {
+  use crate::util::{wait_for_client_event, wait_for_device_added};
+  use buttplug::{
+    client::{ButtplugClient, ButtplugClientEvent, ScalarValueCommand},
+    core::connector::ButtplugInProcessClientConnectorBuilder,
+    server::{
+      device::hardware::communication::evdev::{
+        EvdevBackend,
+        EvdevCommunicationManagerBuilder,
+        EvdevDeviceInfo,
+        EvdevRumbleDevice,
+      },
+      ButtplugServerBuilder,
+    },
+  };
+  use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+  };
+  use tokio::{sync::mpsc, time::timeout};
+
+  const DEVICE_PATH: &str = "/dev/input/event42";
+
+  struct MockRumbleDevice {
+    sender: mpsc::UnboundedSender<(u16, u16)>,
+  }
+
+  impl EvdevRumbleDevice for MockRumbleDevice {
+    fn rumble(&mut self, strong_magnitude: u16, weak_magnitude: u16) -> io::Result<()> {
+      let _ = self.sender.send((strong_magnitude, weak_magnitude));
+      Ok(())
+    }
+  }
+
+  #[derive(Clone)]
+  struct MockBackend {
+    devices: Arc<Mutex<Vec<EvdevDeviceInfo>>>,
+    sender: mpsc::UnboundedSender<(u16, u16)>,
+  }
+
+  impl EvdevBackend for MockBackend {
+    fn enumerate(&self) -> Vec<EvdevDeviceInfo> {
+      self
+        .devices
+        .lock()
+        .expect("Test, assuming infallible.")
+        .clone()
+    }
+
+    fn open(&self, _path: &str) -> io::Result<Box<dyn EvdevRumbleDevice>> {
+      Ok(Box::new(MockRumbleDevice {
+        sender: self.sender.clone(),
+      }))
+    }
+  }
+
+  #[tokio::test]
+  async fn test_evdev_rumble_device() {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let backend = MockBackend {
+      devices: Arc::new(Mutex::new(vec![EvdevDeviceInfo::new(
+        DEVICE_PATH,
+        "Mock Gamepad",
+      )])),
+      sender,
+    };
+    let server = ButtplugServerBuilder::default()
+      .comm_manager(EvdevCommunicationManagerBuilder::default().backend(backend.clone()))
+      .finish()
+      .expect("Test, assuming infallible.");
+    let client = ButtplugClient::new("Evdev DCM Test Client");
+    client
+      .connect(
+        ButtplugInProcessClientConnectorBuilder::default()
+          .server(server)
+          .finish(),
+      )
+      .await
+      .expect("Test, assuming infallible.");
+    let mut event_stream = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+
+    let device = wait_for_device_added(&mut event_stream).await;
+    assert_eq!(device.name(), "Force Feedback Gamepad");
+    client
+      .stop_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+
+    // Strong motor first, weak motor second.
+    device
+      .vibrate(&ScalarValueCommand::ScalarValueVec(vec![1.0, 0.5]))
+      .await
+      .expect("Test, assuming infallible.");
+    let rumble = timeout(Duration::from_secs(1), receiver.recv())
+      .await
+      .expect("Rumble never sent.")
+      .expect("Test, assuming infallible.");
+    assert_eq!(rumble, (65535, 32768));
+
+    // Unplugging the device removes it.
+    backend
+      .devices
+      .lock()
+      .expect("Test, assuming infallible.")
+      .clear();
+    wait_for_client_event(&mut event_stream, "device removal", |event| {
+      matches!(event, ButtplugClientEvent::DeviceRemoved(_)).then_some(())
+    })
+    .await;
+  }
+}
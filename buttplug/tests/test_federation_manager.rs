@@ -0,0 +1,244 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "federation-manager")]
+mod federation_manager_tests {
+  use crate::util::{
+    test_device_manager::TestDeviceIdentifier,
+    wait_for_client_event,
+    wait_for_device_added,
+    TestDeviceChannelHost,
+    TestDeviceCommunicationManagerBuilder,
+    TestHardwareEvent,
+  };
+  use buttplug::{
+    client::{ButtplugClient, ButtplugClientEvent, ScalarValueCommand},
+    core::{
+      connector::{
+        ButtplugConnector,
+        ButtplugConnectorError,
+        ButtplugConnectorResultFuture,
+        ButtplugInProcessClientConnector,
+        ButtplugInProcessClientConnectorBuilder,
+      },
+      message::{ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage, Endpoint},
+    },
+    server::{
+      device::hardware::{
+        communication::buttplug_federation::ButtplugFederationCommunicationManagerBuilder,
+        HardwareCommand,
+        HardwareWriteCmd,
+      },
+      ButtplugServer,
+      ButtplugServerBuilder,
+    },
+  };
+  use futures::{future::BoxFuture, FutureExt};
+  use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+  };
+  use tokio::{sync::mpsc, time::timeout};
+  use tokio_util::sync::CancellationToken;
+
+  fn remote_server() -> (ButtplugServer, TestDeviceChannelHost) {
+    let mut remote_builder = TestDeviceCommunicationManagerBuilder::default();
+    let device = remote_builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+    let server = ButtplugServerBuilder::default()
+      .comm_manager(remote_builder)
+      .finish()
+      .expect("Test, assuming infallible.");
+    (server, device)
+  }
+
+  async fn local_client(
+    federation: ButtplugFederationCommunicationManagerBuilder,
+  ) -> ButtplugClient {
+    let local_server = ButtplugServerBuilder::default()
+      .comm_manager(federation)
+      .finish()
+      .expect("Test, assuming infallible.");
+    let client = ButtplugClient::new("Test Client");
+    client
+      .connect(
+        ButtplugInProcessClientConnectorBuilder::default()
+          .server(local_server)
+          .finish(),
+      )
+      .await
+      .expect("Test, assuming infallible.");
+    client
+  }
+
+  #[tokio::test]
+  async fn test_federated_device_relays_commands() {
+    let (remote_server, mut device) = remote_server();
+    let remote_server = Mutex::new(Some(remote_server));
+    let client = local_client(ButtplugFederationCommunicationManagerBuilder::new(
+      move || {
+        ButtplugInProcessClientConnectorBuilder::default()
+          .server(
+            remote_server
+              .lock()
+              .expect("Test, assuming infallible.")
+              .take()
+              .expect("Test only connects once."),
+          )
+          .finish()
+      },
+    ))
+    .await;
+    let mut event_stream = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+
+    let local_device = wait_for_device_added(&mut event_stream).await;
+    assert_eq!(local_device.name(), "Aneros Vivi");
+    assert_eq!(local_device.vibrate_attributes().len(), 2);
+
+    local_device
+      .vibrate(&ScalarValueCommand::ScalarValue(0.5))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(
+      timeout(Duration::from_secs(1), device.receiver.recv())
+        .await
+        .expect("Remote device never received a command."),
+      Some(HardwareCommand::Write(HardwareWriteCmd::new(
+        Endpoint::Tx,
+        vec![0xF1, 64],
+        false
+      )))
+    );
+
+    // Removing the device from the remote server removes it locally.
+    device
+      .sender
+      .send(TestHardwareEvent::Disconnect)
+      .await
+      .expect("Test, assuming infallible.");
+    let removed =
+      wait_for_client_event(
+        &mut event_stream,
+        "the device to be removed",
+        |event| match event {
+          ButtplugClientEvent::DeviceRemoved(device) => Some(device),
+          _ => None,
+        },
+      )
+      .await;
+    assert_eq!(removed.index(), local_device.index());
+  }
+
+  // In process connector whose connection to the client can be cut, as if the remote server went
+  // away.
+  struct DroppableConnector {
+    inner: ButtplugInProcessClientConnector,
+    dropped: CancellationToken,
+  }
+
+  impl ButtplugConnector<ButtplugCurrentSpecClientMessage, ButtplugCurrentSpecServerMessage>
+    for DroppableConnector
+  {
+    fn connect(
+      &mut self,
+      message_sender: mpsc::Sender<ButtplugCurrentSpecServerMessage>,
+    ) -> BoxFuture<'static, Result<(), ButtplugConnectorError>> {
+      let (inner_sender, mut inner_receiver) = mpsc::channel(256);
+      let connect = self.inner.connect(inner_sender);
+      let dropped = self.dropped.clone();
+      async move {
+        connect.await?;
+        // Dropping message_sender is what tells the client the connection is gone.
+        tokio::spawn(async move {
+          loop {
+            tokio::select! {
+              message = inner_receiver.recv() => match message {
+                Some(message) => {
+                  if message_sender.send(message).await.is_err() {
+                    return;
+                  }
+                }
+                None => return,
+              },
+              _ = dropped.cancelled() => return,
+            }
+          }
+        });
+        Ok(())
+      }
+      .boxed()
+    }
+
+    fn disconnect(&self) -> ButtplugConnectorResultFuture {
+      self.inner.disconnect()
+    }
+
+    fn send(&self, msg: ButtplugCurrentSpecClientMessage) -> ButtplugConnectorResultFuture {
+      self.inner.send(msg)
+    }
+  }
+
+  #[tokio::test]
+  async fn test_federation_reconnects_on_scan() {
+    // Each connection gets a fresh remote server, whose device channel has to be kept alive.
+    let connections: Arc<Mutex<Vec<(CancellationToken, TestDeviceChannelHost)>>> =
+      Arc::new(Mutex::new(vec![]));
+    let factory_connections = connections.clone();
+    let client = local_client(ButtplugFederationCommunicationManagerBuilder::new(
+      move || {
+        let (server, device) = remote_server();
+        let dropped = CancellationToken::new();
+        factory_connections
+          .lock()
+          .expect("Test, assuming infallible.")
+          .push((dropped.clone(), device));
+        DroppableConnector {
+          inner: ButtplugInProcessClientConnectorBuilder::default()
+            .server(server)
+            .finish(),
+          dropped,
+        }
+      },
+    ))
+    .await;
+    let mut event_stream = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let first_device = wait_for_device_added(&mut event_stream).await;
+
+    // Losing the remote server removes its devices.
+    connections.lock().expect("Test, assuming infallible.")[0]
+      .0
+      .cancel();
+    wait_for_client_event(&mut event_stream, "the device to be removed", |event| {
+      matches!(event, ButtplugClientEvent::DeviceRemoved(_)).then_some(())
+    })
+    .await;
+
+    // The next scan connects again, and finds the device again.
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let second_device = wait_for_device_added(&mut event_stream).await;
+    assert_eq!(second_device.name(), first_device.name());
+    assert_eq!(
+      connections
+        .lock()
+        .expect("Test, assuming infallible.")
+        .len(),
+      2
+    );
+  }
+}
@@ -0,0 +1,279 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "ffi")]
+mod ffi_tests {
+  use crate::util::{
+    test_device_manager::{check_test_recv_value, TestDeviceIdentifier},
+    TestDeviceCommunicationManagerBuilder,
+  };
+  use buttplug::{
+    core::message::Endpoint,
+    ffi::*,
+    server::{
+      device::hardware::{HardwareCommand, HardwareWriteCmd},
+      ButtplugServerBuilder,
+    },
+  };
+  use std::{
+    ffi::{c_void, CStr, CString},
+    ptr,
+    sync::{
+      mpsc::{channel, Receiver, Sender},
+      Mutex,
+    },
+    time::Duration,
+  };
+
+  type EventSender = Mutex<Sender<(ButtplugFfiEventType, u32, Option<String>)>>;
+
+  extern "C" fn event_callback(event: *const ButtplugFfiEvent, user_data: *mut c_void) {
+    let (event, sender) = unsafe { (&*event, &*(user_data as *const EventSender)) };
+    let message = (!event.message.is_null()).then(|| {
+      unsafe { CStr::from_ptr(event.message) }
+        .to_string_lossy()
+        .into_owned()
+    });
+    let _ = sender
+      .lock()
+      .expect("Test")
+      .send((event.event_type, event.device_index, message));
+  }
+
+  struct ReentrantState {
+    client: *mut ButtplugFfiClient,
+    events: EventSender,
+    statuses: Mutex<Sender<(ButtplugFfiStatus, ButtplugFfiStatus)>>,
+  }
+
+  // Makes blocking calls and swaps itself out for event_callback, from inside the callback.
+  extern "C" fn reentrant_callback(event: *const ButtplugFfiEvent, user_data: *mut c_void) {
+    let state = unsafe { &*(user_data as *const ReentrantState) };
+    if unsafe { &*event }.event_type != ButtplugFfiEventType::DeviceAdded {
+      return;
+    }
+    let statuses = unsafe {
+      (
+        buttplug_client_stop_all_devices(state.client),
+        buttplug_client_set_event_callback(
+          state.client,
+          Some(event_callback),
+          &state.events as *const EventSender as *mut c_void,
+        ),
+      )
+    };
+    let _ = state.statuses.lock().expect("Test").send(statuses);
+  }
+
+  fn last_error() -> String {
+    let message = buttplug_last_error_message();
+    assert!(!message.is_null());
+    unsafe { CStr::from_ptr(message) }
+      .to_string_lossy()
+      .into_owned()
+  }
+
+  fn wait_for_event(
+    receiver: &Receiver<(ButtplugFfiEventType, u32, Option<String>)>,
+    event_type: ButtplugFfiEventType,
+  ) -> (u32, Option<String>) {
+    loop {
+      let (received_type, device_index, message) = receiver
+        .recv_timeout(Duration::from_secs(5))
+        .expect("Timed out waiting for event.");
+      if received_type == event_type {
+        return (device_index, message);
+      }
+    }
+  }
+
+  #[test]
+  fn test_ffi_client_lifecycle() {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    let mut test_device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+    let mut server_builder = ButtplugServerBuilder::default();
+    server_builder.comm_manager(builder);
+    let server = ButtplugFfiServer::new_handle(&mut server_builder);
+    assert!(!server.is_null());
+
+    let (sender, receiver) = channel();
+    let sender: Box<EventSender> = Box::new(Mutex::new(sender));
+    let name = CString::new("FFI Test Client").expect("Test");
+    unsafe {
+      let client = buttplug_client_new(name.as_ptr());
+      assert!(!client.is_null());
+      assert_eq!(
+        buttplug_client_set_event_callback(
+          client,
+          Some(event_callback),
+          &*sender as *const EventSender as *mut c_void
+        ),
+        ButtplugFfiStatus::Ok
+      );
+      assert!(!buttplug_client_connected(client));
+      assert_eq!(
+        buttplug_client_connect_in_process(client, server),
+        ButtplugFfiStatus::Ok
+      );
+      assert!(buttplug_client_connected(client));
+      let server_name = buttplug_client_server_name(client);
+      assert!(!server_name.is_null());
+      buttplug_free_string(server_name);
+
+      assert_eq!(
+        buttplug_client_start_scanning(client),
+        ButtplugFfiStatus::Ok
+      );
+      let (device_index, device_name) =
+        wait_for_event(&receiver, ButtplugFfiEventType::DeviceAdded);
+      assert_eq!(device_name.as_deref(), Some("Aneros Vivi"));
+
+      let mut indexes = [u32::MAX; 4];
+      assert_eq!(
+        buttplug_client_device_indexes(client, ptr::null_mut(), 0),
+        1
+      );
+      assert_eq!(
+        buttplug_client_device_indexes(client, indexes.as_mut_ptr(), indexes.len()),
+        1
+      );
+      assert_eq!(indexes[0], device_index);
+
+      let device = buttplug_client_get_device(client, device_index);
+      assert!(!device.is_null());
+      assert_eq!(buttplug_device_index(device), device_index);
+      let name = buttplug_device_name(device);
+      assert_eq!(CStr::from_ptr(name).to_str(), Ok("Aneros Vivi"));
+      buttplug_free_string(name);
+      assert!(buttplug_device_connected(device));
+      assert!(buttplug_device_scalar_count(device) > 0);
+      assert_eq!(
+        buttplug_device_scalar_actuator_type(device, 0),
+        ButtplugFfiActuatorType::Vibrate
+      );
+      assert_eq!(buttplug_device_rotate_count(device), 0);
+      let attributes = buttplug_device_message_attributes_json(device);
+      assert!(CStr::from_ptr(attributes)
+        .to_string_lossy()
+        .contains("ScalarCmd"));
+      buttplug_free_string(attributes);
+
+      assert_eq!(
+        buttplug_device_scalar(device, 0, 0.5, ButtplugFfiActuatorType::Vibrate),
+        ButtplugFfiStatus::Ok
+      );
+      check_test_recv_value(
+        &mut test_device,
+        HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+      );
+
+      // Errors are reported through the status and the last error message.
+      assert_eq!(
+        buttplug_device_rotate(device, 0, 0.5, true),
+        ButtplugFfiStatus::DeviceError
+      );
+      assert!(!last_error().is_empty());
+      assert!(!buttplug_device_has_battery_level(device));
+      let mut level = 0.0f64;
+      assert_eq!(
+        buttplug_device_battery_level(device, &mut level),
+        ButtplugFfiStatus::DeviceError
+      );
+      let endpoint = CString::new("notanendpoint").expect("Test");
+      assert_eq!(
+        buttplug_device_raw_write(device, endpoint.as_ptr(), ptr::null(), 0, false),
+        ButtplugFfiStatus::InvalidArgument
+      );
+      assert!(last_error().contains("notanendpoint"));
+      assert!(buttplug_client_get_device(client, 10).is_null());
+      assert!(last_error().contains("10"));
+      assert_eq!(
+        buttplug_client_start_scanning(ptr::null_mut()),
+        ButtplugFfiStatus::InvalidArgument
+      );
+
+      assert_eq!(buttplug_device_stop(device), ButtplugFfiStatus::Ok);
+      assert_eq!(
+        buttplug_client_stop_all_devices(client),
+        ButtplugFfiStatus::Ok
+      );
+      assert_eq!(buttplug_client_disconnect(client), ButtplugFfiStatus::Ok);
+      wait_for_event(&receiver, ButtplugFfiEventType::ServerDisconnect);
+      assert!(!buttplug_client_connected(client));
+      // Device handles outlive their connection, but commands fail.
+      assert!(!buttplug_device_connected(device));
+      assert_ne!(buttplug_device_stop(device), ButtplugFfiStatus::Ok);
+      buttplug_device_free(device);
+      buttplug_client_free(client);
+    }
+  }
+
+  #[test]
+  fn test_ffi_client_calls_from_event_callback() {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    let _test_device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+    let mut server_builder = ButtplugServerBuilder::default();
+    server_builder.comm_manager(builder);
+    let server = ButtplugFfiServer::new_handle(&mut server_builder);
+
+    let (event_sender, event_receiver) = channel();
+    let (status_sender, status_receiver) = channel();
+    let name = CString::new("FFI Test Client").expect("Test");
+    unsafe {
+      let client = buttplug_client_new(name.as_ptr());
+      let state = Box::new(ReentrantState {
+        client,
+        events: Mutex::new(event_sender),
+        statuses: Mutex::new(status_sender),
+      });
+      assert_eq!(
+        buttplug_client_set_event_callback(
+          client,
+          Some(reentrant_callback),
+          &*state as *const ReentrantState as *mut c_void
+        ),
+        ButtplugFfiStatus::Ok
+      );
+      assert_eq!(
+        buttplug_client_connect_in_process(client, server),
+        ButtplugFfiStatus::Ok
+      );
+      assert_eq!(
+        buttplug_client_start_scanning(client),
+        ButtplugFfiStatus::Ok
+      );
+      assert_eq!(
+        status_receiver
+          .recv_timeout(Duration::from_secs(5))
+          .expect("Callback never finished."),
+        (ButtplugFfiStatus::Ok, ButtplugFfiStatus::Ok)
+      );
+      // Later events go to the callback set from inside the first one.
+      assert_eq!(buttplug_client_disconnect(client), ButtplugFfiStatus::Ok);
+      wait_for_event(&event_receiver, ButtplugFfiEventType::ServerDisconnect);
+      buttplug_client_free(client);
+    }
+  }
+
+  #[test]
+  fn test_ffi_connect_takes_server_ownership() {
+    let name = CString::new("FFI Test Client").expect("Test");
+    unsafe {
+      let client = buttplug_client_new(name.as_ptr());
+      assert_eq!(
+        buttplug_client_connect_in_process(client, ptr::null_mut()),
+        ButtplugFfiStatus::InvalidArgument
+      );
+      assert!(buttplug_client_new(ptr::null()).is_null());
+      buttplug_client_free(client);
+      buttplug_client_free(ptr::null_mut());
+      buttplug_server_free(ptr::null_mut());
+    }
+  }
+}
@@ -0,0 +1,205 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "http-gateway")]
+mod http_gateway_tests {
+  use crate::util::{
+    test_device_manager::{check_test_recv_value, TestDeviceIdentifier},
+    TestDeviceCommunicationManagerBuilder,
+  };
+  use buttplug::{
+    core::message::Endpoint,
+    server::{
+      authenticator::SharedTokenAuthenticator,
+      device::hardware::{HardwareCommand, HardwareWriteCmd},
+      http_gateway::{ButtplugHttpGatewayBuilder, ButtplugHttpGatewayError},
+      ButtplugServerBuilder,
+    },
+    util::async_manager,
+  };
+  use futures::StreamExt;
+  use serde_json::{json, Value};
+  use std::time::Duration;
+  use tokio::time::sleep;
+
+  const GATEWAY_ADDRESS: &str = "http://127.0.0.1:12365";
+
+  #[tokio::test]
+  async fn test_http_gateway() {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    let mut device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+    let mut server_builder = ButtplugServerBuilder::default();
+    server_builder.comm_manager(builder);
+    let gateway = ButtplugHttpGatewayBuilder::default()
+      .port(12365)
+      .finish(server_builder.finish().expect("Test, assuming infallible."));
+    async_manager::spawn(async move {
+      gateway.run().await.expect("Test, assuming infallible.");
+    });
+
+    let client = reqwest::Client::new();
+    // The gateway may not be listening yet, so retry a few times.
+    let mut device_list = None;
+    for _ in 0..10u8 {
+      if let Ok(response) = client
+        .get(format!("{}/devices", GATEWAY_ADDRESS))
+        .send()
+        .await
+      {
+        device_list = Some(
+          response
+            .json::<Value>()
+            .await
+            .expect("Test, assuming infallible."),
+        );
+        break;
+      }
+      sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(
+      device_list.expect("Gateway never started listening.")["DeviceList"]["Devices"],
+      json!([])
+    );
+
+    // Open the event stream before scanning, so we can't miss the device.
+    let mut events = client
+      .get(format!("{}/events", GATEWAY_ADDRESS))
+      .send()
+      .await
+      .expect("Test, assuming infallible.")
+      .bytes_stream();
+    let response = client
+      .post(format!("{}/scanning/start", GATEWAY_ADDRESS))
+      .send()
+      .await
+      .expect("Test, assuming infallible.");
+    assert!(response.status().is_success());
+    let mut event_text = String::new();
+    while !event_text.contains("event:DeviceAdded") {
+      let chunk = events
+        .next()
+        .await
+        .expect("Event stream closed.")
+        .expect("Test, assuming infallible.");
+      event_text.push_str(&String::from_utf8_lossy(&chunk));
+    }
+    assert!(event_text.contains("Aneros Vivi"));
+
+    let device_info: Value = client
+      .get(format!("{}/devices/0", GATEWAY_ADDRESS))
+      .send()
+      .await
+      .expect("Test, assuming infallible.")
+      .json()
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(device_info["DeviceName"], "Aneros Vivi");
+    assert!(device_info["DeviceMessages"]["ScalarCmd"].is_array());
+
+    let response = client
+      .get(format!("{}/devices/10", GATEWAY_ADDRESS))
+      .send()
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+    let response = client
+      .post(format!("{}/devices/0/scalar", GATEWAY_ADDRESS))
+      .json(&json!([{ "Index": 0, "Scalar": 0.5, "ActuatorType": "Vibrate" }]))
+      .send()
+      .await
+      .expect("Test, assuming infallible.");
+    assert!(response.status().is_success());
+    let reply: Value = response.json().await.expect("Test, assuming infallible.");
+    assert!(reply.get("Ok").is_some());
+    check_test_recv_value(
+      &mut device,
+      HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false)),
+    );
+
+    // Aneros Vivi has no rotators, so the server should refuse this.
+    let response = client
+      .post(format!("{}/devices/0/rotate", GATEWAY_ADDRESS))
+      .json(&json!([{ "Index": 0, "Speed": 0.5, "Clockwise": true }]))
+      .send()
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+    let reply: Value = response.json().await.expect("Test, assuming infallible.");
+    assert!(reply.get("Error").is_some());
+
+    let response = client
+      .post(format!("{}/devices/stop", GATEWAY_ADDRESS))
+      .send()
+      .await
+      .expect("Test, assuming infallible.");
+    assert!(response.status().is_success());
+  }
+
+  #[tokio::test]
+  async fn test_http_gateway_authentication() {
+    let gateway = ButtplugHttpGatewayBuilder::default()
+      .port(12368)
+      .authenticator(SharedTokenAuthenticator::new("secret"))
+      .finish(
+        ButtplugServerBuilder::default()
+          .finish()
+          .expect("Test, assuming infallible."),
+      );
+    async_manager::spawn(async move {
+      gateway.run().await.expect("Test, assuming infallible.");
+    });
+
+    let client = reqwest::Client::new();
+    let address = "http://127.0.0.1:12368";
+    // The gateway may not be listening yet, so retry a few times.
+    let mut response = None;
+    for _ in 0..10u8 {
+      if let Ok(r) = client.get(format!("{}/devices", address)).send().await {
+        response = Some(r);
+        break;
+      }
+      sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(
+      response.expect("Gateway never started listening.").status(),
+      reqwest::StatusCode::UNAUTHORIZED
+    );
+    let response = client
+      .post(format!("{}/scanning/start", address))
+      .bearer_auth("wrong")
+      .send()
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let response = client
+      .get(format!("{}/devices", address))
+      .bearer_auth("secret")
+      .send()
+      .await
+      .expect("Test, assuming infallible.");
+    assert!(response.status().is_success());
+  }
+
+  #[tokio::test]
+  async fn test_http_gateway_requires_authenticator_on_all_interfaces() {
+    let gateway = ButtplugHttpGatewayBuilder::default()
+      .port(12369)
+      .listen_on_all_interfaces(true)
+      .finish(
+        ButtplugServerBuilder::default()
+          .finish()
+          .expect("Test, assuming infallible."),
+      );
+    assert!(matches!(
+      gateway.run().await,
+      Err(ButtplugHttpGatewayError::AuthenticatorRequired(_))
+    ));
+  }
+}
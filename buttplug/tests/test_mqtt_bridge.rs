@@ -0,0 +1,225 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "mqtt-bridge")]
+mod mqtt_bridge_tests {
+  use crate::util::test_client_with_device;
+  use buttplug::{
+    client::{mqtt_bridge::ButtplugMqttBridgeBuilder, ButtplugClientEvent},
+    core::message::Endpoint,
+    server::device::hardware::{HardwareCommand, HardwareWriteCmd},
+  };
+  use bytes::BytesMut;
+  use futures::StreamExt;
+  use rumqttc::{
+    matches,
+    mqttbytes::v4::{read, ConnAck, ConnectReturnCode, Packet, PubAck, Publish, SubAck},
+    QoS,
+    SubscribeReasonCode,
+  };
+  use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+  };
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::timeout,
+  };
+
+  type Subscriptions = Arc<Mutex<Vec<(String, mpsc::UnboundedSender<Publish>)>>>;
+
+  /// Just enough of an MQTT 3.1.1 broker to test the bridge against. Everything published by
+  /// clients (including last wills) is sent to the test, and the test can publish to subscribers.
+  struct TestBroker {
+    port: u16,
+    published: mpsc::UnboundedReceiver<Publish>,
+    subscriptions: Subscriptions,
+  }
+
+  impl TestBroker {
+    async fn start() -> Self {
+      let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Test, assuming infallible.");
+      let port = listener
+        .local_addr()
+        .expect("Test, assuming infallible.")
+        .port();
+      let (published_sender, published) = mpsc::unbounded_channel();
+      let subscriptions = Subscriptions::default();
+      let connection_subscriptions = subscriptions.clone();
+      tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+          tokio::spawn(run_connection(
+            stream,
+            published_sender.clone(),
+            connection_subscriptions.clone(),
+          ));
+        }
+      });
+      Self {
+        port,
+        published,
+        subscriptions,
+      }
+    }
+
+    fn publish(&self, topic: &str, payload: &str) {
+      for (filter, sender) in self.subscriptions.lock().expect("Test").iter() {
+        if matches(topic, filter) {
+          let _ = sender.send(Publish::new(topic, QoS::AtMostOnce, payload));
+        }
+      }
+    }
+
+    async fn wait_for_subscription(&self) {
+      for _ in 0..50u8 {
+        if !self.subscriptions.lock().expect("Test").is_empty() {
+          return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+      }
+      panic!("Bridge never subscribed.");
+    }
+
+    async fn next_publish_to(&mut self, topic: &str) -> Publish {
+      loop {
+        let publish = timeout(Duration::from_secs(5), self.published.recv())
+          .await
+          .expect("Timed out waiting for publish.")
+          .expect("Test, assuming infallible.");
+        if publish.topic == topic {
+          return publish;
+        }
+      }
+    }
+  }
+
+  async fn run_connection(
+    mut stream: TcpStream,
+    published: mpsc::UnboundedSender<Publish>,
+    subscriptions: Subscriptions,
+  ) {
+    let (outgoing_sender, mut outgoing) = mpsc::unbounded_channel::<Publish>();
+    let mut last_will = None;
+    let mut read_buf = BytesMut::new();
+    loop {
+      let mut write_buf = BytesMut::new();
+      tokio::select! {
+        read_result = stream.read_buf(&mut read_buf) => {
+          if !matches!(read_result, Ok(len) if len > 0) {
+            // Connection dropped without a disconnect packet, so send the will.
+            if let Some(will) = last_will {
+              let _ = published.send(will);
+            }
+            return;
+          }
+          while let Ok(packet) = read(&mut read_buf, 65536) {
+            match packet {
+              Packet::Connect(connect) => {
+                last_will = connect.last_will.map(|will| {
+                  let mut publish = Publish::new(will.topic, will.qos, will.message.to_vec());
+                  publish.retain = will.retain;
+                  publish
+                });
+                ConnAck::new(ConnectReturnCode::Success, false).write(&mut write_buf).expect("Test");
+              }
+              Packet::Subscribe(subscribe) => {
+                let mut subscriptions = subscriptions.lock().expect("Test");
+                for filter in &subscribe.filters {
+                  subscriptions.push((filter.path.clone(), outgoing_sender.clone()));
+                }
+                SubAck::new(
+                  subscribe.pkid,
+                  subscribe
+                    .filters
+                    .iter()
+                    .map(|_| SubscribeReasonCode::Success(QoS::AtMostOnce))
+                    .collect(),
+                )
+                .write(&mut write_buf)
+                .expect("Test");
+              }
+              Packet::Publish(publish) => {
+                if publish.qos == QoS::AtLeastOnce {
+                  PubAck::new(publish.pkid).write(&mut write_buf).expect("Test");
+                }
+                let _ = published.send(publish);
+              }
+              Packet::PingReq => write_buf.extend_from_slice(&[0xD0, 0]),
+              Packet::Disconnect => return,
+              _ => {}
+            }
+          }
+        }
+        Some(publish) = outgoing.recv() => {
+          publish.write(&mut write_buf).expect("Test");
+        }
+      }
+      if !write_buf.is_empty() && stream.write_all(&write_buf).await.is_err() {
+        return;
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_mqtt_bridge() {
+    let (client, mut device) = test_client_with_device().await;
+    let client = Arc::new(client);
+    let mut event_stream = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    while let Some(event) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(_) = event {
+        break;
+      }
+    }
+
+    let mut broker = TestBroker::start().await;
+    let bridge = ButtplugMqttBridgeBuilder::default()
+      .broker_port(broker.port)
+      .finish(client.clone());
+    let bridge_task = tokio::spawn(async move { bridge.run().await });
+
+    let status = broker.next_publish_to("buttplug/status").await;
+    assert_eq!(&status.payload[..], b"online");
+    assert!(status.retain);
+    let info = broker.next_publish_to("buttplug/0/info").await;
+    let info: serde_json::Value =
+      serde_json::from_slice(&info.payload).expect("Test, assuming infallible.");
+    assert_eq!(info["Name"], "Aneros Vivi");
+    assert!(info["DeviceMessages"]["ScalarCmd"].is_array());
+    let available = broker.next_publish_to("buttplug/0/available").await;
+    assert_eq!(&available.payload[..], b"online");
+    assert!(available.retain);
+
+    broker.wait_for_subscription().await;
+    broker.publish("buttplug/0/vibrate/0/set", "0.5");
+    assert_eq!(
+      timeout(Duration::from_secs(5), device.receiver.recv())
+        .await
+        .expect("Device never received a command."),
+      Some(HardwareCommand::Write(HardwareWriteCmd::new(
+        Endpoint::Tx,
+        vec![0xF1, 64],
+        false
+      )))
+    );
+
+    // Dropping the bridge drops its connection, so the broker should send the last will.
+    bridge_task.abort();
+    let status = broker.next_publish_to("buttplug/status").await;
+    assert_eq!(&status.payload[..], b"offline");
+    assert!(status.retain);
+  }
+}
@@ -0,0 +1,291 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "network-manager")]
+mod network_device_comm_manager_tests {
+  use crate::util::{wait_for_client_event, wait_for_device_added};
+  use buttplug::{
+    client::{ButtplugClient, ButtplugClientDeviceEvent, ButtplugClientEvent, ScalarValueCommand},
+    core::{
+      connector::ButtplugInProcessClientConnectorBuilder,
+      message::{ButtplugCurrentSpecServerMessage, Endpoint},
+    },
+    server::{
+      device::hardware::communication::{
+        network::{
+          NetworkCommunicationManagerBuilder,
+          NetworkDeviceAnnouncement,
+          NetworkDeviceInfo,
+          NetworkTransport,
+        },
+        HardwareCommunicationManagerBuilder,
+        HardwareCommunicationManagerEvent,
+      },
+      ButtplugServerBuilder,
+    },
+  };
+  use futures::StreamExt;
+  use std::time::Duration;
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, UdpSocket},
+    sync::mpsc,
+    time::timeout,
+  };
+
+  // DIY devices speak an existing protocol, selected by their identifier.
+  const USER_CONFIG_JSON: &str = r#"
+  {
+    "version": {
+      "major": 2,
+      "minor": 999
+    },
+    "user-configs": {
+      "specifiers": {
+        "aneros": {
+          "network": {
+            "names": ["DIY Vibe"]
+          }
+        }
+      }
+    }
+  }
+  "#;
+
+  async fn setup_client(builder: NetworkCommunicationManagerBuilder) -> ButtplugClient {
+    let server = ButtplugServerBuilder::default()
+      .comm_manager(builder)
+      .user_device_configuration_json(Some(USER_CONFIG_JSON.to_owned()))
+      .allow_raw_messages()
+      .finish()
+      .expect("Test, assuming infallible.");
+    let client = ButtplugClient::new("Network DCM Test Client");
+    client
+      .connect(
+        ButtplugInProcessClientConnectorBuilder::default()
+          .server(server)
+          .finish(),
+      )
+      .await
+      .expect("Test, assuming infallible.");
+    client
+  }
+
+  // The comm manager binds the discovery port itself, so find one that's free for it.
+  fn free_udp_port() -> u16 {
+    std::net::UdpSocket::bind("127.0.0.1:0")
+      .and_then(|socket| socket.local_addr())
+      .expect("Test, assuming infallible.")
+      .port()
+  }
+
+  async fn wait_for_discovered_device(
+    device_socket: &UdpSocket,
+    discovery_port: u16,
+    event_stream: &mut (impl futures::Stream<Item = ButtplugClientEvent> + Unpin),
+  ) -> std::sync::Arc<buttplug::client::ButtplugClientDevice> {
+    let announcement = serde_json::to_vec(&NetworkDeviceAnnouncement::new(
+      "DIY Vibe",
+      device_socket
+        .local_addr()
+        .expect("Test, assuming infallible.")
+        .port(),
+      NetworkTransport::Udp,
+    ))
+    .expect("Test, assuming infallible.");
+    // The discovery port may not be bound yet, so keep announcing until the device shows up.
+    timeout(Duration::from_secs(5), async {
+      loop {
+        device_socket
+          .send_to(&announcement, ("127.0.0.1", discovery_port))
+          .await
+          .expect("Test, assuming infallible.");
+        match timeout(Duration::from_millis(100), event_stream.next()).await {
+          Ok(Some(ButtplugClientEvent::DeviceAdded(device))) => break device,
+          Ok(None) => panic!("Client event stream closed."),
+          _ => continue,
+        }
+      }
+    })
+    .await
+    .expect("Network device was never discovered.")
+  }
+
+  #[tokio::test]
+  async fn test_network_tcp_device() {
+    let listener = TcpListener::bind("127.0.0.1:0")
+      .await
+      .expect("Test, assuming infallible.");
+    let client = setup_client(NetworkCommunicationManagerBuilder::default().device(
+      NetworkDeviceInfo::new(
+        "DIY Vibe",
+        listener.local_addr().expect("Test, assuming infallible."),
+        NetworkTransport::Tcp,
+      ),
+    ))
+    .await;
+    let mut event_stream = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let (mut stream, _) = timeout(Duration::from_secs(5), listener.accept())
+      .await
+      .expect("Device was never connected to.")
+      .expect("Test, assuming infallible.");
+    let device = wait_for_device_added(&mut event_stream).await;
+    assert_eq!(device.name(), "Aneros Vivi (Raw Messages Allowed)");
+
+    // Writes
+    device
+      .vibrate(&ScalarValueCommand::ScalarValueVec(vec![0.5]))
+      .await
+      .expect("Test, assuming infallible.");
+    let mut buf = [0u8; 2];
+    stream
+      .read_exact(&mut buf)
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(buf, [0xF1, 64]);
+
+    // Reads return the next data sent by the device.
+    let read = device.raw_read(Endpoint::Rx, 0, 1000);
+    let (reading, _) = tokio::join!(read, async {
+      tokio::time::sleep(Duration::from_millis(100)).await;
+      stream
+        .write_all(&[1, 2, 3])
+        .await
+        .expect("Test, assuming infallible.");
+    });
+    assert_eq!(reading.expect("Test, assuming infallible."), vec![1, 2, 3]);
+
+    // Subscriptions turn device data into notifications.
+    let mut device_events = device.event_stream();
+    device
+      .raw_subscribe(Endpoint::Rx)
+      .await
+      .expect("Test, assuming infallible.");
+    stream
+      .write_all(&[4, 5])
+      .await
+      .expect("Test, assuming infallible.");
+    loop {
+      let event = timeout(Duration::from_secs(1), device_events.next())
+        .await
+        .expect("Notification never arrived.");
+      if let Some(ButtplugClientDeviceEvent::Message(
+        ButtplugCurrentSpecServerMessage::RawReading(reading),
+      )) = event
+      {
+        assert_eq!(reading.endpoint(), Endpoint::Rx);
+        assert_eq!(*reading.data(), vec![4, 5]);
+        break;
+      }
+    }
+
+    // Closing the connection removes the device.
+    drop(stream);
+    wait_for_client_event(&mut event_stream, "device removal", |event| {
+      matches!(event, ButtplugClientEvent::DeviceRemoved(_)).then_some(())
+    })
+    .await;
+  }
+
+  #[tokio::test]
+  async fn test_network_udp_device_discovery() {
+    let device_socket = UdpSocket::bind("127.0.0.1:0")
+      .await
+      .expect("Test, assuming infallible.");
+    let discovery_port = free_udp_port();
+    let client =
+      setup_client(NetworkCommunicationManagerBuilder::default().discovery_port(discovery_port))
+        .await;
+    let mut event_stream = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let device =
+      wait_for_discovered_device(&device_socket, discovery_port, &mut event_stream).await;
+    assert_eq!(device.name(), "Aneros Vivi (Raw Messages Allowed)");
+    client
+      .stop_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+
+    device
+      .vibrate(&ScalarValueCommand::ScalarValueVec(vec![0.5]))
+      .await
+      .expect("Test, assuming infallible.");
+    let mut buf = [0u8; 16];
+    let len = timeout(Duration::from_secs(1), device_socket.recv(&mut buf))
+      .await
+      .expect("Device never received a command.")
+      .expect("Test, assuming infallible.");
+    assert_eq!(&buf[..len], &[0xF1, 64]);
+  }
+
+  #[tokio::test]
+  async fn test_network_scan_restarts_after_stop() {
+    let discovery_port = free_udp_port();
+    let (sender, mut receiver) = mpsc::channel(256);
+    let mut comm_manager = NetworkCommunicationManagerBuilder::default()
+      .discovery_port(discovery_port)
+      .finish(sender);
+    comm_manager
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    comm_manager
+      .stop_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    assert!(!comm_manager.scanning_status());
+    // Starting again before the stopped scan has wound down has to run a new scan, not fold into
+    // the one being stopped.
+    comm_manager
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    assert!(comm_manager.scanning_status());
+
+    let device_socket = UdpSocket::bind("127.0.0.1:0")
+      .await
+      .expect("Test, assuming infallible.");
+    let announcement = serde_json::to_vec(&NetworkDeviceAnnouncement::new(
+      "DIY Vibe",
+      device_socket
+        .local_addr()
+        .expect("Test, assuming infallible.")
+        .port(),
+      NetworkTransport::Udp,
+    ))
+    .expect("Test, assuming infallible.");
+    timeout(Duration::from_secs(5), async {
+      loop {
+        device_socket
+          .send_to(&announcement, ("127.0.0.1", discovery_port))
+          .await
+          .expect("Test, assuming infallible.");
+        match timeout(Duration::from_millis(100), receiver.recv()).await {
+          Ok(Some(HardwareCommunicationManagerEvent::DeviceFound { name, .. })) => {
+            assert_eq!(name, "DIY Vibe");
+            break;
+          }
+          Ok(None) => panic!("Comm manager event channel closed."),
+          _ => continue,
+        }
+      }
+    })
+    .await
+    .expect("Restarted scan never discovered the device.");
+    // The stopped scan finishing must not mark the new one as finished.
+    assert!(comm_manager.scanning_status());
+  }
+}
@@ -0,0 +1,118 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "osc-bridge")]
+mod osc_bridge_tests {
+  use crate::util::test_client_with_device;
+  use buttplug::{
+    client::{
+      osc_bridge::{
+        packet::{OscArgument, OscMessage},
+        ButtplugOscBridgeBuilder,
+        OscAddressMapping,
+        OscDeviceCommand,
+        OscDeviceSelector,
+      },
+      ButtplugClientEvent,
+    },
+    core::message::{ActuatorType, Endpoint},
+    server::device::hardware::{HardwareCommand, HardwareWriteCmd},
+    util::async_manager,
+  };
+  use futures::StreamExt;
+  use std::{net::SocketAddr, sync::Arc, time::Duration};
+  use tokio::{net::UdpSocket, time::timeout};
+
+  const BRIDGE_PORT: u16 = 12366;
+
+  #[tokio::test]
+  async fn test_osc_bridge_scalar_mapping() {
+    let (client, mut device) = test_client_with_device().await;
+    let client = Arc::new(client);
+    let mut event_stream = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    while let Some(event) = event_stream.next().await {
+      if let ButtplugClientEvent::DeviceAdded(_) = event {
+        break;
+      }
+    }
+
+    let bridge = ButtplugOscBridgeBuilder::default()
+      .listen_address(SocketAddr::from(([127, 0, 0, 1], BRIDGE_PORT)))
+      .mapping(OscAddressMapping::new(
+        "/avatar/parameters/Vibe",
+        OscDeviceSelector::Name("Aneros Vivi".to_owned()),
+        OscDeviceCommand::Scalar {
+          actuator_type: ActuatorType::Vibrate,
+          feature_index: Some(0),
+        },
+      ))
+      .mapping(OscAddressMapping::new(
+        "/avatar/parameters/Spin",
+        OscDeviceSelector::All,
+        OscDeviceCommand::Rotate {
+          feature_index: None,
+        },
+      ))
+      .finish(client.clone());
+    async_manager::spawn(async move {
+      bridge.run().await.expect("Test, assuming infallible.");
+    });
+
+    let socket = UdpSocket::bind("127.0.0.1:0")
+      .await
+      .expect("Test, assuming infallible.");
+    // Aneros Vivi has no rotators, so this should be ignored, and an unmapped address should be
+    // ignored too.
+    for address in ["/avatar/parameters/Spin", "/avatar/parameters/Unmapped"] {
+      let msg = OscMessage::new(address, vec![OscArgument::Float(0.5)]);
+      socket
+        .send_to(&msg.encode(), ("127.0.0.1", BRIDGE_PORT))
+        .await
+        .expect("Test, assuming infallible.");
+    }
+    // The bridge may not be listening yet, so keep sending until the device gets a command.
+    let msg = OscMessage::new("/avatar/parameters/Vibe", vec![OscArgument::Float(0.5)]);
+    let mut command = None;
+    for _ in 0..10u8 {
+      socket
+        .send_to(&msg.encode(), ("127.0.0.1", BRIDGE_PORT))
+        .await
+        .expect("Test, assuming infallible.");
+      if let Ok(received) = timeout(Duration::from_millis(100), device.receiver.recv()).await {
+        command = received;
+        break;
+      }
+    }
+    assert_eq!(
+      command.expect("Device never received a command."),
+      HardwareCommand::Write(HardwareWriteCmd::new(Endpoint::Tx, vec![0xF1, 64], false))
+    );
+
+    // Out of range values are clamped.
+    let msg = OscMessage::new("/avatar/parameters/Vibe", vec![OscArgument::Int(5)]);
+    socket
+      .send_to(&msg.encode(), ("127.0.0.1", BRIDGE_PORT))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(
+      timeout(Duration::from_secs(1), device.receiver.recv())
+        .await
+        .expect("Device never received a command."),
+      Some(HardwareCommand::Write(HardwareWriteCmd::new(
+        Endpoint::Tx,
+        vec![0xF1, 127],
+        false
+      )))
+    );
+  }
+}
@@ -27,6 +27,7 @@ use buttplug::{
     },
   },
   server::{
+    authenticator::SharedTokenAuthenticator,
     device::hardware::{HardwareCommand, HardwareWriteCmd},
     ButtplugServer,
     ButtplugServerBuilder,
@@ -79,6 +80,39 @@ async fn test_server_handshake_not_done_first() {
   assert!(!server.connected());
 }
 
+#[tokio::test]
+async fn test_server_handshake_authentication() {
+  let server = ButtplugServerBuilder::default()
+    .authenticator(SharedTokenAuthenticator::new("secret"))
+    .finish()
+    .expect("Test, assuming infallible.");
+  for msg in [
+    message::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION),
+    message::RequestServerInfo::new_with_auth_token(
+      "Test Client",
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+      "wrong",
+    ),
+  ] {
+    let result = server.parse_message(msg.into()).await;
+    assert!(matches!(
+      result.unwrap_err().original_error(),
+      ButtplugError::ButtplugHandshakeError(ButtplugHandshakeError::AuthenticationFailed)
+    ));
+    assert!(!server.connected());
+  }
+  let msg = message::RequestServerInfo::new_with_auth_token(
+    "Test Client",
+    BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    "secret",
+  );
+  assert!(matches!(
+    server.parse_message(msg.into()).await,
+    Ok(ButtplugServerMessage::ServerInfo(_))
+  ));
+  assert!(server.connected());
+}
+
 #[tokio::test]
 async fn test_client_version_older_than_server() {
   let msg =
@@ -6,14 +6,39 @@
 // for full license information.
 
 mod util;
-use buttplug::core::{
-  errors::{ButtplugDeviceError, ButtplugError},
-  message::{self, ButtplugServerMessage, Endpoint, BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION},
+use async_trait::async_trait;
+use buttplug::{
+  core::{
+    errors::{ButtplugDeviceError, ButtplugError},
+    message::{
+      self,
+      ButtplugServerMessage,
+      Endpoint,
+      UnsupportedDeviceReason,
+      BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION,
+    },
+  },
+  server::{
+    device::{
+      configuration::{BluetoothLESpecifier, ProtocolCommunicationSpecifier},
+      hardware::Hardware,
+      protocol::{ProtocolIdentifier, ProtocolIdentifierFactory, ProtocolInitializer},
+      ServerDeviceIdentifier,
+    },
+    ButtplugServerBuilder,
+  },
 };
 use futures::{pin_mut, StreamExt};
-use std::matches;
+use std::{
+  collections::{HashMap, HashSet},
+  matches,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+};
 pub use util::test_device_manager::TestDeviceCommunicationManagerBuilder;
-use util::test_server_with_device;
+use util::{test_device_manager::TestDeviceIdentifier, test_server_with_device};
 
 // Test devices that have protocols that support movements not all devices do.
 // For instance, the Onyx+ is part of a protocol that supports vibration, but
@@ -178,6 +203,160 @@ async fn test_reject_on_no_raw_message() {
   }
 }
 
+static FAILING_PROTOCOL_ATTEMPTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Default)]
+struct FailingProtocolIdentifierFactory {}
+
+impl ProtocolIdentifierFactory for FailingProtocolIdentifierFactory {
+  fn identifier(&self) -> &str {
+    "failing-test-protocol"
+  }
+
+  fn create(&self) -> Box<dyn ProtocolIdentifier> {
+    Box::new(FailingProtocolIdentifier {})
+  }
+}
+
+struct FailingProtocolIdentifier {}
+
+#[async_trait]
+impl ProtocolIdentifier for FailingProtocolIdentifier {
+  async fn identify(
+    &mut self,
+    _: Arc<Hardware>,
+  ) -> Result<(ServerDeviceIdentifier, Box<dyn ProtocolInitializer>), ButtplugDeviceError> {
+    FAILING_PROTOCOL_ATTEMPTED.store(true, Ordering::SeqCst);
+    Err(ButtplugDeviceError::ProtocolSpecificError(
+      "failing-test-protocol".to_owned(),
+      "Identification always fails".to_owned(),
+    ))
+  }
+}
+
+#[tokio::test]
+async fn test_server_protocol_fallback() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _device = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+  let mut server_builder = ButtplugServerBuilder::default();
+  server_builder
+    .comm_manager(builder)
+    .protocol_factory(FailingProtocolIdentifierFactory::default())
+    .communication_specifier(
+      "failing-test-protocol",
+      ProtocolCommunicationSpecifier::BluetoothLE(BluetoothLESpecifier::new(
+        HashSet::from(["Massage Demo".to_owned()]),
+        vec![],
+        HashSet::new(),
+        HashMap::new(),
+      )),
+    )
+    .protocol_priority("failing-test-protocol", 100);
+  let server = server_builder.finish().expect("Test, assuming infallible.");
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  server
+    .parse_message(
+      message::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
+    )
+    .await
+    .expect("Test, assuming infallible.");
+  server
+    .parse_message(message::StartScanning::default().into())
+    .await
+    .expect("Test, assuming infallible.");
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessage::DeviceAdded(da) = msg {
+      // The higher priority protocol should have been tried and failed, with the device falling
+      // back to the next matching protocol.
+      assert!(FAILING_PROTOCOL_ATTEMPTED.load(Ordering::SeqCst));
+      assert_eq!(da.device_name(), "Aneros Vivi");
+      return;
+    }
+  }
+  panic!("Device was never added.");
+}
+
+#[tokio::test]
+async fn test_server_reports_unsupported_devices() {
+  let mut builder = TestDeviceCommunicationManagerBuilder::default();
+  let _unsupported_device =
+    builder.add_test_device(&TestDeviceIdentifier::new("Not A Real Toy", None));
+  let _denied_device = builder.add_test_device(&TestDeviceIdentifier::new(
+    "Massage Demo",
+    Some("DeniedAddress".to_owned()),
+  ));
+  let mut server_builder = ButtplugServerBuilder::default();
+  server_builder
+    .comm_manager(builder)
+    .denied_address("DeniedAddress");
+  let server = server_builder.finish().expect("Test, assuming infallible.");
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  server
+    .parse_message(
+      message::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
+    )
+    .await
+    .expect("Test, assuming infallible.");
+  server
+    .parse_message(message::RequestUnsupportedDeviceReports::new(true).into())
+    .await
+    .expect("Test, assuming infallible.");
+  server
+    .parse_message(message::StartScanning::default().into())
+    .await
+    .expect("Test, assuming infallible.");
+  let mut reasons = HashMap::new();
+  while let Some(msg) = recv.next().await {
+    if let ButtplugServerMessage::UnsupportedDeviceFound(msg) = msg {
+      assert_eq!(
+        msg.communication_manager(),
+        "TestDeviceCommunicationManager"
+      );
+      assert!(msg.specifier().is_object());
+      reasons.insert(msg.device_name().clone(), msg.reason());
+      if reasons.len() == 2 {
+        break;
+      }
+    }
+  }
+  assert_eq!(
+    reasons.get("Not A Real Toy"),
+    Some(&UnsupportedDeviceReason::NoMatchingProtocol)
+  );
+  assert_eq!(
+    reasons.get("Massage Demo"),
+    Some(&UnsupportedDeviceReason::Denied)
+  );
+}
+
+#[tokio::test]
+async fn test_server_unsupported_device_reports_off_by_default() {
+  let (server, _device) = test_server_with_device("Not A Real Toy", false).await;
+  let recv = server.event_stream();
+  pin_mut!(recv);
+  server
+    .parse_message(
+      message::RequestServerInfo::new("Test Client", BUTTPLUG_CURRENT_MESSAGE_SPEC_VERSION).into(),
+    )
+    .await
+    .expect("Test, assuming infallible.");
+  server
+    .parse_message(message::StartScanning::default().into())
+    .await
+    .expect("Test, assuming infallible.");
+  while let Some(msg) = recv.next().await {
+    match msg {
+      ButtplugServerMessage::ScanningFinished(_) => return,
+      ButtplugServerMessage::UnsupportedDeviceFound(_) => {
+        panic!("Unsupported devices should not be reported unless requested.")
+      }
+      _ => continue,
+    }
+  }
+}
+
 /*
 #[cfg(target_os = "windows")]
 #[ignore = "Has weird timeout issues"]
@@ -0,0 +1,137 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+mod util;
+
+#[cfg(feature = "serialize-json")]
+mod stream_connector_tests {
+  use crate::util::{
+    test_device_manager::TestDeviceIdentifier,
+    ButtplugTestServer,
+    TestDeviceCommunicationManagerBuilder,
+  };
+  use buttplug::{
+    client::{ButtplugClient, ButtplugClientEvent},
+    core::{
+      connector::{
+        ButtplugRemoteClientConnector,
+        ButtplugRemoteServerConnector,
+        ButtplugStreamTransport,
+      },
+      message::serializer::{ButtplugClientJSONSerializer, ButtplugServerJSONSerializer},
+    },
+    server::ButtplugServerBuilder,
+    util::async_manager,
+  };
+  use futures::StreamExt;
+  use std::sync::Arc;
+
+  fn test_server() -> Arc<ButtplugTestServer> {
+    let mut builder = TestDeviceCommunicationManagerBuilder::default();
+    let _ = builder.add_test_device(&TestDeviceIdentifier::new("Massage Demo", None));
+    let mut server_builder = ButtplugServerBuilder::default();
+    server_builder.comm_manager(builder);
+    Arc::new(ButtplugTestServer::new(
+      server_builder.finish().expect("Test, assuming infallible."),
+    ))
+  }
+
+  async fn check_client_gets_device(client: &ButtplugClient) {
+    let mut recv = client.event_stream();
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    while let Some(event) = recv.next().await {
+      if let ButtplugClientEvent::DeviceAdded(device) = event {
+        assert_eq!(device.name(), "Aneros Vivi");
+        return;
+      }
+    }
+    panic!("Device never added.");
+  }
+
+  #[tokio::test]
+  async fn test_client_stream_server_stream() {
+    let server = test_server();
+    let (client_stream, server_stream) = tokio::io::duplex(4096);
+    let (server_reader, server_writer) = tokio::io::split(server_stream);
+    let server_clone = server.clone();
+    async_manager::spawn(async move {
+      let connector = ButtplugRemoteServerConnector::<_, ButtplugServerJSONSerializer>::new(
+        ButtplugStreamTransport::new(server_reader, server_writer),
+      );
+      server_clone
+        .start(connector)
+        .await
+        .expect("Test, assuming infallible.");
+    });
+    let (client_reader, client_writer) = tokio::io::split(client_stream);
+    let connector = ButtplugRemoteClientConnector::<_, ButtplugClientJSONSerializer>::new(
+      ButtplugStreamTransport::new(client_reader, client_writer),
+    );
+    let client = ButtplugClient::new("Test Client");
+    client
+      .connect(connector)
+      .await
+      .expect("Test, assuming infallible.");
+    check_client_gets_device(&client).await;
+    client
+      .disconnect()
+      .await
+      .expect("Test, assuming infallible.");
+  }
+
+  #[cfg(feature = "tcp-transport")]
+  #[tokio::test]
+  async fn test_client_tcp_client_server_tcp_server() {
+    use buttplug::core::connector::{
+      new_json_tcp_client_connector,
+      ButtplugTcpServerTransport,
+      ButtplugTcpServerTransportBuilder,
+    };
+    use std::time::Duration;
+    use tokio::time::sleep;
+
+    let server = test_server();
+    let server_clone = server.clone();
+    async_manager::spawn(async move {
+      let connector = ButtplugRemoteServerConnector::<
+        ButtplugTcpServerTransport,
+        ButtplugServerJSONSerializer,
+      >::new(
+        ButtplugTcpServerTransportBuilder::default()
+          .port(12360)
+          .finish(),
+      );
+      server_clone
+        .start(connector)
+        .await
+        .expect("Test, assuming infallible.");
+    });
+    // The server may not be listening yet, so retry a few times.
+    let client = ButtplugClient::new("Test Client");
+    let mut connected = false;
+    for _ in 0..10u8 {
+      if client
+        .connect(new_json_tcp_client_connector("127.0.0.1:12360"))
+        .await
+        .is_ok()
+      {
+        connected = true;
+        break;
+      }
+      sleep(Duration::from_millis(100)).await;
+    }
+    assert!(connected);
+    check_client_gets_device(&client).await;
+    client
+      .disconnect()
+      .await
+      .expect("Test, assuming infallible.");
+  }
+}
@@ -0,0 +1,207 @@
+// Buttplug Rust Source Code File - See https://buttplug.io for more info.
+//
+// Copyright 2016-2022 Nonpolynomial Labs LLC. All rights reserved.
+//
+// Licensed under the BSD 3-Clause license. See LICENSE file in the project root
+// for full license information.
+
+#[cfg(feature = "testing")]
+mod testing_harness_tests {
+  use buttplug::{
+    client::{
+      ButtplugClient,
+      ButtplugClientDevice,
+      ButtplugClientDeviceEvent,
+      ButtplugClientEvent,
+      LinearCommand,
+      RotateCommand,
+      ScalarValueCommand,
+    },
+    core::message::{
+      ActuatorType,
+      ButtplugCurrentSpecServerMessage,
+      ScalarCmd,
+      ScalarSubcommand,
+      SensorReading,
+      SensorType,
+    },
+    testing::{ButtplugTestServerBuilder, FakeDeviceBuilder},
+  };
+  use futures::{Stream, StreamExt};
+  use std::{ops::RangeInclusive, sync::Arc, time::Duration};
+  use tokio::time::timeout;
+
+  async fn next_device_added(
+    event_stream: &mut (impl Stream<Item = ButtplugClientEvent> + Unpin),
+  ) -> Arc<ButtplugClientDevice> {
+    loop {
+      if let ButtplugClientEvent::DeviceAdded(device) =
+        timeout(Duration::from_secs(5), event_stream.next())
+          .await
+          .expect("Device never showed up.")
+          .expect("Test, assuming infallible.")
+      {
+        return device;
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_fake_device_records_commands() {
+    let mut builder = ButtplugTestServerBuilder::default();
+    builder.server_builder().name("Fake Server");
+    let fake_device = builder.add_device(
+      FakeDeviceBuilder::new("Fake Toy")
+        .display_name("My Toy")
+        .scalar(ActuatorType::Vibrate, 20)
+        .scalar(ActuatorType::Oscillate, 10)
+        .rotate(10)
+        .linear(100),
+    );
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    client
+      .connect(builder.connector().expect("Test, assuming infallible."))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(client.server_name(), Some("Fake Server".to_owned()));
+    assert!(!fake_device.connected());
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let device = next_device_added(&mut event_stream).await;
+    assert!(fake_device.connected());
+    assert_eq!(device.name(), "Fake Toy");
+    assert_eq!(*device.display_name(), Some("My Toy".to_owned()));
+    assert_eq!(device.vibrate_attributes().len(), 1);
+    assert_eq!(device.oscillate_attributes().len(), 1);
+    assert_eq!(*device.rotate_attributes()[0].step_count(), 10);
+    assert_eq!(device.linear_attributes().len(), 1);
+
+    device
+      .vibrate(&ScalarValueCommand::ScalarValue(0.5))
+      .await
+      .expect("Test, assuming infallible.");
+    fake_device.assert_received(&[ScalarCmd::new(
+      0,
+      vec![ScalarSubcommand::new(0, 0.5, ActuatorType::Vibrate)],
+    )
+    .into()]);
+    device
+      .rotate(&RotateCommand::Rotate(0.3, false))
+      .await
+      .expect("Test, assuming infallible.");
+    device
+      .linear(&LinearCommand::Linear(500, 0.25))
+      .await
+      .expect("Test, assuming infallible.");
+    device
+      .vibrate(&ScalarValueCommand::ScalarValue(1.0))
+      .await
+      .expect("Test, assuming infallible.");
+    assert_eq!(fake_device.scalar_levels(0), vec![0.5, 1.0]);
+    assert!(fake_device.scalar_levels(1).is_empty());
+    assert_eq!(fake_device.rotations(0), vec![(0.3, false)]);
+    assert_eq!(fake_device.linear_movements(0), vec![(500, 0.25)]);
+    assert_eq!(fake_device.commands().len(), 4);
+
+    fake_device.clear_commands();
+    device.stop().await.expect("Test, assuming infallible.");
+    assert_eq!(fake_device.scalar_levels(0), vec![0.0]);
+    assert_eq!(fake_device.rotations(0), vec![(0.0, false)]);
+    assert_eq!(
+      fake_device
+        .wait_for_commands(2, Duration::from_millis(100))
+        .await
+        .len(),
+      2
+    );
+  }
+
+  #[tokio::test]
+  async fn test_fake_device_sensors_and_disconnect() {
+    let mut builder = ButtplugTestServerBuilder::default();
+    let fake_device = builder.add_device(
+      FakeDeviceBuilder::new("Fake Sensor Toy")
+        .scalar(ActuatorType::Vibrate, 20)
+        .battery()
+        .sensor_read(SensorType::Pressure, &[RangeInclusive::new(0, 1000)])
+        .sensor_subscribe(SensorType::Button, &[RangeInclusive::new(0, 1)]),
+    );
+    let client = ButtplugClient::new("Test Client");
+    let mut event_stream = client.event_stream();
+    client
+      .connect(builder.connector().expect("Test, assuming infallible."))
+      .await
+      .expect("Test, assuming infallible.");
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let device = next_device_added(&mut event_stream).await;
+
+    // Reads fail until a value has been injected.
+    assert!(device.battery_level().await.is_err());
+    fake_device.set_battery_level(0.75);
+    assert_eq!(
+      device
+        .battery_level()
+        .await
+        .expect("Test, assuming infallible."),
+      0.75
+    );
+    fake_device.set_sensor_value(1, vec![512]);
+    assert_eq!(
+      device
+        .read_sensor(1, SensorType::Pressure)
+        .await
+        .expect("Test, assuming infallible."),
+      vec![512]
+    );
+
+    // Subscribed readings come through as device events.
+    let mut device_events = device.event_stream();
+    device
+      .subscribe_sensor(0, SensorType::Button)
+      .await
+      .expect("Test, assuming infallible.");
+    fake_device.emit_sensor_reading(0, vec![1]);
+    let event = timeout(Duration::from_secs(5), device_events.next())
+      .await
+      .expect("Reading never showed up.")
+      .expect("Test, assuming infallible.");
+    if let ButtplugClientDeviceEvent::Message(ButtplugCurrentSpecServerMessage::SensorReading(
+      reading,
+    )) = event
+    {
+      assert_eq!(
+        reading,
+        SensorReading::new(device.index(), 0, SensorType::Button, vec![1])
+      );
+    } else {
+      panic!("Expected a sensor reading, got {:?}", event);
+    }
+
+    // Disconnected devices are removed, and found again on the next scan.
+    fake_device.disconnect();
+    loop {
+      if let ButtplugClientEvent::DeviceRemoved(_) =
+        timeout(Duration::from_secs(5), event_stream.next())
+          .await
+          .expect("Device never removed.")
+          .expect("Test, assuming infallible.")
+      {
+        break;
+      }
+    }
+    assert!(!fake_device.connected());
+    client
+      .start_scanning()
+      .await
+      .expect("Test, assuming infallible.");
+    let device = next_device_added(&mut event_stream).await;
+    assert_eq!(device.name(), "Fake Sensor Toy");
+    assert!(fake_device.connected());
+  }
+}
@@ -115,4 +115,227 @@ mod websocket_connector_tests {
   }
 }
 
+#[cfg(feature = "websockets")]
+mod websocket_tls_connector_tests {
+  use crate::util::ButtplugTestServer;
+  use buttplug::{
+    client::ButtplugClient,
+    core::{
+      connector::{
+        transport::{ButtplugConnectorTransport, ButtplugConnectorTransportSpecificError},
+        ButtplugConnectorError,
+        ButtplugRemoteClientConnector,
+        ButtplugRemoteServerConnector,
+        ButtplugWebsocketClientTransport,
+        ButtplugWebsocketServerTransport,
+        ButtplugWebsocketServerTransportBuilder,
+      },
+      message::serializer::{ButtplugClientJSONSerializer, ButtplugServerJSONSerializer},
+    },
+    util::async_manager,
+  };
+  use std::{sync::Arc, time::Duration};
+  use tokio::{sync::mpsc, time::sleep};
+
+  /// Returns a (certificate, private key) PEM pair for a self-signed "localhost" certificate.
+  fn self_signed_certificate() -> (String, String) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()])
+      .expect("Test, assuming infallible.");
+    (
+      cert.serialize_pem().expect("Test, assuming infallible."),
+      cert.serialize_private_key_pem(),
+    )
+  }
+
+  fn start_tls_server(port: u16, certificate: &str, private_key: &str) -> Arc<ButtplugTestServer> {
+    let server = Arc::new(ButtplugTestServer::default());
+    let server_clone = server.clone();
+    let transport = ButtplugWebsocketServerTransportBuilder::default()
+      .port(port)
+      .tls_pem(certificate.as_bytes(), private_key.as_bytes())
+      .finish();
+    async_manager::spawn(async move {
+      let connector = ButtplugRemoteServerConnector::<
+        ButtplugWebsocketServerTransport,
+        ButtplugServerJSONSerializer,
+      >::new(transport);
+      // Connection may fail on purpose in some tests, so don't check the result.
+      let _ = server_clone.start(connector).await;
+    });
+    server
+  }
+
+  async fn connect_client_with_retry(
+    create_transport: impl Fn() -> ButtplugWebsocketClientTransport,
+  ) {
+    let mut connected = false;
+    for _ in 0..10u8 {
+      let connector = ButtplugRemoteClientConnector::<
+        ButtplugWebsocketClientTransport,
+        ButtplugClientJSONSerializer,
+      >::new(create_transport());
+      let client = ButtplugClient::new("Test Client");
+      if client.connect(connector).await.is_ok() {
+        connected = true;
+        break;
+      }
+      sleep(Duration::from_millis(100)).await;
+    }
+    assert!(connected);
+  }
+
+  #[tokio::test]
+  async fn test_client_wss_client_server_wss_server_custom_ca() {
+    let (certificate, private_key) = self_signed_certificate();
+    let server = start_tls_server(12361, &certificate, &private_key);
+    connect_client_with_retry(|| {
+      ButtplugWebsocketClientTransport::new_secure_connector_with_ca(
+        "wss://localhost:12361",
+        certificate.as_bytes(),
+      )
+    })
+    .await;
+    server
+      .disconnect()
+      .await
+      .expect("Test, assuming infallible.");
+  }
+
+  #[tokio::test]
+  async fn test_client_wss_client_server_wss_server_pinned_cert() {
+    let (certificate, private_key) = self_signed_certificate();
+    let server = start_tls_server(12362, &certificate, &private_key);
+    connect_client_with_retry(|| {
+      ButtplugWebsocketClientTransport::new_secure_connector_with_pinned_cert(
+        "wss://127.0.0.1:12362",
+        certificate.as_bytes(),
+      )
+    })
+    .await;
+    server
+      .disconnect()
+      .await
+      .expect("Test, assuming infallible.");
+  }
+
+  #[tokio::test]
+  async fn test_client_wss_client_rejects_unpinned_cert() {
+    let (certificate, private_key) = self_signed_certificate();
+    let (other_certificate, _) = self_signed_certificate();
+    let _server = start_tls_server(12363, &certificate, &private_key);
+    for _ in 0..10u8 {
+      let transport = ButtplugWebsocketClientTransport::new_secure_connector_with_pinned_cert(
+        "wss://127.0.0.1:12363",
+        other_certificate.as_bytes(),
+      );
+      let (_outgoing_sender, outgoing_receiver) = mpsc::channel(256);
+      let (incoming_sender, _incoming_receiver) = mpsc::channel(256);
+      match transport.connect(outgoing_receiver, incoming_sender).await {
+        Err(ButtplugConnectorError::TransportSpecificError(
+          ButtplugConnectorTransportSpecificError::TlsError(_),
+        )) => return,
+        Ok(_) => panic!("Should not connect with a mismatched pinned certificate."),
+        // Server may not be listening yet.
+        Err(_) => sleep(Duration::from_millis(100)).await,
+      }
+    }
+    panic!("Never reached the server.");
+  }
+}
+
+#[cfg(all(feature = "websockets", feature = "server"))]
+mod websocket_auth_connector_tests {
+  use crate::util::ButtplugTestServer;
+  use async_tungstenite::tungstenite::{http::StatusCode, Error as TungsteniteError};
+  use buttplug::{
+    client::ButtplugClient,
+    core::{
+      connector::{
+        transport::{ButtplugConnectorTransport, ButtplugConnectorTransportSpecificError},
+        ButtplugConnectorError,
+        ButtplugRemoteClientConnector,
+        ButtplugRemoteServerConnector,
+        ButtplugWebsocketClientTransport,
+        ButtplugWebsocketServerTransport,
+        ButtplugWebsocketServerTransportBuilder,
+      },
+      message::serializer::{ButtplugClientJSONSerializer, ButtplugServerJSONSerializer},
+    },
+    server::authenticator::SharedTokenAuthenticator,
+    util::async_manager,
+  };
+  use std::{sync::Arc, time::Duration};
+  use tokio::{sync::mpsc, time::sleep};
+
+  fn start_authenticated_server(port: u16) -> Arc<ButtplugTestServer> {
+    let server = Arc::new(ButtplugTestServer::default());
+    let server_clone = server.clone();
+    let transport = ButtplugWebsocketServerTransportBuilder::default()
+      .port(port)
+      .authenticator(SharedTokenAuthenticator::new("secret"))
+      .finish();
+    async_manager::spawn(async move {
+      let connector = ButtplugRemoteServerConnector::<
+        ButtplugWebsocketServerTransport,
+        ButtplugServerJSONSerializer,
+      >::new(transport);
+      // Connection fails on purpose in some tests, so don't check the result.
+      let _ = server_clone.start(connector).await;
+    });
+    server
+  }
+
+  #[tokio::test]
+  async fn test_websocket_server_rejects_wrong_bearer_token() {
+    let _server = start_authenticated_server(12364);
+    for _ in 0..10u8 {
+      let transport =
+        ButtplugWebsocketClientTransport::new_insecure_connector("ws://127.0.0.1:12364")
+          .with_bearer_token("wrong");
+      let (_outgoing_sender, outgoing_receiver) = mpsc::channel(256);
+      let (incoming_sender, _incoming_receiver) = mpsc::channel(256);
+      match transport.connect(outgoing_receiver, incoming_sender).await {
+        Err(ButtplugConnectorError::TransportSpecificError(
+          ButtplugConnectorTransportSpecificError::TungsteniteError(TungsteniteError::Http(
+            response,
+          )),
+        )) => {
+          assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+          return;
+        }
+        Ok(_) => panic!("Should not connect without the right bearer token."),
+        // Server may not be listening yet.
+        Err(_) => sleep(Duration::from_millis(100)).await,
+      }
+    }
+    panic!("Never reached the server.");
+  }
+
+  #[tokio::test]
+  async fn test_websocket_server_accepts_bearer_token() {
+    let server = start_authenticated_server(12367);
+    let mut connected = false;
+    for _ in 0..10u8 {
+      let connector = ButtplugRemoteClientConnector::<
+        ButtplugWebsocketClientTransport,
+        ButtplugClientJSONSerializer,
+      >::new(
+        ButtplugWebsocketClientTransport::new_insecure_connector("ws://127.0.0.1:12367")
+          .with_bearer_token("secret"),
+      );
+      let client = ButtplugClient::new("Test Client");
+      if client.connect(connector).await.is_ok() {
+        connected = true;
+        break;
+      }
+      sleep(Duration::from_millis(100)).await;
+    }
+    assert!(connected);
+    server
+      .disconnect()
+      .await
+      .expect("Test, assuming infallible.");
+  }
+}
+
 // TODO Test disconnection event from server side
@@ -14,17 +14,20 @@ pub mod test_device_manager;
 pub use delay_device_communication_manager::DelayDeviceCommunicationManagerBuilder;
 mod channel_transport;
 use buttplug::{
-  client::ButtplugClient,
+  client::{ButtplugClient, ButtplugClientDevice, ButtplugClientEvent},
   core::connector::ButtplugInProcessClientConnectorBuilder,
   server::{ButtplugServer, ButtplugServerBuilder},
 };
 pub use channel_transport::*;
+use futures::{Stream, StreamExt};
+use std::{sync::Arc, time::Duration};
 pub use test_device_manager::{
   TestDeviceChannelHost,
   TestDeviceCommunicationManagerBuilder,
   TestHardwareEvent,
   TestHardwareNotification,
 };
+use tokio::time::timeout;
 
 use crate::util::test_device_manager::TestDeviceIdentifier;
 
@@ -33,6 +36,43 @@ pub fn setup_logging() {
   tracing_subscriber::fmt::init();
 }
 
+/// Waits for a client event that `filter` maps to a value, skipping any others. Panics with
+/// `description` if none arrives within 5 seconds.
+#[allow(dead_code)]
+pub async fn wait_for_client_event<T>(
+  event_stream: &mut (impl Stream<Item = ButtplugClientEvent> + Unpin),
+  description: &str,
+  mut filter: impl FnMut(ButtplugClientEvent) -> Option<T>,
+) -> T {
+  loop {
+    match timeout(Duration::from_secs(5), event_stream.next())
+      .await
+      .unwrap_or_else(|_| panic!("Timed out waiting for {}.", description))
+    {
+      Some(event) => {
+        if let Some(value) = filter(event) {
+          return value;
+        }
+      }
+      None => panic!(
+        "Client event stream closed while waiting for {}.",
+        description
+      ),
+    }
+  }
+}
+
+#[allow(dead_code)]
+pub async fn wait_for_device_added(
+  event_stream: &mut (impl Stream<Item = ButtplugClientEvent> + Unpin),
+) -> Arc<ButtplugClientDevice> {
+  wait_for_client_event(event_stream, "a device to be added", |event| match event {
+    ButtplugClientEvent::DeviceAdded(device) => Some(device),
+    _ => None,
+  })
+  .await
+}
+
 #[allow(dead_code)]
 pub async fn test_client() -> ButtplugClient {
   let mut server_builder = ButtplugServerBuilder::default();
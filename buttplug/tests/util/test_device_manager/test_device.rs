@@ -39,8 +39,8 @@ use tokio::sync::{broadcast, mpsc, Mutex};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TestHardwareNotification {
-  endpoint: Endpoint,
-  data: Vec<u8>,
+  pub endpoint: Endpoint,
+  pub data: Vec<u8>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -89,14 +89,12 @@ impl HardwareConnector for TestHardwareConnector {
 }
 
 pub struct TestHardwareSpecializer {
-  hardware: Option<TestDevice>,
+  hardware: TestDevice,
 }
 
 impl TestHardwareSpecializer {
   fn new(hardware: TestDevice) -> Self {
-    Self {
-      hardware: Some(hardware),
-    }
+    Self { hardware }
   }
 }
 
@@ -106,7 +104,10 @@ impl HardwareSpecializer for TestHardwareSpecializer {
     &mut self,
     specifiers: &[ProtocolCommunicationSpecifier],
   ) -> Result<Hardware, ButtplugDeviceError> {
-    let mut device = self.hardware.take().expect("Test");
+    // Like the btleplug specializer, this builds fresh hardware for each protocol candidate over the
+    // same underlying device, so a protocol that fails to identify or initialize can fall back.
+    let mut device = self.hardware.clone();
+    device.endpoints.clear();
     let mut endpoints = vec![];
     if let Some(ProtocolCommunicationSpecifier::BluetoothLE(btle)) = specifiers
       .iter()
@@ -154,6 +155,7 @@ pub fn new_device_channel() -> (TestDeviceChannelHost, TestDeviceChannelDevice)
   )
 }
 
+#[derive(Clone)]
 pub struct TestDevice {
   name: String,
   address: String,
@@ -16,7 +16,7 @@ use super::{
 };
 use buttplug::{
   core::ButtplugResultFuture,
-  server::device::configuration::{BluetoothLESpecifier, ProtocolCommunicationSpecifier},
+  server::device::configuration::{BluetoothLEAdvertisement, ProtocolCommunicationSpecifier},
   server::device::hardware::communication::{
     HardwareCommunicationManager,
     HardwareCommunicationManagerBuilder,
@@ -110,8 +110,8 @@ fn new_uninitialized_ble_test_device(
   device_channel: TestDeviceChannelDevice,
 ) -> TestHardwareConnector {
   let address = identifier.address.clone();
-  let specifier = ProtocolCommunicationSpecifier::BluetoothLE(
-    BluetoothLESpecifier::new_from_device(&identifier.name, &HashMap::new(), &[]),
+  let specifier = ProtocolCommunicationSpecifier::BluetoothLEAdvertisement(
+    BluetoothLEAdvertisement::new(&identifier.name, &HashMap::new(), &[]),
   );
   let hardware = TestDevice::new(&identifier.name, &address, device_channel);
   TestHardwareConnector::new(specifier, hardware)